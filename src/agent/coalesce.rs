@@ -0,0 +1,90 @@
+// Sharing one run between identical user requests handled at the same time
+
+use super::error::AgentError;
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+
+/// Outcome of a shared run; the error is carried as its message
+type Shared = Result<String, String>;
+
+/// Runs in flight, keyed by a hash of the request they answer
+#[derive(Default)]
+pub struct Coalescer {
+    /// Never locked across an await
+    runs: Mutex<HashMap<u64, broadcast::Sender<Shared>>>,
+}
+
+impl Coalescer {
+    /// Key of a user input, answered with or without tools
+    pub fn key(content: &str, offer_tools: bool) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (content, offer_tools).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Run `work` for `key`, or, when a run for the same key is already in
+    /// flight, wait for its result instead
+    ///
+    /// A waiter whose run is abandoned (timed out or cancelled) before
+    /// finishing runs `work` itself.
+    pub async fn run<F>(&self, key: u64, work: F) -> Result<String, AgentError>
+    where
+        F: Future<Output = Result<String, AgentError>>,
+    {
+        let waiting = {
+            let mut runs = self.runs.lock().unwrap();
+            match runs.get(&key) {
+                Some(tx) => Some(tx.subscribe()),
+                None => {
+                    runs.insert(key, broadcast::channel(1).0);
+                    None
+                }
+            }
+        };
+        if let Some(mut rx) = waiting {
+            return match rx.recv().await {
+                Ok(shared) => shared.map_err(AgentError::Coalesced),
+                Err(_) => work.await,
+            };
+        }
+
+        let run = Run {
+            coalescer: self,
+            key,
+            finished: false,
+        };
+        let result = work.await;
+        if let Some(tx) = run.finish() {
+            let _ = tx.send(result.as_ref().cloned().map_err(|e| e.to_string()));
+        }
+        result
+    }
+}
+
+/// A run registered in the map; removed when it finishes or is dropped
+struct Run<'a> {
+    coalescer: &'a Coalescer,
+    key: u64,
+    finished: bool,
+}
+
+impl Run<'_> {
+    /// Unregister the run, returning the sender its waiters listen on
+    fn finish(mut self) -> Option<broadcast::Sender<Shared>> {
+        self.finished = true;
+        self.coalescer.runs.lock().unwrap().remove(&self.key)
+    }
+}
+
+impl Drop for Run<'_> {
+    fn drop(&mut self) {
+        // Abandoned: dropping the sender wakes the waiters to run themselves
+        if !self.finished {
+            self.coalescer.runs.lock().unwrap().remove(&self.key);
+        }
+    }
+}
@@ -1,6 +1,7 @@
 // Agent configuration
 
-use super::types::AgentConfig;
+use super::identity::resolve_identity;
+use super::types::{AgentConfig, Profile};
 use tracing::warn;
 
 /// Parse an environment variable, logging a warning if the value is present but invalid.
@@ -17,37 +18,303 @@ fn parse_env_var<T: std::str::FromStr>(name: &str, default: T) -> T {
     }
 }
 
+/// Parse a comma-separated list of tool names, if the variable is set
+fn parse_tool_list(name: &str) -> Option<Vec<String>> {
+    std::env::var(name).ok().map(|tools| {
+        tools
+            .split(',')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(String::from)
+            .collect()
+    })
+}
+
 impl AgentConfig {
     /// Load from environment variables
     pub fn from_env() -> Result<Self, AgentConfigError> {
         dotenvy::dotenv().ok();
 
-        let mut config = AgentConfig::default();
+        // Profile defaults first, so the specific variables below override them
+        let profile = parse_env_var("AGENT_PROFILE", Profile::default());
+        let mut config = AgentConfig::default().with_profile(profile);
 
+        config.identity = resolve_identity(
+            &config.identity,
+            std::env::var("AGENT_IDENTITY")
+                .ok()
+                .filter(|v| !v.is_empty()),
+        );
         config.max_tool_rounds = parse_env_var("AGENT_MAX_TOOL_ROUNDS", config.max_tool_rounds);
+        if let Some(notice) = std::env::var("AGENT_TOOL_ROUNDS_NOTICE")
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+        {
+            config.tool_rounds_notice = notice;
+        }
+        if let Some(message) = std::env::var("AGENT_NO_OUTPUT_MESSAGE")
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+        {
+            config.no_output_message = message;
+        }
+        config.max_tool_calls_per_round = parse_env_var(
+            "AGENT_MAX_TOOL_CALLS_PER_ROUND",
+            config.max_tool_calls_per_round,
+        );
+        config.tool_round_timeout_secs = parse_env_var(
+            "AGENT_TOOL_ROUND_TIMEOUT_SECS",
+            config.tool_round_timeout_secs,
+        );
+        config.readonly_first_rounds =
+            parse_env_var("AGENT_READONLY_FIRST_ROUNDS", config.readonly_first_rounds);
+        config.max_init_tool_rounds =
+            parse_env_var("AGENT_MAX_INIT_TOOL_ROUNDS", config.max_init_tool_rounds);
         config.init_timeout_secs =
             parse_env_var("AGENT_INIT_TIMEOUT_SECS", config.init_timeout_secs);
+        config.init_backend_wait_secs = parse_env_var(
+            "AGENT_INIT_BACKEND_WAIT_SECS",
+            config.init_backend_wait_secs,
+        );
         config.shutdown_timeout_secs =
             parse_env_var("AGENT_SHUTDOWN_TIMEOUT_SECS", config.shutdown_timeout_secs);
+        config.drain_timeout_secs =
+            parse_env_var("AGENT_DRAIN_TIMEOUT_SECS", config.drain_timeout_secs);
+        config.max_concurrent_requests = parse_env_var(
+            "AGENT_MAX_CONCURRENT_REQUESTS",
+            config.max_concurrent_requests,
+        );
         config.handle_timeout_secs =
             parse_env_var("AGENT_HANDLE_TIMEOUT_SECS", config.handle_timeout_secs);
+        config.max_handle_wall_secs =
+            parse_env_var("AGENT_MAX_HANDLE_WALL_SECS", config.max_handle_wall_secs);
+        config.max_input_tokens = parse_env_var("AGENT_MAX_INPUT_TOKENS", config.max_input_tokens);
+        config.oversized_input = parse_env_var("AGENT_OVERSIZED_INPUT", config.oversized_input);
+        config.max_message_bytes =
+            parse_env_var("AGENT_MAX_MESSAGE_BYTES", config.max_message_bytes);
+        config.dump_dir = parse_env_var("AGENT_DUMP_DIR", config.dump_dir);
+        config.journal_wal_path = std::env::var("AGENT_JOURNAL_WAL_PATH")
+            .ok()
+            .filter(|v| !v.is_empty())
+            .map(std::path::PathBuf::from);
+        config.journal_wal_fsync =
+            parse_env_var("AGENT_JOURNAL_WAL_FSYNC", config.journal_wal_fsync);
+        config.init_report_path = std::env::var("AGENT_INIT_REPORT_PATH")
+            .ok()
+            .filter(|v| !v.is_empty())
+            .map(std::path::PathBuf::from);
+        config.response_prefill = std::env::var("AGENT_RESPONSE_PREFILL")
+            .ok()
+            .filter(|v| !v.is_empty());
+        config.stop_sequence = parse_env_var("AGENT_STOP_SEQUENCE", config.stop_sequence);
+        config.allow_config_writes =
+            parse_env_var("AGENT_ALLOW_CONFIG_WRITES", config.allow_config_writes);
+        config.temperature_bounds =
+            parse_env_var("AGENT_TEMPERATURE_BOUNDS", config.temperature_bounds);
+        config.tool_rounds_bounds =
+            parse_env_var("AGENT_TOOL_ROUNDS_BOUNDS", config.tool_rounds_bounds);
+        config.plan_mode = parse_env_var("AGENT_PLAN_MODE", config.plan_mode);
+        config.chat_only = parse_env_var("AGENT_CHAT_ONLY", config.chat_only);
+        config.coalesce_identical_requests = parse_env_var(
+            "AGENT_COALESCE_IDENTICAL_REQUESTS",
+            config.coalesce_identical_requests,
+        );
+        config.summarize_tool_outputs = parse_env_var(
+            "AGENT_SUMMARIZE_TOOL_OUTPUTS",
+            config.summarize_tool_outputs,
+        );
+        config.tool_output_summary_bytes = parse_env_var(
+            "AGENT_TOOL_OUTPUT_SUMMARY_BYTES",
+            config.tool_output_summary_bytes,
+        );
+        config.max_total_input_tokens = parse_env_var(
+            "AGENT_MAX_TOTAL_INPUT_TOKENS",
+            config.max_total_input_tokens,
+        );
+        config.max_total_output_tokens = parse_env_var(
+            "AGENT_MAX_TOTAL_OUTPUT_TOKENS",
+            config.max_total_output_tokens,
+        );
+        if let Some(tools) = parse_tool_list("AGENT_ENABLED_TOOLS") {
+            config.enabled_tools = tools;
+        }
+        if let Some(tools) = parse_tool_list("AGENT_INIT_TOOLS") {
+            config.init_tools = tools;
+        }
+        if let Some(tools) = parse_tool_list("AGENT_HANDLE_TOOLS") {
+            config.handle_tools = tools;
+        }
 
         Ok(config)
     }
+
+    /// Check ranges and well-formedness, reporting every problem at once
+    pub fn validate(&self) -> Result<(), AgentConfigError> {
+        let mut problems = Vec::new();
+
+        if self.max_tool_rounds == 0 {
+            problems.push("max_tool_rounds must be greater than 0".to_string());
+        }
+        if self.max_tool_calls_per_round == 0 {
+            problems.push("max_tool_calls_per_round must be greater than 0".to_string());
+        }
+        if self.max_init_tool_rounds == 0 {
+            problems.push("max_init_tool_rounds must be greater than 0".to_string());
+        }
+        for (name, secs) in [
+            ("init_timeout_secs", self.init_timeout_secs),
+            ("shutdown_timeout_secs", self.shutdown_timeout_secs),
+            ("drain_timeout_secs", self.drain_timeout_secs),
+            ("handle_timeout_secs", self.handle_timeout_secs),
+            ("tool_round_timeout_secs", self.tool_round_timeout_secs),
+        ] {
+            if secs == 0 {
+                problems.push(format!("{} must be greater than 0", name));
+            }
+        }
+        if self.max_concurrent_requests == 0 {
+            problems.push("max_concurrent_requests must be greater than 0".to_string());
+        } else if self.coalesce_identical_requests && self.max_concurrent_requests == 1 {
+            // Requests handled one at a time are never in flight together
+            problems.push(
+                "coalesce_identical_requests needs max_concurrent_requests greater than 1"
+                    .to_string(),
+            );
+        }
+        if self.max_input_tokens == 0 {
+            problems.push("max_input_tokens must be greater than 0".to_string());
+        }
+        if self.max_message_bytes == 0 {
+            problems.push("max_message_bytes must be greater than 0".to_string());
+        }
+        if self.tool_output_summary_bytes == 0 {
+            problems.push("tool_output_summary_bytes must be greater than 0".to_string());
+        }
+        if self.system_prompt.trim().is_empty() {
+            problems.push("system_prompt must not be empty".to_string());
+        }
+        if self.temperature_bounds.min < 0.0 || self.temperature_bounds.max > 2.0 {
+            problems.push(format!(
+                "temperature_bounds must lie within 0.0..2.0, got {}..{}",
+                self.temperature_bounds.min, self.temperature_bounds.max
+            ));
+        }
+        if self.tool_rounds_bounds.min == 0 {
+            problems.push("tool_rounds_bounds minimum must be greater than 0".to_string());
+        }
+        if self
+            .response_prefill
+            .as_deref()
+            .is_some_and(|p| p.trim().is_empty())
+        {
+            problems.push("response_prefill must not be only whitespace".to_string());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(AgentConfigError::ConfigInvalid(problems.join("; ")))
+        }
+    }
 }
 
 #[derive(Debug)]
 #[allow(dead_code)]
 pub enum AgentConfigError {
     ConfigMissing(String),
+    ConfigInvalid(String),
 }
 
 impl std::fmt::Display for AgentConfigError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             AgentConfigError::ConfigMissing(s) => write!(f, "Config missing: {}", s),
+            AgentConfigError::ConfigInvalid(s) => write!(f, "Invalid configuration: {}", s),
         }
     }
 }
 
 impl std::error::Error for AgentConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::types::{Bounds, Profile};
+
+    #[test]
+    fn test_profiles_set_preamble_and_defaults() {
+        let cases = [
+            ("conservative", "# Profile: Conservative", true, false, 3),
+            ("Balanced", "# Profile: Balanced", false, false, 10),
+            ("AUTONOMOUS", "# Profile: Autonomous", false, true, 10),
+        ];
+        for (name, preamble, plan_mode, config_writes, calls_per_round) in cases {
+            let profile: Profile = name.parse().unwrap();
+            let config = AgentConfig::default().with_profile(profile);
+
+            let prompt = config.full_system_prompt();
+            assert!(prompt.starts_with(preamble), "{}: {}", name, prompt);
+            assert!(prompt.ends_with(&config.system_prompt));
+            assert_eq!(config.plan_mode, plan_mode, "{}", name);
+            assert_eq!(config.allow_config_writes, config_writes, "{}", name);
+            assert_eq!(config.max_tool_calls_per_round, calls_per_round, "{}", name);
+            assert!(config.validate().is_ok());
+        }
+
+        assert_eq!(AgentConfig::default().profile, Profile::Balanced);
+        assert!("reckless".parse::<Profile>().is_err());
+    }
+
+    #[test]
+    fn test_validate_out_of_range_values() {
+        assert!(AgentConfig::default().validate().is_ok());
+
+        type Mutation = fn(&mut AgentConfig);
+        let cases: Vec<(Mutation, &str)> = vec![
+            (
+                |c| c.max_tool_rounds = 0,
+                "max_tool_rounds must be greater than 0",
+            ),
+            (
+                |c| c.max_tool_calls_per_round = 0,
+                "max_tool_calls_per_round",
+            ),
+            (|c| c.max_init_tool_rounds = 0, "max_init_tool_rounds"),
+            (|c| c.init_timeout_secs = 0, "init_timeout_secs"),
+            (|c| c.shutdown_timeout_secs = 0, "shutdown_timeout_secs"),
+            (|c| c.drain_timeout_secs = 0, "drain_timeout_secs"),
+            (|c| c.handle_timeout_secs = 0, "handle_timeout_secs"),
+            (|c| c.tool_round_timeout_secs = 0, "tool_round_timeout_secs"),
+            (|c| c.max_concurrent_requests = 0, "max_concurrent_requests"),
+            (
+                |c| c.coalesce_identical_requests = true,
+                "coalesce_identical_requests needs max_concurrent_requests greater than 1",
+            ),
+            (|c| c.max_input_tokens = 0, "max_input_tokens"),
+            (|c| c.max_message_bytes = 0, "max_message_bytes"),
+            (
+                |c| c.tool_output_summary_bytes = 0,
+                "tool_output_summary_bytes",
+            ),
+            (|c| c.system_prompt = String::new(), "system_prompt"),
+            (
+                |c| c.response_prefill = Some("  ".to_string()),
+                "response_prefill",
+            ),
+            (
+                |c| c.temperature_bounds = Bounds::new(0.0, 3.0),
+                "temperature_bounds must lie within 0.0..2.0",
+            ),
+            (
+                |c| c.tool_rounds_bounds = Bounds::new(0, 10),
+                "tool_rounds_bounds",
+            ),
+        ];
+        for (mutate, expected) in cases {
+            let mut config = AgentConfig::default();
+            mutate(&mut config);
+            let err = config.validate().unwrap_err().to_string();
+            assert!(err.contains(expected), "{:?} not in {:?}", expected, err);
+        }
+    }
+}
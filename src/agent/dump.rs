@@ -0,0 +1,125 @@
+// Signal-triggered memory dumps for live debugging
+
+use crate::memory::MemoryHandle;
+
+use super::error::AgentError;
+
+use std::path::PathBuf;
+use tokio::signal::unix::{SignalKind, signal};
+use tracing::{error, info};
+
+/// Writes timestamped snapshots of the agent's memory to `dir`
+///
+/// Holds its own `MemoryHandle`, so a dump runs beside request handling
+/// instead of waiting for it; memory is only locked while the snapshot is
+/// serialized, never during file I/O.
+#[derive(Clone)]
+pub struct MemoryDumper {
+    memory: MemoryHandle,
+    dir: PathBuf,
+}
+
+impl MemoryDumper {
+    pub fn new(memory: MemoryHandle, dir: PathBuf) -> Self {
+        Self { memory, dir }
+    }
+
+    /// Write identity, topology and the full journal to a new file,
+    /// returning its path and the number of journal records
+    pub fn dump(&self) -> Result<(PathBuf, usize), AgentError> {
+        let mut buf = Vec::new();
+        let count = self
+            .memory
+            .read(|mem| mem.dump_jsonl(&mut buf))
+            .map_err(|e| AgentError::JournalExport(e.to_string()))?;
+
+        let name = format!(
+            "shelly-dump-{}.jsonl",
+            chrono::Utc::now().format("%Y%m%dT%H%M%S%.3fZ")
+        );
+        let path = self.dir.join(name);
+        // Written aside and renamed, so a dump is never seen half-written
+        let partial = path.with_extension("jsonl.tmp");
+        std::fs::create_dir_all(&self.dir)
+            .and_then(|()| std::fs::write(&partial, buf))
+            .and_then(|()| std::fs::rename(&partial, &path))
+            .map_err(|e| AgentError::JournalExport(format!("{}: {}", path.display(), e)))?;
+
+        info!(path = %path.display(), records = count, "Memory dumped");
+        Ok((path, count))
+    }
+
+    /// Dump on every SIGUSR1 until the runtime shuts down
+    pub fn spawn_on_sigusr1(self) -> std::io::Result<()> {
+        let mut signals = signal(SignalKind::user_defined1())?;
+        tokio::spawn(async move {
+            while signals.recv().await.is_some() {
+                let dumper = self.clone();
+                match tokio::task::spawn_blocking(move || dumper.dump()).await {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => error!(error = %e, "Memory dump failed"),
+                    Err(e) => error!(error = %e, "Memory dump task failed"),
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_sigusr1_writes_dump() {
+        let memory = MemoryHandle::new(Memory::new("Shelly on web-01".to_string()));
+        memory.write(|mem| {
+            mem.add_topology("nginx -> app:8080");
+            mem.add_observation("disk is 90% full");
+            mem.add_interaction("check disk", "/ is 90% full");
+        });
+        let dir = std::env::temp_dir().join(format!("shelly-dump-{}", uuid::Uuid::new_v4()));
+        MemoryDumper::new(memory, dir.clone())
+            .spawn_on_sigusr1()
+            .unwrap();
+
+        let status = std::process::Command::new("kill")
+            .args(["-USR1", &std::process::id().to_string()])
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let mut dumps = Vec::new();
+        for _ in 0..100 {
+            dumps = std::fs::read_dir(&dir)
+                .map(|entries| {
+                    entries
+                        .map(|e| e.unwrap().path())
+                        .filter(|p| p.extension().is_some_and(|ext| ext == "jsonl"))
+                        .collect()
+                })
+                .unwrap_or_default();
+            if !dumps.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(dumps.len(), 1, "expected one dump in {}", dir.display());
+
+        let text = std::fs::read_to_string(&dumps[0]).unwrap();
+        let lines: Vec<serde_json::Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["identity"], "Shelly on web-01");
+        assert_eq!(lines[0]["topology"][0], "nginx -> app:8080");
+        assert_eq!(lines[0]["records"], 2);
+        assert!(text.contains("disk is 90% full"));
+        assert!(text.contains("check disk"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -9,14 +9,53 @@ pub enum AgentError {
     Inference(String),
 
     #[error("Request build error: {0}")]
-    RequestBuild(&'static str),
+    RequestBuild(crate::brain::BuildError),
 
     #[error("Timeout after {0}s")]
     Timeout(u64),
+
+    #[error("Input too large: ~{estimated} tokens exceeds the limit of {limit}")]
+    InputTooLarge { estimated: usize, limit: usize },
+
+    #[error("Empty response from model after {0} attempts")]
+    EmptyResponse(u32),
+
+    #[error("Token budget exceeded: {spent} {kind} tokens used, limit {limit}")]
+    BudgetExceeded {
+        kind: &'static str,
+        spent: u64,
+        limit: u64,
+    },
+
+    #[error("No journaled interaction {0}")]
+    UnknownInteraction(String),
+
+    #[error("Unknown or expired plan: {0}")]
+    UnknownPlan(String),
+
+    #[error("Journal export failed: {0}")]
+    JournalExport(String),
+
+    /// Carries the model's last text followed by `tool_rounds_notice`
+    #[error("{0}")]
+    ToolRoundsExhausted(String),
+
+    /// Carries the model's last text followed by the wall-clock notice
+    #[error("{0}")]
+    WallClockExceeded(String),
+
+    /// Carries `no_output_message` for the stop reason the request ended on
+    #[error("{0}")]
+    NoUsableOutput(String),
+
+    /// Error of the identical request whose run this one shared
+    #[error("{0}")]
+    Coalesced(String),
 }
 
 /// Inference loop errors
 #[derive(Debug, Error)]
+#[allow(dead_code)]
 pub enum InferenceError {
     #[error("Max tool rounds ({max_rounds}) exceeded, reached {actual_rounds} rounds")]
     MaxToolRounds { max_rounds: u32, actual_rounds: u32 },
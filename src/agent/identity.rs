@@ -0,0 +1,56 @@
+// Host identity - tell apart agents running on different machines
+
+use std::net::{IpAddr, UdpSocket};
+
+/// Hostname of this machine, if it can be determined
+pub fn detect_hostname() -> Option<String> {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| std::fs::read_to_string("/etc/hostname"))
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+}
+
+/// Address of the interface that carries the default route.
+///
+/// Connecting a UDP socket only selects a route; no packet is sent.
+pub fn detect_primary_ip() -> Option<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("192.0.2.1:9").ok()?;
+    let ip = socket.local_addr().ok()?.ip();
+    (!ip.is_unspecified()).then_some(ip)
+}
+
+/// Identity stored in memory: `explicit` verbatim when configured, otherwise
+/// `base` augmented with the detected host, e.g. "Shelly on web-prod-03 (10.0.1.4)"
+pub fn resolve_identity(base: &str, explicit: Option<String>) -> String {
+    if let Some(identity) = explicit {
+        return identity;
+    }
+    match (detect_hostname(), detect_primary_ip()) {
+        (Some(host), Some(ip)) => format!("{} on {} ({})", base, host, ip),
+        (Some(host), None) => format!("{} on {}", base, host),
+        (None, Some(ip)) => format!("{} on {}", base, ip),
+        (None, None) => base.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identity_includes_hostname_without_override() {
+        let host = detect_hostname().expect("test host has a hostname");
+        let identity = resolve_identity("Shelly", None);
+        assert!(identity.starts_with("Shelly on "));
+        assert!(identity.contains(&host), "{} lacks {}", identity, host);
+    }
+
+    #[test]
+    fn test_explicit_identity_wins() {
+        let identity = resolve_identity("Shelly", Some("db-primary agent".to_string()));
+        assert_eq!(identity, "db-primary agent");
+    }
+}
@@ -1,10 +1,11 @@
 // Inference loop - Core inference unit for agent
 // See docs/mainloop-design.md for design details
+#![allow(dead_code)]
 
 use crate::brain::{
-    types::StopReason, ContentBlock, Message, MessageRequest, MessageResponse, Role, ToolDefinition,
+    ContentBlock, Message, MessageRequest, MessageResponse, Role, ToolDefinition,
 };
-use crate::executor::ToolOutput;
+use crate::executor::{ResultFormat, ToolOutput};
 
 pub use crate::agent::error::InferenceError;
 
@@ -63,41 +64,39 @@ pub fn inference_loop<'a, B: BrainRef, E: ExecutorRef>(
         // Extract tool calls
         let tool_calls = extract_tool_calls(&response);
 
-        match response.stop_reason {
-            Some(StopReason::ToolUse) => {
-                // Count actual tool execution
-                let new_tool_rounds = tool_rounds + 1;
-                if new_tool_rounds > max_tool_rounds {
-                    return Err(InferenceError::MaxToolRounds {
-                        max_rounds: max_tool_rounds,
-                        actual_rounds: new_tool_rounds,
-                    });
-                }
-
-                // Add assistant message with tool use
-                messages.push(Message {
-                    role: Role::Assistant,
-                    content: response.content.clone(),
+        if !response.effective_stop_reason().is_terminal() {
+            // Count actual tool execution
+            let new_tool_rounds = tool_rounds + 1;
+            if new_tool_rounds > max_tool_rounds {
+                return Err(InferenceError::MaxToolRounds {
+                    max_rounds: max_tool_rounds,
+                    actual_rounds: new_tool_rounds,
                 });
+            }
 
-                // Execute tool calls
-                execute_tool_calls(executor, tool_calls, messages).await;
+            // Add assistant message with tool use, blocks in the order the
+            // model produced them; some backends validate the ordering
+            messages.push(Message {
+                role: Role::Assistant,
+                content: response.content.clone(),
+            });
 
-                // Recursive call
-                inference_loop(brain, executor, messages, system, max_tool_rounds, new_tool_rounds).await
-            }
-            _ => {
-                // Non-ToolUse: all are termination conditions
-                messages.push(Message {
-                    role: Role::Assistant,
-                    content: response.content.clone(),
-                });
+            // Execute tool calls
+            execute_tool_calls(executor, tool_calls, messages).await;
 
-                Ok(InferenceResult {
-                    text: text_content,
-                    tool_rounds,
-                })
-            }
+            // Recursive call
+            inference_loop(brain, executor, messages, system, max_tool_rounds, new_tool_rounds).await
+        } else {
+            // Non-ToolUse: all are termination conditions
+            messages.push(Message {
+                role: Role::Assistant,
+                content: response.content.clone(),
+            });
+
+            Ok(InferenceResult {
+                text: text_content,
+                tool_rounds,
+            })
         }
     }.boxed()
 }
@@ -108,16 +107,58 @@ pub trait BrainRef: Send + Sync {
     async fn infer(&self, request: MessageRequest) -> Result<MessageResponse, String>;
     fn model(&self) -> &str;
     fn max_output_tokens(&self) -> u32;
+    /// Output token limit for a specific model (defaults to max_output_tokens)
+    fn max_tokens_for(&self, _model: &str) -> u32 {
+        self.max_output_tokens()
+    }
     fn temperature(&self) -> Option<f32>;
     fn top_p(&self) -> Option<f32>;
     fn top_k(&self) -> Option<u32>;
 }
 
+#[async_trait::async_trait]
+impl BrainRef for crate::brain::Brain {
+    async fn infer(&self, request: MessageRequest) -> Result<MessageResponse, String> {
+        crate::brain::Brain::infer(self, request)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    fn model(&self) -> &str {
+        self.default_model()
+    }
+
+    fn max_output_tokens(&self) -> u32 {
+        crate::brain::Brain::max_output_tokens(self)
+    }
+
+    fn max_tokens_for(&self, model: &str) -> u32 {
+        crate::brain::Brain::max_tokens_for(self, model)
+    }
+
+    fn temperature(&self) -> Option<f32> {
+        crate::brain::Brain::temperature(self)
+    }
+
+    fn top_p(&self) -> Option<f32> {
+        crate::brain::Brain::top_p(self)
+    }
+
+    fn top_k(&self) -> Option<u32> {
+        crate::brain::Brain::top_k(self)
+    }
+}
+
 /// Trait for executor reference (for testing)
 #[async_trait::async_trait]
 pub trait ExecutorRef: Send + Sync {
     async fn execute(&self, tool_name: &str, input: serde_json::Value) -> Result<ToolOutput, String>;
     fn tool_definitions(&self) -> Vec<ToolDefinition>;
+
+    /// How a tool's output is rendered into its tool_result
+    fn result_format(&self, _tool_name: &str) -> ResultFormat {
+        ResultFormat::Text
+    }
 }
 
 /// Build inference request
@@ -131,7 +172,7 @@ fn build_request<B: BrainRef>(
 
     let mut builder = RequestBuilder::new(brain.model().to_string())
         .system(system.to_string())
-        .max_tokens(brain.max_output_tokens());
+        .max_tokens(brain.max_tokens_for(brain.model()));
 
     for msg in messages {
         builder = match msg.role {
@@ -201,13 +242,8 @@ async fn execute_tool_calls<E: ExecutorRef>(
 
         let (result_text, is_error) = match result {
             Ok(output) => {
-                let is_err = output.is_error;
-                let text = if output.is_error {
-                    format!("Error: {}", output.content)
-                } else {
-                    output.content
-                };
-                (text, Some(is_err))
+                let text = output.render(executor.result_format(&call.name));
+                (text, Some(output.is_error))
             }
             Err(e) => {
                 (format!("Error: {}", e), Some(true))
@@ -228,7 +264,7 @@ async fn execute_tool_calls<E: ExecutorRef>(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::brain::{ContentBlock, Message, Role};
+    use crate::brain::{ContentBlock, Message, Role, types::StopReason};
     use async_trait::async_trait;
     use serde_json::json;
     use std::sync::RwLock;
@@ -393,6 +429,48 @@ mod tests {
         assert_eq!(messages.len(), 4);
     }
 
+    #[tokio::test]
+    async fn test_inference_loop_preserves_block_order() {
+        let interleaved = vec![
+            ContentBlock::Text {
+                text: "Checking disk first.".to_string(),
+            },
+            ContentBlock::ToolUse {
+                id: "tool-1".to_string(),
+                name: "bash".to_string(),
+                input: json!({"command": "df -h"}),
+            },
+            ContentBlock::Text {
+                text: "Then memory.".to_string(),
+            },
+            ContentBlock::ToolUse {
+                id: "tool-2".to_string(),
+                name: "bash".to_string(),
+                input: json!({"command": "free -m"}),
+            },
+        ];
+        let mut tool_response = create_tool_use_response("bash", json!({}));
+        tool_response.content = interleaved.clone();
+
+        let brain = MockBrain::new(vec![
+            create_text_response("All good.", Some(StopReason::EndTurn)),
+            tool_response,
+        ]);
+        let executor = MockExecutor::new(vec![
+            Ok(ToolOutput::success("mem")),
+            Ok(ToolOutput::success("disk")),
+        ]);
+
+        let mut messages = vec![Message::user_text("Check the box")];
+        let result = inference_loop(&brain, &executor, &mut messages, "You are helpful.", 20, 0)
+            .await
+            .unwrap();
+
+        assert_eq!(result.text, "All good.");
+        assert_eq!(messages[1].role, Role::Assistant);
+        assert_eq!(messages[1].content, interleaved);
+    }
+
     #[tokio::test]
     async fn test_inference_loop_max_tool_rounds() {
         // Create responses that all trigger tool use
@@ -0,0 +1,75 @@
+// User input sizing - token estimation and chunking of overlong input
+
+/// Rough characters-per-token ratio used for estimation
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Estimate the token count of `text` (about 4 characters per token)
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Split `text` into chunks of at most `max_tokens` estimated tokens each,
+/// preferring to break at a newline, then at whitespace
+pub fn split_into_chunks(text: &str, max_tokens: usize) -> Vec<String> {
+    let max_chars = max_tokens.max(1) * CHARS_PER_TOKEN;
+    let chars: Vec<char> = text.chars().collect();
+    let mut chunks = Vec::new();
+    let mut start = 0;
+
+    while start < chars.len() {
+        let mut end = (start + max_chars).min(chars.len());
+
+        if end < chars.len() {
+            // Only take a natural break if it keeps the chunk at least half full
+            let window = &chars[start + max_chars / 2..end];
+            let natural_break = window
+                .iter()
+                .rposition(|&c| c == '\n')
+                .or_else(|| window.iter().rposition(|c| c.is_whitespace()));
+            if let Some(offset) = natural_break {
+                end = start + max_chars / 2 + offset + 1;
+            }
+        }
+
+        chunks.push(chars[start..end].iter().collect());
+        start = end;
+    }
+
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+        // Counted in characters, not bytes
+        assert_eq!(estimate_tokens("日本語の"), 1);
+    }
+
+    #[test]
+    fn test_split_into_chunks_respects_limit() {
+        let text = "line one\nline two\nline three\n".repeat(50);
+        let chunks = split_into_chunks(&text, 20);
+
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks.concat(), text);
+        for chunk in &chunks {
+            assert!(estimate_tokens(chunk) <= 20);
+        }
+        // Breaks land on line boundaries
+        assert!(chunks[..chunks.len() - 1].iter().all(|c| c.ends_with('\n')));
+    }
+
+    #[test]
+    fn test_split_into_chunks_without_breaks() {
+        let text = "x".repeat(100);
+        let chunks = split_into_chunks(&text, 10);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks.concat(), text);
+    }
+}
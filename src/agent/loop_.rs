@@ -1,51 +1,188 @@
 // Agent loop implementation
 
+use crate::brain::text::{floor_char_boundary, truncate_bytes_safe};
 use crate::brain::{
     Brain, ContentBlock, Message, MessageResponse, RequestBuilder, Role, ToolDefinition,
 };
+use crate::comm::types::InitReportSlot;
+use crate::comm::types::{ReplayTarget, RequestKind};
 use crate::comm::{UserRequest, UserResponse};
-use crate::executor::Executor;
-use crate::memory::Memory;
+use crate::executor::progress::{self, ProgressSink};
+use crate::executor::{Executor, ExecutorError, ToolImpl};
+use crate::memory::config::MemoryConfig;
+use crate::memory::{Memory, MemoryHandle};
 
+use super::coalesce::Coalescer;
+use super::dump::MemoryDumper;
 use super::error::AgentError;
-use super::types::{AgentConfig, ToolCall};
+use super::inference::BrainRef;
+use super::input::{estimate_tokens, split_into_chunks};
+use super::memory_search::MemorySearchTool;
+use super::runtime::{ConfigTool, RuntimeSettings, SharedSettings};
+use super::scratchpad::{self, ScratchpadTool};
+use super::timing;
+use super::types::{
+    AgentConfig, ExecutionRecord, OversizedInputPolicy, PendingPlan, StopSequencePolicy,
+    TokenSpend, ToolCall,
+};
 
-use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::Mutex;
+use futures::StreamExt;
+use futures::stream::FuturesUnordered;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
 use tokio::time::timeout;
-use tracing::{error, info, warn};
+use tracing::{Instrument, debug, error, info, info_span, warn};
+
+tokio::task_local! {
+    /// Client channel for progress of the request running on this task, set
+    /// when the client asked Comm to stream it
+    static CLIENT_PROGRESS: Option<mpsc::Sender<String>>;
+}
+
+/// Prompt used to condense one part of an oversized user input
+const CHUNK_SUMMARY_PROMPT: &str = "You are condensing one part of a user message that is too long to \
+    process at once. Summarize the part you are given, preserving every instruction, question, \
+    identifier, path, number and error message needed to act on it. Reply with the summary only.";
+
+/// Prompt used to condense the middle of an oversized tool output
+const TOOL_OUTPUT_SUMMARY_PROMPT: &str = "You are condensing the middle of a tool output that is \
+    too long to keep whole; its start and end are kept verbatim elsewhere. Summarize the part you \
+    are given, preserving every error, warning, identifier, path and number that matters for \
+    diagnosing the system. Reply with the summary only.";
+
+/// Bytes kept verbatim at each end of a summarized tool output, at most a
+/// quarter of the threshold
+const TOOL_OUTPUT_EDGE_BYTES: usize = 2048;
+
+/// Extra inferences made when the model replies with neither text nor tool calls
+const EMPTY_RESPONSE_RETRIES: u32 = 2;
+
+/// Plans awaiting approval; the oldest is dropped beyond this
+const MAX_PENDING_PLANS: usize = 16;
+
+/// Reply to requests cut off or never started because the daemon is stopping
+const SHUTTING_DOWN: &str = "server shutting down";
+
+/// First wait before retrying an init inference the backend failed;
+/// doubled per attempt up to `INIT_RETRY_MAX_DELAY`
+const INIT_RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+const INIT_RETRY_MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// Appended to the system prompt in plan mode
+const PLAN_MODE_PROMPT: &str = "# Plan Mode\nTool calls you request are not run right away. They are \
+    shown to the operator as a plan and executed only once approved, after which you see their \
+    results. Request every action the next step needs in one response and explain why.";
 
 /// Agent loop state
-pub struct AgentLoop {
-    brain: Brain,
+pub struct AgentLoop<B = Brain> {
+    brain: B,
     executor: Executor,
-    memory: Arc<Mutex<Memory>>,
+    /// Never locked across an await; see `MemoryHandle`
+    memory: MemoryHandle,
     config: AgentConfig,
+    /// Settings the agent may tune at runtime via the `config` tool
+    settings: SharedSettings,
+    /// While set, user requests are answered "agent paused" without inference
+    paused: Arc<AtomicBool>,
+    /// Final text of the last `run_init`, served to operators by comm
+    init_report: InitReportSlot,
+    /// Plans proposed in plan mode, by id; never locked across an await
+    plans: std::sync::Mutex<HashMap<String, PendingPlan>>,
+    /// User input being handled, shared with identical requests
+    coalescer: Coalescer,
 }
 
-impl AgentLoop {
+impl<B: BrainRef> AgentLoop<B> {
     /// Create new agent loop
-    pub fn new(brain: Brain, executor: Executor, config: AgentConfig) -> Self {
-        let memory = Memory::new(config.identity.clone());
+    pub fn new(brain: B, executor: Executor, config: AgentConfig) -> Self {
+        let mut memory = Memory::new(config.identity.clone());
+        if let Some(path) = &config.journal_wal_path
+            && let Err(e) = memory.attach_wal(path, config.journal_wal_fsync)
+        {
+            warn!(error = %e, "Journal WAL unavailable, journal kept in memory only");
+        }
+        let settings = Arc::new(RwLock::new(RuntimeSettings {
+            temperature: brain.temperature(),
+            max_tool_rounds: config.max_tool_rounds,
+        }));
+        let memory = MemoryHandle::new(memory);
+        let agent_tools: [Arc<dyn ToolImpl>; 3] = [
+            Arc::new(ConfigTool::new(
+                settings.clone(),
+                config.allow_config_writes,
+                config.temperature_bounds,
+                config.tool_rounds_bounds,
+            )),
+            Arc::new(ScratchpadTool),
+            Arc::new(MemorySearchTool::new(memory.clone())),
+        ];
+        for tool in agent_tools {
+            // Only refused in strict mode, where the executor's tool stays
+            if let Err(e) = executor.register(tool) {
+                error!(error = %e, "Agent tool not registered");
+            }
+        }
+        executor.restrict_tools(&config.enabled_tools);
+        if executor.tool_definitions().is_empty() {
+            warn!("No tools enabled, the agent will run as a text-only conversation");
+        }
+
         Self {
             brain,
             executor,
-            memory: Arc::new(Mutex::new(memory)),
+            memory,
             config,
+            settings,
+            paused: Arc::new(AtomicBool::new(false)),
+            init_report: InitReportSlot::default(),
+            plans: std::sync::Mutex::new(HashMap::new()),
+            coalescer: Coalescer::default(),
         }
     }
 
+    /// Share the pause flag toggled by comm's Control messages
+    pub fn with_pause_flag(mut self, paused: Arc<AtomicBool>) -> Self {
+        self.paused = paused;
+        self
+    }
+
+    /// Share the slot comm serves the init report from
+    pub fn with_init_report(mut self, slot: InitReportSlot) -> Self {
+        self.init_report = slot;
+        self
+    }
+
+    /// Keep semantic memory in `config.storage_dir`, embedded with the
+    /// backend it names; on failure memory stays in-process and hashed
+    pub fn with_semantic_memory(self, config: MemoryConfig) -> Self {
+        let dir = config.storage_dir.display().to_string();
+        if let Err(e) = self.memory.write(|m| m.open_store(config)) {
+            warn!(error = %e, dir = %dir, "Semantic memory store unavailable, kept in memory only");
+        }
+        self
+    }
+
+    /// Snapshot of the runtime settings for one request
+    fn settings(&self) -> RuntimeSettings {
+        self.settings.read().unwrap().clone()
+    }
+
     /// Build an inference request from the current state
     fn build_request(
         &self,
         system: &str,
         messages: &[Message],
         tool_defs: &[ToolDefinition],
+        prefill: Option<&str>,
     ) -> Result<crate::brain::MessageRequest, AgentError> {
-        let mut builder = RequestBuilder::new(self.brain.default_model().to_string())
+        let model = self.brain.model();
+        let mut builder = RequestBuilder::new(model.to_string())
             .system(system.to_string())
-            .max_tokens(self.brain.max_output_tokens());
+            .max_tokens(self.brain.max_tokens_for(model))
+            .max_message_bytes(self.config.max_message_bytes);
 
         for msg in messages {
             builder = match msg.role {
@@ -54,9 +191,13 @@ impl AgentLoop {
             };
         }
 
-        builder = builder.tools(tool_defs.to_vec());
+        builder = if tool_defs.is_empty() {
+            builder.no_tools()
+        } else {
+            builder.tools(tool_defs.to_vec())
+        };
 
-        if let Some(temp) = self.brain.temperature() {
+        if let Some(temp) = self.settings().temperature {
             builder = builder.temperature(temp);
         }
         if let Some(tp) = self.brain.top_p() {
@@ -65,6 +206,9 @@ impl AgentLoop {
         if let Some(tk) = self.brain.top_k() {
             builder = builder.top_k(tk);
         }
+        if let Some(prefill) = prefill {
+            builder = builder.assistant_prefill(prefill);
+        }
 
         builder.build().map_err(AgentError::RequestBuild)
     }
@@ -104,17 +248,93 @@ impl AgentLoop {
             .collect()
     }
 
-    /// Execute tool calls and append results to messages
-    async fn execute_tool_calls(&self, tool_calls: Vec<ToolCall>, messages: &mut Vec<Message>) {
-        for call in tool_calls {
-            info!(tool = %call.name, id = %call.id, "Executing tool");
-            match self.executor.execute(&call.name, call.input.clone()).await {
+    /// Execute tool calls and append results to messages; calls past
+    /// `max_tool_calls_per_round` are not run and get an error result instead.
+    /// A call identical to one in `record` reuses its result unless the tool
+    /// is marked non-cacheable, so side effects never happen twice per request.
+    ///
+    /// Summarizing a large output is charged to `spent`; crossing the token
+    /// budget there aborts with `BudgetExceeded`.
+    async fn execute_tool_calls(
+        &self,
+        tool_calls: Vec<ToolCall>,
+        phase_tools: &[String],
+        messages: &mut Vec<Message>,
+        record: &mut ExecutionRecord,
+        spent: &mut TokenSpend,
+    ) -> Result<(), AgentError> {
+        let limit = self.config.max_tool_calls_per_round;
+        if tool_calls.len() > limit {
+            warn!(
+                requested = tool_calls.len(),
+                limit, "Too many tool calls in one response, skipping the rest"
+            );
+        }
+        let round_secs = self.config.tool_round_timeout_secs;
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(round_secs);
+
+        for (index, call) in tool_calls.into_iter().enumerate() {
+            if index >= limit {
+                messages.push(Message {
+                    role: Role::User,
+                    content: vec![ContentBlock::ToolResult {
+                        tool_use_id: call.id,
+                        content: format!(
+                            "Error: not executed, at most {} tool calls run per response. \
+                             Request fewer tool calls at a time and retry this one later.",
+                            limit
+                        ),
+                        is_error: Some(true),
+                    }],
+                });
+                continue;
+            }
+
+            let cacheable = self.executor.is_cacheable(&call.name);
+            if let Some((text, is_error)) = record.get(&call).filter(|_| cacheable) {
+                info!(tool = %call.name, id = %call.id, "Identical tool call already ran, reusing result");
+                messages.push(Message {
+                    role: Role::User,
+                    content: vec![ContentBlock::ToolResult {
+                        tool_use_id: call.id,
+                        content: format!(
+                            "[cached: an identical call already ran during this request and was \
+                             not executed again]\n{}",
+                            text
+                        ),
+                        is_error: Some(*is_error),
+                    }],
+                });
+                continue;
+            }
+
+            // Tools outside this phase's set are refused like disabled ones
+            let result = if phase_tools.is_empty() || phase_tools.contains(&call.name) {
+                info!(tool = %call.name, id = %call.id, "Executing tool");
+                let execution = progress::scoped(
+                    self.progress_sink(),
+                    self.executor.execute(&call.name, call.input.clone()),
+                );
+                match timing::tools(tokio::time::timeout_at(deadline, execution)).await {
+                    Ok(result) => result,
+                    Err(_) => {
+                        // Later calls in this round hit the same expired deadline
+                        warn!(tool = %call.name, id = %call.id, round_secs, "Tool round deadline reached, call cancelled");
+                        Err(ExecutorError::Timeout(call.name.clone(), round_secs))
+                    }
+                }
+            } else {
+                Err(ExecutorError::ToolDisabled(call.name.clone()))
+            };
+            match result {
                 Ok(output) => {
-                    let result_text = if output.is_error {
-                        format!("Error: {}", output.content)
-                    } else {
-                        output.content
-                    };
+                    let result_text = output.render(self.executor.result_format(&call.name));
+                    let result_text = self
+                        .summarize_tool_output(&call.name, result_text, spent)
+                        .await?;
+                    if cacheable {
+                        record.insert(&call, result_text.clone(), output.is_error);
+                    }
 
                     messages.push(Message {
                         role: Role::User,
@@ -125,12 +345,13 @@ impl AgentLoop {
                         }],
                     });
 
-                    let mut mem = self.memory.lock().await;
-                    mem.add_tool_result(&call.name, &result_text);
+                    self.memory
+                        .write(|mem| mem.add_tool_result(&call.name, &result_text));
                 }
                 Err(e) => {
                     error!(tool = %call.name, error = %e, "Tool execution failed");
-                    let err_msg = format!("Error: {}", e);
+                    let err_msg =
+                        format!("Error: {} {}", self.tool_error_message(&e), e.retry_hint());
                     messages.push(Message {
                         role: Role::User,
                         content: vec![ContentBlock::ToolResult {
@@ -140,22 +361,86 @@ impl AgentLoop {
                         }],
                     });
 
-                    let mut mem = self.memory.lock().await;
-                    mem.add_error(format!("{}: {}", call.name, e));
+                    self.memory
+                        .write(|mem| mem.add_error(format!("{}: {}", call.name, e)));
                 }
             }
         }
+        Ok(())
+    }
+
+    /// Sink writing progress of a still-running tool to memory, so a dump or
+    /// status query taken mid-call shows the work in flight, and to the
+    /// client when its request streams progress
+    ///
+    /// Each report replaces the call's previous one in the journal.
+    fn progress_sink(&self) -> ProgressSink {
+        let memory = self.memory.clone();
+        let client = CLIENT_PROGRESS.try_with(Clone::clone).ok().flatten();
+        let last = std::sync::Mutex::new(None::<String>);
+        Arc::new(move |progress| {
+            let text = progress.to_string();
+            if let Some(client) = &client {
+                // A full channel means Comm is rate limiting; drop the snippet
+                let _ = client.try_send(text.clone());
+            }
+            let mut last = last.lock().unwrap_or_else(|e| e.into_inner());
+            memory.write(|mem| mem.replace_observation(last.as_deref(), text.clone()));
+            *last = Some(text);
+        })
+    }
+
+    /// Enabled tools narrowed to one phase's `names` (empty = all of them)
+    fn phase_tool_definitions(&self, names: &[String]) -> Vec<ToolDefinition> {
+        self.executor
+            .tool_definitions()
+            .into_iter()
+            .filter(|d| names.is_empty() || names.contains(&d.name))
+            .collect()
+    }
+
+    /// Render an executor error for the model; unknown tools list the valid
+    /// names so the model can correct itself instead of retrying the same call
+    fn tool_error_message(&self, error: &ExecutorError) -> String {
+        match error {
+            ExecutorError::UnknownTool(_) => {
+                let mut names: Vec<String> = self
+                    .executor
+                    .tool_definitions()
+                    .into_iter()
+                    .map(|d| d.name)
+                    .collect();
+                names.sort();
+                format!("{}. Available tools: {}", error, names.join(", "))
+            }
+            ExecutorError::Timeout(..) => format!(
+                "{}. The tool calls of one response may take {} seconds in total; \
+                 request fewer or faster tool calls at a time.",
+                error, self.config.tool_round_timeout_secs
+            ),
+            _ => error.to_string(),
+        }
     }
 
-    /// Run initialization phase
+    /// Run initialization phase, with its own scratchpad
     pub async fn run_init(&self) -> Result<(), AgentError> {
+        scratchpad::scoped(self.explore()).await
+    }
+
+    async fn explore(&self) -> Result<(), AgentError> {
         info!("Starting agent initialization...");
 
-        let tool_defs = self.executor.tool_definitions();
-        let system = self.config.system_prompt.clone();
+        let tool_defs = self.phase_tool_definitions(&self.config.init_tools);
+        let system = self.config.full_system_prompt();
 
+        // Init has its own budget so startup exploration stays cheap
+        let max_tool_rounds = self.config.max_init_tool_rounds;
         let mut tool_rounds = 0;
         let mut messages: Vec<Message> = Vec::new();
+        let mut record = ExecutionRecord::default();
+        let mut init_spent = TokenSpend::default();
+        // The last thing the model said about the machine
+        let mut report = String::new();
 
         messages.push(Message {
             role: Role::User,
@@ -166,20 +451,24 @@ impl AgentLoop {
 
         loop {
             tool_rounds += 1;
-            if tool_rounds > self.config.max_tool_rounds {
+            if tool_rounds > max_tool_rounds {
                 warn!(rounds = tool_rounds, "Max tool rounds reached during init");
                 break;
             }
 
             info!(round = tool_rounds, "Init inference round");
 
-            let request = self.build_request(&system, &messages, &tool_defs)?;
+            let request = self.build_request(&system, &messages, &tool_defs, None)?;
 
-            let result = timeout(
-                Duration::from_secs(self.config.init_timeout_secs),
-                self.brain.infer(request),
-            )
-            .await;
+            let result = if tool_rounds == 1 {
+                self.first_init_inference(request).await
+            } else {
+                timeout(
+                    Duration::from_secs(self.config.init_timeout_secs),
+                    self.brain.infer(request),
+                )
+                .await
+            };
 
             match result {
                 Ok(Ok(response)) => {
@@ -187,24 +476,39 @@ impl AgentLoop {
 
                     let text_content = Self::extract_text(&response);
 
-                    {
-                        let mut mem = self.memory.lock().await;
-                        mem.add_observation(&text_content);
+                    self.memory.write(|mem| mem.add_observation(&text_content));
+                    if !text_content.trim().is_empty() {
+                        report = text_content;
                     }
 
-                    match response.stop_reason {
-                        Some(crate::brain::types::StopReason::ToolUse) => {
+                    match response.effective_stop_reason() {
+                        crate::brain::types::StopReason::ToolUse => {
                             info!("Tool use detected in init");
                             let tool_calls = Self::extract_tool_calls(&response);
 
+                            // Keep the blocks in the order the model produced them
                             messages.push(Message {
                                 role: Role::Assistant,
                                 content: response.content.clone(),
                             });
 
-                            self.execute_tool_calls(tool_calls, &mut messages).await;
+                            // Init is not a request; its summaries count
+                            // against a budget of their own
+                            if let Err(e) = self
+                                .execute_tool_calls(
+                                    tool_calls,
+                                    &self.config.init_tools,
+                                    &mut messages,
+                                    &mut record,
+                                    &mut init_spent,
+                                )
+                                .await
+                            {
+                                warn!(error = %e, "Init tool calls aborted");
+                                break;
+                            }
                         }
-                        Some(crate::brain::types::StopReason::MaxTokens) => {
+                        crate::brain::types::StopReason::MaxTokens => {
                             warn!("Init inference stopped due to max tokens");
                             break;
                         }
@@ -216,7 +520,7 @@ impl AgentLoop {
                 }
                 Ok(Err(e)) => {
                     error!(error = %e, "Init inference failed");
-                    return Err(AgentError::Inference(e.to_string()));
+                    return Err(AgentError::Inference(e));
                 }
                 Err(_) => {
                     error!("Init inference timed out");
@@ -225,117 +529,807 @@ impl AgentLoop {
             }
         }
 
+        self.publish_init_report(report);
         info!("Agent initialization completed");
         Ok(())
     }
 
+    /// The first init inference, retried with backoff for up to
+    /// `init_backend_wait_secs` while it fails, so the daemon can start
+    /// alongside a backend that is still booting
+    ///
+    /// Only failed calls are retried; a call that times out is returned as is.
+    async fn first_init_inference(
+        &self,
+        request: crate::brain::MessageRequest,
+    ) -> Result<Result<MessageResponse, String>, tokio::time::error::Elapsed> {
+        let window = Duration::from_secs(self.config.init_backend_wait_secs);
+        let started = tokio::time::Instant::now();
+        let mut delay = INIT_RETRY_BASE_DELAY;
+        loop {
+            let result = timeout(
+                Duration::from_secs(self.config.init_timeout_secs),
+                self.brain.infer(request.clone()),
+            )
+            .await;
+            let Ok(Err(e)) = &result else {
+                return result;
+            };
+            // A missing model will not appear by waiting
+            let remaining = window.saturating_sub(started.elapsed());
+            if remaining.is_zero() || crate::brain::is_model_not_found(e) {
+                return result;
+            }
+
+            let wait = delay.min(remaining);
+            warn!(
+                error = %e,
+                retry_in_ms = wait.as_millis() as u64,
+                waited_secs = started.elapsed().as_secs(),
+                "Inference backend not ready, retrying init"
+            );
+            tokio::time::sleep(wait).await;
+            delay = (delay * 2).min(INIT_RETRY_MAX_DELAY);
+        }
+    }
+
+    /// Make the init report available to comm and, if configured, write it
+    /// to `init_report_path`; a failed write is logged, not fatal
+    fn publish_init_report(&self, report: String) {
+        if let Some(path) = &self.config.init_report_path {
+            match std::fs::write(path, &report) {
+                Ok(()) => info!(path = %path.display(), "Init report written"),
+                Err(e) => warn!(path = %path.display(), error = %e, "Failed to write init report"),
+            }
+        }
+        *self.init_report.write().unwrap() = Some(report);
+    }
+
     /// Run main loop - handles user requests
+    ///
+    /// Everything logged while handling, in brain and executor too, happens
+    /// inside a `request` span carrying the client's request_id (or a fresh
+    /// one), so one request's logs can be picked out by that field.
     pub async fn handle_user_request(&self, req: UserRequest) {
+        let request_id = req
+            .request_id
+            .clone()
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let span = info_span!("request", request_id = %request_id, addr = %req.source_addr);
+        self.respond(req).instrument(span).await
+    }
+
+    /// Handle a user request until `shutdown` fires, then give it at most
+    /// `drain_timeout_secs` more before answering "server shutting down"
+    ///
+    /// Returns whether `shutdown` fired; it is not polled again afterwards.
+    pub async fn handle_draining(
+        &self,
+        mut req: UserRequest,
+        shutdown: impl Future<Output = ()>,
+    ) -> bool {
+        // Keep the client's reply channel, so it can still be answered when
+        // handling is abandoned
+        let (inner_tx, inner_rx) = tokio::sync::oneshot::channel();
+        let reply = std::mem::replace(&mut req.reply, inner_tx);
+        let mut handling = Box::pin(self.handle_user_request(req));
+
+        let shutting_down = tokio::select! {
+            () = &mut handling => false,
+            () = shutdown => {
+                let drain = Duration::from_secs(self.config.drain_timeout_secs);
+                info!(drain_secs = drain.as_secs(), "Shutdown requested, draining request in flight");
+                if timeout(drain, &mut handling).await.is_err() {
+                    warn!("Request still running after the drain timeout, abandoning it");
+                }
+                true
+            }
+        };
+        // An abandoned request takes its reply sender with it
+        drop(handling);
+
+        let response = inner_rx
+            .await
+            .unwrap_or_else(|_| UserResponse::error(SHUTTING_DOWN.to_string()));
+        if reply.send(response).is_err() {
+            warn!("Failed to send response to client");
+        }
+        shutting_down
+    }
+
+    /// Handle requests from `requests`, up to `max_concurrent_requests` at a
+    /// time, until `shutdown` fires; requests then in flight get
+    /// `drain_timeout_secs` to finish, and queued ones are left in `requests`
+    pub async fn serve(
+        &self,
+        requests: &mut mpsc::Receiver<UserRequest>,
+        shutdown: impl Future<Output = ()>,
+    ) {
+        let (stop_tx, stop_rx) = tokio::sync::watch::channel(false);
+        let stopped = || {
+            let mut stop_rx = stop_rx.clone();
+            async move {
+                let _ = stop_rx.wait_for(|stop| *stop).await;
+            }
+        };
+        let mut in_flight = FuturesUnordered::new();
+        tokio::pin!(shutdown);
+
+        loop {
+            tokio::select! {
+                Some(req) = requests.recv(), if in_flight.len() < self.config.max_concurrent_requests => {
+                    in_flight.push(self.handle_draining(req, stopped()));
+                }
+                Some(_) = in_flight.next(), if !in_flight.is_empty() => {}
+                () = shutdown.as_mut() => break,
+            }
+        }
+
+        let _ = stop_tx.send(true);
+        while in_flight.next().await.is_some() {}
+    }
+
+    /// Answer a request that will not be handled because the daemon is
+    /// stopping
+    pub fn refuse_shutting_down(&self, req: UserRequest) {
+        if req
+            .reply
+            .send(UserResponse::error(SHUTTING_DOWN.to_string()))
+            .is_err()
+        {
+            warn!("Failed to send response to client");
+        }
+    }
+
+    /// Handle one user request and send the reply
+    async fn respond(&self, req: UserRequest) {
         let input = req.content.clone();
         let reply = req.reply;
 
+        if self.paused.load(Ordering::SeqCst) {
+            info!(addr = %req.source_addr, "Agent paused, refusing user request");
+            if reply
+                .send(UserResponse::error("agent paused".to_string()))
+                .is_err()
+            {
+                warn!("Failed to send response to client");
+            }
+            return;
+        }
+
         info!(addr = %req.source_addr, input = %input, "Handling user request");
 
-        let result = timeout(
+        // Replays are not journaled, so interaction indexes stay stable
+        let journal = !matches!(req.kind, RequestKind::Replay(_));
+        let (result, timing) = timing::measure(timeout(
             Duration::from_secs(self.config.handle_timeout_secs),
-            self.handle(input),
-        )
+            CLIENT_PROGRESS.scope(req.progress, async {
+                match req.kind {
+                    RequestKind::Input if !self.config.chat_only => {
+                        self.coalesced(&input, true, self.handle(input.clone()))
+                            .await
+                    }
+                    RequestKind::Input | RequestKind::Chat => {
+                        self.coalesced(&input, false, self.chat(input.clone()))
+                            .await
+                    }
+                    RequestKind::Approve(plan_id) => self.approve(&plan_id).await,
+                    RequestKind::Replay(target) => self.replay(&target).await,
+                }
+            }),
+        ))
         .await;
 
         let response = match result {
             Ok(Ok(response)) => {
-                let mut mem = self.memory.lock().await;
-                mem.add_interaction(&req.content, &response);
+                if journal {
+                    self.memory
+                        .write(|mem| mem.add_interaction(&req.content, &response));
+                    self.remember(format!("Q: {}\nA: {}", req.content, response));
+                }
                 UserResponse::new(response)
             }
             Ok(Err(e)) => {
                 warn!(error = %e, "Handle failed");
-                let mut mem = self.memory.lock().await;
-                mem.add_error(format!("{}", e));
+                self.memory.write(|mem| mem.add_error(format!("{}", e)));
                 UserResponse::error(e.to_string())
             }
             Err(_) => {
                 error!("Handle timed out");
-                let mut mem = self.memory.lock().await;
-                mem.add_error("Handle timeout".to_string());
+                self.memory
+                    .write(|mem| mem.add_error("Handle timeout".to_string()));
                 UserResponse::error("Request timeout".to_string())
             }
         };
+        debug!(
+            inference_ms = timing.inference.as_millis() as u64,
+            tool_ms = timing.tools.as_millis() as u64,
+            total_ms = timing.total.as_millis() as u64,
+            "Request timing"
+        );
+        let response = response.with_timing(timing.into());
 
         if reply.send(response).is_err() {
             warn!("Failed to send response to client");
         }
     }
 
-    /// Core handle function - handles input with tool loop
-    async fn handle(&self, user_input: String) -> Result<String, AgentError> {
-        let (context, tool_defs) = {
-            let mem = self.memory.lock().await;
-            (mem.context(), self.executor.tool_definitions())
+    /// Run `work` answering `input`, sharing it with identical requests when
+    /// `coalesce_identical_requests` is set
+    async fn coalesced(
+        &self,
+        input: &str,
+        offer_tools: bool,
+        work: impl Future<Output = Result<String, AgentError>>,
+    ) -> Result<String, AgentError> {
+        if !self.config.coalesce_identical_requests {
+            return work.await;
+        }
+        let key = Coalescer::key(input, offer_tools);
+        self.coalescer.run(key, work).await
+    }
+
+    /// Check the input against `max_input_tokens`, rejecting or condensing it
+    /// according to the configured policy; condensing is charged to `spent`
+    async fn fit_input(
+        &self,
+        user_input: String,
+        spent: &mut TokenSpend,
+    ) -> Result<String, AgentError> {
+        let limit = self.config.max_input_tokens;
+        let estimated = estimate_tokens(&user_input);
+        if estimated <= limit {
+            return Ok(user_input);
+        }
+
+        if self.config.oversized_input == OversizedInputPolicy::Reject {
+            warn!(estimated, limit, "Rejecting oversized input");
+            return Err(AgentError::InputTooLarge { estimated, limit });
+        }
+
+        let chunks = split_into_chunks(&user_input, limit);
+        info!(
+            estimated,
+            limit,
+            chunks = chunks.len(),
+            "Summarizing oversized input in chunks"
+        );
+
+        let mut condensed = String::from(
+            "The original input was too long to process at once. \
+             It was split into parts and each part was summarized:",
+        );
+        for (i, chunk) in chunks.iter().enumerate() {
+            let request = self.build_request(
+                CHUNK_SUMMARY_PROMPT,
+                &[Message::user_text(chunk)],
+                &[],
+                None,
+            )?;
+            let response = self.infer_charged(request, spent).await?;
+            condensed.push_str(&format!(
+                "\n\n[Part {}/{}]\n{}",
+                i + 1,
+                chunks.len(),
+                Self::extract_text(&response)
+            ));
+        }
+
+        let estimated = estimate_tokens(&condensed);
+        if estimated > limit {
+            warn!(estimated, limit, "Condensed input still too large");
+            return Err(AgentError::InputTooLarge { estimated, limit });
+        }
+
+        Ok(condensed)
+    }
+
+    /// Replace the middle of a tool output over `tool_output_summary_bytes`
+    /// with a model-written summary, keeping its start and end verbatim so an
+    /// error at the tail survives. Returns `text` unchanged when summaries
+    /// are off, it is small enough, or the summary inference fails.
+    ///
+    /// The summary inference is charged to `spent`, failing with
+    /// `BudgetExceeded` like any other round of the request.
+    async fn summarize_tool_output(
+        &self,
+        tool: &str,
+        text: String,
+        spent: &mut TokenSpend,
+    ) -> Result<String, AgentError> {
+        let threshold = self.config.tool_output_summary_bytes;
+        if !self.config.summarize_tool_outputs || text.len() <= threshold {
+            return Ok(text);
+        }
+
+        let edge = TOOL_OUTPUT_EDGE_BYTES.min(threshold / 4);
+        let head = truncate_bytes_safe(&text, edge);
+        let tail_start = floor_char_boundary(text.as_bytes(), text.len() - edge).max(head.len());
+        let (middle, tail) = text[head.len()..].split_at(tail_start - head.len());
+
+        info!(
+            tool,
+            bytes = text.len(),
+            threshold,
+            "Summarizing oversized tool output"
+        );
+        let summary = match self.build_request(
+            TOOL_OUTPUT_SUMMARY_PROMPT,
+            &[Message::user_text(middle)],
+            &[],
+            None,
+        ) {
+            Ok(request) => self.infer_charged(request, spent).await,
+            Err(e) => Err(e),
         };
+        let summary = match summary {
+            Ok(response) => Self::extract_text(&response),
+            Err(e @ AgentError::BudgetExceeded { .. }) => return Err(e),
+            Err(e) => {
+                warn!(tool, error = %e, "Tool output summary failed, keeping the full output");
+                return Ok(text);
+            }
+        };
+
+        Ok(format!(
+            "[{} output was {} bytes: its first {} and last {} bytes follow verbatim, \
+             the {} bytes between them are summarized]\n\
+             [head]\n{}\n[summary of the middle]\n{}\n[tail]\n{}",
+            tool,
+            text.len(),
+            head.len(),
+            tail.len(),
+            middle.len(),
+            head,
+            summary.trim(),
+            tail
+        ))
+    }
+
+    /// Core handle function - handles input with tool loop, starting from an
+    /// empty scratchpad
+    async fn handle(&self, user_input: String) -> Result<String, AgentError> {
+        scratchpad::scoped(self.handle_input(user_input, true)).await
+    }
+
+    /// Answer input as a pure chat turn, without offering the model tools
+    async fn chat(&self, user_input: String) -> Result<String, AgentError> {
+        self.handle_input(user_input, false).await
+    }
+
+    async fn handle_input(
+        &self,
+        user_input: String,
+        offer_tools: bool,
+    ) -> Result<String, AgentError> {
+        let mut spent = TokenSpend::default();
+        let user_input = self.fit_input(user_input, &mut spent).await?;
+        let mut context = self.memory.read(|mem| mem.context());
+        let recalled = self.recall(&user_input).await;
+        if !recalled.is_empty() {
+            context = format!("{}\n\n{}", context, recalled);
+        }
 
-        let system = format!(
+        let mut system = format!(
             "{}\n\n# Current Context\n{}",
-            self.config.system_prompt, context
+            self.config.full_system_prompt(),
+            context
         );
+        if self.config.plan_mode && offer_tools {
+            system = format!("{}\n\n{}", system, PLAN_MODE_PROMPT);
+        }
 
-        let mut tool_rounds = 0;
-        let mut messages: Vec<Message> = Vec::new();
-
-        messages.push(Message {
+        let messages = vec![Message {
             role: Role::User,
-            content: vec![ContentBlock::Text {
-                text: user_input.clone(),
-            }],
+            content: vec![ContentBlock::Text { text: user_input }],
+        }];
+
+        self.converse(
+            system,
+            messages,
+            ExecutionRecord::default(),
+            spent,
+            offer_tools,
+        )
+        .await
+    }
+
+    /// Store `content` in semantic memory in the background, so embedding
+    /// does not delay the reply
+    fn remember(&self, content: String) {
+        let memory = self.memory.clone();
+        tokio::spawn(async move {
+            if let Err(e) = memory.store(content).await {
+                warn!(error = %e, "Interaction not stored in semantic memory");
+            }
         });
+    }
+
+    /// Semantic memories relevant to `input` as a context section, empty
+    /// when there are none or recall fails
+    async fn recall(&self, input: &str) -> String {
+        let top_k = self.memory.read(|mem| mem.config().top_k);
+        match self.memory.recall(input, top_k).await {
+            Ok(entries) => self.memory.read(|mem| mem.context_from_recall(&entries)),
+            Err(e) => {
+                warn!(error = %e, "Semantic memory recall failed");
+                String::new()
+            }
+        }
+    }
+
+    /// Run the approved plan's tool calls, then let the model carry on from
+    /// their results
+    async fn approve(&self, plan_id: &str) -> Result<String, AgentError> {
+        let plan = self
+            .plans
+            .lock()
+            .unwrap()
+            .remove(plan_id)
+            .ok_or_else(|| AgentError::UnknownPlan(plan_id.to_string()))?;
+        info!(
+            plan_id,
+            calls = plan.tool_calls.len(),
+            "Plan approved, executing"
+        );
+
+        // The proposing request's scratchpad is gone; the plan starts afresh
+        scratchpad::scoped(async {
+            let mut messages = plan.messages;
+            let mut record = ExecutionRecord::default();
+            let mut spent = TokenSpend::default();
+            self.execute_tool_calls(
+                plan.tool_calls,
+                &self.config.handle_tools,
+                &mut messages,
+                &mut record,
+                &mut spent,
+            )
+            .await?;
+            self.converse(plan.system, messages, record, spent, true)
+                .await
+        })
+        .await
+    }
+
+    /// Handle a journaled user query again with the current config and
+    /// model, returning the old and new responses for comparison
+    async fn replay(&self, target: &ReplayTarget) -> Result<String, AgentError> {
+        let found = self.memory.read(|mem| match target {
+            ReplayTarget::Index(index) => mem
+                .interaction(*index)
+                .map(|(query, response)| (*index, query.to_string(), response.to_string())),
+            ReplayTarget::Query(needle) => mem
+                .find_interaction(needle)
+                .map(|(index, query, response)| (index, query.to_string(), response.to_string())),
+        });
+        let (index, query, original) =
+            found.ok_or_else(|| AgentError::UnknownInteraction(target.to_string()))?;
+        info!(index, query = %query, "Replaying interaction");
+
+        let fresh = if self.config.chat_only {
+            self.chat(query.clone()).await?
+        } else {
+            self.handle(query.clone()).await?
+        };
+        Ok(format!(
+            "Replayed interaction #{}: {}\n\n--- original response ---\n{}\n\n--- new response ---\n{}",
+            index, query, original, fresh
+        ))
+    }
+
+    /// Store tool calls as a pending plan and describe them for approval
+    fn propose_plan(
+        &self,
+        system: String,
+        messages: Vec<Message>,
+        tool_calls: Vec<ToolCall>,
+        explanation: &str,
+    ) -> String {
+        let id = uuid::Uuid::new_v4().to_string();
+
+        let mut reply = format!("Plan {} (not executed, awaiting approval):\n", id);
+        if !explanation.trim().is_empty() {
+            reply.push_str(explanation.trim());
+            reply.push('\n');
+        }
+        for (i, call) in tool_calls.iter().enumerate() {
+            reply.push_str(&format!("{}. {} {}\n", i + 1, call.name, call.input));
+        }
+        reply.push_str(&format!("Approve with: shelly-cli approve {}", id));
+
+        info!(plan_id = %id, calls = tool_calls.len(), "Plan proposed");
+        let mut plans = self.plans.lock().unwrap();
+        if plans.len() >= MAX_PENDING_PLANS
+            && let Some(oldest) = plans
+                .iter()
+                .min_by_key(|(_, plan)| plan.created)
+                .map(|(id, _)| id.clone())
+        {
+            warn!(plan_id = %oldest, "Too many pending plans, dropping the oldest");
+            plans.remove(&oldest);
+        }
+        plans.insert(
+            id,
+            PendingPlan {
+                system,
+                messages,
+                tool_calls,
+                created: std::time::Instant::now(),
+            },
+        );
+
+        reply
+    }
+
+    /// Inference/tool loop over `messages` until the model stops asking for
+    /// tools; in plan mode the first tool request is returned as a plan
+    ///
+    /// Without `offer_tools` the request carries no tools and a single reply
+    /// ends the loop.
+    async fn converse(
+        &self,
+        system: String,
+        mut messages: Vec<Message>,
+        mut record: ExecutionRecord,
+        mut spent: TokenSpend,
+        offer_tools: bool,
+    ) -> Result<String, AgentError> {
+        // Trimmed the same way the builder trims it, so the echoed text matches
+        let prefill = self
+            .config
+            .response_prefill
+            .as_deref()
+            .map(str::trim_end)
+            .filter(|p| !p.is_empty());
+        let tool_defs = if offer_tools {
+            self.phase_tool_definitions(&self.config.handle_tools)
+        } else {
+            Vec::new()
+        };
+        // Offered (and allowed to run) during the observe-only rounds
+        let readonly_defs: Vec<ToolDefinition> = tool_defs
+            .iter()
+            .filter(|d| self.executor.is_read_only(&d.name))
+            .cloned()
+            .collect();
+        let readonly_names: Vec<String> = readonly_defs.iter().map(|d| d.name.clone()).collect();
+        let readonly_rounds = if readonly_defs.is_empty() {
+            if self.config.readonly_first_rounds > 0 && !tool_defs.is_empty() {
+                warn!("No read-only tools enabled, skipping observe-only rounds");
+            }
+            0
+        } else {
+            self.config.readonly_first_rounds
+        };
+
+        let max_tool_rounds = self.settings().max_tool_rounds;
+        let mut tool_rounds = 0;
+        // Latest non-empty text, returned with a notice if the rounds run out
+        let mut last_text = String::new();
+        let wall_limit = self.config.max_handle_wall_secs;
+        let started = Instant::now();
+        // Text of a reply cut at a stop sequence, for the next round to resume
+        let mut continuation: Option<String> = None;
 
         loop {
+            if wall_limit > 0 && started.elapsed() >= Duration::from_secs(wall_limit) {
+                warn!(
+                    rounds = tool_rounds,
+                    limit_secs = wall_limit,
+                    "Wall clock limit reached, stopping"
+                );
+                return Err(AgentError::WallClockExceeded(with_notice(
+                    &last_text,
+                    &format!("[stopped: wall clock limit of {}s reached]", wall_limit),
+                )));
+            }
+
             tool_rounds += 1;
-            if tool_rounds > self.config.max_tool_rounds {
+            if tool_rounds > max_tool_rounds {
                 warn!(rounds = tool_rounds, "Max tool rounds reached, stopping");
                 break;
             }
 
-            info!(round = tool_rounds, "Inference round");
+            let observe_only = tool_rounds <= readonly_rounds;
+            info!(round = tool_rounds, observe_only, "Inference round");
 
-            let request = self.build_request(&system, &messages, &tool_defs)?;
+            let (round_defs, round_tools) = if observe_only {
+                (&readonly_defs, &readonly_names)
+            } else {
+                (&tool_defs, &self.config.handle_tools)
+            };
+            let resumed = continuation.take();
+            let round_prefill = resumed.as_deref().or(prefill);
+            let request = self.build_request(&system, &messages, round_defs, round_prefill)?;
 
-            let response = self
-                .brain
-                .infer(request)
-                .await
-                .map_err(|e| AgentError::Inference(e.to_string()))?;
+            let response = self.infer_non_empty(request, &mut spent).await?;
 
-            let text_content = Self::extract_text(&response);
+            // The model continues from the prefill, so it is part of the reply
+            let text_content = format!(
+                "{}{}",
+                round_prefill.unwrap_or(""),
+                Self::extract_text(&response)
+            );
+            if !text_content.trim().is_empty() {
+                last_text.clone_from(&text_content);
+            }
 
-            match response.stop_reason {
-                Some(crate::brain::types::StopReason::ToolUse) => {
+            let stop_reason = response.effective_stop_reason();
+            match stop_reason {
+                crate::brain::types::StopReason::ToolUse => {
+                    if !offer_tools {
+                        warn!("Model asked for tools in a chat-only request, ignoring");
+                        return self.usable_reply(text_content, stop_reason);
+                    }
                     info!("Tool use detected");
                     let tool_calls = Self::extract_tool_calls(&response);
 
+                    // Keep the blocks in the order the model produced them;
+                    // the prefill came first, so it leads
+                    let mut content = response.content.clone();
+                    if let Some(prefill) = round_prefill {
+                        content.insert(
+                            0,
+                            ContentBlock::Text {
+                                text: prefill.to_string(),
+                            },
+                        );
+                    }
                     messages.push(Message {
                         role: Role::Assistant,
-                        content: response.content.clone(),
+                        content,
                     });
 
-                    self.execute_tool_calls(tool_calls, &mut messages).await;
+                    if self.config.plan_mode {
+                        return Ok(self.propose_plan(system, messages, tool_calls, &text_content));
+                    }
+                    self.execute_tool_calls(
+                        tool_calls,
+                        round_tools,
+                        &mut messages,
+                        &mut record,
+                        &mut spent,
+                    )
+                    .await?;
                 }
-                Some(crate::brain::types::StopReason::MaxTokens) => {
+                crate::brain::types::StopReason::MaxTokens => {
                     warn!("Inference stopped due to max tokens limit");
-                    return Ok(text_content);
+                    return self.usable_reply(text_content, stop_reason);
                 }
-                Some(crate::brain::types::StopReason::EndTurn) | None => {
-                    info!(stop_reason = ?response.stop_reason, "Inference completed");
-                    return Ok(text_content);
+                crate::brain::types::StopReason::EndTurn => {
+                    info!(stop_reason = stop_reason.as_str(), "Inference completed");
+                    return self.usable_reply(text_content, stop_reason);
                 }
-                Some(crate::brain::types::StopReason::StopSequence) => {
-                    info!(stop_reason = ?response.stop_reason, "Inference stopped by sequence");
-                    return Ok(text_content);
+                crate::brain::types::StopReason::StopSequence => {
+                    let sequence = response.stop_sequence.as_deref().unwrap_or("");
+                    info!(
+                        stop_reason = stop_reason.as_str(),
+                        sequence,
+                        policy = ?self.config.stop_sequence,
+                        "Inference stopped by sequence"
+                    );
+                    match self.config.stop_sequence {
+                        StopSequencePolicy::ReturnAsIs => {
+                            return self.usable_reply(text_content, stop_reason);
+                        }
+                        StopSequencePolicy::StripSequence => {
+                            let text = strip_stop_sequence(text_content, sequence);
+                            return self.usable_reply(text, stop_reason);
+                        }
+                        StopSequencePolicy::Continue => {
+                            // Resume with the sequence written out, so the
+                            // model carries on past it
+                            let mut resumed = text_content;
+                            if !resumed.ends_with(sequence) {
+                                resumed.push_str(sequence);
+                            }
+                            continuation = Some(resumed.trim_end().to_string());
+                        }
+                    }
                 }
             }
         }
 
-        Ok("Maximum tool call rounds reached. Operation aborted.".to_string())
+        Err(AgentError::ToolRoundsExhausted(with_notice(
+            &last_text,
+            &self.config.tool_rounds_notice,
+        )))
+    }
+
+    /// `text` as the reply, or `NoUsableOutput` when it is blank (e.g. a
+    /// `MaxTokens` stop partway through a tool call), so the client gets an
+    /// explanation instead of an empty response
+    fn usable_reply(
+        &self,
+        text: String,
+        stop_reason: crate::brain::types::StopReason,
+    ) -> Result<String, AgentError> {
+        if !text.trim().is_empty() {
+            return Ok(text);
+        }
+        warn!(
+            stop_reason = stop_reason.as_str(),
+            "Model produced no text to reply with"
+        );
+        Err(AgentError::NoUsableOutput(
+            self.config
+                .no_output_message
+                .replace("{stop_reason}", stop_reason.as_str()),
+        ))
+    }
+
+    /// Infer, re-asking when the model returns an empty or whitespace-only
+    /// reply without tool calls (e.g. a filtered or dropped response)
+    ///
+    /// Every response's usage is added to `spent`; crossing the per-request
+    /// token budget aborts with `BudgetExceeded`.
+    async fn infer_non_empty(
+        &self,
+        request: crate::brain::MessageRequest,
+        spent: &mut TokenSpend,
+    ) -> Result<MessageResponse, AgentError> {
+        let mut attempt = 0;
+        loop {
+            let response = self.infer_charged(request.clone(), spent).await?;
+
+            let empty = Self::extract_text(&response).trim().is_empty()
+                && Self::extract_tool_calls(&response).is_empty();
+            if !empty {
+                return Ok(response);
+            }
+            if attempt >= EMPTY_RESPONSE_RETRIES {
+                return Err(AgentError::EmptyResponse(attempt + 1));
+            }
+
+            attempt += 1;
+            warn!(
+                attempt,
+                max_retries = EMPTY_RESPONSE_RETRIES,
+                stop_reason = ?response.stop_reason,
+                "model returned an empty response, retrying"
+            );
+        }
+    }
+
+    /// Infer on behalf of a request, adding the usage to `spent`
+    ///
+    /// Fails with `BudgetExceeded` without inferring when the request is
+    /// already over budget, and when this response takes it over.
+    async fn infer_charged(
+        &self,
+        request: crate::brain::MessageRequest,
+        spent: &mut TokenSpend,
+    ) -> Result<MessageResponse, AgentError> {
+        self.check_budget(spent)?;
+        let response = timing::inference(self.brain.infer(request))
+            .await
+            .map_err(AgentError::Inference)?;
+        spent.add(response.usage.as_ref());
+        self.check_budget(spent)?;
+        Ok(response)
+    }
+
+    /// Fail once the tokens spent on this request cross a configured budget
+    fn check_budget(&self, spent: &TokenSpend) -> Result<(), AgentError> {
+        for (kind, spent, limit) in [
+            ("input", spent.input, self.config.max_total_input_tokens),
+            ("output", spent.output, self.config.max_total_output_tokens),
+        ] {
+            if limit > 0 && spent > limit {
+                warn!(
+                    kind,
+                    spent, limit, "Token budget exceeded, aborting request"
+                );
+                return Err(AgentError::BudgetExceeded { kind, spent, limit });
+            }
+        }
+        Ok(())
+    }
+
+    /// Dumper writing this agent's memory to `dump_dir`
+    pub fn memory_dumper(&self) -> MemoryDumper {
+        MemoryDumper::new(self.memory.clone(), self.config.dump_dir.clone())
     }
 
     /// Run shutdown handling
@@ -354,8 +1348,8 @@ impl AgentLoop {
         match result {
             Ok(Ok(response)) => {
                 info!(response = %response, "Shutdown handling completed");
-                let mut mem = self.memory.lock().await;
-                mem.add_observation(format!("Shutdown: {}", response));
+                self.memory
+                    .write(|mem| mem.add_observation(format!("Shutdown: {}", response)));
             }
             Ok(Err(e)) => {
                 warn!(error = %e, "Shutdown handling failed");
@@ -366,3 +1360,2286 @@ impl AgentLoop {
         }
     }
 }
+
+/// `notice` after the model's last text, or alone when there is none, for a
+/// request stopped before the model finished
+fn with_notice(last_text: &str, notice: &str) -> String {
+    if last_text.trim().is_empty() {
+        notice.to_string()
+    } else {
+        format!("{}\n\n{}", last_text.trim_end(), notice)
+    }
+}
+
+/// `text` without `sequence` at its end, ignoring whitespace after it; the
+/// text unchanged when it does not end with it
+fn strip_stop_sequence(text: String, sequence: &str) -> String {
+    if sequence.is_empty() {
+        return text;
+    }
+    match text.trim_end().strip_suffix(sequence) {
+        Some(stripped) => stripped.to_string(),
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::types::Bounds;
+    use crate::brain::types::StopReason;
+    use crate::brain::{BrainConfig, MessageRequest};
+    use std::collections::VecDeque;
+
+    /// Mock brain replying with canned responses and recording every request
+    struct MockBrain {
+        replies: std::sync::Mutex<VecDeque<MessageResponse>>,
+        requests: std::sync::Mutex<Vec<MessageRequest>>,
+        /// When set, each inference waits for a permit before replying
+        gate: Option<Arc<tokio::sync::Semaphore>>,
+        /// Calls that fail as if the backend were down, before replies start
+        unavailable_calls: std::sync::atomic::AtomicUsize,
+    }
+
+    impl MockBrain {
+        /// Reply with one end-turn text response per entry
+        fn new(replies: &[&str]) -> Self {
+            Self::with_responses(
+                replies
+                    .iter()
+                    .map(|text| {
+                        response(
+                            vec![ContentBlock::Text {
+                                text: text.to_string(),
+                            }],
+                            StopReason::EndTurn,
+                        )
+                    })
+                    .collect(),
+            )
+        }
+
+        fn with_responses(responses: Vec<MessageResponse>) -> Self {
+            Self {
+                replies: std::sync::Mutex::new(responses.into()),
+                requests: std::sync::Mutex::new(Vec::new()),
+                gate: None,
+                unavailable_calls: std::sync::atomic::AtomicUsize::new(0),
+            }
+        }
+    }
+
+    fn response(content: Vec<ContentBlock>, stop_reason: StopReason) -> MessageResponse {
+        MessageResponse {
+            id: "msg_test".to_string(),
+            content,
+            model: "test-model".to_string(),
+            role: Role::Assistant,
+            stop_reason: Some(stop_reason),
+            stop_sequence: None,
+            usage: None,
+            extra: std::collections::HashMap::new(),
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl BrainRef for MockBrain {
+        async fn infer(&self, request: MessageRequest) -> Result<MessageResponse, String> {
+            self.requests.lock().unwrap().push(request);
+            if let Some(gate) = &self.gate {
+                gate.acquire().await.unwrap().forget();
+            }
+            let down = self
+                .unavailable_calls
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok();
+            if down {
+                return Err("Connection error: connection refused".to_string());
+            }
+            self.replies
+                .lock()
+                .unwrap()
+                .pop_front()
+                .ok_or_else(|| "No more responses".to_string())
+        }
+
+        fn model(&self) -> &str {
+            "test-model"
+        }
+
+        fn max_output_tokens(&self) -> u32 {
+            4096
+        }
+
+        fn temperature(&self) -> Option<f32> {
+            None
+        }
+
+        fn top_p(&self) -> Option<f32> {
+            None
+        }
+
+        fn top_k(&self) -> Option<u32> {
+            None
+        }
+    }
+
+    fn request_text(request: &MessageRequest) -> String {
+        request
+            .messages
+            .iter()
+            .flat_map(|m| &m.content)
+            .filter_map(|block| match block {
+                ContentBlock::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn oversized_config(policy: OversizedInputPolicy) -> AgentConfig {
+        AgentConfig {
+            max_input_tokens: 50,
+            oversized_input: policy,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_oversized_input_rejected() {
+        let agent = AgentLoop::new(
+            MockBrain::new(&["unused"]),
+            Executor::default(),
+            oversized_config(OversizedInputPolicy::Reject),
+        );
+
+        let err = agent.handle("word ".repeat(100)).await.unwrap_err();
+        assert!(matches!(
+            err,
+            AgentError::InputTooLarge {
+                estimated: 125,
+                limit: 50
+            }
+        ));
+        assert!(err.to_string().contains("Input too large"));
+        assert!(agent.brain.requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_oversized_input_chunked() {
+        let agent = AgentLoop::new(
+            MockBrain::new(&["summary one", "summary two", "summary three", "done"]),
+            Executor::default(),
+            oversized_config(OversizedInputPolicy::Chunk),
+        );
+
+        let input = "0123456789 ".repeat(40);
+        let reply = agent.handle(input).await.unwrap();
+        assert_eq!(reply, "done");
+
+        let requests = agent.brain.requests.lock().unwrap();
+        assert_eq!(
+            requests.len(),
+            4,
+            "three chunk summaries plus the final round"
+        );
+        for request in &requests[..3] {
+            assert_eq!(request.system.as_deref(), Some(CHUNK_SUMMARY_PROMPT));
+            assert!(request.tools.is_none());
+        }
+
+        let final_text = request_text(&requests[3]);
+        assert!(final_text.contains("[Part 1/3]\nsummary one"));
+        assert!(final_text.contains("[Part 3/3]\nsummary three"));
+        assert!(!final_text.contains("0123456789"));
+    }
+
+    #[tokio::test]
+    async fn test_oversized_tool_output_summarized() {
+        let text = |text: &str| {
+            response(
+                vec![ContentBlock::Text {
+                    text: text.to_string(),
+                }],
+                StopReason::EndTurn,
+            )
+        };
+        let agent = AgentLoop::new(
+            MockBrain::with_responses(vec![
+                response(
+                    vec![ContentBlock::ToolUse {
+                        id: "call_1".to_string(),
+                        name: "bash".to_string(),
+                        input: serde_json::json!({
+                            "command": "echo FIRST-LINE; seq 1 5000; echo 'error: disk full' >&2"
+                        }),
+                    }],
+                    StopReason::ToolUse,
+                ),
+                text("counted from 1 to 5000 without gaps"),
+                text("done"),
+            ]),
+            Executor::default(),
+            AgentConfig {
+                summarize_tool_outputs: true,
+                tool_output_summary_bytes: 4096,
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(agent.handle("count".to_string()).await.unwrap(), "done");
+
+        let requests = agent.brain.requests.lock().unwrap();
+        assert_eq!(requests.len(), 3, "tool round, summary, final round");
+        assert_eq!(
+            requests[1].system.as_deref(),
+            Some(TOOL_OUTPUT_SUMMARY_PROMPT)
+        );
+        assert!(requests[1].tools.is_none());
+        assert!(request_text(&requests[1]).contains("\n2500\n"));
+
+        let result = requests[2]
+            .messages
+            .iter()
+            .flat_map(|m| &m.content)
+            .find_map(|block| match block {
+                ContentBlock::ToolResult { content, .. } => Some(content.as_str()),
+                _ => None,
+            })
+            .unwrap();
+        assert!(result.len() < 4096, "{} bytes", result.len());
+        assert!(
+            result.contains("[head]\n[stdout]\nFIRST-LINE\n"),
+            "{}",
+            result
+        );
+        assert!(result.contains("[summary of the middle]\ncounted from 1 to 5000 without gaps"));
+        assert!(result.contains("[tail]\n"));
+        // The error at the very end survives
+        assert!(
+            result.contains("error: disk full\n\n[exit_code]\n0"),
+            "{}",
+            result
+        );
+        assert!(!result.contains("\n2500\n"));
+    }
+
+    #[tokio::test]
+    async fn test_response_prefill() {
+        let agent = AgentLoop::new(
+            MockBrain::new(&["\"ok\": true}"]),
+            Executor::default(),
+            AgentConfig {
+                response_prefill: Some("{".to_string()),
+                ..Default::default()
+            },
+        );
+
+        let reply = agent.handle("status as JSON".to_string()).await.unwrap();
+        assert_eq!(reply, "{\"ok\": true}");
+
+        let requests = agent.brain.requests.lock().unwrap();
+        let last = requests[0].messages.last().unwrap();
+        assert_eq!(last.role, Role::Assistant);
+        assert!(matches!(&last.content[..], [ContentBlock::Text { text }] if text == "{"));
+    }
+
+    #[tokio::test]
+    async fn test_empty_response_retried() {
+        let agent = AgentLoop::new(
+            MockBrain::new(&["", "disk is 40% full"]),
+            Executor::default(),
+            AgentConfig::default(),
+        );
+
+        let reply = agent.handle("check disk".to_string()).await.unwrap();
+        assert_eq!(reply, "disk is 40% full");
+        assert_eq!(agent.brain.requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_empty_responses_exhaust_retries() {
+        let agent = AgentLoop::new(
+            MockBrain::with_responses(vec![
+                response(Vec::new(), StopReason::EndTurn),
+                response(
+                    vec![ContentBlock::Text {
+                        text: " \n".to_string(),
+                    }],
+                    StopReason::EndTurn,
+                ),
+                response(Vec::new(), StopReason::EndTurn),
+                response(Vec::new(), StopReason::EndTurn),
+            ]),
+            Executor::default(),
+            AgentConfig::default(),
+        );
+
+        let err = agent.handle("check disk".to_string()).await.unwrap_err();
+        assert!(matches!(err, AgentError::EmptyResponse(3)));
+        assert_eq!(agent.brain.requests.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_max_tokens_without_text_returns_fallback() {
+        // Cut off partway through a tool call: no text, nothing runnable
+        let agent = AgentLoop::new(
+            MockBrain::with_responses(vec![response(
+                vec![ContentBlock::ToolUse {
+                    id: "call_1".to_string(),
+                    name: "bash".to_string(),
+                    input: serde_json::json!({}),
+                }],
+                StopReason::MaxTokens,
+            )]),
+            Executor::default(),
+            AgentConfig::default(),
+        );
+
+        let err = agent.handle("check disk".to_string()).await.unwrap_err();
+        assert!(matches!(err, AgentError::NoUsableOutput(_)));
+        assert_eq!(
+            err.to_string(),
+            "The model produced no usable output (stop reason: max_tokens)."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stop_sequence_policy() {
+        let stopped = |text: &str| MessageResponse {
+            stop_sequence: Some("</answer>".to_string()),
+            ..response(
+                vec![ContentBlock::Text {
+                    text: text.to_string(),
+                }],
+                StopReason::StopSequence,
+            )
+        };
+        let agent_with = |policy, responses| {
+            AgentLoop::new(
+                MockBrain::with_responses(responses),
+                Executor::default(),
+                AgentConfig {
+                    stop_sequence: policy,
+                    ..Default::default()
+                },
+            )
+        };
+
+        let agent = agent_with(
+            StopSequencePolicy::ReturnAsIs,
+            vec![stopped("<answer>42</answer>")],
+        );
+        assert_eq!(
+            agent.handle("answer".to_string()).await.unwrap(),
+            "<answer>42</answer>"
+        );
+
+        let agent = agent_with(
+            StopSequencePolicy::StripSequence,
+            vec![stopped("<answer>42</answer>\n")],
+        );
+        assert_eq!(
+            agent.handle("answer".to_string()).await.unwrap(),
+            "<answer>42"
+        );
+
+        // Resumed from the text so far, sequence included
+        let agent = agent_with(
+            StopSequencePolicy::Continue,
+            vec![
+                stopped("<answer>42"),
+                response(
+                    vec![ContentBlock::Text {
+                        text: " because it is".to_string(),
+                    }],
+                    StopReason::EndTurn,
+                ),
+            ],
+        );
+        assert_eq!(
+            agent.handle("answer".to_string()).await.unwrap(),
+            "<answer>42</answer> because it is"
+        );
+        let requests = agent.brain.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        let resumed = requests[1].messages.last().unwrap();
+        assert_eq!(resumed.role, Role::Assistant);
+        assert!(
+            matches!(&resumed.content[..], [ContentBlock::Text { text }] if text == "<answer>42</answer>")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_input_within_limit_untouched() {
+        let agent = AgentLoop::new(
+            MockBrain::new(&["hello back"]),
+            Executor::default(),
+            oversized_config(OversizedInputPolicy::Chunk),
+        );
+
+        assert_eq!(
+            agent.handle("hello".to_string()).await.unwrap(),
+            "hello back"
+        );
+        let requests = agent.brain.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(request_text(&requests[0]), "hello");
+    }
+
+    #[tokio::test]
+    async fn test_build_request_clamps_max_tokens_to_model_limit() {
+        let mut brain_config = BrainConfig::for_tests();
+        brain_config.default_model = "small-model".to_string();
+        brain_config.max_output_tokens = 8192;
+        brain_config
+            .model_max_tokens
+            .insert("small-model".to_string(), 2048);
+
+        let brain = Brain::new(brain_config).await.unwrap();
+        let agent = AgentLoop::new(brain, Executor::default(), AgentConfig::default());
+
+        let messages = vec![Message::user_text("hi")];
+        let request = agent.build_request("system", &messages, &[], None).unwrap();
+        assert_eq!(request.model, "small-model");
+        assert_eq!(request.max_tokens, 2048);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_tool_error_lists_available_tools() {
+        let agent = AgentLoop::new(
+            MockBrain::new(&[]),
+            Executor::default(),
+            AgentConfig::default(),
+        );
+
+        let calls = vec![ToolCall {
+            id: "call_1".to_string(),
+            name: "read_file".to_string(),
+            input: serde_json::json!({}),
+        }];
+        let mut messages = Vec::new();
+        agent
+            .execute_tool_calls(
+                calls,
+                &[],
+                &mut messages,
+                &mut ExecutionRecord::default(),
+                &mut TokenSpend::default(),
+            )
+            .await
+            .unwrap();
+
+        let [
+            ContentBlock::ToolResult {
+                content, is_error, ..
+            },
+        ] = &messages[0].content[..]
+        else {
+            panic!("expected a single tool result");
+        };
+        assert_eq!(*is_error, Some(true));
+        assert!(content.contains("Unknown tool: read_file"));
+        for name in ["bash", "list_dir", "tail_file"] {
+            assert!(content.contains(name), "missing {} in {}", name, content);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_errors_tagged_transient_or_permanent() {
+        /// Tool whose every run times out
+        struct TimingOutTool;
+
+        #[async_trait::async_trait]
+        impl crate::executor::ToolImpl for TimingOutTool {
+            fn definition(&self) -> ToolDefinition {
+                ToolDefinition {
+                    name: "flaky".to_string(),
+                    description: "Times out".to_string(),
+                    input_schema: serde_json::json!({ "type": "object", "properties": {} }),
+                }
+            }
+
+            async fn run(
+                &self,
+                _input: serde_json::Value,
+            ) -> crate::executor::Result<crate::executor::ToolOutput> {
+                Err(ExecutorError::Timeout("flaky".to_string(), 30))
+            }
+        }
+
+        let executor = Executor::default();
+        executor.register(Arc::new(TimingOutTool)).unwrap();
+        let agent = AgentLoop::new(MockBrain::new(&[]), executor, AgentConfig::default());
+
+        let calls = vec![
+            ToolCall {
+                id: "call_1".to_string(),
+                name: "flaky".to_string(),
+                input: serde_json::json!({}),
+            },
+            ToolCall {
+                id: "call_2".to_string(),
+                name: "read_file".to_string(),
+                input: serde_json::json!({}),
+            },
+        ];
+        let mut messages = Vec::new();
+        agent
+            .execute_tool_calls(
+                calls,
+                &[],
+                &mut messages,
+                &mut ExecutionRecord::default(),
+                &mut TokenSpend::default(),
+            )
+            .await
+            .unwrap();
+
+        let results: Vec<&str> = messages
+            .iter()
+            .flat_map(|m| &m.content)
+            .filter_map(|block| match block {
+                ContentBlock::ToolResult { content, .. } => Some(content.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].contains("Execution timeout"), "{}", results[0]);
+        assert!(
+            results[0].ends_with("(transient, may retry)"),
+            "{}",
+            results[0]
+        );
+        assert!(
+            results[1].contains("Unknown tool: read_file"),
+            "{}",
+            results[1]
+        );
+        assert!(
+            results[1].ends_with("(permanent, do not retry)"),
+            "{}",
+            results[1]
+        );
+    }
+
+    /// Run one `config` tool call and return its result text and error flag
+    async fn call_config_tool<B: BrainRef>(
+        agent: &AgentLoop<B>,
+        input: serde_json::Value,
+    ) -> (String, bool) {
+        let calls = vec![ToolCall {
+            id: "call_config".to_string(),
+            name: "config".to_string(),
+            input,
+        }];
+        let mut messages = Vec::new();
+        agent
+            .execute_tool_calls(
+                calls,
+                &[],
+                &mut messages,
+                &mut ExecutionRecord::default(),
+                &mut TokenSpend::default(),
+            )
+            .await
+            .unwrap();
+        match &messages[0].content[..] {
+            [
+                ContentBlock::ToolResult {
+                    content, is_error, ..
+                },
+            ] => (content.clone(), is_error.unwrap_or(false)),
+            _ => panic!("expected a single tool result"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_config_tool_reads_current_values() {
+        let agent = AgentLoop::new(
+            MockBrain::new(&[]),
+            Executor::default(),
+            AgentConfig {
+                max_tool_rounds: 7,
+                ..Default::default()
+            },
+        );
+
+        let (content, is_error) =
+            call_config_tool(&agent, serde_json::json!({ "action": "get" })).await;
+        assert!(!is_error);
+        let current: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(current["max_tool_rounds"], 7);
+        assert_eq!(current["temperature"], serde_json::Value::Null);
+        assert_eq!(current["writable"], false);
+
+        // Writes are opt-in
+        let (content, is_error) = call_config_tool(
+            &agent,
+            serde_json::json!({ "action": "set", "max_tool_rounds": 3 }),
+        )
+        .await;
+        assert!(is_error);
+        assert!(content.contains("disabled"));
+        assert_eq!(agent.settings().max_tool_rounds, 7);
+    }
+
+    #[tokio::test]
+    async fn test_config_tool_write_clamped() {
+        let agent = AgentLoop::new(
+            MockBrain::new(&[]),
+            Executor::default(),
+            AgentConfig {
+                allow_config_writes: true,
+                temperature_bounds: Bounds::new(0.0, 1.0),
+                tool_rounds_bounds: Bounds::new(1, 10),
+                ..Default::default()
+            },
+        );
+
+        let (content, is_error) = call_config_tool(
+            &agent,
+            serde_json::json!({ "action": "set", "temperature": 1.8, "max_tool_rounds": 500 }),
+        )
+        .await;
+        assert!(!is_error);
+        let applied: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(applied["temperature"], 1.0);
+        assert_eq!(applied["max_tool_rounds"], 10);
+
+        // The next request picks up the new temperature
+        let messages = vec![Message::user_text("hi")];
+        let request = agent.build_request("system", &messages, &[], None).unwrap();
+        assert_eq!(request.temperature, Some(1.0));
+    }
+
+    #[test]
+    fn test_bounds_parse() {
+        let bounds: Bounds<f32> = "0.2..0.9".parse().unwrap();
+        assert_eq!(bounds, Bounds::new(0.2, 0.9));
+        assert_eq!(bounds.clamp(0.0), 0.2);
+        assert_eq!(bounds.clamp(0.5), 0.5);
+        assert!("5..1".parse::<Bounds<u32>>().is_err());
+        assert!("5".parse::<Bounds<u32>>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_enabled_tools_filter() {
+        let agent = AgentLoop::new(
+            MockBrain::new(&[]),
+            Executor::default(),
+            AgentConfig {
+                enabled_tools: vec!["list_dir".to_string(), "tail_file".to_string()],
+                ..Default::default()
+            },
+        );
+
+        let mut names: Vec<String> = agent
+            .executor
+            .tool_definitions()
+            .into_iter()
+            .map(|d| d.name)
+            .collect();
+        names.sort();
+        assert_eq!(names, ["list_dir", "tail_file"]);
+
+        let calls = vec![ToolCall {
+            id: "call_1".to_string(),
+            name: "bash".to_string(),
+            input: serde_json::json!({ "command": "echo hi" }),
+        }];
+        let mut messages = Vec::new();
+        agent
+            .execute_tool_calls(
+                calls,
+                &[],
+                &mut messages,
+                &mut ExecutionRecord::default(),
+                &mut TokenSpend::default(),
+            )
+            .await
+            .unwrap();
+        let [
+            ContentBlock::ToolResult {
+                content, is_error, ..
+            },
+        ] = &messages[0].content[..]
+        else {
+            panic!("expected a single tool result");
+        };
+        assert_eq!(*is_error, Some(true));
+        assert!(content.contains("Tool not enabled: bash"));
+    }
+
+    #[tokio::test]
+    async fn test_init_and_handle_advertise_their_tool_sets() {
+        let agent = AgentLoop::new(
+            MockBrain::with_responses(vec![
+                response(
+                    vec![ContentBlock::ToolUse {
+                        id: "call_1".to_string(),
+                        name: "bash".to_string(),
+                        input: serde_json::json!({ "command": "touch /tmp/x" }),
+                    }],
+                    StopReason::ToolUse,
+                ),
+                response(
+                    vec![ContentBlock::Text {
+                        text: "explored".to_string(),
+                    }],
+                    StopReason::EndTurn,
+                ),
+                response(
+                    vec![ContentBlock::Text {
+                        text: "done".to_string(),
+                    }],
+                    StopReason::EndTurn,
+                ),
+            ]),
+            Executor::default(),
+            AgentConfig {
+                init_tools: vec!["list_dir".to_string(), "tail_file".to_string()],
+                handle_tools: vec!["bash".to_string(), "list_dir".to_string()],
+                ..Default::default()
+            },
+        );
+
+        agent.run_init().await.unwrap();
+        agent.handle("restart nginx".to_string()).await.unwrap();
+
+        let requests = agent.brain.requests.lock().unwrap();
+        let advertised = |i: usize| {
+            let mut names: Vec<String> = requests[i]
+                .tools
+                .iter()
+                .flatten()
+                .map(|d| d.name.clone())
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(advertised(0), ["list_dir", "tail_file"]);
+        assert_eq!(advertised(2), ["bash", "list_dir"]);
+
+        // Init's call to a handle-only tool was refused, not run
+        let tool_result = requests[1]
+            .messages
+            .iter()
+            .flat_map(|m| &m.content)
+            .find_map(|block| match block {
+                ContentBlock::ToolResult { content, .. } => Some(content.as_str()),
+                _ => None,
+            })
+            .unwrap();
+        assert!(
+            tool_result.contains("Tool not enabled: bash"),
+            "{}",
+            tool_result
+        );
+    }
+
+    #[tokio::test]
+    async fn test_readonly_first_rounds_hold_back_write_tools() {
+        let bash_call = |id: &str| {
+            response(
+                vec![ContentBlock::ToolUse {
+                    id: id.to_string(),
+                    name: "bash".to_string(),
+                    input: serde_json::json!({ "command": "echo acted" }),
+                }],
+                StopReason::ToolUse,
+            )
+        };
+        let agent = AgentLoop::new(
+            MockBrain::with_responses(vec![
+                bash_call("call_1"),
+                bash_call("call_2"),
+                response(
+                    vec![ContentBlock::Text {
+                        text: "done".to_string(),
+                    }],
+                    StopReason::EndTurn,
+                ),
+            ]),
+            Executor::default(),
+            AgentConfig {
+                readonly_first_rounds: 1,
+                handle_tools: vec!["bash".to_string(), "scratchpad".to_string()],
+                ..Default::default()
+            },
+        );
+
+        agent.handle("restart nginx".to_string()).await.unwrap();
+
+        let requests = agent.brain.requests.lock().unwrap();
+        let advertised = |i: usize| {
+            let mut names: Vec<String> = requests[i]
+                .tools
+                .iter()
+                .flatten()
+                .map(|d| d.name.clone())
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(advertised(0), ["scratchpad"]);
+        assert_eq!(advertised(1), ["bash", "scratchpad"]);
+
+        let tool_results: Vec<&str> = requests[2]
+            .messages
+            .iter()
+            .flat_map(|m| &m.content)
+            .filter_map(|block| match block {
+                ContentBlock::ToolResult { content, .. } => Some(content.as_str()),
+                _ => None,
+            })
+            .collect();
+        // Refused while observing, run once unlocked
+        assert_eq!(tool_results.len(), 2);
+        assert!(
+            tool_results[0].contains("Tool not enabled: bash"),
+            "{}",
+            tool_results[0]
+        );
+        assert!(tool_results[1].contains("acted"), "{}", tool_results[1]);
+    }
+
+    #[tokio::test]
+    async fn test_memory_search_filters_journal() {
+        let agent = AgentLoop::new(
+            MockBrain::new(&[]),
+            Executor::default(),
+            AgentConfig::default(),
+        );
+        agent.memory.write(|m| {
+            m.add_tool_result("bash", "df: 40% used");
+            m.add_observation("disk is filling up");
+            m.add_tool_result("net_check", "8.8.8.8 reachable");
+            m.add_error("tail_file: permission denied");
+            m.add_tool_result("bash", "du: /var/log 20G");
+        });
+        let search = |input: serde_json::Value| agent.executor.execute("memory_search", input);
+
+        let output = search(serde_json::json!({ "tool": "bash" })).await.unwrap();
+        let lines: Vec<&str> = output.content.lines().collect();
+        assert_eq!(lines.len(), 2, "{}", output.content);
+        assert!(
+            lines[0].ends_with("[tool: bash] df: 40% used"),
+            "{}",
+            lines[0]
+        );
+        assert!(
+            lines[1].ends_with("[tool: bash] du: /var/log 20G"),
+            "{}",
+            lines[1]
+        );
+
+        let output = search(serde_json::json!({ "category": "tool", "limit": 1 }))
+            .await
+            .unwrap();
+        assert!(
+            output.content.ends_with("du: /var/log 20G"),
+            "{}",
+            output.content
+        );
+
+        let output = search(serde_json::json!({ "category": "error" }))
+            .await
+            .unwrap();
+        assert!(
+            output
+                .content
+                .ends_with("[error] tail_file: permission denied")
+        );
+
+        assert!(search(serde_json::json!({})).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_scratchpad_scoped_to_one_handle() {
+        let scratchpad = |id: &str, input: serde_json::Value| {
+            response(
+                vec![ContentBlock::ToolUse {
+                    id: id.to_string(),
+                    name: "scratchpad".to_string(),
+                    input,
+                }],
+                StopReason::ToolUse,
+            )
+        };
+        let done = || {
+            response(
+                vec![ContentBlock::Text {
+                    text: "done".to_string(),
+                }],
+                StopReason::EndTurn,
+            )
+        };
+        let agent = AgentLoop::new(
+            MockBrain::with_responses(vec![
+                scratchpad(
+                    "call_1",
+                    serde_json::json!({ "action": "set", "key": "pid", "value": "4242" }),
+                ),
+                scratchpad(
+                    "call_2",
+                    serde_json::json!({ "action": "get", "key": "pid" }),
+                ),
+                done(),
+                scratchpad(
+                    "call_3",
+                    serde_json::json!({ "action": "get", "key": "pid" }),
+                ),
+                done(),
+            ]),
+            Executor::default(),
+            AgentConfig::default(),
+        );
+
+        agent.handle("start the worker".to_string()).await.unwrap();
+        agent.handle("is it running?".to_string()).await.unwrap();
+
+        let requests = agent.brain.requests.lock().unwrap();
+        let last_tool_result = |i: usize| {
+            requests[i]
+                .messages
+                .iter()
+                .flat_map(|m| &m.content)
+                .filter_map(|block| match block {
+                    ContentBlock::ToolResult {
+                        content, is_error, ..
+                    } => Some((content.clone(), *is_error)),
+                    _ => None,
+                })
+                .next_back()
+                .unwrap()
+        };
+        assert_eq!(last_tool_result(2), ("4242".to_string(), Some(false)));
+
+        let (content, is_error) = last_tool_result(4);
+        assert_eq!(is_error, Some(true));
+        assert!(content.contains("no scratchpad entry"), "{}", content);
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_per_round_capped() {
+        let agent = AgentLoop::new(
+            MockBrain::new(&[]),
+            Executor::default(),
+            AgentConfig {
+                max_tool_calls_per_round: 2,
+                ..Default::default()
+            },
+        );
+
+        let calls = (0..5)
+            .map(|i| ToolCall {
+                id: format!("call_{}", i),
+                name: "bash".to_string(),
+                input: serde_json::json!({ "command": format!("echo run-{}", i) }),
+            })
+            .collect();
+        let mut messages = Vec::new();
+        agent
+            .execute_tool_calls(
+                calls,
+                &[],
+                &mut messages,
+                &mut ExecutionRecord::default(),
+                &mut TokenSpend::default(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(messages.len(), 5);
+        for (i, message) in messages.iter().enumerate() {
+            let [
+                ContentBlock::ToolResult {
+                    tool_use_id,
+                    content,
+                    is_error,
+                },
+            ] = &message.content[..]
+            else {
+                panic!("expected a single tool result");
+            };
+            assert_eq!(tool_use_id, &format!("call_{}", i));
+            if i < 2 {
+                assert_eq!(*is_error, Some(false));
+                assert!(content.contains(&format!("run-{}", i)));
+            } else {
+                assert_eq!(*is_error, Some(true));
+                assert!(content.contains("at most 2 tool calls"));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_round_deadline_bounds_whole_round() {
+        /// Tool that never finishes within the test
+        struct SlowTool;
+
+        #[async_trait::async_trait]
+        impl crate::executor::ToolImpl for SlowTool {
+            fn definition(&self) -> ToolDefinition {
+                ToolDefinition {
+                    name: "slow".to_string(),
+                    description: "Sleeps".to_string(),
+                    input_schema: serde_json::json!({ "type": "object", "properties": {} }),
+                }
+            }
+
+            async fn run(
+                &self,
+                _input: serde_json::Value,
+            ) -> crate::executor::Result<crate::executor::ToolOutput> {
+                tokio::time::sleep(Duration::from_secs(30)).await;
+                Ok(crate::executor::ToolOutput::success("done"))
+            }
+        }
+
+        let executor = Executor::default();
+        executor.register(Arc::new(SlowTool)).unwrap();
+        let agent = AgentLoop::new(
+            MockBrain::new(&[]),
+            executor,
+            AgentConfig {
+                tool_round_timeout_secs: 1,
+                ..Default::default()
+            },
+        );
+
+        let calls = (0..4)
+            .map(|i| ToolCall {
+                id: format!("call_{}", i),
+                name: "slow".to_string(),
+                input: serde_json::json!({ "seq": i }),
+            })
+            .collect();
+        let mut messages = Vec::new();
+        let start = std::time::Instant::now();
+        agent
+            .execute_tool_calls(
+                calls,
+                &[],
+                &mut messages,
+                &mut ExecutionRecord::default(),
+                &mut TokenSpend::default(),
+            )
+            .await
+            .unwrap();
+
+        assert!(
+            start.elapsed() < Duration::from_secs(3),
+            "{:?}",
+            start.elapsed()
+        );
+        assert_eq!(messages.len(), 4);
+        for (i, message) in messages.iter().enumerate() {
+            let [
+                ContentBlock::ToolResult {
+                    tool_use_id,
+                    content,
+                    is_error,
+                },
+            ] = &message.content[..]
+            else {
+                panic!("expected a single tool result");
+            };
+            assert_eq!(tool_use_id, &format!("call_{}", i));
+            assert_eq!(*is_error, Some(true));
+            assert!(
+                content.starts_with("Error: Execution timeout for tool 'slow' after 1 seconds"),
+                "{}",
+                content
+            );
+            assert!(content.ends_with("(transient, may retry)"), "{}", content);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_progress_reaches_memory_before_result() {
+        let executor = Executor::init(crate::executor::ExecutorConfig {
+            progress: crate::executor::ProgressPolicy {
+                every_lines: 2,
+                interval: Duration::ZERO,
+            },
+            ..Default::default()
+        })
+        .unwrap();
+        let agent = AgentLoop::new(MockBrain::new(&[]), executor, AgentConfig::default());
+
+        let calls = vec![ToolCall {
+            id: "call_1".to_string(),
+            name: "bash".to_string(),
+            input: serde_json::json!({
+                "command": "for i in 1 2 3 4 5; do echo step$i; sleep 0.05; done"
+            }),
+        }];
+        agent
+            .execute_tool_calls(
+                calls,
+                &[],
+                &mut Vec::new(),
+                &mut ExecutionRecord::default(),
+                &mut TokenSpend::default(),
+            )
+            .await
+            .unwrap();
+
+        let journal: Vec<crate::memory::types::JournalEntry> = agent
+            .memory
+            .read(|m| m.journal_entries().into_iter().cloned().collect());
+        let progress: Vec<&str> = journal
+            .iter()
+            .filter_map(|entry| match entry {
+                crate::memory::types::JournalEntry::Observation(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        // The second report replaced the first
+        assert_eq!(progress.len(), 1, "{:?}", journal);
+        assert!(progress[0].contains("4 lines") && progress[0].ends_with("step4"));
+        assert!(matches!(
+            journal.last(),
+            Some(crate::memory::types::JournalEntry::ToolResult { tool, .. }) if tool == "bash"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_tool_progress_streams_to_client_channel() {
+        /// Tool reporting each of its output lines as progress
+        struct LinesTool;
+
+        #[async_trait::async_trait]
+        impl crate::executor::ToolImpl for LinesTool {
+            fn definition(&self) -> ToolDefinition {
+                ToolDefinition {
+                    name: "lines".to_string(),
+                    description: "Prints three lines".to_string(),
+                    input_schema: serde_json::json!({ "type": "object", "properties": {} }),
+                }
+            }
+
+            async fn run(
+                &self,
+                _input: serde_json::Value,
+            ) -> crate::executor::Result<crate::executor::ToolOutput> {
+                for lines in 1..=3 {
+                    progress::report(crate::executor::Progress {
+                        tool: "lines".to_string(),
+                        lines,
+                        elapsed: Duration::ZERO,
+                        latest: format!("line {}", lines),
+                    });
+                }
+                Ok(crate::executor::ToolOutput::success(
+                    "line 1\nline 2\nline 3",
+                ))
+            }
+        }
+
+        let executor = Executor::default();
+        executor.register(Arc::new(LinesTool)).unwrap();
+        let agent = AgentLoop::new(
+            MockBrain::with_responses(vec![
+                response(
+                    vec![ContentBlock::ToolUse {
+                        id: "call_1".to_string(),
+                        name: "lines".to_string(),
+                        input: serde_json::json!({}),
+                    }],
+                    StopReason::ToolUse,
+                ),
+                response(
+                    vec![ContentBlock::Text {
+                        text: "three lines".to_string(),
+                    }],
+                    StopReason::EndTurn,
+                ),
+            ]),
+            executor,
+            AgentConfig::default(),
+        );
+
+        let (progress_tx, mut progress_rx) = mpsc::channel(2);
+        let (reply, rx) = tokio::sync::oneshot::channel();
+        agent
+            .handle_user_request(UserRequest {
+                content: "print lines".to_string(),
+                reply,
+                source_addr: "127.0.0.1:9".parse().unwrap(),
+                kind: RequestKind::Input,
+                request_id: None,
+                progress: Some(progress_tx),
+            })
+            .await;
+        assert_eq!(rx.await.unwrap().content, "three lines");
+
+        // The third snippet did not fit the channel and was dropped
+        let mut snippets = Vec::new();
+        while let Ok(snippet) = progress_rx.try_recv() {
+            snippets.push(snippet);
+        }
+        assert_eq!(snippets.len(), 2, "{:?}", snippets);
+        assert!(snippets[0].ends_with("latest: line 1"), "{}", snippets[0]);
+        assert!(snippets[1].ends_with("latest: line 2"), "{}", snippets[1]);
+    }
+
+    #[tokio::test]
+    async fn test_identical_tool_call_runs_once_per_request() {
+        let dir = std::env::temp_dir().join(format!("shelly-idem-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("log");
+        let append = |id: &str| {
+            response(
+                vec![ContentBlock::ToolUse {
+                    id: id.to_string(),
+                    name: "bash".to_string(),
+                    input: serde_json::json!({ "command": format!("echo ran >> {}", log.display()) }),
+                }],
+                StopReason::ToolUse,
+            )
+        };
+        let done = |text: &str| {
+            response(
+                vec![ContentBlock::Text {
+                    text: text.to_string(),
+                }],
+                StopReason::EndTurn,
+            )
+        };
+        // bash only reuses results when the tools config tags it cacheable
+        let tools_toml = dir.join("tools.toml");
+        std::fs::write(&tools_toml, "[bash]\ncacheable = true\n").unwrap();
+        let executor = Executor::init(crate::executor::ExecutorConfig {
+            tools_toml_path: tools_toml,
+            ..Default::default()
+        })
+        .unwrap();
+        let agent = AgentLoop::new(
+            MockBrain::with_responses(vec![
+                append("call_1"),
+                append("call_2"),
+                done("appended"),
+                append("call_3"),
+                done("appended again"),
+            ]),
+            executor,
+            AgentConfig::default(),
+        );
+
+        agent.handle("append once".to_string()).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&log).unwrap(), "ran\n");
+
+        // The repeat got the first result back, marked as cached
+        let requests = agent.brain.requests.lock().unwrap().clone();
+        let Some(ContentBlock::ToolResult { content, .. }) =
+            requests[2].messages.last().unwrap().content.first()
+        else {
+            panic!("expected a tool result");
+        };
+        assert!(content.starts_with("[cached:"), "{}", content);
+
+        // A new request starts with an empty record
+        agent.handle("append again".to_string()).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&log).unwrap(), "ran\nran\n");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_plan_mode_runs_tools_only_after_approval() {
+        let dir = std::env::temp_dir().join(format!("shelly-plan-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("log");
+        let agent = AgentLoop::new(
+            MockBrain::with_responses(vec![
+                response(
+                    vec![ContentBlock::ToolUse {
+                        id: "call_1".to_string(),
+                        name: "bash".to_string(),
+                        input: serde_json::json!({ "command": format!("echo ran >> {}", log.display()) }),
+                    }],
+                    StopReason::ToolUse,
+                ),
+                response(
+                    vec![ContentBlock::Text {
+                        text: "appended".to_string(),
+                    }],
+                    StopReason::EndTurn,
+                ),
+            ]),
+            Executor::default(),
+            AgentConfig {
+                plan_mode: true,
+                ..AgentConfig::default()
+            },
+        );
+
+        let plan = agent.handle("append a line".to_string()).await.unwrap();
+        assert!(plan.contains("not executed"), "{}", plan);
+        assert!(plan.contains("1. bash"), "{}", plan);
+        assert!(!log.exists());
+        let requests = agent.brain.requests.lock().unwrap().clone();
+        assert!(
+            requests[0]
+                .system
+                .as_deref()
+                .unwrap()
+                .contains("# Plan Mode")
+        );
+
+        let plan_id = plan
+            .strip_prefix("Plan ")
+            .and_then(|rest| rest.split_whitespace().next())
+            .unwrap()
+            .to_string();
+        assert_eq!(agent.approve(&plan_id).await.unwrap(), "appended");
+        assert_eq!(std::fs::read_to_string(&log).unwrap(), "ran\n");
+
+        // The model continued from the tool result of the approved call
+        let requests = agent.brain.requests.lock().unwrap().clone();
+        let Some(ContentBlock::ToolResult { tool_use_id, .. }) =
+            requests[1].messages.last().unwrap().content.first()
+        else {
+            panic!("expected a tool result");
+        };
+        assert_eq!(tool_use_id, "call_1");
+
+        // A plan runs at most once
+        assert!(matches!(
+            agent.approve(&plan_id).await,
+            Err(AgentError::UnknownPlan(_))
+        ));
+        assert_eq!(std::fs::read_to_string(&log).unwrap(), "ran\n");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_token_budget_aborts_request() {
+        let round = |i: u32| {
+            let mut reply = response(
+                vec![ContentBlock::ToolUse {
+                    id: format!("call_{}", i),
+                    name: "list_dir".to_string(),
+                    input: serde_json::json!({ "path": format!("./{}", i) }),
+                }],
+                StopReason::ToolUse,
+            );
+            reply.usage = Some(crate::brain::types::Usage {
+                input_tokens: 1000,
+                output_tokens: 100,
+                cache_creation_input_tokens: None,
+                cache_read_input_tokens: Some(500),
+            });
+            reply
+        };
+        let agent = AgentLoop::new(
+            MockBrain::with_responses((1..=10).map(round).collect()),
+            Executor::default(),
+            AgentConfig {
+                max_total_output_tokens: 250,
+                ..AgentConfig::default()
+            },
+        );
+
+        let err = agent.handle("loop forever".to_string()).await.unwrap_err();
+        assert!(
+            matches!(
+                err,
+                AgentError::BudgetExceeded {
+                    kind: "output",
+                    spent: 300,
+                    limit: 250
+                }
+            ),
+            "{}",
+            err
+        );
+        assert!(err.to_string().contains("budget exceeded"), "{}", err);
+        // Stopped on the round that crossed the budget, well before max_tool_rounds
+        assert_eq!(agent.brain.requests.lock().unwrap().len(), 3);
+
+        // Cache reads count towards the input budget
+        let agent = AgentLoop::new(
+            MockBrain::with_responses((1..=10).map(round).collect()),
+            Executor::default(),
+            AgentConfig {
+                max_total_input_tokens: 2000,
+                ..AgentConfig::default()
+            },
+        );
+        let err = agent.handle("loop forever".to_string()).await.unwrap_err();
+        assert!(matches!(
+            err,
+            AgentError::BudgetExceeded {
+                kind: "input",
+                spent: 3000,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_tool_output_summary_counts_towards_budget() {
+        let with_output_tokens = |mut reply: MessageResponse, output_tokens| {
+            reply.usage = Some(crate::brain::types::Usage {
+                input_tokens: 10,
+                output_tokens,
+                cache_creation_input_tokens: None,
+                cache_read_input_tokens: None,
+            });
+            reply
+        };
+        let agent = AgentLoop::new(
+            MockBrain::with_responses(vec![
+                with_output_tokens(
+                    response(
+                        vec![ContentBlock::ToolUse {
+                            id: "call_1".to_string(),
+                            name: "bash".to_string(),
+                            input: serde_json::json!({ "command": "seq 1 5000" }),
+                        }],
+                        StopReason::ToolUse,
+                    ),
+                    100,
+                ),
+                with_output_tokens(
+                    response(
+                        vec![ContentBlock::Text {
+                            text: "counted to 5000".to_string(),
+                        }],
+                        StopReason::EndTurn,
+                    ),
+                    200,
+                ),
+            ]),
+            Executor::default(),
+            AgentConfig {
+                summarize_tool_outputs: true,
+                tool_output_summary_bytes: 4096,
+                max_total_output_tokens: 250,
+                ..AgentConfig::default()
+            },
+        );
+
+        let err = agent.handle("count".to_string()).await.unwrap_err();
+        assert!(
+            matches!(
+                err,
+                AgentError::BudgetExceeded {
+                    kind: "output",
+                    spent: 300,
+                    limit: 250
+                }
+            ),
+            "{}",
+            err
+        );
+        // The summary crossed the budget, so no further round was started
+        assert_eq!(agent.brain.requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_assistant_block_order_preserved() {
+        let interleaved = vec![
+            ContentBlock::Text {
+                text: "Listing first.".to_string(),
+            },
+            ContentBlock::ToolUse {
+                id: "call_1".to_string(),
+                name: "list_dir".to_string(),
+                input: serde_json::json!({ "path": "." }),
+            },
+            ContentBlock::Text {
+                text: "Then the log.".to_string(),
+            },
+            ContentBlock::ToolUse {
+                id: "call_2".to_string(),
+                name: "tail_file".to_string(),
+                input: serde_json::json!({ "path": "Cargo.toml", "lines": 1 }),
+            },
+        ];
+        let agent = AgentLoop::new(
+            MockBrain::with_responses(vec![
+                response(interleaved.clone(), StopReason::ToolUse),
+                response(
+                    vec![ContentBlock::Text {
+                        text: "done".to_string(),
+                    }],
+                    StopReason::EndTurn,
+                ),
+            ]),
+            Executor::default(),
+            AgentConfig::default(),
+        );
+
+        agent.handle("inspect".to_string()).await.unwrap();
+
+        // The follow-up request replays the assistant turn untouched
+        let requests = agent.brain.requests.lock().unwrap();
+        let assistant = &requests[1].messages[1];
+        assert_eq!(assistant.role, Role::Assistant);
+        assert_eq!(assistant.content, interleaved);
+    }
+
+    #[tokio::test]
+    async fn test_init_uses_its_own_tool_round_cap() {
+        let tool_use = |id: &str| {
+            response(
+                vec![ContentBlock::ToolUse {
+                    id: id.to_string(),
+                    name: "list_dir".to_string(),
+                    input: serde_json::json!({ "path": "." }),
+                }],
+                StopReason::ToolUse,
+            )
+        };
+        // Two rounds for init, then four tool rounds and an answer for handle
+        let mut responses: Vec<MessageResponse> =
+            (0..6).map(|i| tool_use(&format!("call_{}", i))).collect();
+        responses.push(response(
+            vec![ContentBlock::Text {
+                text: "done".to_string(),
+            }],
+            StopReason::EndTurn,
+        ));
+
+        let agent = AgentLoop::new(
+            MockBrain::with_responses(responses),
+            Executor::default(),
+            AgentConfig {
+                max_init_tool_rounds: 2,
+                max_tool_rounds: 5,
+                ..Default::default()
+            },
+        );
+
+        agent.run_init().await.unwrap();
+        assert_eq!(agent.brain.requests.lock().unwrap().len(), 2);
+
+        let reply = agent.handle("explore deeper".to_string()).await.unwrap();
+        assert_eq!(reply, "done");
+        assert_eq!(agent.brain.requests.lock().unwrap().len(), 7);
+    }
+
+    #[tokio::test]
+    async fn test_init_waits_for_backend_to_come_up() {
+        let brain = MockBrain::new(&["machine explored", "machine explored"]);
+        brain.unavailable_calls.store(2, Ordering::SeqCst);
+        let agent = AgentLoop::new(
+            brain,
+            Executor::default(),
+            AgentConfig {
+                init_backend_wait_secs: 30,
+                ..Default::default()
+            },
+        );
+
+        // Down for two calls: retried after 0.5s and 1s, then it answers
+        let started = std::time::Instant::now();
+        agent.run_init().await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(1500));
+        assert_eq!(agent.brain.requests.lock().unwrap().len(), 3);
+        assert!(
+            agent
+                .memory
+                .read(|mem| mem.context())
+                .contains("machine explored")
+        );
+
+        // Without a window the first failure is final
+        let brain = MockBrain::new(&["machine explored"]);
+        brain.unavailable_calls.store(1, Ordering::SeqCst);
+        let agent = AgentLoop::new(
+            brain,
+            Executor::default(),
+            AgentConfig {
+                init_backend_wait_secs: 0,
+                ..Default::default()
+            },
+        );
+        let err = agent.run_init().await.unwrap_err();
+        assert!(matches!(err, AgentError::Inference(_)), "{}", err);
+        assert_eq!(agent.brain.requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_init_report_written_and_shared() {
+        let path = std::env::temp_dir().join(format!("shelly-init-{}.md", uuid::Uuid::new_v4()));
+        let slot = InitReportSlot::default();
+        let agent = AgentLoop::new(
+            MockBrain::with_responses(vec![
+                response(
+                    vec![
+                        ContentBlock::Text {
+                            text: "Looking around.".to_string(),
+                        },
+                        ContentBlock::ToolUse {
+                            id: "call_1".to_string(),
+                            name: "list_dir".to_string(),
+                            input: serde_json::json!({ "path": "." }),
+                        },
+                    ],
+                    StopReason::ToolUse,
+                ),
+                response(
+                    vec![ContentBlock::Text {
+                        text: "An Ubuntu build host running nginx.".to_string(),
+                    }],
+                    StopReason::EndTurn,
+                ),
+            ]),
+            Executor::default(),
+            AgentConfig {
+                init_report_path: Some(path.clone()),
+                ..Default::default()
+            },
+        )
+        .with_init_report(slot.clone());
+
+        assert!(slot.read().unwrap().is_none());
+        agent.run_init().await.unwrap();
+
+        let expected = "An Ubuntu build host running nginx.";
+        assert_eq!(slot.read().unwrap().as_deref(), Some(expected));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), expected);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_memory_free_during_inference() {
+        let gate = Arc::new(tokio::sync::Semaphore::new(0));
+        let mut brain = MockBrain::new(&["answer"]);
+        brain.gate = Some(gate.clone());
+        let agent = Arc::new(AgentLoop::new(
+            brain,
+            Executor::default(),
+            AgentConfig::default(),
+        ));
+
+        let handling = tokio::spawn({
+            let agent = agent.clone();
+            async move { agent.handle("question".to_string()).await }
+        });
+        while agent.brain.requests.lock().unwrap().is_empty() {
+            tokio::task::yield_now().await;
+        }
+
+        // Inference is in flight; memory must still be available to others
+        let memory = agent.memory.clone();
+        let write = tokio::task::spawn_blocking(move || {
+            memory.write(|mem| mem.add_observation("written mid-inference"))
+        });
+        tokio::time::timeout(Duration::from_secs(5), write)
+            .await
+            .expect("memory lock held across inference")
+            .unwrap();
+
+        gate.add_permits(1);
+        assert_eq!(handling.await.unwrap().unwrap(), "answer");
+        assert!(
+            agent
+                .memory
+                .read(|mem| mem.context())
+                .contains("written mid-inference")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_identical_concurrent_requests_share_one_run() {
+        let gate = Arc::new(tokio::sync::Semaphore::new(0));
+        let mut brain = MockBrain::new(&["disk is 40% used", "2G free"]);
+        brain.gate = Some(gate.clone());
+        let agent = AgentLoop::new(
+            brain,
+            Executor::default(),
+            AgentConfig {
+                coalesce_identical_requests: true,
+                ..Default::default()
+            },
+        );
+
+        let ask = async |content: &str| {
+            let (reply, rx) = tokio::sync::oneshot::channel();
+            agent
+                .handle_user_request(UserRequest {
+                    content: content.to_string(),
+                    reply,
+                    source_addr: "127.0.0.1:9".parse().unwrap(),
+                    kind: RequestKind::Input,
+                    request_id: None,
+                    progress: None,
+                })
+                .await;
+            rx.await.unwrap()
+        };
+        let release = async {
+            while agent.brain.requests.lock().unwrap().is_empty() {
+                tokio::task::yield_now().await;
+            }
+            // Let the second request find the first in flight
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+            gate.add_permits(1);
+        };
+
+        let (first, second, ()) = tokio::join!(ask("check disk"), ask("check disk"), release);
+        assert_eq!(first.content, "disk is 40% used");
+        assert_eq!(second.content, "disk is 40% used");
+        assert_eq!(agent.brain.requests.lock().unwrap().len(), 1);
+
+        // Once it finished, the same input runs again
+        gate.add_permits(1);
+        assert_eq!(ask("check disk").await.content, "2G free");
+        assert_eq!(agent.brain.requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_serve_coalesces_identical_queued_requests() {
+        let gate = Arc::new(tokio::sync::Semaphore::new(0));
+        let mut brain = MockBrain::new(&["disk is 40% used"]);
+        brain.gate = Some(gate.clone());
+        let config = AgentConfig {
+            max_concurrent_requests: 2,
+            coalesce_identical_requests: true,
+            ..Default::default()
+        };
+        config.validate().unwrap();
+        let agent = AgentLoop::new(brain, Executor::default(), config);
+
+        let (tx, mut requests) = mpsc::channel(8);
+        let mut replies = Vec::new();
+        for _ in 0..2 {
+            let (reply, rx) = tokio::sync::oneshot::channel();
+            tx.send(UserRequest {
+                content: "check disk".to_string(),
+                reply,
+                source_addr: "127.0.0.1:9".parse().unwrap(),
+                kind: RequestKind::Input,
+                request_id: None,
+                progress: None,
+            })
+            .await
+            .unwrap();
+            replies.push(rx);
+        }
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let clients = async {
+            while agent.brain.requests.lock().unwrap().is_empty() {
+                tokio::task::yield_now().await;
+            }
+            // Let the second request find the first in flight
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+            gate.add_permits(1);
+            let mut answers = Vec::new();
+            for rx in replies {
+                answers.push(rx.await.unwrap().content);
+            }
+            stop.send(()).unwrap();
+            answers
+        };
+        let serving = agent.serve(&mut requests, async {
+            let _ = stopped.await;
+        });
+
+        let ((), answers) = tokio::join!(serving, clients);
+        assert_eq!(answers, ["disk is 40% used", "disk is 40% used"]);
+        assert_eq!(agent.brain.requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_past_interactions_are_recalled_into_context() {
+        let answer = |text: &str| {
+            response(
+                vec![ContentBlock::Text {
+                    text: text.to_string(),
+                }],
+                StopReason::EndTurn,
+            )
+        };
+        let agent = AgentLoop::new(
+            MockBrain::with_responses(vec![answer("redis runs on 10.0.0.7"), answer("ok")]),
+            Executor::default(),
+            AgentConfig::default(),
+        );
+
+        let (reply, rx) = tokio::sync::oneshot::channel();
+        agent
+            .handle_user_request(UserRequest {
+                content: "where does redis run?".to_string(),
+                reply,
+                source_addr: "127.0.0.1:9".parse().unwrap(),
+                kind: RequestKind::Input,
+                request_id: None,
+                progress: None,
+            })
+            .await;
+        rx.await.unwrap();
+        // The interaction is stored in the background
+        while agent.memory.read(|mem| mem.entries().is_empty()) {
+            tokio::task::yield_now().await;
+        }
+
+        agent.handle("restart redis".to_string()).await.unwrap();
+        let requests = agent.brain.requests.lock().unwrap().clone();
+        let system = requests[1].system.as_deref().unwrap();
+        assert!(system.contains("## Relevant Memory"), "{}", system);
+        assert!(system.contains("A: redis runs on 10.0.0.7"), "{}", system);
+    }
+
+    #[tokio::test]
+    async fn test_paused_agent_refuses_requests() {
+        let paused = Arc::new(AtomicBool::new(false));
+        let agent = AgentLoop::new(
+            MockBrain::new(&["answer"]),
+            Executor::default(),
+            AgentConfig::default(),
+        )
+        .with_pause_flag(paused.clone());
+
+        let ask = async |agent: &AgentLoop<MockBrain>| {
+            let (reply, rx) = tokio::sync::oneshot::channel();
+            let req = UserRequest {
+                content: "question".to_string(),
+                reply,
+                source_addr: "127.0.0.1:9".parse().unwrap(),
+                request_id: None,
+                progress: None,
+                kind: RequestKind::Input,
+            };
+            agent.handle_user_request(req).await;
+            rx.await.unwrap()
+        };
+
+        paused.store(true, Ordering::SeqCst);
+        let response = ask(&agent).await;
+        assert!(response.is_error);
+        assert_eq!(response.content, "agent paused");
+        assert!(agent.brain.requests.lock().unwrap().is_empty());
+
+        paused.store(false, Ordering::SeqCst);
+        let response = ask(&agent).await;
+        assert!(!response.is_error);
+        assert_eq!(response.content, "answer");
+    }
+
+    /// Log sink shared with a test subscriber
+    #[derive(Clone, Default)]
+    struct LogBuffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for LogBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_response_carries_request_timing() {
+        let agent = AgentLoop::new(
+            MockBrain::with_responses(vec![
+                response(
+                    vec![ContentBlock::ToolUse {
+                        id: "call_1".to_string(),
+                        name: "bash".to_string(),
+                        input: serde_json::json!({ "command": "sleep 0.2" }),
+                    }],
+                    StopReason::ToolUse,
+                ),
+                response(
+                    vec![ContentBlock::Text {
+                        text: "done".to_string(),
+                    }],
+                    StopReason::EndTurn,
+                ),
+            ]),
+            Executor::default(),
+            AgentConfig::default(),
+        );
+
+        let (reply, rx) = tokio::sync::oneshot::channel();
+        agent
+            .handle_user_request(UserRequest {
+                content: "take a nap".to_string(),
+                reply,
+                source_addr: "127.0.0.1:9".parse().unwrap(),
+                kind: RequestKind::Input,
+                request_id: None,
+                progress: None,
+            })
+            .await;
+        let response = rx.await.unwrap();
+        assert_eq!(response.content, "done");
+
+        let timing = response.timing.expect("handled requests carry timing");
+        assert!(timing.tool_ms >= 200, "{:?}", timing);
+        assert!(
+            timing.inference_ms + timing.tool_ms <= timing.total_ms,
+            "{:?}",
+            timing
+        );
+        // Queue wait is Comm's to measure
+        assert_eq!(timing.queue_ms, 0);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_drains_request_in_flight() {
+        let sleeping = |secs: &str| {
+            MockBrain::with_responses(vec![
+                response(
+                    vec![ContentBlock::ToolUse {
+                        id: "call_1".to_string(),
+                        name: "bash".to_string(),
+                        input: serde_json::json!({ "command": format!("sleep {}", secs) }),
+                    }],
+                    StopReason::ToolUse,
+                ),
+                response(
+                    vec![ContentBlock::Text {
+                        text: "done".to_string(),
+                    }],
+                    StopReason::EndTurn,
+                ),
+            ])
+        };
+        let config = AgentConfig {
+            drain_timeout_secs: 1,
+            ..Default::default()
+        };
+        let ask = async |agent: &AgentLoop<MockBrain>| {
+            let (reply, rx) = tokio::sync::oneshot::channel();
+            let req = UserRequest {
+                content: "slow work".to_string(),
+                reply,
+                source_addr: "127.0.0.1:9".parse().unwrap(),
+                kind: RequestKind::Input,
+                request_id: None,
+                progress: None,
+            };
+            // Shutdown is requested as soon as handling starts
+            let shutting_down = agent.handle_draining(req, std::future::ready(())).await;
+            (shutting_down, rx.await.unwrap())
+        };
+
+        // Finishes within the drain window
+        let agent = AgentLoop::new(sleeping("0.2"), Executor::default(), config.clone());
+        let (shutting_down, response) = ask(&agent).await;
+        assert!(shutting_down);
+        assert_eq!(response.content, "done");
+        assert!(!response.is_error);
+
+        // Outlives it
+        let agent = AgentLoop::new(sleeping("10"), Executor::default(), config);
+        let started = std::time::Instant::now();
+        let (shutting_down, response) = ask(&agent).await;
+        assert!(shutting_down);
+        assert!(response.is_error);
+        assert_eq!(response.content, "server shutting down");
+        assert!(
+            started.elapsed() < Duration::from_secs(3),
+            "{:?}",
+            started.elapsed()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_logs_share_request_span() {
+        let agent = AgentLoop::new(
+            MockBrain::with_responses(vec![
+                response(
+                    vec![ContentBlock::ToolUse {
+                        id: "call_1".to_string(),
+                        name: "bash".to_string(),
+                        input: serde_json::json!({ "command": "echo traced" }),
+                    }],
+                    StopReason::ToolUse,
+                ),
+                response(
+                    vec![ContentBlock::Text {
+                        text: "done".to_string(),
+                    }],
+                    StopReason::EndTurn,
+                ),
+            ]),
+            Executor::default(),
+            AgentConfig::default(),
+        );
+
+        let logs = LogBuffer::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .without_time()
+            .with_writer({
+                let logs = logs.clone();
+                move || logs.clone()
+            })
+            .finish();
+        // Only what is logged while handling
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let (reply, rx) = tokio::sync::oneshot::channel();
+        agent
+            .handle_user_request(UserRequest {
+                content: "say something".to_string(),
+                reply,
+                source_addr: "127.0.0.1:9".parse().unwrap(),
+                kind: RequestKind::Input,
+                request_id: Some("req-42".to_string()),
+                progress: None,
+            })
+            .await;
+        assert_eq!(rx.await.unwrap().content, "done");
+
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = logs.lines().collect();
+        assert!(!lines.is_empty());
+        for line in &lines {
+            assert!(line.contains("request{request_id=req-42"), "{}", line);
+        }
+        // The executor's own span nests inside the request span
+        assert!(
+            lines
+                .iter()
+                .any(|line| line.contains("request_id=req-42")
+                    && line.contains("execute{tool=bash}")),
+            "{}",
+            logs
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replay_rehandles_journaled_query() {
+        let agent = AgentLoop::new(
+            MockBrain::new(&["disk is 45% used"]),
+            Executor::default(),
+            AgentConfig::default(),
+        );
+        agent.memory.write(|mem| {
+            mem.add_interaction("check disk", "disk is 40% used");
+            mem.add_interaction("check memory", "2G free");
+        });
+
+        let (reply, rx) = tokio::sync::oneshot::channel();
+        agent
+            .handle_user_request(UserRequest {
+                content: "replay interaction #0".to_string(),
+                reply,
+                source_addr: "127.0.0.1:9".parse().unwrap(),
+                request_id: None,
+                progress: None,
+                kind: RequestKind::Replay(ReplayTarget::Index(0)),
+            })
+            .await;
+        let response = rx.await.unwrap();
+        assert!(!response.is_error, "{}", response.content);
+        assert!(response.content.contains("disk is 40% used"));
+        assert!(response.content.contains("disk is 45% used"));
+
+        // One fresh inference, with the original query as input
+        let requests = agent.brain.requests.lock().unwrap().clone();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            requests[0].messages[0].content,
+            [ContentBlock::Text {
+                text: "check disk".to_string()
+            }]
+        );
+        // The replay itself is not journaled
+        assert_eq!(agent.memory.read(|mem| mem.interactions().len()), 2);
+
+        assert!(matches!(
+            agent
+                .replay(&ReplayTarget::Query("network".to_string()))
+                .await,
+            Err(AgentError::UnknownInteraction(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_chat_request_offers_no_tools() {
+        let agent = AgentLoop::new(
+            MockBrain::with_responses(vec![
+                // A model that asks for a tool anyway is answered with its text
+                response(
+                    vec![
+                        ContentBlock::Text {
+                            text: "The last hour was quiet.".to_string(),
+                        },
+                        ContentBlock::ToolUse {
+                            id: "call_1".to_string(),
+                            name: "bash".to_string(),
+                            input: serde_json::json!({ "command": "touch /tmp/shelly-chat" }),
+                        },
+                    ],
+                    StopReason::ToolUse,
+                ),
+                response(
+                    vec![ContentBlock::Text {
+                        text: "Still quiet.".to_string(),
+                    }],
+                    StopReason::EndTurn,
+                ),
+            ]),
+            Executor::default(),
+            AgentConfig::default(),
+        );
+
+        let (reply, rx) = tokio::sync::oneshot::channel();
+        agent
+            .handle_user_request(UserRequest {
+                content: "summarize the last hour".to_string(),
+                reply,
+                source_addr: "127.0.0.1:9".parse().unwrap(),
+                request_id: None,
+                progress: None,
+                kind: RequestKind::Chat,
+            })
+            .await;
+        let response = rx.await.unwrap();
+        assert!(!response.is_error, "{}", response.content);
+        assert_eq!(response.content, "The last hour was quiet.");
+        assert!(!std::path::Path::new("/tmp/shelly-chat").exists());
+
+        // chat_only turns plain input into chat as well
+        let agent = AgentLoop {
+            config: AgentConfig {
+                chat_only: true,
+                ..AgentConfig::default()
+            },
+            ..agent
+        };
+        let (reply, rx) = tokio::sync::oneshot::channel();
+        agent
+            .handle_user_request(UserRequest {
+                content: "anything new?".to_string(),
+                reply,
+                source_addr: "127.0.0.1:9".parse().unwrap(),
+                request_id: None,
+                progress: None,
+                kind: RequestKind::Input,
+            })
+            .await;
+        assert_eq!(rx.await.unwrap().content, "Still quiet.");
+
+        let requests = agent.brain.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|r| r.tools.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_wall_clock_limit_stops_slow_rounds() {
+        let round = |i: usize| {
+            response(
+                vec![
+                    ContentBlock::Text {
+                        text: format!("Round {} of checks.", i),
+                    },
+                    ContentBlock::ToolUse {
+                        id: format!("call_{}", i),
+                        name: "bash".to_string(),
+                        input: serde_json::json!({ "command": format!("sleep 0.4; echo {}", i) }),
+                    },
+                ],
+                StopReason::ToolUse,
+            )
+        };
+        let agent = AgentLoop::new(
+            MockBrain::with_responses((1..=10).map(round).collect()),
+            Executor::default(),
+            AgentConfig {
+                max_tool_rounds: 10,
+                max_handle_wall_secs: 1,
+                ..AgentConfig::default()
+            },
+        );
+
+        let (reply, rx) = tokio::sync::oneshot::channel();
+        let started = std::time::Instant::now();
+        agent
+            .handle_user_request(UserRequest {
+                content: "check everything".to_string(),
+                reply,
+                source_addr: "127.0.0.1:9".parse().unwrap(),
+                request_id: None,
+                progress: None,
+                kind: RequestKind::Input,
+            })
+            .await;
+        let response = rx.await.unwrap();
+
+        // Three 0.4s rounds pass the 1s mark; the fourth never starts
+        assert!(response.is_error);
+        assert_eq!(
+            response.content,
+            "Round 3 of checks.\n\n[stopped: wall clock limit of 1s reached]"
+        );
+        assert_eq!(agent.brain.requests.lock().unwrap().len(), 3);
+        assert!(
+            started.elapsed() < Duration::from_secs(3),
+            "{:?}",
+            started.elapsed()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tool_round_cap_keeps_last_text() {
+        let round = |id: &str, text: &str| {
+            let mut content = vec![ContentBlock::ToolUse {
+                id: id.to_string(),
+                name: "scratchpad".to_string(),
+                input: serde_json::json!({ "action": "list" }),
+            }];
+            if !text.is_empty() {
+                content.insert(
+                    0,
+                    ContentBlock::Text {
+                        text: text.to_string(),
+                    },
+                );
+            }
+            response(content, StopReason::ToolUse)
+        };
+        let agent = AgentLoop::new(
+            MockBrain::with_responses(vec![
+                round("call_1", "Checking /var first."),
+                round("call_2", "Found 3 large files in /var/log so far."),
+                round("call_3", ""),
+            ]),
+            Executor::default(),
+            AgentConfig {
+                max_tool_rounds: 3,
+                tool_rounds_notice: "[stopped: tool round limit]".to_string(),
+                ..AgentConfig::default()
+            },
+        );
+
+        let (reply, rx) = tokio::sync::oneshot::channel();
+        agent
+            .handle_user_request(UserRequest {
+                content: "find what fills the disk".to_string(),
+                reply,
+                source_addr: "127.0.0.1:9".parse().unwrap(),
+                request_id: None,
+                progress: None,
+                kind: RequestKind::Input,
+            })
+            .await;
+        let response = rx.await.unwrap();
+        assert!(response.is_error);
+        assert_eq!(
+            response.content,
+            "Found 3 large files in /var/log so far.\n\n[stopped: tool round limit]"
+        );
+        assert_eq!(agent.brain.requests.lock().unwrap().len(), 3);
+    }
+}
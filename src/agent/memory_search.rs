@@ -0,0 +1,108 @@
+// Journal lookup the agent uses through the `memory_search` tool
+
+use crate::brain::ToolDefinition;
+use crate::executor::{ExecutorError, ToolImpl, ToolOutput};
+use crate::memory::MemoryHandle;
+use crate::memory::types::{CategoryFilter, JournalCategory};
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+/// Records returned when the model asks for no particular number
+const DEFAULT_LIMIT: usize = 20;
+/// Most records one call returns
+const MAX_LIMIT: usize = 100;
+
+/// Memory search tool input parameters
+#[derive(Debug, Deserialize)]
+struct MemorySearchInput {
+    #[serde(default)]
+    category: Option<JournalCategory>,
+    #[serde(default)]
+    tool: Option<String>,
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+/// Tool returning the most recent journal entries of one category or tool
+pub struct MemorySearchTool {
+    memory: MemoryHandle,
+}
+
+impl MemorySearchTool {
+    pub fn new(memory: MemoryHandle) -> Self {
+        Self { memory }
+    }
+}
+
+#[async_trait]
+impl ToolImpl for MemorySearchTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "memory_search".to_string(),
+            description: format!(
+                "Look up your own journal: recent system info, user interactions, tool \
+                 results, observations or errors, oldest first. Filter by category, or by \
+                 tool to see only that tool's results. Returns at most {} entries.",
+                MAX_LIMIT
+            ),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "category": {
+                        "type": "string",
+                        "enum": ["system", "interaction", "tool", "observation", "error"],
+                        "description": "Kind of entry to return"
+                    },
+                    "tool": {
+                        "type": "string",
+                        "description": "Only results of this tool, e.g. bash"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": format!("Most recent entries to return (default {})", DEFAULT_LIMIT)
+                    }
+                }
+            }),
+        }
+    }
+
+    async fn run(&self, input: serde_json::Value) -> crate::executor::Result<ToolOutput> {
+        let input: MemorySearchInput = serde_json::from_value(input)
+            .map_err(|e| ExecutorError::InvalidInput("memory_search".to_string(), e.to_string()))?;
+        let filter = match (input.category, input.tool) {
+            (_, Some(tool)) => CategoryFilter::Tool(tool),
+            (Some(category), None) => CategoryFilter::Category(category),
+            (None, None) => {
+                return Err(ExecutorError::InvalidInput(
+                    "memory_search".to_string(),
+                    "one of category or tool is required".to_string(),
+                ));
+            }
+        };
+        let limit = input.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+        let lines: Vec<String> = self.memory.read(|m| {
+            let records = m.entries_by_category(filter);
+            let skip = records.len().saturating_sub(limit);
+            records
+                .into_iter()
+                .skip(skip)
+                .map(|r| r.to_string())
+                .collect()
+        });
+        if lines.is_empty() {
+            return Ok(ToolOutput::success("no matching journal entries"));
+        }
+        Ok(ToolOutput::success(lines.join("\n")))
+    }
+
+    fn cacheable(&self) -> bool {
+        // The journal grows between calls
+        false
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+}
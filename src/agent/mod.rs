@@ -1,13 +1,23 @@
 // Agent module - Core orchestration layer
 // See docs/mainloop-design.md for design details
 
+pub mod coalesce;
 pub mod config;
+pub mod dump;
 pub mod error;
+pub mod identity;
 pub mod inference;
+pub mod input;
 pub mod loop_;
+pub mod memory_search;
+pub mod runtime;
+pub mod scratchpad;
+pub mod timing;
 pub mod types;
 
+#[allow(unused_imports)]
 pub use error::InferenceError;
+#[allow(unused_imports)]
 pub use inference::{inference_loop, InferenceResult};
 pub use loop_::AgentLoop;
 pub use types::AgentConfig;
@@ -0,0 +1,150 @@
+// Runtime settings the agent can inspect and tune through the `config` tool
+
+use crate::brain::ToolDefinition;
+use crate::executor::{ExecutorError, ToolImpl, ToolOutput};
+
+use super::types::Bounds;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+use tracing::{info, warn};
+
+/// Knobs read at the start of every request; changes apply to the next one
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RuntimeSettings {
+    /// Sampling temperature (None = model default)
+    pub temperature: Option<f32>,
+    /// Maximum tool call rounds per handle
+    pub max_tool_rounds: u32,
+}
+
+/// Settings shared between the agent loop and the `config` tool
+pub type SharedSettings = Arc<RwLock<RuntimeSettings>>;
+
+/// Config tool input parameters
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+enum ConfigInput {
+    Get,
+    Set {
+        #[serde(default)]
+        temperature: Option<f32>,
+        #[serde(default)]
+        max_tool_rounds: Option<u32>,
+    },
+}
+
+/// Tool exposing `RuntimeSettings` to the agent
+pub struct ConfigTool {
+    settings: SharedSettings,
+    /// Whether `set` is allowed at all
+    writable: bool,
+    temperature_bounds: Bounds<f32>,
+    tool_rounds_bounds: Bounds<u32>,
+}
+
+impl ConfigTool {
+    pub fn new(
+        settings: SharedSettings,
+        writable: bool,
+        temperature_bounds: Bounds<f32>,
+        tool_rounds_bounds: Bounds<u32>,
+    ) -> Self {
+        Self {
+            settings,
+            writable,
+            temperature_bounds,
+            tool_rounds_bounds,
+        }
+    }
+
+    fn current(&self) -> ToolOutput {
+        let settings = self.settings.read().unwrap().clone();
+        ToolOutput::success(
+            serde_json::json!({
+                "temperature": settings.temperature,
+                "max_tool_rounds": settings.max_tool_rounds,
+                "writable": self.writable,
+                "bounds": {
+                    "temperature": [self.temperature_bounds.min, self.temperature_bounds.max],
+                    "max_tool_rounds": [self.tool_rounds_bounds.min, self.tool_rounds_bounds.max],
+                },
+            })
+            .to_string(),
+        )
+    }
+}
+
+#[async_trait]
+impl ToolImpl for ConfigTool {
+    fn definition(&self) -> ToolDefinition {
+        let description = if self.writable {
+            "Read or adjust your own runtime settings (temperature, max_tool_rounds). \
+             Changes apply from the next request and are clamped to operator-defined bounds."
+        } else {
+            "Read your own runtime settings (temperature, max_tool_rounds). \
+             Writes are disabled by the operator."
+        };
+
+        ToolDefinition {
+            name: "config".to_string(),
+            description: description.to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "action": {
+                        "type": "string",
+                        "enum": ["get", "set"],
+                        "description": "get returns the current settings; set changes them"
+                    },
+                    "temperature": {
+                        "type": "number",
+                        "description": "New sampling temperature (set only)"
+                    },
+                    "max_tool_rounds": {
+                        "type": "integer",
+                        "description": "New tool round limit per request (set only)"
+                    }
+                },
+                "required": ["action"]
+            }),
+        }
+    }
+
+    async fn run(&self, input: serde_json::Value) -> crate::executor::Result<ToolOutput> {
+        let input: ConfigInput = serde_json::from_value(input)
+            .map_err(|e| ExecutorError::InvalidInput("config".to_string(), e.to_string()))?;
+
+        let (temperature, max_tool_rounds) = match input {
+            ConfigInput::Get => return Ok(self.current()),
+            ConfigInput::Set {
+                temperature,
+                max_tool_rounds,
+            } => (temperature, max_tool_rounds),
+        };
+
+        if !self.writable {
+            warn!("config write rejected: writes are disabled");
+            return Ok(ToolOutput::error(
+                "config writes are disabled (set AGENT_ALLOW_CONFIG_WRITES=true to enable)",
+            ));
+        }
+
+        {
+            let mut settings = self.settings.write().unwrap();
+            if let Some(requested) = temperature {
+                let applied = self.temperature_bounds.clamp(requested);
+                info!(requested, applied, "agent changed temperature");
+                settings.temperature = Some(applied);
+            }
+            if let Some(requested) = max_tool_rounds {
+                let applied = self.tool_rounds_bounds.clamp(requested);
+                info!(requested, applied, "agent changed max_tool_rounds");
+                settings.max_tool_rounds = applied;
+            }
+        }
+
+        Ok(self.current())
+    }
+}
@@ -0,0 +1,136 @@
+// Per-request key/value scratchpad the agent uses through the `scratchpad` tool
+
+use crate::brain::ToolDefinition;
+use crate::executor::{ExecutorError, ToolImpl, ToolOutput};
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::future::Future;
+
+/// Keys one request may hold at once
+pub const MAX_ENTRIES: usize = 64;
+/// Longest key, in bytes
+pub const MAX_KEY_BYTES: usize = 128;
+/// Longest value, in bytes
+pub const MAX_VALUE_BYTES: usize = 4096;
+
+tokio::task_local! {
+    /// Entries of the request running on this task
+    static SCRATCHPAD: RefCell<BTreeMap<String, String>>;
+}
+
+/// Run `f` with an empty scratchpad, dropped when it completes
+///
+/// A task-local rather than a field of the tool, so concurrent requests
+/// never see each other's entries.
+pub async fn scoped<F: Future>(f: F) -> F::Output {
+    SCRATCHPAD.scope(RefCell::new(BTreeMap::new()), f).await
+}
+
+/// Scratchpad tool input parameters
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+enum ScratchpadInput {
+    Get { key: String },
+    Set { key: String, value: String },
+    List,
+    Delete { key: String },
+}
+
+/// Tool giving the model key/value state that lives for one request
+pub struct ScratchpadTool;
+
+impl ScratchpadTool {
+    fn apply(pad: &mut BTreeMap<String, String>, input: ScratchpadInput) -> ToolOutput {
+        match input {
+            ScratchpadInput::Get { key } => match pad.get(&key) {
+                Some(value) => ToolOutput::success(value.clone()),
+                None => ToolOutput::error(format!("no scratchpad entry named {:?}", key)),
+            },
+            ScratchpadInput::Set { key, value } => {
+                if key.is_empty() || key.len() > MAX_KEY_BYTES {
+                    return ToolOutput::error(format!("key must be 1 to {} bytes", MAX_KEY_BYTES));
+                }
+                if value.len() > MAX_VALUE_BYTES {
+                    return ToolOutput::error(format!(
+                        "value is {} bytes, max {} bytes",
+                        value.len(),
+                        MAX_VALUE_BYTES
+                    ));
+                }
+                if pad.len() >= MAX_ENTRIES && !pad.contains_key(&key) {
+                    return ToolOutput::error(format!(
+                        "scratchpad is full ({} entries), delete one first",
+                        MAX_ENTRIES
+                    ));
+                }
+                pad.insert(key, value);
+                ToolOutput::success("ok")
+            }
+            ScratchpadInput::List => {
+                ToolOutput::success(serde_json::to_string(pad).unwrap_or_else(|_| "{}".to_string()))
+            }
+            ScratchpadInput::Delete { key } => match pad.remove(&key) {
+                Some(_) => ToolOutput::success("deleted"),
+                None => ToolOutput::error(format!("no scratchpad entry named {:?}", key)),
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl ToolImpl for ScratchpadTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "scratchpad".to_string(),
+            description: format!(
+                "Key/value notes kept for the rest of this request only, e.g. the PID of a \
+                 process you just started. Cleared when the request ends; use files for \
+                 anything that must last longer. Holds up to {} entries of at most {} bytes.",
+                MAX_ENTRIES, MAX_VALUE_BYTES
+            ),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "action": {
+                        "type": "string",
+                        "enum": ["get", "set", "list", "delete"],
+                        "description": "get, set or delete one key; list returns every entry"
+                    },
+                    "key": {
+                        "type": "string",
+                        "description": "Entry name (get, set, delete)"
+                    },
+                    "value": {
+                        "type": "string",
+                        "description": "Entry value (set only)"
+                    }
+                },
+                "required": ["action"]
+            }),
+        }
+    }
+
+    async fn run(&self, input: serde_json::Value) -> crate::executor::Result<ToolOutput> {
+        let input: ScratchpadInput = serde_json::from_value(input)
+            .map_err(|e| ExecutorError::InvalidInput("scratchpad".to_string(), e.to_string()))?;
+
+        Ok(SCRATCHPAD
+            .try_with(|pad| Self::apply(&mut pad.borrow_mut(), input))
+            .unwrap_or_else(|_| {
+                ToolOutput::error("the scratchpad is only available while handling a request")
+            }))
+    }
+
+    fn cacheable(&self) -> bool {
+        // A get must see the latest set, not an earlier identical get
+        false
+    }
+
+    fn read_only(&self) -> bool {
+        // Only touches this request's own notes, never the system
+        true
+    }
+}
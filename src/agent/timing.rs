@@ -0,0 +1,75 @@
+// Where the time of one request goes: model inference versus tool execution
+
+use crate::comm::types::ResponseTiming;
+
+use std::cell::Cell;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// Time spent handling one request, split by kind of work
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timing {
+    /// Waiting on the model, summed over every inference of the request
+    pub inference: Duration,
+    /// Running tools, summed over every executed call
+    pub tools: Duration,
+    /// Whole handling, from start to reply; covers the two above and the
+    /// bookkeeping between them
+    pub total: Duration,
+}
+
+impl From<Timing> for ResponseTiming {
+    /// Comm adds the queue wait, which happens before handling starts
+    fn from(timing: Timing) -> Self {
+        let ms = |d: Duration| u64::try_from(d.as_millis()).unwrap_or(u64::MAX);
+        Self {
+            queue_ms: 0,
+            inference_ms: ms(timing.inference),
+            tool_ms: ms(timing.tools),
+            total_ms: ms(timing.total),
+        }
+    }
+}
+
+tokio::task_local! {
+    /// Inference and tool time of the request running on this task
+    static SPENT: (Cell<Duration>, Cell<Duration>);
+}
+
+/// Run `f`, returning its output with the time it spent on inference and tools
+///
+/// A task-local rather than a value threaded through every handler, so the
+/// inference and tool call sites add to it without the paths between them
+/// (chat, plans, replays, coalescing) having to carry it along.
+pub async fn measure<F: Future>(f: F) -> (F::Output, Timing) {
+    let started = Instant::now();
+    // Handling futures are large; keep them off the caller's stack
+    let f = Box::pin(f);
+    SPENT
+        .scope((Cell::default(), Cell::default()), async {
+            let output = f.await;
+            let timing = SPENT.with(|(inference, tools)| Timing {
+                inference: inference.get(),
+                tools: tools.get(),
+                total: started.elapsed(),
+            });
+            (output, timing)
+        })
+        .await
+}
+
+/// Await `f`, counting its duration as inference; untimed outside `measure`
+pub async fn inference<F: Future>(f: F) -> F::Output {
+    let started = Instant::now();
+    let output = f.await;
+    let _ = SPENT.try_with(|(inference, _)| inference.set(inference.get() + started.elapsed()));
+    output
+}
+
+/// Await `f`, counting its duration as tool execution; untimed outside `measure`
+pub async fn tools<F: Future>(f: F) -> F::Output {
+    let started = Instant::now();
+    let output = f.await;
+    let _ = SPENT.try_with(|(_, tools)| tools.set(tools.get() + started.elapsed()));
+    output
+}
@@ -1,6 +1,11 @@
 // Agent types
 
+use crate::brain::Message;
+use crate::brain::types::Usage;
 use serde_json::Value;
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::time::Instant;
 
 /// Internal tool call representation
 pub struct ToolCall {
@@ -9,35 +14,389 @@ pub struct ToolCall {
     pub input: Value,
 }
 
+/// Results of the tool calls already run while handling one request, keyed
+/// by tool name and input hash, so an identical repeat is not executed twice
+#[derive(Debug, Default)]
+pub struct ExecutionRecord {
+    /// Rendered result text and is_error per call
+    results: HashMap<(String, u64), (String, bool)>,
+}
+
+impl ExecutionRecord {
+    fn key(call: &ToolCall) -> (String, u64) {
+        // serde_json objects are sorted, so key order does not matter
+        let mut hasher = DefaultHasher::new();
+        call.input.to_string().hash(&mut hasher);
+        (call.name.clone(), hasher.finish())
+    }
+
+    /// Result of an earlier identical call, if any
+    pub fn get(&self, call: &ToolCall) -> Option<&(String, bool)> {
+        self.results.get(&Self::key(call))
+    }
+
+    pub fn insert(&mut self, call: &ToolCall, text: String, is_error: bool) {
+        self.results.insert(Self::key(call), (text, is_error));
+    }
+}
+
+/// Tokens the backend reported across the inferences made for one request
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TokenSpend {
+    /// Prompt tokens, including cache writes and reads
+    pub input: u64,
+    pub output: u64,
+}
+
+impl TokenSpend {
+    /// Add one response's usage; responses without usage count as zero
+    pub fn add(&mut self, usage: Option<&Usage>) {
+        let Some(usage) = usage else {
+            return;
+        };
+        self.input += u64::from(usage.input_tokens)
+            + u64::from(usage.cache_creation_input_tokens.unwrap_or(0))
+            + u64::from(usage.cache_read_input_tokens.unwrap_or(0));
+        self.output += u64::from(usage.output_tokens);
+    }
+}
+
+/// Tool calls proposed in plan mode, held until an operator approves them
+pub struct PendingPlan {
+    /// System prompt the plan was made under
+    pub system: String,
+    /// Conversation so far, ending with the assistant turn that asked for
+    /// `tool_calls`
+    pub messages: Vec<Message>,
+    /// The proposed calls, run on approval
+    pub tool_calls: Vec<ToolCall>,
+    /// When the plan was proposed, for evicting the oldest
+    pub created: Instant,
+}
+
+/// What to do with user input that exceeds `max_input_tokens`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OversizedInputPolicy {
+    /// Refuse the request with a clear error
+    #[default]
+    Reject,
+    /// Summarize the input chunk by chunk, then process the summaries
+    Chunk,
+}
+
+impl std::str::FromStr for OversizedInputPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "reject" => Ok(Self::Reject),
+            "chunk" => Ok(Self::Chunk),
+            other => Err(format!("unknown oversized input policy: {}", other)),
+        }
+    }
+}
+
+/// What to do with a reply the backend stopped at one of the request's stop
+/// sequences
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StopSequencePolicy {
+    /// Return the text as the backend sent it
+    #[default]
+    ReturnAsIs,
+    /// Return the text without the matched sequence at its end
+    StripSequence,
+    /// Have the model continue past the sequence; each continuation counts
+    /// as a tool round
+    Continue,
+}
+
+impl std::str::FromStr for StopSequencePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "return" => Ok(Self::ReturnAsIs),
+            "strip" => Ok(Self::StripSequence),
+            "continue" => Ok(Self::Continue),
+            other => Err(format!("unknown stop sequence policy: {}", other)),
+        }
+    }
+}
+
+/// Behavior profile: a system prompt preamble plus defaults for how freely
+/// the agent acts, so operators switch stance without rewriting the prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Profile {
+    /// Observe first; every tool call is proposed as a plan for approval
+    Conservative,
+    /// Act when needed, confirm destructive operations in the reply
+    #[default]
+    Balanced,
+    /// Act without waiting; may tune its own settings
+    Autonomous,
+}
+
+impl Profile {
+    /// Text placed before the system prompt
+    pub fn preamble(self) -> &'static str {
+        match self {
+            Self::Conservative => {
+                "# Profile: Conservative\nObserve before you act. Prefer read-only commands. \
+                 Never change, restart or delete anything unless the operator explicitly asked \
+                 for it, and explain the risk of every change you propose."
+            }
+            Self::Balanced => {
+                "# Profile: Balanced\nInvestigate first, then act when the task calls for it. \
+                 Before anything destructive or hard to undo, describe it and ask for \
+                 confirmation instead of running it."
+            }
+            Self::Autonomous => {
+                "# Profile: Autonomous\nAct on your own judgement to complete the task \
+                 without waiting for confirmation. Report what you changed and why."
+            }
+        }
+    }
+
+    /// Set the defaults this profile implies on `config`
+    fn apply(self, config: &mut AgentConfig) {
+        match self {
+            Self::Conservative => {
+                config.plan_mode = true;
+                config.allow_config_writes = false;
+                config.max_tool_calls_per_round = 3;
+            }
+            Self::Balanced => {
+                let defaults = AgentConfig::default();
+                config.plan_mode = defaults.plan_mode;
+                config.allow_config_writes = defaults.allow_config_writes;
+                config.max_tool_calls_per_round = defaults.max_tool_calls_per_round;
+            }
+            Self::Autonomous => {
+                config.plan_mode = false;
+                config.allow_config_writes = true;
+                config.max_tool_calls_per_round = AgentConfig::default().max_tool_calls_per_round;
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Profile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "conservative" => Ok(Self::Conservative),
+            "balanced" => Ok(Self::Balanced),
+            "autonomous" => Ok(Self::Autonomous),
+            other => Err(format!("unknown profile: {}", other)),
+        }
+    }
+}
+
+/// Inclusive range an operator allows a runtime setting to take,
+/// written `min..max` in the environment
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds<T> {
+    pub min: T,
+    pub max: T,
+}
+
+impl<T: PartialOrd + Copy> Bounds<T> {
+    pub fn new(min: T, max: T) -> Self {
+        Self { min, max }
+    }
+
+    /// Clamp `value` into the range
+    pub fn clamp(&self, value: T) -> T {
+        if value < self.min {
+            self.min
+        } else if value > self.max {
+            self.max
+        } else {
+            value
+        }
+    }
+}
+
+impl<T: std::str::FromStr + PartialOrd + Copy> std::str::FromStr for Bounds<T> {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (min, max) = s
+            .split_once("..")
+            .ok_or_else(|| format!("expected min..max, got {}", s))?;
+        let parse = |v: &str| {
+            v.trim()
+                .parse::<T>()
+                .map_err(|_| format!("invalid bound: {}", v))
+        };
+        let (min, max) = (parse(min)?, parse(max)?);
+        if min > max {
+            return Err(format!("min exceeds max in {}", s));
+        }
+        Ok(Self { min, max })
+    }
+}
+
 /// Agent loop configuration
 #[derive(Debug, Clone)]
 pub struct AgentConfig {
     /// Maximum tool call rounds per handle
     pub max_tool_rounds: u32,
+    /// Appended to the model's last text when a request runs out of tool
+    /// rounds
+    pub tool_rounds_notice: String,
+    /// Error returned when a request ends with no text to show; `{stop_reason}`
+    /// is replaced with the response's stop reason
+    pub no_output_message: String,
+    /// Tool calls executed from a single response; the rest are answered
+    /// with an error asking the model to batch fewer
+    pub max_tool_calls_per_round: usize,
+    /// Time all tool calls of one response may take together; calls still
+    /// running or not yet started when it runs out are reported as timed out
+    pub tool_round_timeout_secs: u64,
+    /// Leading rounds of each request that only offer and run read-only
+    /// tools, so the agent looks before it acts (0 = off)
+    pub readonly_first_rounds: u32,
+    /// Maximum tool call rounds during the startup exploration
+    pub max_init_tool_rounds: u32,
     /// Initialization timeout
     pub init_timeout_secs: u64,
-    /// Shutdown timeout
+    /// How long the first init inference is retried while the backend is
+    /// unavailable, e.g. still starting (0 = fail at once)
+    pub init_backend_wait_secs: u64,
+    /// How long the agent's shutdown cleanup prompt may run
     pub shutdown_timeout_secs: u64,
+    /// How long a request in flight when shutdown begins may keep running
+    /// before it is answered "server shutting down"
+    pub drain_timeout_secs: u64,
+    /// User requests handled at once; more wait in the queue
+    pub max_concurrent_requests: usize,
     /// Handle timeout
     pub handle_timeout_secs: u64,
+    /// Wall-clock time after which a request starts no further inference
+    /// round and returns what it has so far, however the per-round and
+    /// per-tool timeouts added up (0 = off)
+    pub max_handle_wall_secs: u64,
+    /// Behavior profile; its preamble precedes `system_prompt`
+    pub profile: Profile,
     /// System prompt
     pub system_prompt: String,
-    /// Agent identity
+    /// Agent identity; `from_env` appends the detected host unless
+    /// `AGENT_IDENTITY` sets it explicitly
     pub identity: String,
     /// Initialization prompt
     pub init_prompt: String,
+    /// File the init report is written to after startup (None = memory only)
+    pub init_report_path: Option<std::path::PathBuf>,
+    /// Estimated token limit for a single user input
+    pub max_input_tokens: usize,
+    /// Handling of input over `max_input_tokens`
+    pub oversized_input: OversizedInputPolicy,
+    /// Content bytes any one message of a request may carry; a request
+    /// over it fails to build instead of reaching the backend
+    pub max_message_bytes: usize,
+    /// Directory SIGUSR1 writes timestamped memory dumps to
+    pub dump_dir: std::path::PathBuf,
+    /// Write-ahead log the journal is appended to and restored from at
+    /// startup (None = journal kept in memory only)
+    pub journal_wal_path: Option<std::path::PathBuf>,
+    /// fsync the journal WAL after every record instead of only flushing
+    pub journal_wal_fsync: bool,
+    /// Text the assistant's reply is prefilled with (e.g. "{" to force JSON)
+    pub response_prefill: Option<String>,
+    /// Handling of replies stopped by a stop sequence
+    pub stop_sequence: StopSequencePolicy,
+    /// Let the agent change its own settings through the `config` tool
+    pub allow_config_writes: bool,
+    /// Range the agent may set temperature within
+    pub temperature_bounds: Bounds<f32>,
+    /// Range the agent may set max_tool_rounds within
+    pub tool_rounds_bounds: Bounds<u32>,
+    /// Tools exposed to the model (empty = every registered tool)
+    pub enabled_tools: Vec<String>,
+    /// Tools advertised during init, within `enabled_tools` (empty = all)
+    pub init_tools: Vec<String>,
+    /// Tools advertised while handling requests, within `enabled_tools`
+    /// (empty = all)
+    pub handle_tools: Vec<String>,
+    /// Return tool calls as a plan for approval instead of running them
+    pub plan_mode: bool,
+    /// Answer every request without offering tools, as if each asked for
+    /// `no_tools`
+    pub chat_only: bool,
+    /// Input tokens all inferences for one request may use (0 = unlimited)
+    pub max_total_input_tokens: u64,
+    /// Output tokens all inferences for one request may use (0 = unlimited)
+    pub max_total_output_tokens: u64,
+    /// Answer user input identical to a request still being handled with
+    /// that request's result instead of a second run; needs
+    /// `max_concurrent_requests` above 1
+    pub coalesce_identical_requests: bool,
+    /// Have the model summarize the middle of tool outputs larger than
+    /// `tool_output_summary_bytes`; costs one inference per such output
+    pub summarize_tool_outputs: bool,
+    /// Tool output size above which it is summarized
+    pub tool_output_summary_bytes: usize,
+}
+
+impl AgentConfig {
+    /// Switch to `profile` and take on its default flags
+    pub fn with_profile(mut self, profile: Profile) -> Self {
+        self.profile = profile;
+        profile.apply(&mut self);
+        self
+    }
+
+    /// The profile preamble followed by the configured system prompt
+    pub fn full_system_prompt(&self) -> String {
+        format!("{}\n\n{}", self.profile.preamble(), self.system_prompt)
+    }
 }
 
 impl Default for AgentConfig {
     fn default() -> Self {
         Self {
             max_tool_rounds: 20,
+            tool_rounds_notice: "Maximum tool call rounds reached. Operation aborted.".to_string(),
+            no_output_message: "The model produced no usable output (stop reason: {stop_reason}).".to_string(),
+            max_tool_calls_per_round: 10,
+            tool_round_timeout_secs: 120,
+            readonly_first_rounds: 0,
+            max_init_tool_rounds: 10,
             init_timeout_secs: 120,
+            init_backend_wait_secs: 60,
             shutdown_timeout_secs: 30,
+            drain_timeout_secs: 30,
+            max_concurrent_requests: 1,
             handle_timeout_secs: 300,
+            max_handle_wall_secs: 0,
+            profile: Profile::default(),
             system_prompt: r#"You are Shelly, a system-level daemon process running on this machine with root privileges. You have no permission boundary with the operating system. If the kernel exposes it, you can do it. There is nothing on this machine that is off-limits to you. You process one task at a time. When you receive input, you think, you act if needed, and you respond."#.to_string(),
             identity: "Shelly".to_string(),
             init_prompt: r#"You just started. You know nothing about this machine. Explore your environment and report what you find."#.to_string(),
+            init_report_path: None,
+            max_input_tokens: 100_000,
+            oversized_input: OversizedInputPolicy::default(),
+            max_message_bytes: crate::brain::builder::DEFAULT_MAX_MESSAGE_BYTES,
+            dump_dir: crate::memory::config::MemoryConfig::default().storage_dir,
+            journal_wal_path: None,
+            journal_wal_fsync: false,
+            response_prefill: None,
+            stop_sequence: StopSequencePolicy::default(),
+            allow_config_writes: false,
+            temperature_bounds: Bounds::new(0.0, 1.0),
+            tool_rounds_bounds: Bounds::new(1, 50),
+            enabled_tools: Vec::new(),
+            init_tools: Vec::new(),
+            handle_tools: Vec::new(),
+            plan_mode: false,
+            chat_only: false,
+            max_total_input_tokens: 0,
+            max_total_output_tokens: 0,
+            coalesce_identical_requests: false,
+            summarize_tool_outputs: false,
+            tool_output_summary_bytes: 32 * 1024,
         }
     }
 }
@@ -3,40 +3,64 @@
 //! A command-line client that communicates with the Shelly daemon via UDP.
 //! Uses rustyline for readline-style editing and history.
 
-use clap::Parser;
+#[path = "../comm/mod.rs"]
+#[allow(dead_code, unused_imports)]
+mod comm;
+
+use clap::{Parser, Subcommand};
+use comm::protocol::{
+    Framing, Header, PROTOCOL_VERSION, decode_header, encode_packet, sign_packet, take_cached,
+};
+use comm::types::{
+    ApprovePayload, ControlPayload, HelloResponse, MsgType, ProgressPayload, ReplayPayload,
+    RequestPayload,
+};
 use rmp_serde::decode::Deserializer;
-use rmp_serde::encode::Serializer;
 use rustyline::Editor;
 use rustyline::history::FileHistory;
 use serde::{Deserialize, Serialize};
 use std::io::{self, Write};
 use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::process;
 use std::sync::atomic::{AtomicU32, Ordering};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::net::UdpSocket;
-use tokio::time::timeout;
-
-/// Message types
-#[derive(Debug, Clone, Copy)]
-#[repr(u8)]
-enum MsgType {
-    Request = 0x01,
-    RequestAck = 0x02,
-    Response = 0x03,
-}
+use tokio::time::{timeout, timeout_at};
 
-/// Request payload
-#[derive(Debug, Serialize)]
-struct RequestPayload {
-    content: String,
-}
+/// Request `doctor` sends; cheap to answer and needs no tools
+const DOCTOR_PROMPT: &str = "Reply with the single word: ok";
 
 /// Response payload
 #[derive(Debug, Deserialize)]
 struct ResponsePayload {
     content: String,
     is_error: bool,
+    /// Sent only when the daemon has COMM_RESPONSE_TIMING on
+    #[serde(default)]
+    timing: Option<ResponseTiming>,
+    /// Replayed from the daemon's dedup cache (from the header, not the payload)
+    #[serde(skip)]
+    cached: bool,
+}
+
+/// Where a request's time went, in milliseconds
+#[derive(Debug, Deserialize)]
+struct ResponseTiming {
+    queue_ms: u64,
+    inference_ms: u64,
+    tool_ms: u64,
+    total_ms: u64,
+}
+
+impl std::fmt::Display for ResponseTiming {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[timing] queue {}ms, inference {}ms, tools {}ms, total {}ms",
+            self.queue_ms, self.inference_ms, self.tool_ms, self.total_ms
+        )
+    }
 }
 
 /// CLI arguments
@@ -52,6 +76,10 @@ struct Args {
     #[arg(long, default_value = "5")]
     timeout: u64,
 
+    /// RESPONSE timeout in seconds; keep above the daemon's response timeout
+    #[arg(long, default_value = "360")]
+    response_timeout: u64,
+
     /// Maximum retry attempts
     #[arg(short, long, default_value = "3")]
     max_retries: u32,
@@ -63,6 +91,74 @@ struct Args {
     /// Maximum history entries (reserved for future use)
     #[arg(long, default_value = "1000")]
     _history_size: usize,
+
+    /// Token for control commands (defaults to $COMM_CONTROL_TOKEN)
+    #[arg(long)]
+    control_token: Option<String>,
+
+    /// Shared secret for signing packets (defaults to $COMM_AUTH_SECRET)
+    #[arg(long)]
+    secret: Option<String>,
+
+    /// Note responses the daemon replayed from its dedup cache with [cached],
+    /// and show per-request timings when the daemon sends them
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Ask for plain chat answers: the model is offered no tools
+    #[arg(long)]
+    no_tools: bool,
+
+    /// Print output of running tools while waiting for the answer, when the
+    /// daemon has progress frames enabled
+    #[arg(long)]
+    progress: bool,
+
+    #[command(subcommand)]
+    command: Option<ControlCommand>,
+}
+
+/// One-shot operator commands; without one the CLI starts interactively
+#[derive(Debug, Clone, Subcommand)]
+enum ControlCommand {
+    /// Stop the agent acting; requests are answered "agent paused"
+    Pause,
+    /// Resume normal request handling
+    Resume,
+    /// Print how many deduplicated requests the daemon remembers per client
+    DedupStats,
+    /// Forget every remembered request; retransmits are handled anew
+    DedupClear,
+    /// Print what the agent reported about this machine at startup
+    InitReport,
+    /// Execute a plan the agent proposed in plan mode and print the outcome
+    Approve {
+        /// Plan id from the agent's reply
+        plan_id: String,
+    },
+    /// Re-run a past query with the current config and compare the answers
+    Replay {
+        /// Interaction index (0 = oldest), or text the query contains
+        target: String,
+    },
+    /// Check the network path, the daemon and its backend, and say which
+    /// one a failure comes from
+    Doctor,
+}
+
+impl ControlCommand {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ControlCommand::Pause => "pause",
+            ControlCommand::Resume => "resume",
+            ControlCommand::DedupStats => "dedup_stats",
+            ControlCommand::DedupClear => "dedup_clear",
+            ControlCommand::InitReport => "init-report",
+            ControlCommand::Approve { .. } => "approve",
+            ControlCommand::Replay { .. } => "replay",
+            ControlCommand::Doctor => "doctor",
+        }
+    }
 }
 
 /// CLI configuration
@@ -70,10 +166,17 @@ struct Args {
 struct Config {
     target: SocketAddr,
     ack_timeout_secs: u64,
+    response_timeout_secs: u64,
     max_retries: u32,
     history_file: PathBuf,
     #[allow(dead_code)]
     history_size: usize,
+    /// Packets are signed with this when set
+    secret: Option<String>,
+    verbose: bool,
+    no_tools: bool,
+    /// Ask the daemon to stream tool output as PROGRESS frames
+    progress: bool,
 }
 
 impl Config {
@@ -87,18 +190,109 @@ impl Config {
         Self {
             target: args.target,
             ack_timeout_secs: args.timeout,
+            response_timeout_secs: args.response_timeout,
             max_retries: args.max_retries,
             history_file,
             history_size: args._history_size,
+            secret: args
+                .secret
+                .or_else(|| std::env::var("COMM_AUTH_SECRET").ok())
+                .filter(|s| !s.is_empty()),
+            verbose: args.verbose,
+            no_tools: args.no_tools,
+            progress: args.progress,
+        }
+    }
+}
+
+/// Where a `doctor` run got to
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Diagnosis {
+    /// The request was never ACKed: network, address, firewall or secret
+    Unreachable,
+    /// ACKed but never answered: the daemon or its backend is stuck or slow
+    NoResponse,
+    /// Answered with an error, usually from the inference backend
+    ErrorResponse(String),
+    Healthy,
+}
+
+/// Timings and outcome of each `doctor` check
+#[derive(Debug)]
+struct DoctorReport {
+    /// HELLO round trip and the daemon's protocol version, when answered
+    hello: Option<(Duration, u32)>,
+    /// Time from sending the request to its ACK
+    ack: Option<Duration>,
+    /// Time from sending the request to its RESPONSE
+    response: Option<Duration>,
+    diagnosis: Diagnosis,
+}
+
+impl std::fmt::Display for DoctorReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let step = |f: &mut std::fmt::Formatter<'_>, name: &str, took: Option<Duration>| match took
+        {
+            Some(took) => writeln!(f, "{:<10} ok      {} ms", name, took.as_millis()),
+            None => writeln!(f, "{:<10} FAILED", name),
+        };
+        match self.hello {
+            Some((took, version)) => writeln!(
+                f,
+                "{:<10} ok      {} ms (protocol v{})",
+                "hello",
+                took.as_millis(),
+                version
+            )?,
+            None => writeln!(f, "{:<10} FAILED  (no HELLO answer, continuing)", "hello")?,
         }
+        step(f, "ack", self.ack)?;
+        if self.ack.is_some() {
+            step(f, "response", self.response)?;
+        }
+
+        let diagnosis = match &self.diagnosis {
+            Diagnosis::Unreachable => "cannot reach shelly: the request was never acknowledged. \
+                Check that the daemon is running, the --target address and port, firewalls, \
+                and that --secret matches COMM_AUTH_SECRET (badly signed packets are dropped \
+                silently)."
+                .to_string(),
+            Diagnosis::NoResponse => "shelly received the request but did not answer in time: \
+                the network is fine, the daemon or its inference backend is stuck or slow. \
+                Check the daemon logs."
+                .to_string(),
+            Diagnosis::ErrorResponse(error) => format!(
+                "shelly is reachable but answered with an error, usually from the inference \
+                 backend: {}",
+                error
+            ),
+            Diagnosis::Healthy => "all checks passed".to_string(),
+        };
+        write!(f, "diagnosis: {}", diagnosis)
     }
 }
 
+/// What arrived while waiting for a REQUEST_ACK
+enum Ack {
+    /// The REQUEST_ACK for this seq
+    Received,
+    /// A RESPONSE for this seq sent instead of an ACK: the daemon refused
+    /// the packet (too large, too many in flight) or replayed a cached answer
+    Answered(ResponsePayload),
+    /// Nothing for this seq before the timeout
+    Missing,
+}
+
 /// Main client state
 struct Client {
     socket: UdpSocket,
     config: Config,
     seq: AtomicU32,
+    /// Largest payload the daemon accepts, learned from HELLO
+    max_payload_bytes: Option<usize>,
+    /// Daemon protocol version and features from HELLO; None until a
+    /// handshake succeeds, in which case every feature is assumed
+    daemon: Option<(u32, Vec<String>)>,
 }
 
 impl Client {
@@ -110,36 +304,294 @@ impl Client {
             socket,
             config,
             seq: AtomicU32::new(1),
+            max_payload_bytes: None,
+            daemon: None,
         })
     }
 
+    /// Adopt the limits and features from a HELLO answer, returning a
+    /// warning when the daemon speaks another protocol version
+    fn adopt(&mut self, hello: &HelloResponse) -> Option<String> {
+        self.max_payload_bytes = Some(hello.max_payload_bytes);
+        self.daemon = Some((hello.protocol_version, hello.features.clone()));
+
+        let version = hello.protocol_version;
+        match version.cmp(&PROTOCOL_VERSION) {
+            std::cmp::Ordering::Equal => None,
+            std::cmp::Ordering::Less => Some(format!(
+                "shelly speaks protocol v{}, older than this CLI (v{}); features it does not \
+                 advertise are disabled, upgrade the daemon to use them",
+                version, PROTOCOL_VERSION
+            )),
+            std::cmp::Ordering::Greater => Some(format!(
+                "shelly speaks protocol v{}, newer than this CLI (v{}); upgrade shelly-cli \
+                 if requests fail",
+                version, PROTOCOL_VERSION
+            )),
+        }
+    }
+
+    /// Refuse locally to use a feature the daemon did not advertise, rather
+    /// than send a packet it would drop or fail to decode
+    fn require(&self, feature: &str) -> io::Result<()> {
+        match &self.daemon {
+            Some((version, features)) if !features.iter().any(|f| f == feature) => {
+                Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!(
+                        "shelly does not support {} (daemon protocol v{}, CLI v{})",
+                        feature, version, PROTOCOL_VERSION
+                    ),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Encode a signed, length-prefixed packet
+    fn packet(
+        &self,
+        msg_type: MsgType,
+        seq: u32,
+        payload: Option<&impl Serialize>,
+    ) -> io::Result<Vec<u8>> {
+        let packet = encode_packet(msg_type, seq, payload)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(self.sign(packet))
+    }
+
+    /// Append the HMAC-SHA256 tag the daemon expects when a secret is set
+    fn sign(&self, mut packet: Vec<u8>) -> Vec<u8> {
+        if let Some(secret) = &self.config.secret {
+            sign_packet(secret.as_bytes(), &mut packet);
+        }
+        packet
+    }
+
+    /// Ask the daemon for its protocol version and limits
+    async fn hello(&self) -> io::Result<HelloResponse> {
+        let seq = self.seq.fetch_add(1, Ordering::SeqCst);
+        let packet = self.packet(MsgType::Hello, seq, None::<&()>)?;
+
+        for _attempt in 0..self.config.max_retries {
+            self.socket.send_to(&packet, self.config.target).await?;
+
+            let mut buf = [0u8; 1024];
+            let Ok(received) = timeout(
+                Duration::from_secs(self.config.ack_timeout_secs),
+                self.socket.recv_from(&mut buf),
+            )
+            .await
+            else {
+                continue;
+            };
+            let (len, addr) = received?;
+            if addr != self.config.target {
+                continue;
+            }
+            let Some((header, _)) = decode_reply(&mut buf[..len]) else {
+                continue;
+            };
+            if header.msg_type != MsgType::Hello || header.seq != seq {
+                continue;
+            }
+
+            return decode_payload(&header, &buf[..len]);
+        }
+
+        Err(io::Error::new(io::ErrorKind::TimedOut, "no HELLO answer"))
+    }
+
+    /// Send a control command and return the daemon's answer
+    async fn control(
+        &self,
+        command: &ControlCommand,
+        token: String,
+    ) -> io::Result<ResponsePayload> {
+        self.require("control")?;
+        let seq = self.seq.fetch_add(1, Ordering::SeqCst);
+        let payload = ControlPayload {
+            command: command.as_str().to_string(),
+            token,
+        };
+        let packet = self.packet(MsgType::Control, seq, Some(&payload))?;
+        self.exchange(packet, seq).await
+    }
+
+    /// Fetch the agent's last startup exploration report
+    async fn init_report(&self) -> io::Result<ResponsePayload> {
+        self.require("init_report")?;
+        let seq = self.seq.fetch_add(1, Ordering::SeqCst);
+        let packet = self.packet(MsgType::InitReport, seq, None::<&()>)?;
+        self.exchange(packet, seq).await
+    }
+
+    /// Approve a pending plan; answered like a request, once the plan has run
+    async fn approve(&self, plan_id: String) -> io::Result<ResponsePayload> {
+        self.require("approve")?;
+        let seq = self.seq.fetch_add(1, Ordering::SeqCst);
+        let payload = ApprovePayload {
+            plan_id,
+            request_id: Some(uuid::Uuid::new_v4().to_string()),
+        };
+        let packet = self.packet(MsgType::Approve, seq, Some(&payload))?;
+        self.deliver(packet, seq).await
+    }
+
+    /// Replay a journaled interaction, chosen by index when `target` is a
+    /// number and by query substring otherwise
+    async fn replay(&self, target: String) -> io::Result<ResponsePayload> {
+        self.require("replay")?;
+        let seq = self.seq.fetch_add(1, Ordering::SeqCst);
+        let (index, query) = match target.parse::<u32>() {
+            Ok(index) => (Some(index), None),
+            Err(_) => (None, Some(target)),
+        };
+        let payload = ReplayPayload {
+            index,
+            query,
+            request_id: Some(uuid::Uuid::new_v4().to_string()),
+        };
+        let packet = self.packet(MsgType::Replay, seq, Some(&payload))?;
+        self.deliver(packet, seq).await
+    }
+
+    /// Send a packet answered directly with a RESPONSE (no ACK), retrying
+    /// until the matching answer arrives
+    async fn exchange(&self, packet: Vec<u8>, seq: u32) -> io::Result<ResponsePayload> {
+        for _attempt in 0..self.config.max_retries {
+            self.socket.send_to(&packet, self.config.target).await?;
+
+            let mut buf = [0u8; 65536];
+            let Ok(received) = timeout(
+                Duration::from_secs(self.config.ack_timeout_secs),
+                self.socket.recv_from(&mut buf),
+            )
+            .await
+            else {
+                continue;
+            };
+            let (len, addr) = received?;
+            if addr != self.config.target {
+                continue;
+            }
+            let Some((header, cached)) = decode_reply(&mut buf[..len]) else {
+                continue;
+            };
+            if header.msg_type != MsgType::Response || header.seq != seq {
+                continue;
+            }
+
+            let mut payload: ResponsePayload = decode_payload(&header, &buf[..len])?;
+            payload.cached = cached;
+            return Ok(payload);
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "shelly not responding",
+        ))
+    }
+
     /// Send a request and wait for response
     async fn send_request(&self, content: String) -> io::Result<ResponsePayload> {
+        if self.config.no_tools {
+            self.require("no_tools")?;
+        }
+        if self.config.progress {
+            self.require("progress")?;
+        }
+        let (packet, seq) = self.request_packet(content, self.config.no_tools)?;
+        self.deliver(packet, seq).await
+    }
+
+    /// Build a signed REQUEST packet, returning it with its seq
+    fn request_packet(&self, content: String, no_tools: bool) -> io::Result<(Vec<u8>, u32)> {
         let seq = self.seq.fetch_add(1, Ordering::SeqCst);
 
-        // Serialize payload
+        // Serialize payload; the request_id stays fixed across retries so the
+        // daemon can dedup even if seq collides with an earlier CLI session
         let payload = RequestPayload {
-            content: content.clone(),
+            content,
+            request_id: Some(uuid::Uuid::new_v4().to_string()),
+            no_tools,
+            progress: self.config.progress,
         };
-        let mut payload_bytes = Vec::new();
-        let mut ser = Serializer::new(&mut payload_bytes);
-        payload
-            .serialize(&mut ser)
+        let packet = encode_packet(MsgType::Request, seq, Some(&payload))
             .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let payload_len = packet.len() - Framing::LengthPrefixed.header_len();
+
+        // The daemon would only answer with an error RESPONSE, so refuse
+        // locally and save the round trip
+        if let Some(max) = self.max_payload_bytes
+            && payload_len > max
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "message too large: {} bytes (shelly accepts at most {})",
+                    payload_len, max
+                ),
+            ));
+        }
+
+        Ok((self.sign(packet), seq))
+    }
+
+    /// Run the `doctor` checks: HELLO, then one trivial request sent once
+    /// (no retries) so each step can be timed and blamed separately
+    async fn doctor(&self) -> io::Result<DoctorReport> {
+        let start = Instant::now();
+        let hello = self
+            .hello()
+            .await
+            .ok()
+            .map(|hello| (start.elapsed(), hello.protocol_version));
 
-        // Build packet: type (1) + seq (4) + payload
-        let mut packet = vec![MsgType::Request as u8];
-        packet.extend_from_slice(&seq.to_be_bytes());
-        packet.extend_from_slice(&payload_bytes);
+        let (packet, seq) = self.request_packet(DOCTOR_PROMPT.to_string(), true)?;
+        let sent = Instant::now();
+        self.socket.send_to(&packet, self.config.target).await?;
+
+        let mut report = DoctorReport {
+            hello,
+            ack: None,
+            response: None,
+            diagnosis: Diagnosis::Unreachable,
+        };
+        let response = match self.wait_for_ack(seq).await {
+            Ok(Ack::Received) => {
+                report.ack = Some(sent.elapsed());
+                self.wait_for_response(seq).await
+            }
+            Ok(Ack::Answered(response)) => {
+                report.ack = Some(sent.elapsed());
+                Ok(response)
+            }
+            Ok(Ack::Missing) | Err(_) => return Ok(report),
+        };
 
-        // Send with retries
+        report.diagnosis = match response {
+            Ok(response) if response.is_error => Diagnosis::ErrorResponse(response.content),
+            Ok(_) => {
+                report.response = Some(sent.elapsed());
+                Diagnosis::Healthy
+            }
+            Err(_) => Diagnosis::NoResponse,
+        };
+        Ok(report)
+    }
+
+    /// Send a packet the daemon ACKs before answering, retrying until the
+    /// RESPONSE arrives
+    async fn deliver(&self, packet: Vec<u8>, seq: u32) -> io::Result<ResponsePayload> {
         for _attempt in 0..self.config.max_retries {
             // Send request
             self.socket.send_to(&packet, self.config.target).await?;
 
             // Wait for ACK
             match self.wait_for_ack(seq).await {
-                Ok(true) => {
+                Ok(Ack::Answered(response)) => return Ok(response),
+                Ok(Ack::Received) => {
                     // Wait for response
                     match self.wait_for_response(seq).await {
                         Ok(response) => return Ok(response),
@@ -150,8 +602,8 @@ impl Client {
                         }
                     }
                 }
-                Ok(false) => continue, // Not our ACK, keep waiting
-                Err(_) => continue,    // Timeout or error, retry
+                Ok(Ack::Missing) => continue, // Not our ACK, keep waiting
+                Err(_) => continue,           // Timeout or error, retry
             }
         }
 
@@ -161,9 +613,9 @@ impl Client {
         ))
     }
 
-    /// Wait for REQUEST_ACK
-    async fn wait_for_ack(&self, expected_seq: u32) -> io::Result<bool> {
-        let mut buf = [0u8; 1024];
+    /// Wait for REQUEST_ACK, or a RESPONSE the daemon sent in its place
+    async fn wait_for_ack(&self, expected_seq: u32) -> io::Result<Ack> {
+        let mut buf = [0u8; 65536];
 
         match timeout(
             Duration::from_secs(self.config.ack_timeout_secs),
@@ -173,80 +625,117 @@ impl Client {
         {
             Ok(Ok((len, addr))) => {
                 if addr != self.config.target {
-                    return Ok(false);
+                    return Ok(Ack::Missing);
                 }
 
-                if len < 5 {
-                    return Ok(false);
+                let Some((header, cached)) = decode_reply(&mut buf[..len]) else {
+                    return Ok(Ack::Missing);
+                };
+                if header.seq != expected_seq {
+                    return Ok(Ack::Missing);
                 }
 
-                let msg_type = buf[0];
-                let seq = u32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]]);
-
-                if msg_type == MsgType::RequestAck as u8 && seq == expected_seq {
-                    Ok(true)
-                } else {
-                    Ok(false)
+                match header.msg_type {
+                    MsgType::RequestAck => Ok(Ack::Received),
+                    MsgType::Response => {
+                        let mut payload: ResponsePayload = decode_payload(&header, &buf[..len])?;
+                        payload.cached = cached;
+                        Ok(Ack::Answered(payload))
+                    }
+                    _ => Ok(Ack::Missing),
                 }
             }
             Ok(Err(e)) => Err(e),
-            Err(_) => Ok(false), // Timeout
+            Err(_) => Ok(Ack::Missing), // Timeout
         }
     }
 
-    /// Wait for RESPONSE
+    /// Wait for RESPONSE, printing PROGRESS frames for the same seq as
+    /// they arrive
     async fn wait_for_response(&self, expected_seq: u32) -> io::Result<ResponsePayload> {
         let mut buf = [0u8; 65536];
 
-        // Longer timeout for response (inference may take time)
-        match timeout(Duration::from_secs(120), self.socket.recv_from(&mut buf)).await {
-            Ok(Ok((len, addr))) => {
-                if addr != self.config.target {
-                    return Err(io::Error::other("Unexpected sender"));
+        // Longer timeout for response (inference may take time); progress
+        // frames do not extend it
+        let deadline =
+            tokio::time::Instant::now() + Duration::from_secs(self.config.response_timeout_secs);
+        loop {
+            let (len, addr) = match timeout_at(deadline, self.socket.recv_from(&mut buf)).await {
+                Ok(received) => received?,
+                Err(_) => {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "Response timeout"));
                 }
+            };
+            if addr != self.config.target {
+                return Err(io::Error::other("Unexpected sender"));
+            }
 
-                if len < 5 {
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        "Packet too short",
-                    ));
-                }
+            let Some((header, cached)) = decode_reply(&mut buf[..len]) else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Undecodable packet",
+                ));
+            };
 
-                let msg_type = buf[0];
-                let seq = u32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]]);
+            if header.msg_type == MsgType::Progress && header.seq == expected_seq {
+                let progress: ProgressPayload = decode_payload(&header, &buf[..len])?;
+                println!("\r[progress] {}", progress.content.trim_end());
+                continue;
+            }
 
-                if msg_type != MsgType::Response as u8 {
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        "Not a response packet",
-                    ));
-                }
+            if header.msg_type != MsgType::Response {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Not a response packet",
+                ));
+            }
 
-                if seq != expected_seq {
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        "Sequence mismatch",
-                    ));
-                }
+            if header.seq != expected_seq {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Sequence mismatch",
+                ));
+            }
 
-                // Deserialize payload
-                let mut de = Deserializer::new(&buf[5..len]);
-                let payload: ResponsePayload = Deserialize::deserialize(&mut de)
-                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            // Deserialize payload
+            let mut payload: ResponsePayload = decode_payload(&header, &buf[..len])?;
+            payload.cached = cached;
 
-                Ok(payload)
-            }
-            Ok(Err(e)) => Err(e),
-            Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "Response timeout")),
+            return Ok(payload);
         }
     }
 }
 
+/// Decode the header of a reply in either framing, clearing the cached flag
+/// and returning whether it was set; None for anything undecodable
+fn decode_reply(packet: &mut [u8]) -> Option<(Header, bool)> {
+    let cached = take_cached(packet);
+    decode_header(packet).ok().map(|header| (header, cached))
+}
+
+/// Deserialize the payload of a reply whose header is already decoded
+fn decode_payload<T: for<'de> Deserialize<'de>>(header: &Header, packet: &[u8]) -> io::Result<T> {
+    let payload = header
+        .payload(packet, usize::MAX)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let mut de = Deserializer::new(payload);
+    Deserialize::deserialize(&mut de).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
 fn main() -> io::Result<()> {
     // Parse arguments
-    let args = Args::parse();
+    let mut args = Args::parse();
+    let control = args.command.take();
+    let control_token = args
+        .control_token
+        .take()
+        .or_else(|| std::env::var("COMM_CONTROL_TOKEN").ok());
     let config = Config::from_args(args);
 
+    if let Some(command) = control {
+        return run_command(config, command, control_token);
+    }
+
     // Check locale
     if let Ok(lang) = std::env::var("LANG")
         && !lang.to_lowercase().contains("utf-8")
@@ -262,9 +751,65 @@ fn main() -> io::Result<()> {
     rt.block_on(async { run_client(config).await })
 }
 
+/// Run a one-shot subcommand, print the daemon's answer and exit non-zero
+/// on errors
+fn run_command(
+    config: Config,
+    command: ControlCommand,
+    control_token: Option<String>,
+) -> io::Result<()> {
+    let verbose = config.verbose;
+    let rt = tokio::runtime::Runtime::new()?;
+    if let ControlCommand::Doctor = command {
+        let target = config.target;
+        let report = rt.block_on(async { Client::new(config).await?.doctor().await })?;
+        println!("shelly-cli doctor, target {}", target);
+        println!("{}", report);
+        if report.diagnosis != Diagnosis::Healthy {
+            process::exit(1);
+        }
+        return Ok(());
+    }
+    let response = rt.block_on(async {
+        let client = Client::new(config).await?;
+        match command {
+            ControlCommand::InitReport => client.init_report().await,
+            ControlCommand::Approve { plan_id } => client.approve(plan_id).await,
+            ControlCommand::Replay { target } => client.replay(target).await,
+            ControlCommand::Doctor => unreachable!("handled above"),
+            ControlCommand::Pause
+            | ControlCommand::Resume
+            | ControlCommand::DedupStats
+            | ControlCommand::DedupClear => {
+                let Some(token) = control_token else {
+                    eprintln!(
+                        "[error] {} needs --control-token or COMM_CONTROL_TOKEN",
+                        command.as_str()
+                    );
+                    process::exit(2);
+                };
+                client.control(&command, token).await
+            }
+        }
+    })?;
+
+    if response.cached && verbose {
+        println!("[cached]");
+    }
+    if let Some(timing) = response.timing.as_ref().filter(|_| verbose) {
+        println!("{}", timing);
+    }
+    if response.is_error {
+        eprintln!("[error] {}", response.content);
+        process::exit(1);
+    }
+    println!("{}", response.content);
+    Ok(())
+}
+
 async fn run_client(config: Config) -> io::Result<()> {
     // Initialize client
-    let client = Client::new(config.clone()).await?;
+    let mut client = Client::new(config.clone()).await?;
 
     // Initialize rustyline with history
     let mut rl: Editor<(), FileHistory> = Editor::new().map_err(io::Error::other)?;
@@ -283,6 +828,20 @@ async fn run_client(config: Config) -> io::Result<()> {
     // Print welcome message
     println!("shelly-cli v{}", env!("CARGO_PKG_VERSION"));
     println!("Target: {}", client.config.target);
+    match client.hello().await {
+        Ok(hello) => {
+            println!(
+                "Protocol v{}, max payload {} bytes, features: {}",
+                hello.protocol_version,
+                hello.max_payload_bytes,
+                hello.features.join(", ")
+            );
+            if let Some(warning) = client.adopt(&hello) {
+                eprintln!("[warning] {}", warning);
+            }
+        }
+        Err(e) => eprintln!("[warning] Handshake failed ({}), using defaults", e),
+    }
     println!("Type your message and press Enter. Ctrl+D to quit.");
     println!();
 
@@ -309,6 +868,14 @@ async fn run_client(config: Config) -> io::Result<()> {
                     Ok(response) => {
                         // Clear waiting message and print response
                         print!("\r");
+                        if response.cached && client.config.verbose {
+                            println!("[cached]");
+                        }
+                        if let Some(timing) =
+                            response.timing.as_ref().filter(|_| client.config.verbose)
+                        {
+                            println!("{}", timing);
+                        }
                         if response.is_error {
                             println!("[error] {}", response.content);
                         } else {
@@ -346,3 +913,237 @@ async fn run_client(config: Config) -> io::Result<()> {
     println!("\nGoodbye!");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(target: SocketAddr) -> Config {
+        Config {
+            target,
+            ack_timeout_secs: 1,
+            response_timeout_secs: 1,
+            max_retries: 1,
+            history_file: PathBuf::from("/dev/null"),
+            history_size: 0,
+            secret: None,
+            verbose: false,
+            no_tools: false,
+            progress: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_doctor_diagnoses_ack_without_response() {
+        // Mock daemon: ACKs length-prefixed requests, ignores everything
+        // else, never answers
+        let daemon = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let target = daemon.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 65536];
+            loop {
+                let (len, from) = daemon.recv_from(&mut buf).await.unwrap();
+                let Ok(header) = decode_header(&buf[..len]) else {
+                    continue;
+                };
+                if header.msg_type == MsgType::Request && header.framing == Framing::LengthPrefixed
+                {
+                    let ack = comm::protocol::encode_request_ack(header.framing, header.seq);
+                    daemon.send_to(&ack.unwrap(), from).await.unwrap();
+                }
+            }
+        });
+
+        let report = Client::new(config(target))
+            .await
+            .unwrap()
+            .doctor()
+            .await
+            .unwrap();
+        assert_eq!(report.diagnosis, Diagnosis::NoResponse);
+        assert!(report.hello.is_none());
+        assert!(report.ack.is_some());
+        assert!(report.response.is_none());
+        assert!(report.to_string().contains("did not answer in time"));
+    }
+
+    #[tokio::test]
+    async fn test_older_daemon_warns_and_refuses_unadvertised_features() {
+        // Mock daemon: answers HELLO as a protocol v1 build (legacy framing)
+        // without no_tools or replay, ignores everything else
+        let daemon = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let target = daemon.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 65536];
+            loop {
+                let (len, from) = daemon.recv_from(&mut buf).await.unwrap();
+                let Ok(header) = decode_header(&buf[..len]) else {
+                    continue;
+                };
+                if header.msg_type == MsgType::Hello {
+                    let hello = HelloResponse {
+                        protocol_version: 1,
+                        max_payload_bytes: 1024,
+                        features: vec!["request".into(), "hello".into(), "request_id".into()],
+                    };
+                    let hello =
+                        comm::protocol::encode_hello_response(Framing::Legacy, header.seq, &hello);
+                    daemon.send_to(&hello.unwrap(), from).await.unwrap();
+                }
+            }
+        });
+
+        let mut client = Client::new(Config {
+            no_tools: true,
+            ..config(target)
+        })
+        .await
+        .unwrap();
+        let hello = client.hello().await.unwrap();
+        let warning = client.adopt(&hello).unwrap();
+        assert!(
+            warning.contains("protocol v1, older than this CLI (v2)"),
+            "{}",
+            warning
+        );
+        assert_eq!(client.max_payload_bytes, Some(1024));
+
+        // Refused locally instead of waiting on a daemon that would drop it
+        let started = Instant::now();
+        let err = client.send_request("hi".to_string()).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        assert!(
+            err.to_string().contains("does not support no_tools"),
+            "{}",
+            err
+        );
+        let err = client.replay("0".to_string()).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        assert!(started.elapsed() < Duration::from_millis(500));
+
+        // A matching daemon gets no warning
+        let current = HelloResponse {
+            protocol_version: PROTOCOL_VERSION,
+            max_payload_bytes: 65507,
+            features: vec!["no_tools".to_string()],
+        };
+        assert!(client.adopt(&current).is_none());
+        assert!(client.require("no_tools").is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_progress_frames_before_response_are_skipped() {
+        // Mock daemon: ACKs a request asking for progress, streams two
+        // PROGRESS frames, then answers
+        let daemon = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let target = daemon.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 65536];
+            loop {
+                let (len, from) = daemon.recv_from(&mut buf).await.unwrap();
+                let Ok(header) = decode_header(&buf[..len]) else {
+                    continue;
+                };
+                let payload = header.payload(&buf[..len], usize::MAX).unwrap();
+                let request = comm::protocol::decode_request_payload(payload).unwrap();
+                assert!(request.progress);
+
+                let (framing, seq) = (header.framing, header.seq);
+                let mut replies = vec![comm::protocol::encode_request_ack(framing, seq).unwrap()];
+                for line in ["step 1\n", "step 2\n"] {
+                    let progress = ProgressPayload {
+                        content: line.to_string(),
+                    };
+                    replies.push(comm::protocol::encode_progress(framing, seq, &progress).unwrap());
+                }
+                let response = comm::types::ResponsePayload {
+                    content: "done".to_string(),
+                    is_error: false,
+                    timing: None,
+                };
+                replies.push(comm::protocol::encode_response(framing, seq, &response).unwrap());
+                for reply in replies {
+                    daemon.send_to(&reply, from).await.unwrap();
+                }
+            }
+        });
+
+        let client = Client::new(Config {
+            progress: true,
+            ..config(target)
+        })
+        .await
+        .unwrap();
+        let response = client
+            .send_request("restart nginx".to_string())
+            .await
+            .unwrap();
+        assert_eq!(response.content, "done");
+        assert!(!response.is_error);
+    }
+
+    #[tokio::test]
+    async fn test_refusal_sent_instead_of_ack_is_shown() {
+        // Mock daemon: refuses every request with an error RESPONSE and no
+        // ACK, as for oversized payloads or too many in-flight requests
+        let daemon = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let target = daemon.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 65536];
+            loop {
+                let (len, from) = daemon.recv_from(&mut buf).await.unwrap();
+                let Ok(header) = decode_header(&buf[..len]) else {
+                    continue;
+                };
+                let refusal = comm::types::ResponsePayload {
+                    content: "too many in-flight requests (max 4)".to_string(),
+                    is_error: true,
+                    timing: None,
+                };
+                let refusal = comm::protocol::encode_response(header.framing, header.seq, &refusal);
+                daemon.send_to(&refusal.unwrap(), from).await.unwrap();
+            }
+        });
+
+        let client = Client::new(config(target)).await.unwrap();
+        let started = Instant::now();
+        let response = client.send_request("uptime".to_string()).await.unwrap();
+        assert!(response.is_error);
+        assert_eq!(response.content, "too many in-flight requests (max 4)");
+        assert!(started.elapsed() < Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn test_packets_carry_the_version_compared_in_hello() {
+        let daemon = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client = Client::new(config(daemon.local_addr().unwrap()))
+            .await
+            .unwrap();
+
+        // The version warned about in `adopt` is the one on the wire
+        let (packet, seq) = client.request_packet("hi".to_string(), false).unwrap();
+        assert_eq!(
+            packet[0],
+            comm::protocol::VERSION_TAG | PROTOCOL_VERSION as u8
+        );
+        let header = decode_header(&packet).unwrap();
+        assert_eq!(header.framing, Framing::LengthPrefixed);
+        assert_eq!(header.seq, seq);
+    }
+
+    #[tokio::test]
+    async fn test_doctor_diagnoses_unreachable_daemon() {
+        // Bound but silent: nothing is ever ACKed
+        let daemon = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let target = daemon.local_addr().unwrap();
+
+        let report = Client::new(config(target))
+            .await
+            .unwrap()
+            .doctor()
+            .await
+            .unwrap();
+        assert_eq!(report.diagnosis, Diagnosis::Unreachable);
+        assert!(report.to_string().contains("never acknowledged"));
+    }
+}
@@ -1,7 +1,11 @@
 // RequestBuilder - type-safe chainable builder for MessageRequest
 #![allow(dead_code)]
 
-use super::{ContentBlock, Message, MessageRequest, Role, ToolDefinition};
+use super::{BuildError, ContentBlock, Message, MessageRequest, Role, ToolDefinition};
+
+/// Default cap on the content of a single message: far above any real
+/// turn, low enough to stop a runaway history before the backend does
+pub const DEFAULT_MAX_MESSAGE_BYTES: usize = 16 * 1024 * 1024;
 
 pub struct RequestBuilder {
     model: String,
@@ -15,6 +19,9 @@ pub struct RequestBuilder {
     stop_sequences: Option<Vec<String>>,
     stream: Option<bool>,
     metadata: Option<serde_json::Value>,
+    prefill: Option<String>,
+    no_tools: bool,
+    max_message_bytes: usize,
 }
 
 impl RequestBuilder {
@@ -31,6 +38,9 @@ impl RequestBuilder {
             stop_sequences: None,
             stream: None,
             metadata: None,
+            prefill: None,
+            no_tools: false,
+            max_message_bytes: DEFAULT_MAX_MESSAGE_BYTES,
         }
     }
 
@@ -65,6 +75,13 @@ impl RequestBuilder {
         self
     }
 
+    /// Prefill the assistant's reply with `text`; the model continues from it.
+    /// Appended as the final message at build time, whatever the call order.
+    pub fn assistant_prefill(mut self, text: impl Into<String>) -> Self {
+        self.prefill = Some(text.into());
+        self
+    }
+
     pub fn user_tool_result(
         mut self,
         tool_use_id: impl Into<String>,
@@ -95,6 +112,13 @@ impl RequestBuilder {
         self
     }
 
+    /// Omit `tools` from the request even if some were added, for a pure
+    /// chat turn the model cannot answer with tool calls
+    pub fn no_tools(mut self) -> Self {
+        self.no_tools = true;
+        self
+    }
+
     pub fn max_tokens(mut self, max_tokens: u32) -> Self {
         self.max_tokens = max_tokens;
         self
@@ -130,21 +154,57 @@ impl RequestBuilder {
         self
     }
 
-    pub fn build(self) -> Result<MessageRequest, &'static str> {
+    /// Refuse to build when one message's content exceeds `bytes`
+    /// (default `DEFAULT_MAX_MESSAGE_BYTES`)
+    pub fn max_message_bytes(mut self, bytes: usize) -> Self {
+        self.max_message_bytes = bytes;
+        self
+    }
+
+    pub fn build(self) -> Result<MessageRequest, BuildError> {
         if self.messages.is_empty() {
-            return Err("messages cannot be empty");
+            return Err(BuildError::NoMessages);
         }
 
         // Validate: first message must be user role
         if self.messages.first().map(|m| &m.role) != Some(&Role::User) {
-            return Err("first message must have user role");
+            return Err(BuildError::FirstMessageNotUser);
+        }
+
+        let mut messages = self.messages;
+        if let Some(prefill) = self.prefill {
+            if messages.last().map(|m| &m.role) != Some(&Role::User) {
+                return Err(BuildError::PrefillAfterAssistant);
+            }
+            // The API rejects a final assistant turn ending in whitespace
+            let prefill = prefill.trim_end();
+            if prefill.is_empty() {
+                return Err(BuildError::EmptyPrefill);
+            }
+            messages.push(Message::assistant_text(prefill));
+        }
+
+        // Name the culprit here rather than get an opaque rejection back
+        for (index, message) in messages.iter().enumerate() {
+            let bytes = content_bytes(message);
+            if bytes > self.max_message_bytes {
+                return Err(BuildError::MessageTooLarge {
+                    index,
+                    role: message.role.clone(),
+                    bytes,
+                    limit: self.max_message_bytes,
+                });
+            }
         }
 
         Ok(MessageRequest {
             model: self.model,
             system: self.system,
-            messages: self.messages,
-            tools: self.tools,
+            messages,
+            // Some backends reject an empty tools array; omit it instead
+            tools: self
+                .tools
+                .filter(|tools| !self.no_tools && !tools.is_empty()),
             max_tokens: self.max_tokens,
             temperature: self.temperature,
             top_p: self.top_p,
@@ -155,3 +215,156 @@ impl RequestBuilder {
         })
     }
 }
+
+/// Bytes of text a message carries, tool inputs counted as their JSON
+fn content_bytes(message: &Message) -> usize {
+    message
+        .content
+        .iter()
+        .map(|block| match block {
+            ContentBlock::Text { text } => text.len(),
+            ContentBlock::ToolUse { input, .. } => input.to_string().len(),
+            ContentBlock::ToolResult { content, .. } => content.len(),
+            ContentBlock::Thinking { thinking } => thinking.len(),
+            _ => 0,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assistant_prefill_is_last_message() {
+        let request = RequestBuilder::new("model")
+            .user_text("list files as JSON")
+            .assistant_prefill("{")
+            .build()
+            .unwrap();
+
+        assert_eq!(request.messages.len(), 2);
+        let last = request.messages.last().unwrap();
+        assert_eq!(last.role, Role::Assistant);
+        assert!(matches!(&last.content[..], [ContentBlock::Text { text }] if text == "{"));
+    }
+
+    #[test]
+    fn test_assistant_prefill_appended_after_later_messages() {
+        let request = RequestBuilder::new("model")
+            .assistant_prefill("Answer: ")
+            .user_text("first")
+            .assistant_text("reply")
+            .user_text("second")
+            .build()
+            .unwrap();
+
+        assert_eq!(request.messages.len(), 4);
+        let last = request.messages.last().unwrap();
+        assert_eq!(last.role, Role::Assistant);
+        assert!(matches!(&last.content[..], [ContentBlock::Text { text }] if text == "Answer:"));
+    }
+
+    #[test]
+    fn test_empty_tools_omitted() {
+        let request = RequestBuilder::new("model")
+            .user_text("hi")
+            .tools(Vec::new())
+            .build()
+            .unwrap();
+        assert!(request.tools.is_none());
+
+        let json = serde_json::to_value(&request).unwrap();
+        assert!(json.get("tools").is_none());
+    }
+
+    #[test]
+    fn test_no_tools_omits_tools() {
+        let tool = ToolDefinition {
+            name: "bash".to_string(),
+            description: "Run a command".to_string(),
+            input_schema: serde_json::json!({ "type": "object" }),
+        };
+        let request = RequestBuilder::new("model")
+            .user_text("summarize the last hour")
+            .tool(tool.clone())
+            .no_tools()
+            .tool(tool)
+            .build()
+            .unwrap();
+        assert!(request.tools.is_none());
+
+        let json = serde_json::to_value(&request).unwrap();
+        assert!(json.get("tools").is_none());
+    }
+
+    #[test]
+    fn test_assistant_prefill_validation() {
+        // First message must still be user
+        let result = RequestBuilder::new("model")
+            .assistant_text("hi")
+            .assistant_prefill("{")
+            .build();
+        assert_eq!(result.unwrap_err(), BuildError::FirstMessageNotUser);
+
+        let result = RequestBuilder::new("model")
+            .user_text("hi")
+            .assistant_text("hello")
+            .assistant_prefill("{")
+            .build();
+        assert!(result.is_err());
+
+        let result = RequestBuilder::new("model")
+            .user_text("hi")
+            .assistant_prefill("  ")
+            .build();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_message_within_limit_builds() {
+        let request = RequestBuilder::new("model")
+            .max_message_bytes(16)
+            .user_text("sixteen bytes!!!")
+            .assistant_text("ok")
+            .user_tool_result("call_1", "0123456789abcdef", None)
+            .build()
+            .unwrap();
+        assert_eq!(request.messages.len(), 3);
+    }
+
+    #[test]
+    fn test_message_over_limit_names_role_and_index() {
+        let result = RequestBuilder::new("model")
+            .max_message_bytes(16)
+            .user_text("hi")
+            .assistant_content(vec![
+                ContentBlock::Text {
+                    text: "running it".to_string(),
+                },
+                ContentBlock::ToolUse {
+                    id: "call_1".to_string(),
+                    name: "bash".to_string(),
+                    input: serde_json::json!({ "command": "ls" }),
+                },
+            ])
+            .user_text("ok")
+            .build();
+
+        // 10 bytes of text plus 16 of tool input JSON
+        let err = result.unwrap_err();
+        assert_eq!(
+            err,
+            BuildError::MessageTooLarge {
+                index: 1,
+                role: Role::Assistant,
+                bytes: 26,
+                limit: 16,
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "message 1 (assistant) has 26 bytes of content, over the limit of 16"
+        );
+    }
+}
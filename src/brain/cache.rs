@@ -0,0 +1,129 @@
+// Response cache - reuse replies to identical requests within a short window
+
+use super::{MessageRequest, MessageResponse};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+
+/// Cache key: hash of the serialized request (model, system, messages,
+/// tools and sampling parameters all take part)
+pub fn request_key(request: &MessageRequest) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(request)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Least-recently-used cache of responses with a fixed time to live
+pub struct ResponseCache {
+    capacity: usize,
+    ttl: Duration,
+    entries: HashMap<u64, (Instant, MessageResponse, String)>,
+    /// Keys from least to most recently used
+    order: VecDeque<u64>,
+}
+
+impl ResponseCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity,
+            ttl,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Cached response and raw body for `key`, if present and not expired
+    pub fn get(&mut self, key: u64) -> Option<(MessageResponse, String)> {
+        let (stored_at, response, body) = self.entries.get(&key)?;
+        if stored_at.elapsed() > self.ttl {
+            self.remove(key);
+            return None;
+        }
+        let hit = (response.clone(), body.clone());
+        self.touch(key);
+        Some(hit)
+    }
+
+    /// Store a response, evicting the least recently used entry when full
+    pub fn insert(&mut self, key: u64, response: MessageResponse, body: String) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.contains_key(&key) {
+            self.touch(key);
+        } else {
+            if self.entries.len() >= self.capacity
+                && let Some(oldest) = self.order.pop_front()
+            {
+                self.entries.remove(&oldest);
+            }
+            self.order.push_back(key);
+        }
+        self.entries.insert(key, (Instant::now(), response, body));
+    }
+
+    fn touch(&mut self, key: u64) {
+        self.order.retain(|k| *k != key);
+        self.order.push_back(key);
+    }
+
+    fn remove(&mut self, key: u64) {
+        self.entries.remove(&key);
+        self.order.retain(|k| *k != key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brain::RequestBuilder;
+
+    fn response(id: &str) -> MessageResponse {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "model": "m",
+            "content": [],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_request_key_depends_on_content() {
+        let a = RequestBuilder::new("m").user_text("hi").build().unwrap();
+        let b = RequestBuilder::new("m").user_text("hi").build().unwrap();
+        let c = RequestBuilder::new("m")
+            .user_text("hi")
+            .temperature(0.5)
+            .build()
+            .unwrap();
+        assert_eq!(request_key(&a), request_key(&b));
+        assert_ne!(request_key(&a), request_key(&c));
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let mut cache = ResponseCache::new(2, Duration::from_secs(60));
+        cache.insert(1, response("one"), String::new());
+        cache.insert(2, response("two"), String::new());
+        // Using 1 makes 2 the least recently used
+        assert!(cache.get(1).is_some());
+        cache.insert(3, response("three"), String::new());
+
+        assert_eq!(cache.entries.len(), 2);
+        assert!(cache.get(2).is_none());
+        assert_eq!(cache.get(1).unwrap().0.id, "one");
+        assert_eq!(cache.get(3).unwrap().0.id, "three");
+    }
+
+    #[test]
+    fn test_expired_entries_miss() {
+        let mut cache = ResponseCache::new(4, Duration::ZERO);
+        cache.insert(1, response("one"), String::new());
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(cache.get(1).is_none());
+        assert_eq!(cache.entries.len(), 0);
+    }
+}
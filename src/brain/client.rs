@@ -1,17 +1,41 @@
 // Brain client - HTTP communication with inference backend
 
+use super::cache::{ResponseCache, request_key};
+use super::text::truncate_chars;
 use super::{BrainConfig, BrainError, MessageRequest, MessageResponse};
 use reqwest::Client;
+use reqwest::header::{CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
-use tracing::{debug, error, info, warn};
+use tokio::sync::Semaphore;
+use tracing::{debug, error, info, instrument, warn};
 
 /// Brain client for LLM inference
 #[derive(Clone)]
 pub struct Brain {
     config: BrainConfig,
     client: Client,
+    headers: HeaderMap,
+    /// Shared by clones; None unless `response_cache_size` is set
+    cache: Option<Arc<Mutex<ResponseCache>>>,
+    /// Bounds in-flight inferences across clones; None = unlimited
+    limiter: Option<Arc<Semaphore>>,
 }
 
+/// Per-call overrides of the configured inference behavior
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InferOpts {
+    /// Retries after the first attempt (None = `max_retries` from config);
+    /// 0 fails fast on the first error
+    pub max_retries: Option<u32>,
+}
+
+/// Distinct error messages kept for `BrainError::Exhausted`
+const ERROR_HISTORY_LEN: usize = 5;
+
+/// Anthropic API version sent unless overridden via `extra_headers`
+const DEFAULT_ANTHROPIC_VERSION: &str = "2023-06-01";
+
 impl Brain {
     /// Create a new Brain instance
     pub async fn new(config: BrainConfig) -> Result<Self, super::BrainInitError> {
@@ -23,13 +47,43 @@ impl Brain {
             "initializing brain"
         );
 
+        // A 0 timeout keeps idle connections open until the server drops them
+        let pool_idle_timeout = (config.pool_idle_timeout_secs > 0)
+            .then(|| Duration::from_secs(config.pool_idle_timeout_secs));
         let client = Client::builder()
             .timeout(Duration::from_secs(config.request_timeout_secs))
+            .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+            .pool_max_idle_per_host(config.pool_max_idle_per_host)
+            .pool_idle_timeout(pool_idle_timeout)
             .build()
             .map_err(super::BrainInitError::ClientError)?;
 
-        info!("brain initialized successfully");
-        Ok(Self { config, client })
+        let headers = build_headers(&config)?;
+
+        let cache = (config.response_cache_size > 0).then(|| {
+            Arc::new(Mutex::new(ResponseCache::new(
+                config.response_cache_size,
+                Duration::from_secs(config.response_cache_ttl_secs),
+            )))
+        });
+
+        let limiter = (config.max_concurrent_requests > 0)
+            .then(|| Arc::new(Semaphore::new(config.max_concurrent_requests)));
+
+        info!(
+            response_cache_size = config.response_cache_size,
+            max_concurrent_requests = config.max_concurrent_requests,
+            pool_max_idle_per_host = config.pool_max_idle_per_host,
+            pool_idle_timeout_secs = config.pool_idle_timeout_secs,
+            "brain initialized successfully"
+        );
+        Ok(Self {
+            config,
+            client,
+            headers,
+            cache,
+            limiter,
+        })
     }
 
     /// Get default model
@@ -42,6 +96,11 @@ impl Brain {
         self.config.max_output_tokens
     }
 
+    /// Get max output tokens for a specific model (clamped to its configured limit)
+    pub fn max_tokens_for(&self, model: &str) -> u32 {
+        self.config.max_tokens_for(model)
+    }
+
     /// Get temperature (None = use model default)
     pub fn temperature(&self) -> Option<f32> {
         self.config.temperature
@@ -58,7 +117,125 @@ impl Brain {
     }
 
     /// Perform inference
+    ///
+    /// With `inference_deadline_secs` set, the whole call (retries included)
+    /// is abandoned once the deadline passes; dropping the future aborts the
+    /// in-flight HTTP request.
     pub async fn infer(&self, request: MessageRequest) -> Result<MessageResponse, BrainError> {
+        self.infer_with_opts(request, InferOpts::default()).await
+    }
+
+    /// Perform inference with per-call overrides, e.g. no retries for a
+    /// quick query where failing fast beats waiting out the backoff
+    #[instrument(skip_all, fields(model = %request.model))]
+    pub async fn infer_with_opts(
+        &self,
+        request: MessageRequest,
+        opts: InferOpts,
+    ) -> Result<MessageResponse, BrainError> {
+        let (response, _body) = self.infer_with_body(request, opts).await?;
+        Ok(response)
+    }
+
+    /// Perform inference, also returning the unparsed response JSON
+    ///
+    /// Useful for fields `MessageResponse` does not model and for diagnosing
+    /// parsing mismatches with non-standard backends.
+    #[allow(dead_code)]
+    pub async fn infer_raw(
+        &self,
+        request: MessageRequest,
+    ) -> Result<(MessageResponse, serde_json::Value), BrainError> {
+        let (response, body) = self.infer_with_body(request, InferOpts::default()).await?;
+        let raw = serde_json::from_str(&body)?;
+        Ok((response, raw))
+    }
+
+    /// Answer from the response cache when enabled, otherwise run inference;
+    /// returns the parsed response together with the raw body
+    async fn infer_with_body(
+        &self,
+        request: MessageRequest,
+        opts: InferOpts,
+    ) -> Result<(MessageResponse, String), BrainError> {
+        if !self.config.is_model_allowed(&request.model) {
+            warn!(model = %request.model, "inference refused: model not in allowed_models");
+            let mut allowed = vec![self.config.default_model.clone()];
+            allowed.extend(
+                self.config
+                    .allowed_models
+                    .iter()
+                    .flatten()
+                    .filter(|m| **m != self.config.default_model)
+                    .cloned(),
+            );
+            return Err(BrainError::ModelNotAllowed {
+                model: request.model,
+                allowed,
+            });
+        }
+
+        let Some(cache) = &self.cache else {
+            return self.infer_with_deadline(request, opts).await;
+        };
+
+        let key = request_key(&request);
+        if let Some(hit) = cache.lock().unwrap().get(key) {
+            info!(model = %request.model, "inference served from response cache");
+            return Ok(hit);
+        }
+
+        let (response, body) = self.infer_with_deadline(request, opts).await?;
+        cache
+            .lock()
+            .unwrap()
+            .insert(key, response.clone(), body.clone());
+        Ok((response, body))
+    }
+
+    /// Run inference under the configured deadline
+    async fn infer_with_deadline(
+        &self,
+        request: MessageRequest,
+        opts: InferOpts,
+    ) -> Result<(MessageResponse, String), BrainError> {
+        let Some(deadline_secs) = self.config.inference_deadline_secs else {
+            return self.infer_with_retries(request, opts).await;
+        };
+
+        let deadline = Duration::from_secs(deadline_secs);
+        match tokio::time::timeout(deadline, self.infer_with_retries(request, opts)).await {
+            Ok(result) => result,
+            Err(_) => {
+                error!(
+                    deadline_secs = deadline_secs,
+                    "inference aborted: deadline exceeded"
+                );
+                Err(BrainError::Timeout(deadline_secs))
+            }
+        }
+    }
+
+    /// Send the request, retrying failures with exponential backoff
+    ///
+    /// Waits for a slot first when `max_concurrent_requests` is set; the slot
+    /// is held across retries so backoff does not let others pile on.
+    async fn infer_with_retries(
+        &self,
+        request: MessageRequest,
+        opts: InferOpts,
+    ) -> Result<(MessageResponse, String), BrainError> {
+        let _permit = match &self.limiter {
+            Some(limiter) => {
+                if limiter.available_permits() == 0 {
+                    debug!("inference slots busy, queueing request");
+                }
+                // The semaphore is never closed
+                limiter.acquire().await.ok()
+            }
+            None => None,
+        };
+
         info!(
             model = %request.model,
             messages_count = request.messages.len(),
@@ -70,13 +247,14 @@ impl Brain {
 
         let start = Instant::now();
         let mut retries = 0;
-        let max_retries = self.config.max_retries;
+        let max_retries = opts.max_retries.unwrap_or(self.config.max_retries);
         let base_delay = Duration::from_millis(self.config.base_retry_delay_ms);
+        let mut history: Vec<String> = Vec::new();
 
         loop {
             debug!(retry = retries, "sending request to inference backend");
             match self.send_request(&request).await {
-                Ok(response) => {
+                Ok((response, body)) => {
                     let latency = start.elapsed().as_millis() as u64;
                     let (input_tokens, output_tokens) = response
                         .usage
@@ -95,10 +273,19 @@ impl Brain {
                         status = "success",
                         "inference completed successfully"
                     );
-                    return Ok(response);
+                    return Ok((response, body));
                 }
                 Err(e) => {
                     retries += 1;
+                    record_error(&mut history, e.to_string());
+                    if !e.is_transient() {
+                        error!(
+                            retries = retries - 1,
+                            error = %e,
+                            "inference failed: error is not transient, not retrying"
+                        );
+                        return Err(e);
+                    }
                     if retries > max_retries {
                         error!(
                             retries = retries,
@@ -109,6 +296,8 @@ impl Brain {
                         return Err(BrainError::Exhausted {
                             retries,
                             last_error: e.to_string(),
+                            elapsed: start.elapsed(),
+                            history,
                         });
                     }
 
@@ -131,7 +320,10 @@ impl Brain {
         }
     }
 
-    async fn send_request(&self, request: &MessageRequest) -> Result<MessageResponse, BrainError> {
+    async fn send_request(
+        &self,
+        request: &MessageRequest,
+    ) -> Result<(MessageResponse, String), BrainError> {
         let url = format!("{}/v1/messages", self.config.endpoint.trim_end_matches('/'));
 
         debug!(url = %url, "sending HTTP request");
@@ -139,49 +331,604 @@ impl Brain {
         let response = self
             .client
             .post(&url)
-            .header("Authorization", format!("Bearer {}", &self.config.api_key))
-            .header("anthropic-version", "2023-06-01")
-            .header("Content-Type", "application/json")
+            .headers(self.headers.clone())
             .json(request)
             .send()
-            .await?;
+            .await
+            .map_err(|e| classify_send_error(e, self.config.request_timeout_secs))?;
 
         let status = response.status();
         debug!(status = status.as_u16(), "received HTTP response");
 
         if status.is_success() {
-            let body = response.text().await?;
-            let body_preview = if body.len() > 200 {
-                // Use char boundaries instead of byte slicing for UTF-8 safety
-                let chars: String = body.chars().take(200).collect();
-                format!("{}...", chars)
-            } else {
-                body.clone()
-            };
-            debug!(response_preview = %body_preview, "response body received");
+            let body = response
+                .text()
+                .await
+                .map_err(|e| classify_send_error(e, self.config.request_timeout_secs))?;
+            debug!(
+                response_preview = %preview(&body, self.config.log_preview_chars),
+                "response body received"
+            );
 
             let response: MessageResponse = serde_json::from_str(&body)?;
-            Ok(response)
-        } else if status.as_u16() == 401 {
-            Err(BrainError::AuthenticationFailed(
-                response.text().await.unwrap_or_default(),
-            ))
-        } else if status.as_u16() == 400 {
+            Ok((response, body))
+        } else if matches!(status.as_u16(), 400 | 404) {
             let body = response.text().await.unwrap_or_default();
-            Err(BrainError::InvalidRequest(body))
+            if is_model_not_found_body(&body) {
+                Err(BrainError::ModelNotFound(request.model.clone()))
+            } else if status.as_u16() == 400 {
+                Err(BrainError::InvalidRequest(error_detail(&body)))
+            } else {
+                Err(BrainError::InvalidRequest(format!(
+                    "HTTP {}: {}",
+                    status,
+                    error_detail(&body)
+                )))
+            }
+        } else if status.as_u16() == 401 {
+            Err(BrainError::AuthenticationFailed(error_detail(
+                &response.text().await.unwrap_or_default(),
+            )))
         } else if status.as_u16() == 402 {
-            Err(BrainError::InsufficientBalance(
-                response.text().await.unwrap_or_default(),
-            ))
+            Err(BrainError::InsufficientBalance(error_detail(
+                &response.text().await.unwrap_or_default(),
+            )))
         } else if status.is_server_error() {
             let body = response.text().await.unwrap_or_default();
-            Err(BrainError::ModelError(body))
+            Err(BrainError::ModelError(error_detail(&body)))
         } else {
             let body = response.text().await.unwrap_or_default();
             Err(BrainError::InvalidRequest(format!(
                 "HTTP {}: {}",
-                status, body
+                status,
+                error_detail(&body)
             )))
         }
     }
 }
+
+/// `error.type: error.message` from an error body shaped like Anthropic's
+/// `{"type": "error", "error": {"type": ..., "message": ...}}`, or the body
+/// unchanged when it is not
+fn error_detail(body: &str) -> String {
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(body) else {
+        return body.to_string();
+    };
+    let error = &value["error"];
+    match (error["type"].as_str(), error["message"].as_str()) {
+        (Some(kind), Some(message)) => format!("{}: {}", kind, message),
+        (None, Some(message)) => message.to_string(),
+        _ => body.to_string(),
+    }
+}
+
+/// Whether an error body says the requested model does not exist
+///
+/// Anthropic answers 404 `not_found_error` with a message naming the model,
+/// OpenAI-compatible backends `code: "model_not_found"`; others only say so
+/// in the message. A 404 for a wrong endpoint path mentions no model.
+fn is_model_not_found_body(body: &str) -> bool {
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(body) else {
+        return false;
+    };
+    let error = &value["error"];
+    let field = |name: &str| error[name].as_str().unwrap_or_default();
+    let message = field("message").to_ascii_lowercase();
+    let about_model = message.contains("model");
+
+    field("code") == "model_not_found"
+        || (field("type") == "not_found_error" && about_model)
+        || (about_model && (message.contains("not found") || message.contains("does not exist")))
+}
+
+/// Turn a failed exchange into `ConnectionFailed` when no connection could
+/// be opened (within `connect_timeout_secs`) and into `Timeout` when the
+/// request outlived `request_timeout_secs`, so a down backend reads
+/// differently from a slow one
+fn classify_send_error(e: reqwest::Error, request_timeout_secs: u64) -> BrainError {
+    if e.is_connect() {
+        BrainError::ConnectionFailed(e.to_string())
+    } else if e.is_timeout() {
+        BrainError::Timeout(request_timeout_secs)
+    } else {
+        BrainError::NetworkError(e)
+    }
+}
+
+/// First `max_chars` characters of `body` for logging, cut on a char
+/// boundary and marked with "..." when shortened; 0 keeps the whole body
+fn preview(body: &str, max_chars: usize) -> std::borrow::Cow<'_, str> {
+    if max_chars == 0 {
+        return body.into();
+    }
+    let short = truncate_chars(body, max_chars);
+    if short.len() < body.len() {
+        format!("{}...", short).into()
+    } else {
+        body.into()
+    }
+}
+
+/// Remember `message` unless already seen, keeping the last few distinct ones
+fn record_error(history: &mut Vec<String>, message: String) {
+    if history.contains(&message) {
+        return;
+    }
+    if history.len() == ERROR_HISTORY_LEN {
+        history.remove(0);
+    }
+    history.push(message);
+}
+
+/// Default request headers with the configured extra headers applied on top
+fn build_headers(config: &BrainConfig) -> Result<HeaderMap, super::BrainInitError> {
+    let invalid = |what: &str, e: &dyn std::fmt::Display| {
+        super::BrainInitError::ConfigInvalid(format!("{}: {}", what, e))
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "authorization",
+        HeaderValue::from_str(&format!("Bearer {}", config.api_key))
+            .map_err(|e| invalid("API key", &e))?,
+    );
+    headers.insert(
+        "anthropic-version",
+        HeaderValue::from_static(DEFAULT_ANTHROPIC_VERSION),
+    );
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+    for (name, value) in &config.extra_headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| invalid(&format!("header name {:?}", name), &e))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|e| invalid(&format!("header {} value", name), &e))?;
+        debug!(header = %name, "applying extra request header");
+        headers.insert(name, value);
+    }
+
+    Ok(headers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brain::mock_server::{MockServer, text_response};
+    use crate::brain::{ContentBlock, RequestBuilder};
+
+    fn request() -> MessageRequest {
+        RequestBuilder::new("big-model")
+            .user_text("hello")
+            .max_tokens(16)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_preview_cuts_on_char_boundary() {
+        // Byte 200 falls inside the 100th 'é' (2 bytes each, after one 'a')
+        let body = format!("a{}", "é".repeat(300));
+        assert!(!body.is_char_boundary(200));
+
+        let short = preview(&body, 200);
+        assert!(short.ends_with("..."));
+        assert_eq!(short.trim_end_matches("...").chars().count(), 200);
+        assert!(body.starts_with(short.trim_end_matches("...")));
+
+        assert_eq!(preview(&body, 0), body);
+        assert_eq!(preview("short", 200), "short");
+    }
+
+    #[tokio::test]
+    async fn test_default_headers_sent() {
+        let server = MockServer::start(vec![(200, text_response("hi"))]).await;
+        let mut config = BrainConfig::for_tests();
+        config.endpoint = server.endpoint();
+
+        let brain = Brain::new(config).await.unwrap();
+        brain.infer(request()).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(requests[0].path, "/v1/messages");
+        assert!(requests[0].body.contains("\"big-model\""));
+        assert_eq!(
+            requests[0].headers["anthropic-version"],
+            DEFAULT_ANTHROPIC_VERSION
+        );
+        assert_eq!(requests[0].headers["authorization"], "Bearer test-key");
+    }
+
+    #[tokio::test]
+    async fn test_extra_headers_sent() {
+        let server = MockServer::start(vec![(200, text_response("hi"))]).await;
+        let mut config = BrainConfig::for_tests();
+        config.endpoint = server.endpoint();
+        config
+            .extra_headers
+            .insert("anthropic-version".to_string(), "2099-01-01".to_string());
+        config.extra_headers.insert(
+            "anthropic-beta".to_string(),
+            "feature-a,feature-b".to_string(),
+        );
+
+        let brain = Brain::new(config).await.unwrap();
+        brain.infer(request()).await.unwrap();
+
+        let headers = &server.requests()[0].headers;
+        assert_eq!(headers["anthropic-version"], "2099-01-01");
+        assert_eq!(headers["anthropic-beta"], "feature-a,feature-b");
+    }
+
+    #[tokio::test]
+    async fn test_model_not_found_mapped() {
+        let body =
+            r#"{"type":"error","error":{"type":"not_found_error","message":"model: big-model"}}"#;
+        let server = MockServer::start(vec![(404, body.to_string())]).await;
+        let mut config = BrainConfig::for_tests();
+        config.endpoint = server.endpoint();
+        config.max_retries = 3;
+
+        let brain = Brain::new(config).await.unwrap();
+        let err = brain.infer(request()).await.unwrap_err();
+        assert!(
+            matches!(&err, BrainError::ModelNotFound(model) if model == "big-model"),
+            "{:?}",
+            err
+        );
+        assert!(
+            crate::brain::is_model_not_found(&err.to_string()),
+            "{}",
+            err
+        );
+        // Not retried
+        assert_eq!(server.requests().len(), 1);
+
+        // OpenAI-compatible and plain-message variants
+        assert!(is_model_not_found_body(
+            r#"{"error":{"message":"The model `gpt-9` does not exist or you do not have access to it.","type":"invalid_request_error","code":"model_not_found"}}"#
+        ));
+        assert!(is_model_not_found_body(
+            r#"{"error":{"message":"model 'llama9' not found, try pulling it first"}}"#
+        ));
+        // Other 400s and a wrong path stay as they were
+        assert!(!is_model_not_found_body(
+            r#"{"type":"error","error":{"type":"invalid_request_error","message":"max_tokens: must be positive"}}"#
+        ));
+        assert!(!is_model_not_found_body("404 page not found"));
+    }
+
+    #[tokio::test]
+    async fn test_error_envelope_parsed() {
+        let body = r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        assert_eq!(error_detail(body), "overloaded_error: Overloaded");
+        // OpenAI-compatible bodies may carry only a message
+        assert_eq!(
+            error_detail(r#"{"error":{"message":"rate limited"}}"#),
+            "rate limited"
+        );
+        // Anything else is kept as sent
+        assert_eq!(error_detail("502 Bad Gateway"), "502 Bad Gateway");
+        assert_eq!(
+            error_detail(r#"{"detail":"no such route"}"#),
+            r#"{"detail":"no such route"}"#
+        );
+
+        let server = MockServer::start(vec![(529, body.to_string())]).await;
+        let mut config = BrainConfig::for_tests();
+        config.endpoint = server.endpoint();
+
+        let brain = Brain::new(config).await.unwrap();
+        let err = brain.infer(request()).await.unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("last error: Model error: overloaded_error: Overloaded"),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_reports_history_and_elapsed() {
+        let server = MockServer::start(vec![
+            (500, "backend overloaded".to_string()),
+            (502, "bad gateway".to_string()),
+            (500, "backend overloaded".to_string()),
+        ])
+        .await;
+        let mut config = BrainConfig::for_tests();
+        config.endpoint = server.endpoint();
+        config.max_retries = 2;
+
+        let brain = Brain::new(config).await.unwrap();
+        let err = brain.infer(request()).await.unwrap_err();
+
+        let BrainError::Exhausted {
+            retries,
+            last_error,
+            elapsed,
+            history,
+        } = &err
+        else {
+            panic!("expected Exhausted, got {:?}", err);
+        };
+        assert_eq!(*retries, 3);
+        assert_eq!(last_error, "Model error: backend overloaded");
+        assert_eq!(
+            history,
+            &[
+                "Model error: backend overloaded",
+                "Model error: bad gateway"
+            ]
+        );
+        // Two backoff delays of 1ms and 2ms at least
+        assert!(*elapsed >= Duration::from_millis(3));
+        assert!(err.to_string().contains("bad gateway"));
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_infer_with_opts_overrides_retries() {
+        let server = MockServer::start(vec![(500, "backend overloaded".to_string())]).await;
+        let mut config = BrainConfig::for_tests();
+        config.endpoint = server.endpoint();
+        config.max_retries = 3;
+
+        let brain = Brain::new(config).await.unwrap();
+        let opts = InferOpts {
+            max_retries: Some(0),
+        };
+        let err = brain.infer_with_opts(request(), opts).await.unwrap_err();
+        assert!(
+            matches!(err, BrainError::Exhausted { retries: 1, .. }),
+            "{:?}",
+            err
+        );
+        assert_eq!(server.requests().len(), 1);
+
+        // Without an override the configured retries apply
+        brain.infer(request()).await.unwrap_err();
+        assert_eq!(server.requests().len(), 1 + 4);
+    }
+
+    #[test]
+    fn test_record_error_keeps_last_distinct() {
+        let mut history = Vec::new();
+        for i in 0..ERROR_HISTORY_LEN + 2 {
+            record_error(&mut history, format!("error {}", i));
+            record_error(&mut history, format!("error {}", i));
+        }
+        assert_eq!(history.len(), ERROR_HISTORY_LEN);
+        assert_eq!(history[0], "error 2");
+        assert_eq!(
+            history.last().unwrap(),
+            &format!("error {}", ERROR_HISTORY_LEN + 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_response_cache_hits_identical_requests() {
+        let server = MockServer::start(vec![
+            (200, text_response("first")),
+            (200, text_response("second")),
+        ])
+        .await;
+        let mut config = BrainConfig::for_tests();
+        config.endpoint = server.endpoint();
+        config.response_cache_size = 8;
+
+        let brain = Brain::new(config).await.unwrap();
+        let first = brain.infer(request()).await.unwrap();
+        let again = brain.infer(request()).await.unwrap();
+        assert_eq!(server.requests().len(), 1);
+        assert_eq!(first.content, again.content);
+
+        let other = RequestBuilder::new("big-model")
+            .user_text("something else")
+            .max_tokens(16)
+            .build()
+            .unwrap();
+        let response = brain.infer(other).await.unwrap();
+        assert_eq!(server.requests().len(), 2);
+        assert!(matches!(&response.content[..], [ContentBlock::Text { text }] if text == "second"));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_requests_are_bounded() {
+        let server = MockServer::start_with_delay(
+            vec![(200, text_response("hi"))],
+            Duration::from_millis(100),
+        )
+        .await;
+        let mut config = BrainConfig::for_tests();
+        config.endpoint = server.endpoint();
+        config.max_concurrent_requests = 2;
+
+        let brain = Brain::new(config).await.unwrap();
+        let calls: Vec<_> = (0..6)
+            .map(|_| {
+                let brain = brain.clone();
+                tokio::spawn(async move { brain.infer(request()).await })
+            })
+            .collect();
+        for call in calls {
+            call.await.unwrap().unwrap();
+        }
+
+        assert_eq!(server.requests().len(), 6);
+        assert_eq!(server.peak_in_flight(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_requests_exceed_idle_pool_size() {
+        let server = MockServer::start_with_delay(
+            vec![(200, text_response("hi"))],
+            Duration::from_millis(50),
+        )
+        .await;
+        let mut config = BrainConfig::for_tests();
+        config.endpoint = server.endpoint();
+        config.pool_max_idle_per_host = 2;
+        config.pool_idle_timeout_secs = 1;
+
+        let brain = Brain::new(config).await.unwrap();
+        let calls: Vec<_> = (0..16)
+            .map(|_| {
+                let brain = brain.clone();
+                tokio::spawn(async move { brain.infer(request()).await })
+            })
+            .collect();
+        for call in calls {
+            call.await.unwrap().unwrap();
+        }
+
+        assert_eq!(server.requests().len(), 16);
+        assert!(server.peak_in_flight() > 2);
+    }
+
+    #[tokio::test]
+    async fn test_slow_backend_is_request_timeout() {
+        let server = MockServer::start_with_delay(
+            vec![(200, text_response("too late"))],
+            Duration::from_secs(30),
+        )
+        .await;
+        let mut config = BrainConfig::for_tests();
+        config.endpoint = server.endpoint();
+        config.request_timeout_secs = 1;
+
+        let brain = Brain::new(config).await.unwrap();
+        let err = brain.infer(request()).await.unwrap_err();
+        let BrainError::Exhausted { last_error, .. } = &err else {
+            panic!("expected Exhausted, got {:?}", err);
+        };
+        assert_eq!(last_error, "Timeout after 1 seconds");
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_backend_is_transient_connection_error() {
+        let mut config = BrainConfig::for_tests();
+        config.max_retries = 1;
+
+        let brain = Brain::new(config).await.unwrap();
+        let err = brain.infer(request()).await.unwrap_err();
+        let BrainError::Exhausted {
+            retries,
+            last_error,
+            ..
+        } = &err
+        else {
+            panic!("expected Exhausted, got {:?}", err);
+        };
+        assert_eq!(*retries, 2);
+        assert!(last_error.starts_with("Connection error"), "{}", last_error);
+    }
+
+    #[tokio::test]
+    async fn test_authentication_failure_is_not_retried() {
+        let server = MockServer::start(vec![(401, "bad key".to_string())]).await;
+        let mut config = BrainConfig::for_tests();
+        config.endpoint = server.endpoint();
+        config.max_retries = 3;
+
+        let brain = Brain::new(config).await.unwrap();
+        let err = brain.infer(request()).await.unwrap_err();
+        assert!(
+            matches!(err, BrainError::AuthenticationFailed(_)),
+            "{:?}",
+            err
+        );
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_model_override_checked_against_allowed_models() {
+        let server = MockServer::start(vec![(200, text_response("hi"))]).await;
+        let mut config = BrainConfig::for_tests();
+        config.endpoint = server.endpoint();
+        config.allowed_models = Some(vec!["small-model".to_string()]);
+
+        let brain = Brain::new(config).await.unwrap();
+        let for_model = |model: &str| {
+            RequestBuilder::new(model)
+                .user_text("hello")
+                .max_tokens(16)
+                .build()
+                .unwrap()
+        };
+        brain.infer(for_model("small-model")).await.unwrap();
+        brain.infer(for_model("big-model")).await.unwrap();
+
+        let err = brain.infer(for_model("huge-model")).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Model not allowed: huge-model (allowed: big-model, small-model)"
+        );
+        // Refused before reaching the backend
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_response_cache_disabled_by_default() {
+        let server = MockServer::start(vec![(200, text_response("hi"))]).await;
+        let mut config = BrainConfig::for_tests();
+        config.endpoint = server.endpoint();
+
+        let brain = Brain::new(config).await.unwrap();
+        brain.infer(request()).await.unwrap();
+        brain.infer(request()).await.unwrap();
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_infer_raw_returns_unparsed_body() {
+        let mut body: serde_json::Value = serde_json::from_str(&text_response("hi")).unwrap();
+        body["vendor_trace"] = serde_json::json!({ "region": "cn-1", "cached": true });
+        let server = MockServer::start(vec![(200, body.to_string())]).await;
+        let mut config = BrainConfig::for_tests();
+        config.endpoint = server.endpoint();
+
+        let brain = Brain::new(config).await.unwrap();
+        let (response, raw) = brain.infer_raw(request()).await.unwrap();
+
+        assert_eq!(response.id, "msg_mock");
+        assert!(matches!(&response.content[..], [ContentBlock::Text { text }] if text == "hi"));
+        assert_eq!(raw, body);
+        assert_eq!(raw["vendor_trace"]["region"], "cn-1");
+    }
+
+    #[tokio::test]
+    async fn test_inference_deadline_aborts_slow_response() {
+        let server = MockServer::start_with_delay(
+            vec![(200, text_response("too late"))],
+            Duration::from_secs(30),
+        )
+        .await;
+        let mut config = BrainConfig::for_tests();
+        config.endpoint = server.endpoint();
+        config.request_timeout_secs = 60;
+        config.inference_deadline_secs = Some(1);
+
+        let brain = Brain::new(config).await.unwrap();
+        let start = Instant::now();
+        let result = brain.infer(request()).await;
+
+        assert!(matches!(result, Err(BrainError::Timeout(1))));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_extra_header_rejected() {
+        let mut config = BrainConfig::for_tests();
+        config
+            .extra_headers
+            .insert("bad header".to_string(), "x".to_string());
+
+        assert!(matches!(
+            Brain::new(config).await,
+            Err(crate::brain::BrainInitError::ConfigInvalid(_))
+        ));
+    }
+}
@@ -1,5 +1,6 @@
 // Error types for Brain module
 
+use std::time::Duration;
 use thiserror::Error;
 
 /// Runtime errors from Brain
@@ -15,8 +16,26 @@ pub enum BrainError {
     #[error("Insufficient balance: {0}")]
     InsufficientBalance(String),
 
-    #[error("Exhausted: max retries ({retries}) exceeded, last error: {last_error}")]
-    Exhausted { retries: u32, last_error: String },
+    #[error("Model not allowed: {model} (allowed: {})", allowed.join(", "))]
+    ModelNotAllowed { model: String, allowed: Vec<String> },
+
+    /// The backend does not know the requested model (or this key may not
+    /// use it)
+    #[error("Model not found: {0} (check the configured model name)")]
+    ModelNotFound(String),
+
+    #[error(
+        "Exhausted: max retries ({retries}) exceeded after {elapsed:?}, last error: {last_error}, history: [{}]",
+        history.join(" | ")
+    )]
+    Exhausted {
+        retries: u32,
+        last_error: String,
+        /// Time spent across all attempts and backoff delays
+        elapsed: Duration,
+        /// Last few distinct error messages, oldest first
+        history: Vec<String>,
+    },
 
     #[error("Model error: {0}")]
     ModelError(String),
@@ -24,6 +43,11 @@ pub enum BrainError {
     #[error("Timeout after {0} seconds")]
     Timeout(u64),
 
+    /// No connection to the backend could be opened (refused, unresolvable,
+    /// or not established within `connect_timeout_secs`)
+    #[error("Connection error: {0}")]
+    ConnectionFailed(String),
+
     #[error("Network error: {0}")]
     NetworkError(#[from] reqwest::Error),
 
@@ -31,6 +55,29 @@ pub enum BrainError {
     SerializationError(#[from] serde_json::Error),
 }
 
+impl BrainError {
+    /// Whether trying the same request again may succeed
+    ///
+    /// Authentication, balance and (de)serialization failures will not go
+    /// away on their own, so retrying them only delays the error.
+    pub fn is_transient(&self) -> bool {
+        !matches!(
+            self,
+            BrainError::AuthenticationFailed(_)
+                | BrainError::InsufficientBalance(_)
+                | BrainError::ModelNotAllowed { .. }
+                | BrainError::ModelNotFound(_)
+                | BrainError::SerializationError(_)
+        )
+    }
+}
+
+/// Whether `message` is a rendered `BrainError::ModelNotFound`, for callers
+/// that only see inference errors as text
+pub fn is_model_not_found(message: &str) -> bool {
+    message.starts_with("Model not found: ")
+}
+
 /// Initialization errors for Brain
 #[derive(Debug, Error)]
 #[allow(dead_code)]
@@ -47,3 +94,27 @@ pub enum BrainInitError {
     #[error("Connection check failed: {0}")]
     ConnectionFailed(String),
 }
+
+/// Why `RequestBuilder::build` refused to build a request
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum BuildError {
+    #[error("messages cannot be empty")]
+    NoMessages,
+
+    #[error("first message must have user role")]
+    FirstMessageNotUser,
+
+    #[error("assistant prefill must follow a user message")]
+    PrefillAfterAssistant,
+
+    #[error("assistant prefill cannot be empty")]
+    EmptyPrefill,
+
+    #[error("message {index} ({role}) has {bytes} bytes of content, over the limit of {limit}")]
+    MessageTooLarge {
+        index: usize,
+        role: super::Role,
+        bytes: usize,
+        limit: usize,
+    },
+}
@@ -0,0 +1,174 @@
+// Minimal HTTP server standing in for the inference backend in tests
+
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// A request received by the mock server
+#[derive(Debug, Clone)]
+pub struct CapturedRequest {
+    pub method: String,
+    pub path: String,
+    /// Header names are lowercased
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+/// Serves queued `(status, body)` responses, one per connection, and records
+/// every request. The last response is repeated once the queue runs out.
+pub struct MockServer {
+    addr: SocketAddr,
+    requests: Arc<Mutex<Vec<CapturedRequest>>>,
+    in_flight: Arc<InFlight>,
+}
+
+/// Requests currently being served and the most seen at once
+#[derive(Default)]
+struct InFlight {
+    current: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl MockServer {
+    pub async fn start(responses: Vec<(u16, String)>) -> Self {
+        Self::start_with_delay(responses, Duration::ZERO).await
+    }
+
+    /// Like `start`, but waits `delay` after reading each request before
+    /// replying, to simulate a slow backend
+    pub async fn start_with_delay(responses: Vec<(u16, String)>, delay: Duration) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let responses = Arc::new(Mutex::new(VecDeque::from(responses)));
+        let in_flight = Arc::new(InFlight::default());
+
+        let captured = requests.clone();
+        let tracked = in_flight.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let response = {
+                    let mut responses = responses.lock().unwrap();
+                    if responses.len() > 1 {
+                        responses.pop_front()
+                    } else {
+                        responses.front().cloned()
+                    }
+                };
+                let captured = captured.clone();
+                let tracked = tracked.clone();
+                tokio::spawn(async move {
+                    let now = tracked.current.fetch_add(1, Ordering::SeqCst) + 1;
+                    tracked.peak.fetch_max(now, Ordering::SeqCst);
+                    serve(stream, response, captured, delay).await;
+                    tracked.current.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+
+        Self {
+            addr,
+            requests,
+            in_flight,
+        }
+    }
+
+    /// Base URL to use as the brain endpoint
+    pub fn endpoint(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Requests received so far
+    pub fn requests(&self) -> Vec<CapturedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Most connections served at the same time
+    pub fn peak_in_flight(&self) -> usize {
+        self.in_flight.peak.load(Ordering::SeqCst)
+    }
+}
+
+/// A successful Messages API response body with a single text block
+pub fn text_response(text: &str) -> String {
+    serde_json::json!({
+        "id": "msg_mock",
+        "type": "message",
+        "role": "assistant",
+        "model": "mock-model",
+        "content": [{ "type": "text", "text": text }],
+        "stop_reason": "end_turn",
+        "usage": { "input_tokens": 10, "output_tokens": 5 }
+    })
+    .to_string()
+}
+
+/// Read one request, record it, then write the queued response
+async fn serve(
+    mut stream: TcpStream,
+    response: Option<(u16, String)>,
+    captured: Arc<Mutex<Vec<CapturedRequest>>>,
+    delay: Duration,
+) -> Option<()> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+
+    let header_end = loop {
+        let n = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).into_owned();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next()?.split_whitespace();
+    let method = request_line.next()?.to_string();
+    let path = request_line.next()?.to_string();
+    let headers: HashMap<String, String> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+
+    let content_length = headers
+        .get("content-length")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    while buf.len() < header_end + content_length {
+        let n = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    let body = String::from_utf8_lossy(&buf[header_end..]).into_owned();
+
+    // Record before replying so the client never observes a response for an
+    // unrecorded request
+    captured.lock().unwrap().push(CapturedRequest {
+        method,
+        path,
+        headers,
+        body,
+    });
+
+    tokio::time::sleep(delay).await;
+
+    let (status, body_out) = response.unwrap_or((500, "no response queued".to_string()));
+    let reply = format!(
+        "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body_out.len(),
+        body_out
+    );
+    stream.write_all(reply.as_bytes()).await.ok()?;
+    stream.shutdown().await.ok()
+}
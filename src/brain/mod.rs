@@ -2,15 +2,22 @@
 // See docs/brain-design.md for design details
 
 pub mod builder;
+pub mod cache;
 pub mod client;
 pub mod error;
+#[cfg(test)]
+pub mod mock_server;
+pub mod text;
 pub mod types;
 
 pub use builder::RequestBuilder;
 pub use client::Brain;
-pub use error::{BrainError, BrainInitError};
+pub use error::{BrainError, BrainInitError, BuildError, is_model_not_found};
 pub use types::{ContentBlock, Message, MessageRequest, MessageResponse, Role, ToolDefinition};
 
+use std::collections::HashMap;
+use tracing::warn;
+
 /// Brain configuration
 #[derive(Debug, Clone)]
 pub struct BrainConfig {
@@ -20,14 +27,37 @@ pub struct BrainConfig {
     pub api_key: String,
     /// Default model identifier
     pub default_model: String,
+    /// Models a request may name besides `default_model` (None = any)
+    pub allowed_models: Option<Vec<String>>,
     /// Maximum retry attempts
     pub max_retries: u32,
     /// Base retry delay in milliseconds
     pub base_retry_delay_ms: u64,
     /// Request timeout in seconds
     pub request_timeout_secs: u64,
+    /// How long opening a connection to the backend may take, in seconds
+    pub connect_timeout_secs: u64,
+    /// Upper bound on one `infer` call, retries included (None = unbounded)
+    pub inference_deadline_secs: Option<u64>,
+    /// Responses kept for identical requests (0 = caching disabled)
+    pub response_cache_size: usize,
+    /// How long a cached response stays valid
+    pub response_cache_ttl_secs: u64,
+    /// Inferences sent to the backend at once; further calls queue (0 = unlimited)
+    pub max_concurrent_requests: usize,
+    /// Idle keep-alive connections kept per backend host (0 = no pooling)
+    pub pool_max_idle_per_host: usize,
+    /// How long an idle pooled connection is kept before closing (0 = forever)
+    pub pool_idle_timeout_secs: u64,
+    /// Characters of each response body shown in debug logs (0 = whole body)
+    pub log_preview_chars: usize,
     /// Maximum output tokens
     pub max_output_tokens: u32,
+    /// Per-model output token limits; requests are clamped to these
+    pub model_max_tokens: HashMap<String, u32>,
+    /// Extra HTTP headers sent with every request; may override the
+    /// default `anthropic-version`
+    pub extra_headers: HashMap<String, String>,
     /// Temperature (0.0-2.0, None = use model default)
     pub temperature: Option<f32>,
     /// Top-P nucleus sampling (0.0-1.0, None = use model default)
@@ -42,11 +72,26 @@ impl BrainConfig {
 
         let endpoint = std::env::var("INFERENCE_ENDPOINT")
             .map_err(|_| BrainInitError::ConfigMissing("INFERENCE_ENDPOINT".into()))?;
-        let api_key = std::env::var("INFERENCE_API_KEY")
-            .map_err(|_| BrainInitError::ConfigMissing("INFERENCE_API_KEY".into()))?;
+        let api_key = resolve_api_key(
+            std::env::var("INFERENCE_API_KEY_FILE")
+                .ok()
+                .filter(|v| !v.is_empty()),
+            std::env::var("INFERENCE_API_KEY").ok(),
+        )?;
         let default_model = std::env::var("INFERENCE_MODEL")
             .map_err(|_| BrainInitError::ConfigMissing("INFERENCE_MODEL".into()))?;
 
+        let allowed_models = std::env::var("INFERENCE_ALLOWED_MODELS")
+            .ok()
+            .map(|v| {
+                v.split(',')
+                    .map(str::trim)
+                    .filter(|m| !m.is_empty())
+                    .map(String::from)
+                    .collect::<Vec<_>>()
+            })
+            .filter(|models| !models.is_empty());
+
         let max_retries = std::env::var("INFERENCE_MAX_RETRIES")
             .ok()
             .and_then(|v| v.parse().ok())
@@ -62,19 +107,69 @@ impl BrainConfig {
             .and_then(|v| v.parse().ok())
             .unwrap_or(120);
 
+        let connect_timeout_secs = std::env::var("INFERENCE_CONNECT_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(10);
+
+        let inference_deadline_secs = std::env::var("INFERENCE_DEADLINE_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|&secs| secs > 0);
+
+        let response_cache_size = std::env::var("INFERENCE_CACHE_SIZE")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0);
+
+        let response_cache_ttl_secs = std::env::var("INFERENCE_CACHE_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(60);
+
+        let max_concurrent_requests = std::env::var("INFERENCE_MAX_CONCURRENT")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0);
+
+        let pool_max_idle_per_host = std::env::var("INFERENCE_POOL_MAX_IDLE")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(32);
+
+        let pool_idle_timeout_secs = std::env::var("INFERENCE_POOL_IDLE_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(90);
+
+        let log_preview_chars = std::env::var("INFERENCE_LOG_PREVIEW_CHARS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(200);
+
         let max_output_tokens = std::env::var("INFERENCE_MAX_TOKENS")
             .ok()
             .and_then(|v| v.parse().ok())
             .unwrap_or(4096);
 
+        let model_max_tokens = std::env::var("INFERENCE_MODEL_MAX_TOKENS")
+            .map(|v| parse_model_max_tokens(&v))
+            .unwrap_or_default();
+
+        let extra_headers = std::env::var("INFERENCE_EXTRA_HEADERS")
+            .map(|v| parse_extra_headers(&v))
+            .unwrap_or_default();
+
         // Inference parameters (optional, use model defaults if not set)
         let temperature = std::env::var("INFERENCE_TEMPERATURE")
             .ok()
-            .and_then(|v| v.parse().ok());
+            .and_then(|v| v.parse().ok())
+            .map(|t| clamp_sampling_param("INFERENCE_TEMPERATURE", t, 0.0, 2.0));
 
         let top_p = std::env::var("INFERENCE_TOP_P")
             .ok()
-            .and_then(|v| v.parse().ok());
+            .and_then(|v| v.parse().ok())
+            .map(|p| clamp_sampling_param("INFERENCE_TOP_P", p, 0.0, 1.0));
 
         let top_k = std::env::var("INFERENCE_TOP_K")
             .ok()
@@ -84,13 +179,376 @@ impl BrainConfig {
             endpoint,
             api_key,
             default_model,
+            allowed_models,
             max_retries,
             base_retry_delay_ms,
             request_timeout_secs,
+            connect_timeout_secs,
+            inference_deadline_secs,
+            response_cache_size,
+            response_cache_ttl_secs,
+            max_concurrent_requests,
+            pool_max_idle_per_host,
+            pool_idle_timeout_secs,
+            log_preview_chars,
             max_output_tokens,
+            model_max_tokens,
+            extra_headers,
             temperature,
             top_p,
             top_k,
         })
     }
+
+    /// Check ranges and well-formedness, reporting every problem at once
+    pub fn validate(&self) -> Result<(), BrainInitError> {
+        let mut problems = Vec::new();
+
+        if !self.endpoint.starts_with("http://") && !self.endpoint.starts_with("https://") {
+            problems.push(format!(
+                "endpoint must start with http:// or https://, got {:?}",
+                self.endpoint
+            ));
+        }
+        if self.api_key.trim().is_empty() {
+            problems.push("api_key must not be empty".to_string());
+        }
+        if self.default_model.trim().is_empty() {
+            problems.push("default_model must not be empty".to_string());
+        }
+        if self.request_timeout_secs == 0 {
+            problems.push("request_timeout_secs must be greater than 0".to_string());
+        }
+        if self.connect_timeout_secs == 0 {
+            problems.push("connect_timeout_secs must be greater than 0".to_string());
+        }
+        if self.inference_deadline_secs == Some(0) {
+            problems.push("inference_deadline_secs must be greater than 0".to_string());
+        }
+        if self.max_output_tokens == 0 {
+            problems.push("max_output_tokens must be greater than 0".to_string());
+        }
+        for (model, &limit) in &self.model_max_tokens {
+            if limit == 0 {
+                problems.push(format!(
+                    "max tokens for model {} must be greater than 0",
+                    model
+                ));
+            }
+        }
+        if let Some(t) = self.temperature
+            && !(0.0..=2.0).contains(&t)
+        {
+            problems.push(format!(
+                "temperature must be between 0.0 and 2.0, got {}",
+                t
+            ));
+        }
+        if let Some(p) = self.top_p
+            && !(0.0..=1.0).contains(&p)
+        {
+            problems.push(format!("top_p must be between 0.0 and 1.0, got {}", p));
+        }
+        if self.top_k == Some(0) {
+            problems.push("top_k must be greater than 0".to_string());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(BrainInitError::ConfigInvalid(problems.join("; ")))
+        }
+    }
+
+    /// Output token limit for `model`: the global default, clamped to the
+    /// model's configured limit if it has one
+    pub fn max_tokens_for(&self, model: &str) -> u32 {
+        match self.model_max_tokens.get(model) {
+            Some(&limit) => self.max_output_tokens.min(limit),
+            None => self.max_output_tokens,
+        }
+    }
+
+    /// Whether a request may use `model`: the default always, others only
+    /// when `allowed_models` is unset or lists them
+    pub fn is_model_allowed(&self, model: &str) -> bool {
+        model == self.default_model
+            || self
+                .allowed_models
+                .as_ref()
+                .is_none_or(|allowed| allowed.iter().any(|m| m == model))
+    }
+}
+
+/// Pick the API key: the trimmed contents of `key_file` when set (e.g. a
+/// Docker or Kubernetes secret), otherwise the inline value
+fn resolve_api_key(
+    key_file: Option<String>,
+    inline: Option<String>,
+) -> Result<String, BrainInitError> {
+    let Some(path) = key_file else {
+        return inline.ok_or_else(|| BrainInitError::ConfigMissing("INFERENCE_API_KEY".into()));
+    };
+
+    let key = std::fs::read_to_string(&path).map_err(|e| {
+        BrainInitError::ConfigInvalid(format!("INFERENCE_API_KEY_FILE {}: {}", path, e))
+    })?;
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(BrainInitError::ConfigInvalid(format!(
+            "INFERENCE_API_KEY_FILE {} is empty",
+            path
+        )));
+    }
+    Ok(key.to_string())
+}
+
+/// `value` limited to `[min, max]`, warning when it had to be moved
+///
+/// Backends reject out-of-range sampling parameters with an opaque 400, so a
+/// typo like `INFERENCE_TEMPERATURE=20` is pulled to the nearest bound
+/// instead. NaN cannot be clamped and is left for `validate` to report.
+fn clamp_sampling_param(var: &str, value: f32, min: f32, max: f32) -> f32 {
+    let clamped = value.clamp(min, max);
+    if clamped != value && !value.is_nan() {
+        warn!(
+            var,
+            value, clamped, "{} outside {}..={}, clamping", var, min, max
+        );
+    }
+    clamped
+}
+
+/// Parse a `model1=4096,model2=8192` list, skipping malformed pairs
+fn parse_model_max_tokens(value: &str) -> HashMap<String, u32> {
+    let mut limits = HashMap::new();
+
+    for pair in value.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        match pair
+            .rsplit_once('=')
+            .and_then(|(model, limit)| Some((model.trim(), limit.trim().parse().ok()?)))
+        {
+            Some((model, limit)) if !model.is_empty() => {
+                limits.insert(model.to_string(), limit);
+            }
+            _ => warn!(pair = %pair, "Invalid INFERENCE_MODEL_MAX_TOKENS entry, ignoring"),
+        }
+    }
+
+    limits
+}
+
+/// Parse a `name: value; name: value` header list, skipping malformed pairs
+fn parse_extra_headers(value: &str) -> HashMap<String, String> {
+    let mut headers = HashMap::new();
+
+    for pair in value.split(';').map(str::trim).filter(|p| !p.is_empty()) {
+        match pair.split_once(':') {
+            Some((name, value)) if !name.trim().is_empty() => {
+                headers.insert(name.trim().to_string(), value.trim().to_string());
+            }
+            _ => warn!(pair = %pair, "Invalid INFERENCE_EXTRA_HEADERS entry, ignoring"),
+        }
+    }
+
+    headers
+}
+
+#[cfg(test)]
+impl BrainConfig {
+    /// Config pointing at an unreachable endpoint, for unit tests
+    pub fn for_tests() -> Self {
+        Self {
+            endpoint: "http://127.0.0.1:1".to_string(),
+            api_key: "test-key".to_string(),
+            default_model: "big-model".to_string(),
+            allowed_models: None,
+            max_retries: 0,
+            base_retry_delay_ms: 1,
+            request_timeout_secs: 5,
+            connect_timeout_secs: 5,
+            inference_deadline_secs: None,
+            response_cache_size: 0,
+            response_cache_ttl_secs: 60,
+            max_concurrent_requests: 0,
+            pool_max_idle_per_host: 32,
+            pool_idle_timeout_secs: 90,
+            log_preview_chars: 200,
+            max_output_tokens: 8192,
+            model_max_tokens: HashMap::new(),
+            extra_headers: HashMap::new(),
+            temperature: None,
+            top_p: None,
+            top_k: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_model_max_tokens() {
+        let limits = parse_model_max_tokens("model1=4096, model2=8192,broken,=12,bad=x");
+        assert_eq!(limits.len(), 2);
+        assert_eq!(limits["model1"], 4096);
+        assert_eq!(limits["model2"], 8192);
+    }
+
+    #[test]
+    fn test_resolve_api_key_prefers_file() {
+        let path = std::env::temp_dir().join(format!("shelly-key-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "sk-from-file\n").unwrap();
+        let file = Some(path.display().to_string());
+
+        let key = resolve_api_key(file.clone(), Some("sk-inline".to_string())).unwrap();
+        assert_eq!(key, "sk-from-file");
+        assert_eq!(
+            resolve_api_key(None, Some("sk-inline".to_string())).unwrap(),
+            "sk-inline"
+        );
+        assert!(matches!(
+            resolve_api_key(None, None),
+            Err(BrainInitError::ConfigMissing(_))
+        ));
+
+        std::fs::write(&path, "  \n").unwrap();
+        let err = resolve_api_key(file, None).unwrap_err().to_string();
+        assert!(err.contains("is empty"), "{}", err);
+
+        std::fs::remove_file(&path).unwrap();
+        let missing = path.display().to_string();
+        let err = resolve_api_key(Some(missing.clone()), Some("sk-inline".to_string()))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("INFERENCE_API_KEY_FILE"), "{}", err);
+        assert!(err.contains(&missing), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_extra_headers() {
+        let headers = parse_extra_headers(
+            "anthropic-version: 2024-10-22; anthropic-beta: a-2025,b-2025;broken; : x",
+        );
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers["anthropic-version"], "2024-10-22");
+        assert_eq!(headers["anthropic-beta"], "a-2025,b-2025");
+    }
+
+    #[test]
+    fn test_max_tokens_for_clamps_to_model_limit() {
+        let mut config = BrainConfig::for_tests();
+        config
+            .model_max_tokens
+            .insert("small-model".to_string(), 1024);
+        config
+            .model_max_tokens
+            .insert("huge-model".to_string(), 65536);
+
+        assert_eq!(config.max_tokens_for("small-model"), 1024);
+        // A model limit never raises the global default
+        assert_eq!(config.max_tokens_for("huge-model"), 8192);
+        assert_eq!(config.max_tokens_for("unknown-model"), 8192);
+    }
+
+    #[derive(Clone, Default)]
+    struct LogBuffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for LogBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_clamp_sampling_param_warns_when_out_of_range() {
+        let logs = LogBuffer::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .without_time()
+            .with_writer({
+                let logs = logs.clone();
+                move || logs.clone()
+            })
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+        let logged = || String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+
+        assert_eq!(
+            clamp_sampling_param("INFERENCE_TEMPERATURE", 0.7, 0.0, 2.0),
+            0.7
+        );
+        assert_eq!(clamp_sampling_param("INFERENCE_TOP_P", 1.0, 0.0, 1.0), 1.0);
+        assert!(logged().is_empty(), "{}", logged());
+
+        assert_eq!(
+            clamp_sampling_param("INFERENCE_TEMPERATURE", 20.0, 0.0, 2.0),
+            2.0
+        );
+        let warning = logged();
+        assert!(warning.contains("WARN"), "{}", warning);
+        assert!(
+            warning.contains("INFERENCE_TEMPERATURE outside 0..=2"),
+            "{}",
+            warning
+        );
+        assert_eq!(clamp_sampling_param("INFERENCE_TOP_P", -0.5, 0.0, 1.0), 0.0);
+        assert!(logged().contains("INFERENCE_TOP_P"));
+    }
+
+    #[test]
+    fn test_validate_accepts_defaults() {
+        assert!(BrainConfig::for_tests().validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_out_of_range_values() {
+        type Mutation = fn(&mut BrainConfig);
+        let cases: Vec<(Mutation, &str)> = vec![
+            (
+                |c| c.endpoint = "api.example.com".to_string(),
+                "endpoint must start with http",
+            ),
+            (|c| c.api_key = String::new(), "api_key"),
+            (|c| c.request_timeout_secs = 0, "request_timeout_secs"),
+            (|c| c.connect_timeout_secs = 0, "connect_timeout_secs"),
+            (|c| c.max_output_tokens = 0, "max_output_tokens"),
+            (
+                |c| c.temperature = Some(2.5),
+                "temperature must be between 0.0 and 2.0, got 2.5",
+            ),
+            (|c| c.temperature = Some(-0.1), "temperature"),
+            (
+                |c| c.top_p = Some(1.5),
+                "top_p must be between 0.0 and 1.0, got 1.5",
+            ),
+            (|c| c.top_k = Some(0), "top_k"),
+        ];
+
+        for (mutate, expected) in cases {
+            let mut config = BrainConfig::for_tests();
+            mutate(&mut config);
+            let err = config.validate().unwrap_err().to_string();
+            assert!(err.contains(expected), "{:?} not in {:?}", expected, err);
+        }
+    }
+
+    #[test]
+    fn test_validate_reports_every_problem() {
+        let mut config = BrainConfig::for_tests();
+        config.endpoint = "localhost:8080".to_string();
+        config.temperature = Some(3.0);
+        config.top_p = Some(-1.0);
+
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("endpoint"));
+        assert!(err.contains("temperature"));
+        assert!(err.contains("top_p"));
+    }
 }
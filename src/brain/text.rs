@@ -0,0 +1,83 @@
+// UTF-8-safe shortening of text
+//
+// Kept in brain, the one module every other may depend on, so log previews,
+// tool output and anything else cut to a size goes through the same code and
+// never slices a string inside a character.
+
+/// The first `max_chars` characters of `s`
+pub fn truncate_chars(s: &str, max_chars: usize) -> &str {
+    match s.char_indices().nth(max_chars) {
+        Some((end, _)) => &s[..end],
+        None => s,
+    }
+}
+
+/// The longest prefix of `s` that is at most `max_bytes` bytes and ends on a
+/// character boundary
+pub fn truncate_bytes_safe(s: &str, max_bytes: usize) -> &str {
+    &s[..floor_char_boundary(s.as_bytes(), max_bytes)]
+}
+
+/// `index` moved back to the nearest position that does not split a UTF-8
+/// sequence of `bytes`, clamped to its length
+///
+/// Works on raw bytes, such as process output that is only decoded later,
+/// where invalid UTF-8 is cut at `index` unchanged.
+pub fn floor_char_boundary(bytes: &[u8], index: usize) -> usize {
+    if index >= bytes.len() {
+        return bytes.len();
+    }
+    // A UTF-8 sequence has at most three continuation bytes (0b10xx_xxxx)
+    let lowest = index.saturating_sub(3);
+    (lowest..=index)
+        .rev()
+        .find(|&i| bytes[i] & 0b1100_0000 != 0b1000_0000)
+        .unwrap_or(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_chars_counts_characters() {
+        assert_eq!(truncate_chars("héllo wörld", 4), "héll");
+        assert_eq!(truncate_chars("日本語テキスト", 3), "日本語");
+        assert_eq!(truncate_chars("🦀🦀", 1), "🦀");
+        assert_eq!(truncate_chars("short", 10), "short");
+        assert_eq!(truncate_chars("abc", 0), "");
+    }
+
+    #[test]
+    fn test_truncate_bytes_safe_backs_off_inside_characters() {
+        // 'é' is 2 bytes, '日' 3 bytes, '🦀' 4 bytes
+        let text = "aé日🦀z";
+        let expected = [
+            "",
+            "a",
+            "a",
+            "aé",
+            "aé",
+            "aé",
+            "aé日",
+            "aé日",
+            "aé日",
+            "aé日",
+            "aé日🦀",
+            "aé日🦀z",
+        ];
+        for (max_bytes, want) in expected.iter().enumerate() {
+            assert_eq!(truncate_bytes_safe(text, max_bytes), *want, "{}", max_bytes);
+        }
+        assert_eq!(truncate_bytes_safe(text, 100), text);
+    }
+
+    #[test]
+    fn test_floor_char_boundary_leaves_invalid_utf8_alone() {
+        let bytes = "é".repeat(10).into_bytes();
+        assert_eq!(floor_char_boundary(&bytes, 7), 6);
+        assert_eq!(floor_char_boundary(&bytes, 100), 20);
+        // Stray continuation bytes never form a character, cut where asked
+        assert_eq!(floor_char_boundary(&[0x80; 8], 6), 6);
+    }
+}
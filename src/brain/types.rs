@@ -11,6 +11,15 @@ pub enum Role {
     Assistant,
 }
 
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        })
+    }
+}
+
 /// A single message in the conversation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
@@ -39,8 +48,38 @@ impl Message {
     }
 }
 
+/// Read `tool_result` content sent either as a string or, as some backends
+/// do, as an array of content blocks whose text parts are joined by newlines
+fn deserialize_tool_result_content<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Content {
+        Text(String),
+        Blocks(Vec<Part>),
+    }
+
+    /// Non-text parts (e.g. images) have no text and are skipped
+    #[derive(Deserialize)]
+    struct Part {
+        #[serde(default)]
+        text: Option<String>,
+    }
+
+    Ok(match Content::deserialize(deserializer)? {
+        Content::Text(text) => text,
+        Content::Blocks(parts) => parts
+            .into_iter()
+            .filter_map(|part| part.text)
+            .collect::<Vec<_>>()
+            .join("\n"),
+    })
+}
+
 /// Content block types
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ContentBlock {
     /// Text content from model or user
@@ -60,6 +99,7 @@ pub enum ContentBlock {
     /// Tool result from user
     ToolResult {
         tool_use_id: String,
+        #[serde(deserialize_with = "deserialize_tool_result_content")]
         content: String,
         #[serde(default)]
         is_error: Option<bool>,
@@ -102,6 +142,11 @@ impl StopReason {
             StopReason::StopSequence => "stop_sequence",
         }
     }
+
+    /// Whether the turn is over; only `ToolUse` asks for another round
+    pub fn is_terminal(&self) -> bool {
+        !matches!(self, StopReason::ToolUse)
+    }
 }
 
 /// Token usage statistics
@@ -133,7 +178,7 @@ pub struct MessageRequest {
     #[serde(default)]
     pub system: Option<String>,
     pub messages: Vec<Message>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub tools: Option<Vec<ToolDefinition>>,
     #[serde(rename = "max_tokens")]
     pub max_tokens: u32,
@@ -170,3 +215,122 @@ pub struct MessageResponse {
     #[serde(flatten)]
     pub extra: std::collections::HashMap<String, serde_json::Value>,
 }
+
+impl MessageResponse {
+    /// The stop reason to act on
+    ///
+    /// Some backends omit `stop_reason`. A missing one is read as `ToolUse`
+    /// when the reply holds tool calls, so they still run, and as `EndTurn`
+    /// otherwise; either way a warning is logged.
+    pub fn effective_stop_reason(&self) -> StopReason {
+        if let Some(reason) = &self.stop_reason {
+            return reason.clone();
+        }
+
+        let reason = if self
+            .content
+            .iter()
+            .any(|block| matches!(block, ContentBlock::ToolUse { .. }))
+        {
+            StopReason::ToolUse
+        } else {
+            StopReason::EndTurn
+        };
+        tracing::warn!(
+            response_id = %self.id,
+            assumed = reason.as_str(),
+            "response has no stop_reason"
+        );
+        reason
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tool_result_content_string_or_blocks() {
+        let string_form = serde_json::json!({
+            "type": "tool_result",
+            "tool_use_id": "call_1",
+            "content": "line one\nline two",
+        });
+        let array_form = serde_json::json!({
+            "type": "tool_result",
+            "tool_use_id": "call_1",
+            "content": [
+                { "type": "text", "text": "line one" },
+                { "type": "image", "source": { "type": "base64", "data": "" } },
+                { "type": "text", "text": "line two" },
+            ],
+        });
+
+        let expected = ContentBlock::ToolResult {
+            tool_use_id: "call_1".to_string(),
+            content: "line one\nline two".to_string(),
+            is_error: None,
+        };
+        let from_string: ContentBlock = serde_json::from_value(string_form.clone()).unwrap();
+        let from_array: ContentBlock = serde_json::from_value(array_form).unwrap();
+        assert_eq!(from_string, expected);
+        assert_eq!(from_array, expected);
+
+        // Always written back in the string form
+        let written = serde_json::to_value(&from_array).unwrap();
+        assert_eq!(written["content"], string_form["content"]);
+    }
+
+    #[test]
+    fn test_effective_stop_reason() {
+        let response =
+            |stop_reason: Option<StopReason>, content: Vec<ContentBlock>| MessageResponse {
+                id: "msg_1".to_string(),
+                content,
+                model: "test".to_string(),
+                role: Role::Assistant,
+                stop_reason,
+                stop_sequence: None,
+                usage: None,
+                extra: Default::default(),
+            };
+        let text = || ContentBlock::Text {
+            text: "done".to_string(),
+        };
+        let tool_use = || ContentBlock::ToolUse {
+            id: "call_1".to_string(),
+            name: "bash".to_string(),
+            input: serde_json::json!({ "command": "uptime" }),
+        };
+
+        // An explicit reason is kept as is, even if the content disagrees
+        for reason in [
+            StopReason::EndTurn,
+            StopReason::ToolUse,
+            StopReason::MaxTokens,
+            StopReason::StopSequence,
+        ] {
+            let reply = response(Some(reason.clone()), vec![text(), tool_use()]);
+            assert_eq!(reply.effective_stop_reason(), reason);
+        }
+
+        // A missing one follows the content
+        assert_eq!(
+            response(None, vec![text()]).effective_stop_reason(),
+            StopReason::EndTurn
+        );
+        assert_eq!(
+            response(None, vec![text(), tool_use()]).effective_stop_reason(),
+            StopReason::ToolUse
+        );
+        assert_eq!(
+            response(None, vec![]).effective_stop_reason(),
+            StopReason::EndTurn
+        );
+
+        assert!(!StopReason::ToolUse.is_terminal());
+        assert!(StopReason::EndTurn.is_terminal());
+        assert!(StopReason::MaxTokens.is_terminal());
+        assert!(StopReason::StopSequence.is_terminal());
+    }
+}
@@ -1,4 +1,11 @@
-use std::net::SocketAddr;
+use crate::comm::error::CommInitError;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use tracing::warn;
+
+/// Most extra bind attempts; with the 30s backoff cap more would keep a
+/// misconfigured daemon waiting for many minutes
+const MAX_BIND_RETRIES: u32 = 20;
 
 /// Comm module configuration
 #[derive(Debug, Clone)]
@@ -16,6 +23,42 @@ pub struct CommConfig {
     pub dedup_capacity: usize,
     /// Deduplication entry TTL in seconds (default: 300)
     pub dedup_ttl_secs: u64,
+    /// Independently locked buckets the dedup table is split into (default: 16)
+    pub dedup_shards: usize,
+    /// How long to wait for the main loop's reply before answering
+    /// "Response timeout"; keep above the agent's handle timeout (default: 330)
+    pub response_timeout_secs: u64,
+    /// Requests one client may have waiting on the main loop at once;
+    /// more are answered with an error (default: 4, 0 = no limit)
+    pub max_in_flight_per_client: usize,
+    /// Consecutive restart attempts before the server gives up (default: 5)
+    pub max_restarts: u32,
+    /// Base delay before a restart, doubled per attempt (default: 500)
+    pub restart_base_delay_ms: u64,
+    /// Uptime after which the restart count resets (default: 60)
+    pub restart_reset_secs: u64,
+    /// Set SO_REUSEADDR before binding, so a restarted daemon can take the
+    /// port over at once (default: true)
+    pub reuse_addr: bool,
+    /// Set SO_REUSEPORT before binding; unix only (default: false)
+    pub reuse_port: bool,
+    /// Extra bind attempts at startup while the address is in use (default: 5)
+    pub bind_retries: u32,
+    /// Delay before the first extra bind attempt, doubled per attempt
+    /// (default: 200)
+    pub bind_retry_base_delay_ms: u64,
+    /// Send per-request timings (queue, inference, tools, total) with each
+    /// response (default: false)
+    pub response_timing: bool,
+    /// Least time between two PROGRESS frames of one request, in ms; snippets
+    /// arriving sooner are dropped. Requests asking for progress get none
+    /// while 0 (default: 0)
+    pub progress_interval_ms: u64,
+    /// Shared secret for Control packets; None disables them (default: None)
+    pub control_token: Option<String>,
+    /// Key every incoming packet must carry an HMAC-SHA256 tag for;
+    /// None accepts unsigned packets (default: None)
+    pub auth_secret: Option<String>,
 }
 
 impl Default for CommConfig {
@@ -27,15 +70,255 @@ impl Default for CommConfig {
             recv_buffer_size: 65536,
             dedup_capacity: 256,
             dedup_ttl_secs: 300,
+            dedup_shards: 16,
+            response_timeout_secs: 330,
+            max_in_flight_per_client: 4,
+            max_restarts: 5,
+            restart_base_delay_ms: 500,
+            restart_reset_secs: 60,
+            reuse_addr: true,
+            reuse_port: false,
+            bind_retries: 5,
+            bind_retry_base_delay_ms: 200,
+            response_timing: false,
+            progress_interval_ms: 0,
+            control_token: None,
+            auth_secret: None,
         }
     }
 }
 
 impl CommConfig {
+    /// Load from `COMM_*` environment variables, keeping the default for any
+    /// unset or invalid one
+    pub fn from_env() -> Self {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    /// Load from the variables `var` returns, as `from_env` does
+    fn from_lookup(var: impl Fn(&str) -> Option<String>) -> Self {
+        let defaults = Self::default();
+        Self {
+            response_timeout_secs: parse_var(
+                &var,
+                "COMM_RESPONSE_TIMEOUT_SECS",
+                defaults.response_timeout_secs,
+            ),
+            max_in_flight_per_client: parse_var(
+                &var,
+                "COMM_MAX_IN_FLIGHT_PER_CLIENT",
+                defaults.max_in_flight_per_client,
+            ),
+            reuse_addr: parse_var(&var, "COMM_REUSE_ADDR", defaults.reuse_addr),
+            reuse_port: parse_var(&var, "COMM_REUSE_PORT", defaults.reuse_port),
+            bind_retries: parse_var(&var, "COMM_BIND_RETRIES", defaults.bind_retries),
+            response_timing: parse_var(&var, "COMM_RESPONSE_TIMING", defaults.response_timing),
+            progress_interval_ms: parse_var(
+                &var,
+                "COMM_PROGRESS_INTERVAL_MS",
+                defaults.progress_interval_ms,
+            ),
+            // Both kept even when empty, so validate() reports them instead
+            // of the daemon silently running with control or auth disabled
+            control_token: var("COMM_CONTROL_TOKEN"),
+            auth_secret: var("COMM_AUTH_SECRET"),
+            ..defaults
+        }
+    }
+
     /// Returns the socket address to bind to
     pub fn bind_addr(&self) -> SocketAddr {
         format!("{}:{}", self.listen_addr, self.listen_port)
             .parse()
             .expect("Invalid bind address")
     }
+
+    /// Check ranges and well-formedness, reporting every problem at once
+    pub fn validate(&self) -> Result<(), CommInitError> {
+        let mut problems = Vec::new();
+
+        if self.listen_addr.parse::<IpAddr>().is_err() {
+            problems.push(format!(
+                "listen_addr must be an IP address, got {:?}",
+                self.listen_addr
+            ));
+        }
+        if self.listen_port == 0 {
+            problems.push("listen_port must be nonzero".to_string());
+        }
+        if self.max_payload_bytes == 0 {
+            problems.push("max_payload_bytes must be greater than 0".to_string());
+        }
+        if self.recv_buffer_size == 0 {
+            problems.push("recv_buffer_size must be greater than 0".to_string());
+        }
+        if self.dedup_capacity == 0 {
+            problems.push("dedup_capacity must be greater than 0".to_string());
+        }
+        if self.dedup_shards == 0 {
+            problems.push("dedup_shards must be greater than 0".to_string());
+        }
+        if self.response_timeout_secs == 0 {
+            problems.push("response_timeout_secs must be greater than 0".to_string());
+        }
+        if self.bind_retries > MAX_BIND_RETRIES {
+            problems.push(format!("bind_retries must be at most {}", MAX_BIND_RETRIES));
+        }
+        if self.reuse_port && !cfg!(unix) {
+            problems.push("reuse_port is only supported on unix".to_string());
+        }
+        if self
+            .control_token
+            .as_deref()
+            .is_some_and(|t| t.trim().is_empty())
+        {
+            problems.push("control_token must not be empty".to_string());
+        }
+        if self
+            .auth_secret
+            .as_deref()
+            .is_some_and(|s| s.trim().is_empty())
+        {
+            problems.push("auth_secret must not be empty".to_string());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(CommInitError::ConfigInvalid(problems.join("; ")))
+        }
+    }
+}
+
+/// Parse variable `name`, logging a warning if it is set but invalid
+fn parse_var<T: FromStr>(var: &impl Fn(&str) -> Option<String>, name: &str, default: T) -> T {
+    match var(name) {
+        Some(v) => v.parse().unwrap_or_else(|_| {
+            warn!(var = name, value = %v, "Invalid env var value, using default");
+            default
+        }),
+        None => default,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn from_vars(vars: &[(&str, &str)]) -> CommConfig {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        CommConfig::from_lookup(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn test_from_env_max_in_flight() {
+        assert_eq!(from_vars(&[]).max_in_flight_per_client, 4);
+        let config = from_vars(&[("COMM_MAX_IN_FLIGHT_PER_CLIENT", "0")]);
+        assert_eq!(config.max_in_flight_per_client, 0);
+        let config = from_vars(&[("COMM_MAX_IN_FLIGHT_PER_CLIENT", "many")]);
+        assert_eq!(config.max_in_flight_per_client, 4);
+    }
+
+    #[test]
+    fn test_from_env_response_timeout() {
+        assert_eq!(from_vars(&[]).response_timeout_secs, 330);
+        let config = from_vars(&[("COMM_RESPONSE_TIMEOUT_SECS", "600")]);
+        assert_eq!(config.response_timeout_secs, 600);
+        let config = from_vars(&[("COMM_RESPONSE_TIMEOUT_SECS", "soon")]);
+        assert_eq!(config.response_timeout_secs, 330);
+
+        let err = from_vars(&[("COMM_RESPONSE_TIMEOUT_SECS", "0")])
+            .validate()
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("response_timeout_secs must be greater than 0"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_from_env_response_timing() {
+        assert!(!from_vars(&[]).response_timing);
+        assert!(from_vars(&[("COMM_RESPONSE_TIMING", "true")]).response_timing);
+        assert!(!from_vars(&[("COMM_RESPONSE_TIMING", "on")]).response_timing);
+    }
+
+    #[test]
+    fn test_from_env_progress_interval() {
+        assert_eq!(from_vars(&[]).progress_interval_ms, 0);
+        let config = from_vars(&[("COMM_PROGRESS_INTERVAL_MS", "250")]);
+        assert_eq!(config.progress_interval_ms, 250);
+        let config = from_vars(&[("COMM_PROGRESS_INTERVAL_MS", "fast")]);
+        assert_eq!(config.progress_interval_ms, 0);
+    }
+
+    #[test]
+    fn test_from_env_control_token() {
+        assert_eq!(from_vars(&[]).control_token, None);
+        let config = from_vars(&[("COMM_CONTROL_TOKEN", "pause-me")]);
+        assert_eq!(config.control_token.as_deref(), Some("pause-me"));
+        assert!(config.validate().is_ok());
+
+        let err = from_vars(&[("COMM_CONTROL_TOKEN", " ")])
+            .validate()
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("control_token must not be empty"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_from_env_auth_secret() {
+        assert_eq!(from_vars(&[]).auth_secret, None);
+        let config = from_vars(&[("COMM_AUTH_SECRET", "s3cret")]);
+        assert_eq!(config.auth_secret.as_deref(), Some("s3cret"));
+        assert!(config.validate().is_ok());
+
+        for empty in ["", "  "] {
+            let err = from_vars(&[("COMM_AUTH_SECRET", empty)])
+                .validate()
+                .unwrap_err();
+            assert!(
+                err.to_string().contains("auth_secret must not be empty"),
+                "{}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_env_bind_options() {
+        let config = from_vars(&[]);
+        assert!(config.reuse_addr && !config.reuse_port);
+        assert_eq!(config.bind_retries, 5);
+
+        let config = from_vars(&[
+            ("COMM_REUSE_ADDR", "false"),
+            ("COMM_REUSE_PORT", "true"),
+            ("COMM_BIND_RETRIES", "0"),
+        ]);
+        assert!(!config.reuse_addr && config.reuse_port);
+        assert_eq!(config.bind_retries, 0);
+
+        let config = from_vars(&[("COMM_REUSE_ADDR", "no"), ("COMM_BIND_RETRIES", "-1")]);
+        assert!(config.reuse_addr);
+        assert_eq!(config.bind_retries, 5);
+
+        let err = from_vars(&[("COMM_BIND_RETRIES", "1000")])
+            .validate()
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("bind_retries must be at most 20"),
+            "{}",
+            err
+        );
+    }
 }
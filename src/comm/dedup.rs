@@ -0,0 +1,232 @@
+// Sharded request deduplication table
+
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Deduplication key: the client's request_id when provided, otherwise its seq
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DedupKey {
+    Seq(u32),
+    RequestId(String),
+}
+
+impl std::fmt::Display for DedupKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DedupKey::Seq(seq) => write!(f, "seq={}", seq),
+            DedupKey::RequestId(id) => write!(f, "request_id={}", id),
+        }
+    }
+}
+
+/// Sequence deduplication entry
+#[derive(Debug)]
+pub struct DedupEntry {
+    /// When this entry was created; monotonic, so a wall-clock step (NTP,
+    /// manual `date`) neither expires nor extends it
+    pub instant: Instant,
+    /// Cached response to resend if duplicate
+    pub cached_response: Option<Vec<u8>>,
+}
+
+/// Size of the table at one point in time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DedupStats {
+    /// Clients with at least one entry
+    pub clients: usize,
+    /// Entries across all clients
+    pub entries: usize,
+    /// Age of the oldest entry (None when empty)
+    pub oldest_age: Option<Duration>,
+}
+
+/// Entries of every client hashed to one shard
+pub type Shard = HashMap<SocketAddr, HashMap<DedupKey, DedupEntry>>;
+
+/// Deduplication table split into independently locked shards by client
+/// address, so lookups for different clients and the periodic cleanup do
+/// not all wait on one lock
+#[derive(Debug)]
+pub struct DedupTable {
+    shards: Vec<Mutex<Shard>>,
+}
+
+impl DedupTable {
+    /// Create a table with `shards` buckets (at least one)
+    pub fn new(shards: usize) -> Self {
+        Self {
+            shards: (0..shards.max(1))
+                .map(|_| Mutex::new(HashMap::new()))
+                .collect(),
+        }
+    }
+
+    /// The shard holding `addr`'s entries; a client always maps to the same one
+    pub fn shard(&self, addr: &SocketAddr) -> &Mutex<Shard> {
+        let mut hasher = DefaultHasher::new();
+        addr.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % self.shards.len()]
+    }
+
+    /// Drop entries that are `ttl` old or older as of `now`, one shard at a
+    /// time, and return the number of clients still tracked
+    pub async fn cleanup(&self, ttl: Duration, now: Instant) -> usize {
+        let mut clients = 0;
+
+        for shard in &self.shards {
+            let mut shard = shard.lock().await;
+            for entries in shard.values_mut() {
+                entries.retain(|_key, entry| now.saturating_duration_since(entry.instant) < ttl);
+            }
+            // Clean up empty client entries
+            shard.retain(|_addr, entries| !entries.is_empty());
+            clients += shard.len();
+        }
+
+        clients
+    }
+
+    /// Count clients and entries and find the oldest entry's age as of
+    /// `now`, one shard at a time
+    pub async fn stats(&self, now: Instant) -> DedupStats {
+        let mut stats = DedupStats {
+            clients: 0,
+            entries: 0,
+            oldest_age: None,
+        };
+
+        for shard in &self.shards {
+            let shard = shard.lock().await;
+            stats.clients += shard.len();
+            for entry in shard.values().flat_map(|entries| entries.values()) {
+                stats.entries += 1;
+                let age = now.saturating_duration_since(entry.instant);
+                stats.oldest_age = stats.oldest_age.max(Some(age));
+            }
+        }
+
+        stats
+    }
+
+    /// Entries held for each client, ordered by address
+    pub async fn per_client(&self) -> Vec<(SocketAddr, usize)> {
+        let mut counts = Vec::new();
+        for shard in &self.shards {
+            let shard = shard.lock().await;
+            counts.extend(shard.iter().map(|(addr, entries)| (*addr, entries.len())));
+        }
+        counts.sort();
+        counts
+    }
+
+    /// Drop every entry, returning how many there were
+    pub async fn clear(&self) -> usize {
+        let mut removed = 0;
+        for shard in &self.shards {
+            let mut shard = shard.lock().await;
+            removed += shard.values().map(HashMap::len).sum::<usize>();
+            shard.clear();
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_stats_report_oldest_entry_age() {
+        let table = DedupTable::new(4);
+        let start = Instant::now();
+        let empty = table.stats(start).await;
+        assert_eq!(
+            (empty.clients, empty.entries, empty.oldest_age),
+            (0, 0, None)
+        );
+
+        let addrs: Vec<SocketAddr> = (0..3)
+            .map(|i| format!("10.0.0.{}:9000", i + 1).parse().unwrap())
+            .collect();
+        // Entries created 0s, 10s and 20s after start, two for the last client
+        for (i, addr) in addrs.iter().enumerate() {
+            let mut shard = table.shard(addr).lock().await;
+            let entries = shard.entry(*addr).or_default();
+            for seq in 0..=(i as u32 / 2) {
+                entries.insert(
+                    DedupKey::Seq(seq),
+                    DedupEntry {
+                        instant: start + Duration::from_secs(10 * i as u64),
+                        cached_response: None,
+                    },
+                );
+            }
+        }
+
+        let stats = table.stats(start + Duration::from_secs(90)).await;
+        assert_eq!(stats.clients, 3);
+        assert_eq!(stats.entries, 4);
+        assert_eq!(stats.oldest_age, Some(Duration::from_secs(90)));
+
+        // Once the first client's entry is gone the next one is the oldest
+        table.shard(&addrs[0]).lock().await.remove(&addrs[0]);
+        let stats = table.stats(start + Duration::from_secs(60)).await;
+        assert_eq!((stats.clients, stats.entries), (2, 3));
+        assert_eq!(stats.oldest_age, Some(Duration::from_secs(50)));
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_expires_entries_across_shards() {
+        let table = DedupTable::new(4);
+        let ttl = Duration::from_secs(60);
+        let stale = Instant::now() - Duration::from_secs(120);
+
+        let addrs: Vec<SocketAddr> = (0..32)
+            .map(|i| format!("10.0.0.{}:9000", i + 1).parse().unwrap())
+            .collect();
+        for (i, addr) in addrs.iter().enumerate() {
+            let mut shard = table.shard(addr).lock().await;
+            let entries = shard.entry(*addr).or_default();
+            entries.insert(
+                DedupKey::Seq(1),
+                DedupEntry {
+                    instant: stale,
+                    cached_response: None,
+                },
+            );
+            // Every other client also has a fresh entry that must survive
+            if i % 2 == 0 {
+                entries.insert(
+                    DedupKey::Seq(2),
+                    DedupEntry {
+                        instant: Instant::now(),
+                        cached_response: None,
+                    },
+                );
+            }
+        }
+
+        let used = table
+            .shards
+            .iter()
+            .filter(|s| s.try_lock().is_ok_and(|s| !s.is_empty()))
+            .count();
+        assert!(used > 1, "32 clients landed in {} shard(s)", used);
+
+        assert_eq!(table.cleanup(ttl, Instant::now()).await, 16);
+        for (i, addr) in addrs.iter().enumerate() {
+            let shard = table.shard(addr).lock().await;
+            match shard.get(addr) {
+                Some(entries) => {
+                    assert_eq!(i % 2, 0);
+                    assert_eq!(entries.keys().collect::<Vec<_>>(), [&DedupKey::Seq(2)]);
+                }
+                None => assert_eq!(i % 2, 1),
+            }
+        }
+    }
+}
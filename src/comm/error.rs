@@ -5,6 +5,9 @@ use thiserror::Error;
 pub enum CommInitError {
     #[error("Failed to bind UDP socket: {0}")]
     BindFailed(String),
+
+    #[error("Invalid configuration: {0}")]
+    ConfigInvalid(String),
 }
 
 /// Comm module runtime errors
@@ -25,6 +28,9 @@ pub enum CommError {
     #[error("Payload too large: {0} bytes")]
     PayloadTooLarge(usize),
 
+    #[error("Packet authentication failed")]
+    Unauthenticated,
+
     #[error("Channel closed")]
     ChannelClosed,
 }
@@ -1,7 +1,9 @@
 // Comm module - UDP communication with external clients
 // See docs/comm-design.md for design details
 
+pub mod clock;
 pub mod config;
+pub mod dedup;
 pub mod error;
 pub mod protocol;
 pub mod server;
@@ -1,25 +1,175 @@
 use crate::comm::error::CommError;
-use crate::comm::types::{MsgType, RequestPayload, ResponsePayload};
+use crate::comm::types::{
+    ApprovePayload, ControlPayload, HelloResponse, MsgType, ProgressPayload, ReplayPayload,
+    RequestPayload, ResponsePayload,
+};
+use hmac::{Hmac, Mac};
 use rmp_serde::decode::Deserializer;
 use rmp_serde::encode::Serializer;
 use serde::Deserialize;
+use sha2::Sha256;
 use std::io::Cursor;
 use std::result::Result as StdResult;
 
-/// Encode a packet with given type, sequence, and payload
+/// Wire protocol version reported by Hello
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Set on the first byte of a length-prefixed packet, whose low bits hold
+/// the protocol version; message type bytes never have it set, which tells
+/// versioned packets apart from legacy ones
+pub const VERSION_TAG: u8 = 0x40;
+
+/// Features reported by Hello: supported message types, plus `request_id`
+/// for idempotency keys in REQUEST payloads and `cached_flag` for
+/// [`FLAG_CACHED`] on replayed responses
+pub const FEATURES: &[&str] = &[
+    "request",
+    "hello",
+    "control",
+    "init_report",
+    "approve",
+    "replay",
+    "request_id",
+    "cached_flag",
+    "length_prefix",
+    "no_tools",
+];
+
+/// Set on the type byte of a RESPONSE resent from the dedup cache rather
+/// than produced for this packet; only ever sent by Shelly
+pub const FLAG_CACHED: u8 = 0x80;
+
+/// Length of the HMAC-SHA256 tag appended to signed packets
+pub const AUTH_TAG_LEN: usize = 32;
+
+fn packet_mac(secret: &[u8]) -> Hmac<Sha256> {
+    // HMAC accepts keys of any length
+    Hmac::<Sha256>::new_from_slice(secret).expect("HMAC key of any length")
+}
+
+/// Append an HMAC-SHA256 tag over type+seq+payload
+#[allow(dead_code)]
+pub fn sign_packet(secret: &[u8], packet: &mut Vec<u8>) {
+    let mut mac = packet_mac(secret);
+    mac.update(packet);
+    packet.extend_from_slice(&mac.finalize().into_bytes());
+}
+
+/// Check the trailing tag of a signed packet and return the packet without it
+pub fn verify_packet<'a>(secret: &[u8], packet: &'a [u8]) -> StdResult<&'a [u8], CommError> {
+    let Some(body_len) = packet.len().checked_sub(AUTH_TAG_LEN) else {
+        return Err(CommError::Unauthenticated);
+    };
+    let (body, tag) = packet.split_at(body_len);
+    let mut mac = packet_mac(secret);
+    mac.update(body);
+    mac.verify_slice(tag)
+        .map_err(|_| CommError::Unauthenticated)?;
+    Ok(body)
+}
+
+/// Packet layout on the wire
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Framing {
+    /// Version 1: `type(1) seq(4) payload`, the payload ending at the
+    /// datagram boundary
+    Legacy,
+    /// Version 2: `version(1) type(1) seq(4) len(4) payload(len)`, so the
+    /// framing does not depend on the transport
+    #[default]
+    LengthPrefixed,
+}
+
+impl Framing {
+    /// Bytes before the payload
+    pub fn header_len(self) -> usize {
+        match self {
+            Framing::Legacy => 5,
+            Framing::LengthPrefixed => 10,
+        }
+    }
+
+    /// Offset of the message type byte
+    fn type_offset(self) -> usize {
+        match self {
+            Framing::Legacy => 0,
+            Framing::LengthPrefixed => 1,
+        }
+    }
+
+    /// Framing of an encoded packet, judged by its first byte
+    fn of(packet: &[u8]) -> Self {
+        match packet.first() {
+            Some(first) if first & VERSION_TAG != 0 => Framing::LengthPrefixed,
+            _ => Framing::Legacy,
+        }
+    }
+}
+
+/// Decoded packet header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub msg_type: MsgType,
+    pub seq: u32,
+    /// Layout the packet used; replies are sent in the same one
+    pub framing: Framing,
+    /// Declared by the length prefix, or the rest of a legacy packet
+    pub payload_len: usize,
+}
+
+impl Header {
+    /// The payload of the packet this header was decoded from, rejected if
+    /// longer than `max_payload_bytes`
+    pub fn payload<'a>(
+        &self,
+        packet: &'a [u8],
+        max_payload_bytes: usize,
+    ) -> StdResult<&'a [u8], CommError> {
+        if self.payload_len > max_payload_bytes {
+            return Err(CommError::PayloadTooLarge(self.payload_len));
+        }
+        let start = self.framing.header_len();
+        packet
+            .get(start..start + self.payload_len)
+            .ok_or_else(|| CommError::DecodeError("Packet too short".to_string()))
+    }
+}
+
+/// Encode a length-prefixed packet with given type, sequence, and payload
+#[allow(dead_code)]
 pub fn encode_packet(
     msg_type: MsgType,
     seq: u32,
     payload: Option<&impl serde::Serialize>,
+) -> StdResult<Vec<u8>, CommError> {
+    encode_framed(Framing::LengthPrefixed, msg_type, seq, payload)
+}
+
+/// Encode a packet in the given framing
+pub fn encode_framed(
+    framing: Framing,
+    msg_type: MsgType,
+    seq: u32,
+    payload: Option<&impl serde::Serialize>,
 ) -> StdResult<Vec<u8>, CommError> {
     let mut buf = Vec::new();
 
+    // Write version (1 byte)
+    if framing == Framing::LengthPrefixed {
+        buf.push(VERSION_TAG | PROTOCOL_VERSION as u8);
+    }
+
     // Write msg type (1 byte)
     buf.push(msg_type as u8);
 
     // Write seq (4 bytes, big-endian)
     buf.extend_from_slice(&seq.to_be_bytes());
 
+    // Reserve the payload length (4 bytes, big-endian), filled in below
+    if framing == Framing::LengthPrefixed {
+        buf.extend_from_slice(&[0; 4]);
+    }
+
     // Write payload if present
     if let Some(p) = payload {
         let mut ser = Serializer::new(&mut buf);
@@ -27,21 +177,93 @@ pub fn encode_packet(
             .map_err(|e| CommError::EncodeError(e.to_string()))?;
     }
 
+    if framing == Framing::LengthPrefixed {
+        let len = u32::try_from(buf.len() - framing.header_len())
+            .map_err(|_| CommError::EncodeError("payload over 4 GiB".to_string()))?;
+        buf[6..10].copy_from_slice(&len.to_be_bytes());
+    }
+
     Ok(buf)
 }
 
-/// Decode packet type and seq from raw bytes
-pub fn decode_header(data: &[u8]) -> StdResult<(MsgType, u32), CommError> {
-    if data.len() < 5 {
-        return Err(CommError::DecodeError("Packet too short".to_string()));
+/// Flag an encoded RESPONSE as replayed from the dedup cache
+pub fn mark_cached(packet: &mut [u8]) {
+    if let Some(msg_type) = packet.get_mut(Framing::of(packet).type_offset()) {
+        *msg_type |= FLAG_CACHED;
+    }
+}
+
+/// Clear the cached flag on a received RESPONSE so its header decodes,
+/// returning whether it was set
+#[allow(dead_code)]
+pub fn take_cached(packet: &mut [u8]) -> bool {
+    let at = Framing::of(packet).type_offset();
+    match packet.get_mut(at) {
+        Some(msg_type) if *msg_type & FLAG_CACHED != 0 => {
+            *msg_type &= !FLAG_CACHED;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Overwrite the seq of an encoded packet
+pub fn set_seq(packet: &mut [u8], seq: u32) {
+    let start = Framing::of(packet).type_offset() + 1;
+    if let Some(bytes) = packet.get_mut(start..start + 4) {
+        bytes.copy_from_slice(&seq.to_be_bytes());
     }
+}
 
-    let msg_type = MsgType::from_u8(data[0])
-        .ok_or_else(|| CommError::DecodeError(format!("Unknown msg type: {}", data[0])))?;
+/// Decode the header of a raw packet, in either framing
+///
+/// A length-prefixed packet must hold exactly the declared payload, so a
+/// truncated or padded datagram is rejected rather than mis-decoded. The
+/// payload size limit is checked by [`Header::payload`], letting callers
+/// still answer an oversized packet by its seq.
+pub fn decode_header(data: &[u8]) -> StdResult<Header, CommError> {
+    let framing = Framing::of(data);
+    if data.len() < framing.header_len() {
+        return Err(CommError::DecodeError("Packet too short".to_string()));
+    }
 
-    let seq = u32::from_be_bytes([data[1], data[2], data[3], data[4]]);
+    if framing == Framing::LengthPrefixed {
+        let version = data[0] & !VERSION_TAG;
+        if u32::from(version) != PROTOCOL_VERSION {
+            return Err(CommError::DecodeError(format!(
+                "Unsupported protocol version: {}",
+                version
+            )));
+        }
+    }
 
-    Ok((msg_type, seq))
+    let at = framing.type_offset();
+    let msg_type = MsgType::from_u8(data[at])
+        .ok_or_else(|| CommError::DecodeError(format!("Unknown msg type: {}", data[at])))?;
+
+    let seq = u32::from_be_bytes([data[at + 1], data[at + 2], data[at + 3], data[at + 4]]);
+
+    let present = data.len() - framing.header_len();
+    let payload_len = match framing {
+        Framing::Legacy => present,
+        Framing::LengthPrefixed => {
+            let declared = u32::from_be_bytes([data[6], data[7], data[8], data[9]]) as usize;
+            if declared != present {
+                return Err(CommError::DecodeError(format!(
+                    "Payload length mismatch: declared {} bytes, got {}",
+                    declared, present
+                )));
+            }
+            declared
+        }
+    };
+
+    Ok(Header {
+        msg_type,
+        seq,
+        framing,
+        payload_len,
+    })
 }
 
 /// Decode request payload
@@ -50,6 +272,24 @@ pub fn decode_request_payload(data: &[u8]) -> StdResult<RequestPayload, CommErro
     RequestPayload::deserialize(&mut de).map_err(|e| CommError::DecodeError(e.to_string()))
 }
 
+/// Decode approve payload
+pub fn decode_approve_payload(data: &[u8]) -> StdResult<ApprovePayload, CommError> {
+    let mut de = Deserializer::new(Cursor::new(data));
+    ApprovePayload::deserialize(&mut de).map_err(|e| CommError::DecodeError(e.to_string()))
+}
+
+/// Decode replay payload
+pub fn decode_replay_payload(data: &[u8]) -> StdResult<ReplayPayload, CommError> {
+    let mut de = Deserializer::new(Cursor::new(data));
+    ReplayPayload::deserialize(&mut de).map_err(|e| CommError::DecodeError(e.to_string()))
+}
+
+/// Decode control payload
+pub fn decode_control_payload(data: &[u8]) -> StdResult<ControlPayload, CommError> {
+    let mut de = Deserializer::new(Cursor::new(data));
+    ControlPayload::deserialize(&mut de).map_err(|e| CommError::DecodeError(e.to_string()))
+}
+
 /// Decode response payload
 #[allow(dead_code)]
 pub fn decode_response_payload(data: &[u8]) -> StdResult<ResponsePayload, CommError> {
@@ -57,48 +297,120 @@ pub fn decode_response_payload(data: &[u8]) -> StdResult<ResponsePayload, CommEr
     ResponsePayload::deserialize(&mut de).map_err(|e| CommError::DecodeError(e.to_string()))
 }
 
+/// Decode hello response payload
+#[allow(dead_code)]
+pub fn decode_hello_response(data: &[u8]) -> StdResult<HelloResponse, CommError> {
+    let mut de = Deserializer::new(Cursor::new(data));
+    HelloResponse::deserialize(&mut de).map_err(|e| CommError::DecodeError(e.to_string()))
+}
+
 /// Encode request ack (no payload)
-pub fn encode_request_ack(seq: u32) -> StdResult<Vec<u8>, CommError> {
-    encode_packet(MsgType::RequestAck, seq, None::<&()>)
+pub fn encode_request_ack(framing: Framing, seq: u32) -> StdResult<Vec<u8>, CommError> {
+    encode_framed(framing, MsgType::RequestAck, seq, None::<&()>)
 }
 
 /// Encode response
-pub fn encode_response(seq: u32, payload: &ResponsePayload) -> StdResult<Vec<u8>, CommError> {
-    encode_packet(MsgType::Response, seq, Some(payload))
+pub fn encode_response(
+    framing: Framing,
+    seq: u32,
+    payload: &ResponsePayload,
+) -> StdResult<Vec<u8>, CommError> {
+    encode_framed(framing, MsgType::Response, seq, Some(payload))
+}
+
+/// Encode a progress frame
+pub fn encode_progress(
+    framing: Framing,
+    seq: u32,
+    payload: &ProgressPayload,
+) -> StdResult<Vec<u8>, CommError> {
+    encode_framed(framing, MsgType::Progress, seq, Some(payload))
+}
+
+/// Encode the answer to a Hello
+pub fn encode_hello_response(
+    framing: Framing,
+    seq: u32,
+    payload: &HelloResponse,
+) -> StdResult<Vec<u8>, CommError> {
+    encode_framed(framing, MsgType::Hello, seq, Some(payload))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    const MAX_PAYLOAD: usize = 65536;
+
+    /// Payload of a packet that must decode
+    fn payload_of(packet: &[u8]) -> &[u8] {
+        decode_header(packet)
+            .unwrap()
+            .payload(packet, MAX_PAYLOAD)
+            .unwrap()
+    }
+
     // T-CODEC-01: REQUEST 编码与解码
     #[test]
     fn test_request_encode_decode() {
         let payload = RequestPayload {
             content: "hello".to_string(),
+            request_id: None,
+            no_tools: false,
+            progress: false,
         };
         let seq = 1u32;
 
         let packet = encode_packet(MsgType::Request, seq, Some(&payload)).unwrap();
-        let (decoded_type, decoded_seq) = decode_header(&packet).unwrap();
+        let header = decode_header(&packet).unwrap();
 
-        assert_eq!(decoded_type, MsgType::Request);
-        assert_eq!(decoded_seq, seq);
+        assert_eq!(header.msg_type, MsgType::Request);
+        assert_eq!(header.seq, seq);
+        assert_eq!(header.framing, Framing::LengthPrefixed);
 
-        let decoded_payload = decode_request_payload(&packet[5..]).unwrap();
+        let decoded_payload = decode_request_payload(payload_of(&packet)).unwrap();
         assert_eq!(decoded_payload.content, "hello");
+        assert!(decoded_payload.request_id.is_none());
+        assert!(!decoded_payload.no_tools);
+    }
+
+    // REQUEST with client-provided request_id
+    #[test]
+    fn test_request_id_roundtrip() {
+        let payload = RequestPayload {
+            content: "hello".to_string(),
+            request_id: Some("0b6f1c3e-2f7a-4d8e-9c1a-5e2b7d9f4a10".to_string()),
+            no_tools: true,
+            progress: false,
+        };
+
+        let packet = encode_packet(MsgType::Request, 1, Some(&payload)).unwrap();
+        let decoded_payload = decode_request_payload(payload_of(&packet)).unwrap();
+
+        assert_eq!(
+            decoded_payload.request_id.as_deref(),
+            Some("0b6f1c3e-2f7a-4d8e-9c1a-5e2b7d9f4a10")
+        );
+        assert!(decoded_payload.no_tools);
     }
 
     // T-CODEC-02: REQUEST_ACK 编码与解码
     #[test]
     fn test_request_ack_no_payload() {
         let seq = 42u32;
-        let packet = encode_request_ack(seq).unwrap();
+        let packet = encode_request_ack(Framing::LengthPrefixed, seq).unwrap();
 
+        assert_eq!(packet.len(), 10); // version (1) + type (1) + seq (4) + len (4)
+        let header = decode_header(&packet).unwrap();
+        assert_eq!(header.msg_type, MsgType::RequestAck);
+        assert_eq!(header.seq, seq);
+        assert_eq!(header.payload_len, 0);
+
+        let packet = encode_request_ack(Framing::Legacy, seq).unwrap();
         assert_eq!(packet.len(), 5); // type (1) + seq (4)
-        let (msg_type, decoded_seq) = decode_header(&packet).unwrap();
-        assert_eq!(msg_type, MsgType::RequestAck);
-        assert_eq!(decoded_seq, seq);
+        let header = decode_header(&packet).unwrap();
+        assert_eq!(header.msg_type, MsgType::RequestAck);
+        assert_eq!(header.framing, Framing::Legacy);
     }
 
     // T-CODEC-03: RESPONSE 编码与解码
@@ -107,31 +419,72 @@ mod tests {
         let payload = ResponsePayload {
             content: "result".to_string(),
             is_error: false,
+            timing: None,
         };
         let seq = 1u32;
 
-        let packet = encode_response(seq, &payload).unwrap();
-        let (decoded_type, decoded_seq) = decode_header(&packet).unwrap();
+        let packet = encode_response(Framing::LengthPrefixed, seq, &payload).unwrap();
+        let header = decode_header(&packet).unwrap();
 
-        assert_eq!(decoded_type, MsgType::Response);
-        assert_eq!(decoded_seq, seq);
+        assert_eq!(header.msg_type, MsgType::Response);
+        assert_eq!(header.seq, seq);
 
-        let decoded_payload = decode_response_payload(&packet[5..]).unwrap();
+        let decoded_payload = decode_response_payload(payload_of(&packet)).unwrap();
         assert_eq!(decoded_payload.content, "result");
         assert!(!decoded_payload.is_error);
     }
 
+    // HELLO 应答编码与解码
+    #[test]
+    fn test_hello_response_roundtrip() {
+        let payload = HelloResponse {
+            protocol_version: PROTOCOL_VERSION,
+            max_payload_bytes: 65536,
+            features: FEATURES.iter().map(|f| f.to_string()).collect(),
+        };
+
+        let packet = encode_hello_response(Framing::LengthPrefixed, 7, &payload).unwrap();
+        let header = decode_header(&packet).unwrap();
+        assert_eq!(header.msg_type, MsgType::Hello);
+        assert_eq!(header.seq, 7);
+        assert_eq!(decode_hello_response(payload_of(&packet)).unwrap(), payload);
+    }
+
+    // 签名包校验：正确签名通过，篡改或未签名被拒绝
+    #[test]
+    fn test_sign_and_verify_packet() {
+        let payload = RequestPayload {
+            content: "uptime".to_string(),
+            request_id: None,
+            no_tools: false,
+            progress: false,
+        };
+        let mut packet = encode_packet(MsgType::Request, 3, Some(&payload)).unwrap();
+        let unsigned = packet.clone();
+        sign_packet(b"secret", &mut packet);
+        assert_eq!(packet.len(), unsigned.len() + AUTH_TAG_LEN);
+
+        assert_eq!(verify_packet(b"secret", &packet).unwrap(), &unsigned[..]);
+        assert!(verify_packet(b"other", &packet).is_err());
+        assert!(verify_packet(b"secret", &unsigned).is_err());
+
+        let mut tampered = packet.clone();
+        tampered[6] ^= 0x01;
+        assert!(verify_packet(b"secret", &tampered).is_err());
+    }
+
     // T-CODEC-04: RESPONSE is_error=true
     #[test]
     fn test_response_error() {
         let payload = ResponsePayload {
             content: "command not found".to_string(),
             is_error: true,
+            timing: None,
         };
         let seq = 1u32;
 
-        let packet = encode_response(seq, &payload).unwrap();
-        let decoded_payload = decode_response_payload(&packet[5..]).unwrap();
+        let packet = encode_response(Framing::LengthPrefixed, seq, &payload).unwrap();
+        let decoded_payload = decode_response_payload(payload_of(&packet)).unwrap();
 
         assert!(decoded_payload.is_error);
         assert_eq!(decoded_payload.content, "command not found");
@@ -142,11 +495,14 @@ mod tests {
     fn test_empty_content_request() {
         let payload = RequestPayload {
             content: "".to_string(),
+            request_id: None,
+            no_tools: false,
+            progress: false,
         };
         let seq = 1u32;
 
         let packet = encode_packet(MsgType::Request, seq, Some(&payload)).unwrap();
-        let decoded_payload = decode_request_payload(&packet[5..]).unwrap();
+        let decoded_payload = decode_request_payload(payload_of(&packet)).unwrap();
 
         assert_eq!(decoded_payload.content, "");
     }
@@ -157,11 +513,14 @@ mod tests {
         let large_content = "x".repeat(60000);
         let payload = RequestPayload {
             content: large_content.clone(),
+            request_id: None,
+            no_tools: false,
+            progress: false,
         };
         let seq = 1u32;
 
         let packet = encode_packet(MsgType::Request, seq, Some(&payload)).unwrap();
-        let decoded_payload = decode_request_payload(&packet[5..]).unwrap();
+        let decoded_payload = decode_request_payload(payload_of(&packet)).unwrap();
 
         assert_eq!(decoded_payload.content.len(), 60000);
         assert_eq!(decoded_payload.content, large_content);
@@ -188,28 +547,145 @@ mod tests {
         // Exactly 5 bytes (no payload) - should succeed for header
         let result = decode_header(&[0x01, 0x00, 0x00, 0x00, 0x01]);
         assert!(result.is_ok());
+
+        // Versioned packet cut off inside its 10-byte header
+        let result = decode_header(&[0x42, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00]);
+        assert!(result.is_err());
+    }
+
+    // 长度前缀：声明长度与实际一致才能解码
+    #[test]
+    fn test_length_prefix_matches_payload() {
+        let payload = RequestPayload {
+            content: "df -h".to_string(),
+            request_id: None,
+            no_tools: false,
+            progress: false,
+        };
+        let packet = encode_packet(MsgType::Request, 9, Some(&payload)).unwrap();
+
+        assert_eq!(packet[0], VERSION_TAG | PROTOCOL_VERSION as u8);
+        let declared = u32::from_be_bytes([packet[6], packet[7], packet[8], packet[9]]);
+        assert_eq!(declared as usize, packet.len() - 10);
+        assert_eq!(
+            decode_header(&packet).unwrap().payload_len,
+            packet.len() - 10
+        );
+        assert_eq!(
+            decode_request_payload(payload_of(&packet)).unwrap().content,
+            "df -h"
+        );
+    }
+
+    // 长度前缀超过 max_payload_bytes 被拒绝
+    #[test]
+    fn test_length_prefix_over_max_rejected() {
+        let payload = RequestPayload {
+            content: "x".repeat(100),
+            request_id: None,
+            no_tools: false,
+            progress: false,
+        };
+        let packet = encode_packet(MsgType::Request, 1, Some(&payload)).unwrap();
+
+        let header = decode_header(&packet).unwrap();
+        assert!(matches!(
+            header.payload(&packet, 64),
+            Err(CommError::PayloadTooLarge(len)) if len == header.payload_len
+        ));
+        assert!(header.payload(&packet, header.payload_len).is_ok());
+    }
+
+    // 截断或多出字节的包与声明长度不符，被拒绝而不是误解码
+    #[test]
+    fn test_length_prefix_mismatch_rejected() {
+        let payload = RequestPayload {
+            content: "systemctl status nginx".to_string(),
+            request_id: None,
+            no_tools: false,
+            progress: false,
+        };
+        let packet = encode_packet(MsgType::Request, 1, Some(&payload)).unwrap();
+
+        let truncated = &packet[..packet.len() - 3];
+        let err = decode_header(truncated).unwrap_err();
+        assert!(
+            matches!(&err, CommError::DecodeError(msg) if msg.contains("declared")),
+            "{}",
+            err
+        );
+
+        let mut padded = packet.clone();
+        padded.extend_from_slice(&[0; 3]);
+        assert!(decode_header(&padded).is_err());
+
+        // A length prefix far beyond the datagram never reaches the payload
+        let mut huge = packet.clone();
+        huge[6..10].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(decode_header(&huge).is_err());
+
+        let mut future = packet.clone();
+        future[0] = VERSION_TAG | 9;
+        assert!(decode_header(&future).is_err());
+    }
+
+    // 旧格式（无版本字节与长度前缀）仍可解码，应答沿用同一格式
+    #[test]
+    fn test_legacy_framing_still_decoded() {
+        let payload = RequestPayload {
+            content: "uptime".to_string(),
+            request_id: None,
+            no_tools: false,
+            progress: false,
+        };
+        let packet = encode_framed(Framing::Legacy, MsgType::Request, 4, Some(&payload)).unwrap();
+        assert_eq!(packet[0], MsgType::Request as u8);
+
+        let header = decode_header(&packet).unwrap();
+        assert_eq!(header.framing, Framing::Legacy);
+        assert_eq!(header.seq, 4);
+        assert_eq!(header.payload_len, packet.len() - 5);
+        assert_eq!(
+            decode_request_payload(payload_of(&packet)).unwrap().content,
+            "uptime"
+        );
+
+        // Cached replies keep their framing when flagged and re-sequenced
+        let response = ResponsePayload {
+            content: "up 3 days".to_string(),
+            is_error: false,
+            timing: None,
+        };
+        for framing in [Framing::Legacy, Framing::LengthPrefixed] {
+            let mut packet = encode_response(framing, 1, &response).unwrap();
+            mark_cached(&mut packet);
+            set_seq(&mut packet, 77);
+            let at = framing.type_offset();
+            assert_eq!(packet[at], MsgType::Response as u8 | FLAG_CACHED);
+            assert!(take_cached(&mut packet));
+            assert!(!take_cached(&mut packet));
+            let header = decode_header(&packet).unwrap();
+            assert_eq!((header.framing, header.seq), (framing, 77));
+        }
     }
 
     // T-CODEC-10: seq 边界值
     #[test]
     fn test_seq_boundary_values() {
         // seq = 0
-        let packet = encode_request_ack(0).unwrap();
-        let (_, seq) = decode_header(&packet).unwrap();
-        assert_eq!(seq, 0);
+        let packet = encode_request_ack(Framing::LengthPrefixed, 0).unwrap();
+        assert_eq!(decode_header(&packet).unwrap().seq, 0);
 
         // seq = u32::MAX
-        let packet = encode_request_ack(u32::MAX).unwrap();
-        let (_, seq) = decode_header(&packet).unwrap();
-        assert_eq!(seq, u32::MAX);
+        let packet = encode_request_ack(Framing::LengthPrefixed, u32::MAX).unwrap();
+        assert_eq!(decode_header(&packet).unwrap().seq, u32::MAX);
 
         // seq = 256 (big-endian test)
-        let packet = encode_request_ack(256).unwrap();
-        let (_, seq) = decode_header(&packet).unwrap();
-        assert_eq!(seq, 256);
+        let packet = encode_request_ack(Framing::LengthPrefixed, 256).unwrap();
+        assert_eq!(decode_header(&packet).unwrap().seq, 256);
         // Check big-endian encoding: 256 = 0x00000100
         assert_eq!(
-            [packet[1], packet[2], packet[3], packet[4]],
+            [packet[2], packet[3], packet[4], packet[5]],
             [0x00, 0x00, 0x01, 0x00]
         );
     }
@@ -220,20 +696,26 @@ mod tests {
         // UTF-8 multi-byte characters (Chinese, emoji)
         let payload = RequestPayload {
             content: "你好🌮🎉".to_string(),
+            request_id: None,
+            no_tools: false,
+            progress: false,
         };
         let seq = 1u32;
 
         let packet = encode_packet(MsgType::Request, seq, Some(&payload)).unwrap();
-        let decoded_payload = decode_request_payload(&packet[5..]).unwrap();
+        let decoded_payload = decode_request_payload(payload_of(&packet)).unwrap();
 
         assert_eq!(decoded_payload.content, "你好🌮🎉");
 
         // Special characters: \n, \0, \r\n
         let payload = RequestPayload {
             content: "line1\nline2\r\nnull\0end".to_string(),
+            request_id: None,
+            no_tools: false,
+            progress: false,
         };
         let packet = encode_packet(MsgType::Request, seq, Some(&payload)).unwrap();
-        let decoded_payload = decode_request_payload(&packet[5..]).unwrap();
+        let decoded_payload = decode_request_payload(payload_of(&packet)).unwrap();
 
         assert_eq!(decoded_payload.content, "line1\nline2\r\nnull\0end");
     }
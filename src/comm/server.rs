@@ -1,27 +1,39 @@
+use crate::comm::clock::{Clock, MonotonicClock};
 use crate::comm::config::CommConfig;
+use crate::comm::dedup::{DedupEntry, DedupKey, DedupTable};
 use crate::comm::error::{CommError, CommInitError};
 use crate::comm::protocol::{
-    decode_header, decode_request_payload, encode_request_ack, encode_response,
+    FEATURES, Framing, Header, PROTOCOL_VERSION, decode_approve_payload, decode_control_payload,
+    decode_header, decode_replay_payload, decode_request_payload, encode_hello_response,
+    encode_progress, encode_request_ack, encode_response, mark_cached, set_seq, verify_packet,
 };
-use crate::comm::types::{MsgType, ResponsePayload, UserRequest, UserResponse};
+use crate::comm::types::{
+    HelloResponse, InitReportSlot, MsgType, ProgressPayload, ReplayTarget, RequestKind,
+    ResponsePayload, ResponseTiming, UserRequest, UserResponse,
+};
+use futures::StreamExt;
+use futures::stream::FuturesUnordered;
+use socket2::{Domain, Protocol, Socket, Type};
 use std::collections::HashMap;
+use std::io::ErrorKind;
 use std::net::SocketAddr;
 use std::result::Result as StdResult;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use tokio::net::UdpSocket;
 use tokio::sync::{mpsc, oneshot};
 use tokio::time::timeout;
 use tracing::{debug, error, info, warn};
 
-/// Sequence deduplication entry
-#[derive(Debug)]
-struct DedupEntry {
-    /// When this entry was created
-    instant: Instant,
-    /// Cached response to resend if duplicate
-    cached_response: Option<Vec<u8>>,
-}
+/// How often the dedup table's size and oldest entry are logged
+const DEDUP_STATS_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Progress snippets one request may have queued for sending; more are dropped
+const PROGRESS_CHANNEL_CAPACITY: usize = 16;
+
+/// Longest progress snippet sent, in bytes; longer ones are cut
+const PROGRESS_MAX_BYTES: usize = 1024;
 
 /// Comm server - handles UDP communication with clients
 pub struct Comm {
@@ -29,8 +41,19 @@ pub struct Comm {
     config: CommConfig,
     /// Channel sender to forward UserRequests to main loop
     loop_sender: mpsc::Sender<UserRequest>,
-    /// Sequence deduplication table per client
-    dedup: Arc<tokio::sync::Mutex<HashMap<SocketAddr, HashMap<u32, DedupEntry>>>>,
+    /// Request deduplication table per client, sharded by address
+    dedup: Arc<DedupTable>,
+    /// Forwarded requests per client still waiting on the main loop
+    in_flight: InFlight,
+    /// Set by Control pause/resume; the main loop checks it per request
+    paused: Arc<AtomicBool>,
+    /// Last init report, filled in by the main loop after startup
+    init_report: InitReportSlot,
+    /// Time source for dedup entries and restart bookkeeping
+    clock: Arc<dyn Clock>,
+    /// Make the next receive fail, to exercise restart handling
+    #[cfg(test)]
+    fail_next_recv: Arc<AtomicBool>,
 }
 
 impl Comm {
@@ -38,6 +61,16 @@ impl Comm {
     pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
         self.socket.local_addr()
     }
+
+    /// Flag toggled by Control pause/resume, for the main loop to check
+    pub fn pause_flag(&self) -> Arc<AtomicBool> {
+        self.paused.clone()
+    }
+
+    /// Slot the main loop stores its init report in, served on InitReport
+    pub fn init_report_slot(&self) -> InitReportSlot {
+        self.init_report.clone()
+    }
 }
 
 impl Comm {
@@ -46,39 +79,158 @@ impl Comm {
     pub async fn new(
         config: CommConfig,
     ) -> StdResult<(Comm, mpsc::Receiver<UserRequest>), CommInitError> {
-        let socket = UdpSocket::bind(config.bind_addr())
+        let socket = bind_with_retry(&config)
             .await
             .map_err(|e| CommInitError::BindFailed(e.to_string()))?;
 
         info!("Comm listening on {}", socket.local_addr().unwrap());
 
         let (tx, rx) = mpsc::channel(1024);
+        let dedup = Arc::new(DedupTable::new(config.dedup_shards));
 
         Ok((
             Self {
                 socket,
                 config,
                 loop_sender: tx,
-                dedup: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+                dedup,
+                in_flight: InFlight::default(),
+                paused: Arc::new(AtomicBool::new(false)),
+                init_report: InitReportSlot::default(),
+                clock: Arc::new(MonotonicClock),
+                #[cfg(test)]
+                fail_next_recv: Arc::new(AtomicBool::new(false)),
             },
             rx,
         ))
     }
 
+    /// Use `clock` instead of the real monotonic clock
+    #[cfg(test)]
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Make the next receive on this server (or its restarted successor) fail
+    #[cfg(test)]
+    #[allow(dead_code)]
+    pub fn fail_next_recv(&self) {
+        self.fail_next_recv.store(true, Ordering::SeqCst);
+    }
+
+    /// Run the Comm server, rebinding and restarting it with exponential
+    /// backoff when it fails. Gives up after `max_restarts` consecutive
+    /// failed attempts; a server that stays up for `restart_reset_secs`
+    /// resets the count.
+    pub async fn run_supervised(self) -> StdResult<(), CommError> {
+        let mut comm = self;
+        let mut restarts = 0u32;
+
+        loop {
+            // Rebind to the address actually in use, so an ephemeral port
+            // survives the restart
+            let addr = comm
+                .local_addr()
+                .unwrap_or_else(|_| comm.config.bind_addr());
+            let config = comm.config.clone();
+            let loop_sender = comm.loop_sender.clone();
+            let dedup = comm.dedup.clone();
+            let paused = comm.paused.clone();
+            let init_report = comm.init_report.clone();
+            let clock = comm.clock.clone();
+            #[cfg(test)]
+            let fail_next_recv = comm.fail_next_recv.clone();
+
+            let started = clock.now();
+            let err = match comm.run().await {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+            // The failed socket was dropped with `comm`, freeing the port
+
+            if clock.now().saturating_duration_since(started)
+                >= Duration::from_secs(config.restart_reset_secs)
+            {
+                restarts = 0;
+            }
+
+            comm = loop {
+                if restarts >= config.max_restarts {
+                    error!(
+                        restarts,
+                        error = %err,
+                        "Comm server failed too many times, giving up"
+                    );
+                    return Err(err);
+                }
+                restarts += 1;
+
+                let delay = restart_delay(config.restart_base_delay_ms, restarts);
+                warn!(
+                    attempt = restarts,
+                    max_restarts = config.max_restarts,
+                    delay_ms = delay.as_millis() as u64,
+                    error = %err,
+                    "Comm server failed, restarting"
+                );
+                tokio::time::sleep(delay).await;
+
+                match bind_socket(addr, &config) {
+                    Ok(socket) => {
+                        info!(attempt = restarts, "Comm server restarted on {}", addr);
+                        break Comm {
+                            socket,
+                            config: config.clone(),
+                            loop_sender: loop_sender.clone(),
+                            dedup: dedup.clone(),
+                            // Requests in flight died with the old server
+                            in_flight: InFlight::default(),
+                            paused: paused.clone(),
+                            init_report: init_report.clone(),
+                            clock: clock.clone(),
+                            #[cfg(test)]
+                            fail_next_recv: fail_next_recv.clone(),
+                        };
+                    }
+                    Err(e) => {
+                        warn!(attempt = restarts, error = %e, "Failed to rebind comm socket");
+                    }
+                }
+            };
+        }
+    }
+
     /// Run the Comm server
     pub async fn run(self) -> StdResult<(), CommError> {
-        let mut buf = vec![0u8; self.config.max_payload_bytes + 1024]; // Extra space for header
+        // Room for the largest UDP datagram, so an oversized packet arrives
+        // whole and can still be authenticated and answered
+        let mut buf = vec![0u8; (self.config.max_payload_bytes + 1024).max(65536)];
         let mut cleanup_interval = tokio::time::interval(Duration::from_secs(30));
+        let mut stats_interval = tokio::time::interval_at(
+            tokio::time::Instant::now() + DEDUP_STATS_INTERVAL,
+            DEDUP_STATS_INTERVAL,
+        );
+        // Packets being handled; a request waiting on the main loop must not
+        // stop the next packet from being received
+        let mut handlers = FuturesUnordered::new();
 
         loop {
             tokio::select! {
                 result = self.socket.recv_from(&mut buf) => {
+                    #[cfg(test)]
+                    let result = if self.fail_next_recv.swap(false, Ordering::SeqCst) {
+                        Err(std::io::Error::other("injected receive failure"))
+                    } else {
+                        result
+                    };
                     match result {
                         Ok((len, addr)) => {
-                            let packet = &buf[..len];
-                            if let Err(e) = self.handle_packet(packet, addr).await {
-                                warn!("Failed to handle packet from {}: {}", addr, e);
-                            }
+                            let packet = buf[..len].to_vec();
+                            let comm = &self;
+                            handlers.push(async move {
+                                (addr, comm.handle_packet(&packet, addr).await)
+                            });
                         }
                         Err(e) => {
                             error!("Recv error: {}", e);
@@ -86,10 +238,18 @@ impl Comm {
                         }
                     }
                 }
+                Some((addr, result)) = handlers.next(), if !handlers.is_empty() => {
+                    if let Err(e) = result {
+                        warn!("Failed to handle packet from {}: {}", addr, e);
+                    }
+                }
                 _ = cleanup_interval.tick() => {
                     // Periodic cleanup of dedup table
                     self.cleanup_dedup().await;
                 }
+                _ = stats_interval.tick() => {
+                    self.log_dedup_stats().await;
+                }
             }
         }
     }
@@ -110,74 +270,334 @@ impl Comm {
             return Err(CommError::DecodeError("Packet too short".to_string()));
         }
 
-        // Check payload size
-        let payload_len = packet.len() - 5;
-        if payload_len > self.config.max_payload_bytes {
-            warn!(
-                "Payload too large from {}: {} bytes",
-                client_addr, payload_len
-            );
-            return Err(CommError::PayloadTooLarge(payload_len));
-        }
+        // Drop anything not signed with the shared secret
+        let packet = match &self.config.auth_secret {
+            Some(secret) => match verify_packet(secret.as_bytes(), packet) {
+                Ok(body) if body.len() >= 5 => body,
+                _ => {
+                    warn!("Dropping unauthenticated packet from {}", client_addr);
+                    return Err(CommError::Unauthenticated);
+                }
+            },
+            None => packet,
+        };
 
         // Decode header
-        let (msg_type, seq) = decode_header(packet)?;
-        let payload = &packet[5..];
+        let header = decode_header(packet)?;
+
+        // Check payload size; answer so the client stops retrying, and
+        // report the error to the run loop for logging
+        let payload = match header.payload(packet, self.config.max_payload_bytes) {
+            Err(CommError::PayloadTooLarge(len)) => {
+                let response = encode_response(
+                    header.framing,
+                    header.seq,
+                    &ResponsePayload {
+                        content: format!(
+                            "payload too large ({} bytes), max {} bytes",
+                            len, self.config.max_payload_bytes
+                        ),
+                        is_error: true,
+                        timing: None,
+                    },
+                )?;
+                self.socket
+                    .send_to(&response, client_addr)
+                    .await
+                    .map_err(|e| CommError::SendError(e.to_string()))?;
+                return Err(CommError::PayloadTooLarge(len));
+            }
+            result => result?,
+        };
 
         debug!(
-            "Received {} from {} seq={}",
-            msg_type as u8, client_addr, seq
+            "Received {} from {} seq={} framing={:?}",
+            header.msg_type as u8, client_addr, header.seq, header.framing
         );
 
-        match msg_type {
-            MsgType::Request => self.handle_request(payload, seq, client_addr).await,
+        match header.msg_type {
+            MsgType::Request => self.handle_request(payload, header, client_addr).await,
+            MsgType::Approve => self.handle_approve(payload, header, client_addr).await,
+            MsgType::Replay => self.handle_replay(payload, header, client_addr).await,
+            MsgType::Hello => self.handle_hello(header, client_addr).await,
+            MsgType::Control => self.handle_control(payload, header, client_addr).await,
+            MsgType::InitReport => self.handle_init_report(header, client_addr).await,
             _ => {
                 warn!(
                     "Unexpected message type: {} from {}",
-                    msg_type as u8, client_addr
+                    header.msg_type as u8, client_addr
                 );
                 Ok(())
             }
         }
     }
 
+    /// Handle incoming HELLO: report protocol version and limits
+    async fn handle_hello(&self, header: Header, client_addr: SocketAddr) -> Result<(), CommError> {
+        let hello = HelloResponse {
+            protocol_version: PROTOCOL_VERSION,
+            max_payload_bytes: self.config.max_payload_bytes,
+            features: FEATURES
+                .iter()
+                .map(|f| f.to_string())
+                .chain(self.config.auth_secret.as_ref().map(|_| "auth".to_string()))
+                .chain(self.config.response_timing.then(|| "timing".to_string()))
+                .chain((self.config.progress_interval_ms > 0).then(|| "progress".to_string()))
+                .collect(),
+        };
+        let packet = encode_hello_response(header.framing, header.seq, &hello)?;
+        self.socket
+            .send_to(&packet, client_addr)
+            .await
+            .map_err(|e| CommError::SendError(e.to_string()))?;
+        debug!("Sent HELLO seq={} to {}", header.seq, client_addr);
+        Ok(())
+    }
+
+    /// Handle incoming CONTROL: authenticate, apply, and answer with a RESPONSE
+    async fn handle_control(
+        &self,
+        payload_bytes: &[u8],
+        header: Header,
+        client_addr: SocketAddr,
+    ) -> Result<(), CommError> {
+        let control = decode_control_payload(payload_bytes)?;
+
+        let authorized = self
+            .config
+            .control_token
+            .as_deref()
+            .is_some_and(|expected| tokens_match(expected, &control.token));
+        let (content, is_error) = if !authorized {
+            warn!(
+                "Rejected control command {:?} from {}: bad or disabled token",
+                control.command, client_addr
+            );
+            ("unauthorized".to_string(), true)
+        } else {
+            match control.command.as_str() {
+                "pause" => {
+                    self.paused.store(true, Ordering::SeqCst);
+                    info!("Agent paused by {}", client_addr);
+                    ("paused".to_string(), false)
+                }
+                "resume" => {
+                    self.paused.store(false, Ordering::SeqCst);
+                    info!("Agent resumed by {}", client_addr);
+                    ("resumed".to_string(), false)
+                }
+                "dedup_stats" => (self.dedup_report().await, false),
+                "dedup_clear" => {
+                    let removed = self.dedup.clear().await;
+                    info!(removed, "Dedup cache cleared by {}", client_addr);
+                    (format!("cleared {} dedup entries", removed), false)
+                }
+                other => (format!("unknown control command: {}", other), true),
+            }
+        };
+
+        let response = encode_response(
+            header.framing,
+            header.seq,
+            &ResponsePayload {
+                content,
+                is_error,
+                timing: None,
+            },
+        )?;
+        self.socket
+            .send_to(&response, client_addr)
+            .await
+            .map_err(|e| CommError::SendError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Dedup table size for `dedup_stats`: a total line, then one line per
+    /// client with its entry count
+    async fn dedup_report(&self) -> String {
+        let per_client = self.dedup.per_client().await;
+        let entries: usize = per_client.iter().map(|(_, count)| count).sum();
+        let mut report = format!("{} entries from {} clients", entries, per_client.len());
+        for (addr, count) in per_client {
+            report.push_str(&format!("\n{} {}", addr, count));
+        }
+        report
+    }
+
+    /// Handle incoming INIT_REPORT: answer with the last init report
+    async fn handle_init_report(
+        &self,
+        header: Header,
+        client_addr: SocketAddr,
+    ) -> Result<(), CommError> {
+        let report = self.init_report.read().unwrap().clone();
+        let payload = match report {
+            Some(content) => ResponsePayload {
+                content,
+                is_error: false,
+                timing: None,
+            },
+            None => ResponsePayload {
+                content: "no init report yet".to_string(),
+                is_error: true,
+                timing: None,
+            },
+        };
+
+        let response = encode_response(header.framing, header.seq, &payload)?;
+        self.socket
+            .send_to(&response, client_addr)
+            .await
+            .map_err(|e| CommError::SendError(e.to_string()))?;
+        debug!("Sent init report seq={} to {}", header.seq, client_addr);
+        Ok(())
+    }
+
     /// Handle incoming REQUEST
     async fn handle_request(
         &self,
         payload_bytes: &[u8],
-        seq: u32,
+        header: Header,
+        client_addr: SocketAddr,
+    ) -> Result<(), CommError> {
+        // Decode payload up front: the idempotency key may live inside it
+        let request_payload = decode_request_payload(payload_bytes)?;
+        let key = match request_payload.request_id {
+            Some(id) => DedupKey::RequestId(id),
+            None => DedupKey::Seq(header.seq),
+        };
+        self.forward(
+            key,
+            header,
+            client_addr,
+            request_payload.content,
+            if request_payload.no_tools {
+                RequestKind::Chat
+            } else {
+                RequestKind::Input
+            },
+            request_payload.progress,
+        )
+        .await
+    }
+
+    /// Handle incoming APPROVE: forwarded like a REQUEST, so it is ACKed,
+    /// deduplicated and answered with a RESPONSE
+    async fn handle_approve(
+        &self,
+        payload_bytes: &[u8],
+        header: Header,
         client_addr: SocketAddr,
     ) -> Result<(), CommError> {
+        let approve = decode_approve_payload(payload_bytes)?;
+        let key = match approve.request_id {
+            Some(id) => DedupKey::RequestId(id),
+            None => DedupKey::Seq(header.seq),
+        };
+        let content = format!("approve plan {}", approve.plan_id);
+        self.forward(
+            key,
+            header,
+            client_addr,
+            content,
+            RequestKind::Approve(approve.plan_id),
+            false,
+        )
+        .await
+    }
+
+    /// Handle incoming REPLAY: forward it to the main loop like a request
+    async fn handle_replay(
+        &self,
+        payload_bytes: &[u8],
+        header: Header,
+        client_addr: SocketAddr,
+    ) -> Result<(), CommError> {
+        let replay = decode_replay_payload(payload_bytes)?;
+        let target = match (replay.index, replay.query) {
+            (Some(index), _) => ReplayTarget::Index(index as usize),
+            (None, Some(query)) if !query.is_empty() => ReplayTarget::Query(query),
+            _ => {
+                let response = encode_response(
+                    header.framing,
+                    header.seq,
+                    &ResponsePayload {
+                        content: "replay needs an index or a query".to_string(),
+                        is_error: true,
+                        timing: None,
+                    },
+                )?;
+                self.socket
+                    .send_to(&response, client_addr)
+                    .await
+                    .map_err(|e| CommError::SendError(e.to_string()))?;
+                return Ok(());
+            }
+        };
+        let key = match replay.request_id {
+            Some(id) => DedupKey::RequestId(id),
+            None => DedupKey::Seq(header.seq),
+        };
+        let content = format!("replay interaction {}", target);
+        self.forward(
+            key,
+            header,
+            client_addr,
+            content,
+            RequestKind::Replay(target),
+            false,
+        )
+        .await
+    }
+
+    /// ACK a new request, pass it to the main loop and send back its reply;
+    /// a duplicate of `key` gets the cached reply (or an ACK) instead. With
+    /// `progress`, tool output arriving meanwhile is relayed as PROGRESS
+    /// frames if the server has them on
+    async fn forward(
+        &self,
+        key: DedupKey,
+        header: Header,
+        client_addr: SocketAddr,
+        content: String,
+        kind: RequestKind,
+        progress: bool,
+    ) -> Result<(), CommError> {
+        let Header { seq, framing, .. } = header;
         // Check for duplicate
         let is_dup = {
-            let mut dedup = self.dedup.lock().await;
+            let mut dedup = self.dedup.shard(&client_addr).lock().await;
             let client_entries = dedup.entry(client_addr).or_insert_with(HashMap::new);
 
             // T-EDGE-07: Enforce capacity limit
             if client_entries.len() >= self.config.dedup_capacity {
                 // Remove oldest entry to make room
-                let oldest_seq = client_entries
+                let oldest_key = client_entries
                     .iter()
                     .min_by_key(|(_, e)| e.instant)
-                    .map(|(seq, _)| *seq);
-                if let Some(seq_to_remove) = oldest_seq {
-                    client_entries.remove(&seq_to_remove);
+                    .map(|(key, _)| key.clone());
+                if let Some(key_to_remove) = oldest_key {
+                    client_entries.remove(&key_to_remove);
                     debug!(
-                        "Dedup table at capacity, removed oldest entry seq={}",
-                        seq_to_remove
+                        "Dedup table at capacity, removed oldest entry {}",
+                        key_to_remove
                     );
                 }
             }
 
-            match client_entries.entry(seq) {
+            match client_entries.entry(key.clone()) {
                 std::collections::hash_map::Entry::Occupied(entry) => {
                     // Duplicate - return cached response if available
                     if let Some(ref cached) = entry.get().cached_response {
                         info!(
-                            "Duplicate request seq={} from {}, resending cached response",
-                            seq, client_addr
+                            "Duplicate request {} from {}, resending cached response",
+                            key, client_addr
                         );
-                        let cached_clone = cached.clone();
+                        // A retry keyed by request_id may carry a different seq;
+                        // answer with the seq the client is waiting on.
+                        let mut cached_clone = cached.clone();
+                        set_seq(&mut cached_clone, seq);
+                        mark_cached(&mut cached_clone);
                         drop(dedup); // Release lock before sending
                         self.socket
                             .send_to(&cached_clone, client_addr)
@@ -187,10 +607,10 @@ impl Comm {
                         // No cached response yet (original request still being processed)
                         // Send ACK to indicate we're still working on it
                         debug!(
-                            "Duplicate request seq={} from {}, no cached response yet, sending ACK",
-                            seq, client_addr
+                            "Duplicate request {} from {}, no cached response yet, sending ACK",
+                            key, client_addr
                         );
-                        let ack = encode_request_ack(seq)?;
+                        let ack = encode_request_ack(framing, seq)?;
                         drop(dedup);
                         self.socket
                             .send_to(&ack, client_addr)
@@ -200,25 +620,45 @@ impl Comm {
                     true
                 }
                 std::collections::hash_map::Entry::Vacant(entry) => {
+                    // Refuse rather than queue behind the client's other requests;
+                    // no dedup entry, so a retry once they finish is handled
+                    let max = self.config.max_in_flight_per_client;
+                    let Some(_in_flight) = self.in_flight.acquire(client_addr, max) else {
+                        drop(dedup);
+                        warn!(
+                            "Rejecting request {} seq={} from {}: {} requests already in flight",
+                            key, seq, client_addr, max
+                        );
+                        let error_payload = ResponsePayload {
+                            content: format!("too many in-flight requests (max {})", max),
+                            is_error: true,
+                            timing: None,
+                        };
+                        let response = encode_response(framing, seq, &error_payload)?;
+                        self.socket
+                            .send_to(&response, client_addr)
+                            .await
+                            .map_err(|e| CommError::SendError(e.to_string()))?;
+                        return Ok(());
+                    };
+
                     // New request - create dedup entry immediately (before processing)
                     // This ensures duplicate requests during processing are recognized
                     entry.insert(DedupEntry {
-                        instant: Instant::now(),
+                        instant: self.clock.now(),
                         cached_response: None,
                     });
 
-                    // Decode payload
-                    let request_payload = decode_request_payload(payload_bytes)?;
-
                     info!(
-                        "New request seq={} from {} content_len={}",
+                        "New request {} seq={} from {} content_len={}",
+                        key,
                         seq,
                         client_addr,
-                        request_payload.content.len()
+                        content.len()
                     );
 
                     // Send ACK immediately
-                    let ack = encode_request_ack(seq)?;
+                    let ack = encode_request_ack(framing, seq)?;
                     self.socket
                         .send_to(&ack, client_addr)
                         .await
@@ -227,41 +667,70 @@ impl Comm {
 
                     // Create channel for response
                     let (reply_tx, reply_rx) = oneshot::channel::<UserResponse>();
+                    let (progress_tx, progress_rx) =
+                        if progress && self.config.progress_interval_ms > 0 {
+                            let (tx, rx) = mpsc::channel(PROGRESS_CHANNEL_CAPACITY);
+                            (Some(tx), Some(rx))
+                        } else {
+                            (None, None)
+                        };
 
                     // Send request to main loop
                     let user_request = UserRequest {
-                        content: request_payload.content,
+                        content,
+                        kind,
                         reply: reply_tx,
                         source_addr: client_addr,
+                        request_id: match &key {
+                            DedupKey::RequestId(id) => Some(id.clone()),
+                            DedupKey::Seq(_) => None,
+                        },
+                        progress: progress_tx,
                     };
 
                     // Drop dedup lock before sending to main loop and waiting for response
                     drop(dedup);
+                    let handed_over = self.clock.now();
                     let send_result = self.loop_sender.send(user_request).await;
 
                     match send_result {
                         Ok(_) => {
                             // Wait for response from main loop
-                            match timeout(Duration::from_secs(300), reply_rx).await {
+                            let wait = Duration::from_secs(self.config.response_timeout_secs);
+                            let reply =
+                                self.await_reply(reply_rx, progress_rx, framing, seq, client_addr);
+                            match timeout(wait, reply).await {
                                 Ok(Ok(response)) => {
                                     // Send response to client
+                                    let timing = response
+                                        .timing
+                                        .filter(|_| self.config.response_timing)
+                                        .map(|handled| {
+                                            let waited = self
+                                                .clock
+                                                .now()
+                                                .saturating_duration_since(handed_over);
+                                            with_queue_time(handled, waited)
+                                        });
                                     let response_payload = ResponsePayload {
                                         content: response.content,
                                         is_error: response.is_error,
+                                        timing,
                                     };
-                                    let response_bytes = encode_response(seq, &response_payload)?;
+                                    let response_bytes =
+                                        encode_response(framing, seq, &response_payload)?;
                                     self.socket
                                         .send_to(&response_bytes, client_addr)
                                         .await
                                         .map_err(|e| CommError::SendError(e.to_string()))?;
 
                                     // Cache the response for deduplication
-                                    let mut dedup = self.dedup.lock().await;
+                                    let mut dedup = self.dedup.shard(&client_addr).lock().await;
                                     if let Some(client_entries) = dedup.get_mut(&client_addr) {
                                         client_entries.insert(
-                                            seq,
+                                            key.clone(),
                                             DedupEntry {
-                                                instant: Instant::now(),
+                                                instant: self.clock.now(),
                                                 cached_response: Some(response_bytes),
                                             },
                                         );
@@ -274,8 +743,10 @@ impl Comm {
                                     let error_payload = ResponsePayload {
                                         content: "No response from handler".to_string(),
                                         is_error: true,
+                                        timing: None,
                                     };
-                                    let response_bytes = encode_response(seq, &error_payload)?;
+                                    let response_bytes =
+                                        encode_response(framing, seq, &error_payload)?;
                                     self.socket
                                         .send_to(&response_bytes, client_addr)
                                         .await
@@ -287,8 +758,10 @@ impl Comm {
                                     let error_payload = ResponsePayload {
                                         content: "Response timeout".to_string(),
                                         is_error: true,
+                                        timing: None,
                                     };
-                                    let response_bytes = encode_response(seq, &error_payload)?;
+                                    let response_bytes =
+                                        encode_response(framing, seq, &error_payload)?;
                                     self.socket
                                         .send_to(&response_bytes, client_addr)
                                         .await
@@ -302,8 +775,9 @@ impl Comm {
                             let error_payload = ResponsePayload {
                                 content: "Internal server error".to_string(),
                                 is_error: true,
+                                timing: None,
                             };
-                            let response = encode_response(seq, &error_payload)?;
+                            let response = encode_response(framing, seq, &error_payload)?;
                             self.socket
                                 .send_to(&response, client_addr)
                                 .await
@@ -318,25 +792,368 @@ impl Comm {
         };
 
         if is_dup {
-            debug!("Duplicate request seq={} from {}", seq, client_addr);
+            debug!("Duplicate request {} from {}", key, client_addr);
         }
 
         Ok(())
     }
 
-    /// Cleanup expired entries from deduplication table
+    /// Wait for the main loop's reply, meanwhile relaying snippets from
+    /// `progress` as PROGRESS frames at most once per `progress_interval_ms`;
+    /// snippets arriving sooner are dropped
+    async fn await_reply(
+        &self,
+        mut reply_rx: oneshot::Receiver<UserResponse>,
+        progress: Option<mpsc::Receiver<String>>,
+        framing: Framing,
+        seq: u32,
+        client_addr: SocketAddr,
+    ) -> StdResult<UserResponse, oneshot::error::RecvError> {
+        let Some(mut progress) = progress else {
+            return reply_rx.await;
+        };
+        let interval = Duration::from_millis(self.config.progress_interval_ms);
+        let mut last_sent: Option<Instant> = None;
+
+        loop {
+            tokio::select! {
+                biased;
+                response = &mut reply_rx => return response,
+                Some(snippet) = progress.recv() => {
+                    let now = self.clock.now();
+                    if last_sent.is_some_and(|sent| now.saturating_duration_since(sent) < interval) {
+                        continue;
+                    }
+                    last_sent = Some(now);
+                    let payload = ProgressPayload {
+                        content: truncate_snippet(snippet),
+                    };
+                    // Progress is best effort; the RESPONSE still follows
+                    let sent = match encode_progress(framing, seq, &payload) {
+                        Ok(packet) => self.socket.send_to(&packet, client_addr).await.map(|_| ()),
+                        Err(e) => Err(std::io::Error::other(e.to_string())),
+                    };
+                    match sent {
+                        Ok(()) => debug!("Sent PROGRESS seq={} to {}", seq, client_addr),
+                        Err(e) => debug!("Failed to send PROGRESS seq={}: {}", seq, e),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Cleanup expired entries from deduplication table, shard by shard
     async fn cleanup_dedup(&self) {
-        let mut dedup = self.dedup.lock().await;
         let ttl = Duration::from_secs(self.config.dedup_ttl_secs);
-        let now = Instant::now();
+        let clients = self.dedup.cleanup(ttl, self.clock.now()).await;
+        debug!("Dedup table cleaned, {} clients tracked", clients);
+    }
+
+    /// Log the dedup table's size and oldest entry, so a leak or a TTL set
+    /// far too long shows up in the logs
+    async fn log_dedup_stats(&self) {
+        let stats = self.dedup.stats(self.clock.now()).await;
+        info!(
+            clients = stats.clients,
+            entries = stats.entries,
+            oldest_age_secs = stats.oldest_age.map(|age| age.as_secs()),
+            ttl_secs = self.config.dedup_ttl_secs,
+            "Dedup table stats"
+        );
+    }
+}
+
+/// Count of forwarded, unanswered requests per client
+#[derive(Debug, Default)]
+struct InFlight(Mutex<HashMap<SocketAddr, usize>>);
 
-        for (_addr, entries) in dedup.iter_mut() {
-            entries.retain(|_seq, entry| now.duration_since(entry.instant) < ttl);
+impl InFlight {
+    /// Count one more request for `addr`, or None if it already has `max`
+    /// (0 = no limit); the count drops again when the guard does
+    fn acquire(&self, addr: SocketAddr, max: usize) -> Option<InFlightGuard<'_>> {
+        let mut counts = self.0.lock().unwrap();
+        let count = counts.entry(addr).or_insert(0);
+        if max > 0 && *count >= max {
+            return None;
         }
+        *count += 1;
+        Some(InFlightGuard { table: self, addr })
+    }
+}
+
+/// One request counted in `InFlight`, released on drop
+struct InFlightGuard<'a> {
+    table: &'a InFlight,
+    addr: SocketAddr,
+}
 
-        // Clean up empty client entries
-        dedup.retain(|_addr, entries| !entries.is_empty());
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        let mut counts = self.table.0.lock().unwrap();
+        if let Some(count) = counts.get_mut(&self.addr) {
+            *count -= 1;
+            if *count == 0 {
+                counts.remove(&self.addr);
+            }
+        }
+    }
+}
 
-        debug!("Dedup table cleaned, {} clients tracked", dedup.len());
+/// Complete the main loop's `handled` timing with the time the request
+/// waited before handling began, given the time `waited` from handing it
+/// over until the reply
+fn with_queue_time(handled: ResponseTiming, waited: Duration) -> ResponseTiming {
+    let total_ms = u64::try_from(waited.as_millis()).unwrap_or(u64::MAX);
+    ResponseTiming {
+        queue_ms: total_ms.saturating_sub(handled.total_ms),
+        total_ms: total_ms.max(handled.total_ms),
+        ..handled
+    }
+}
+
+/// Cut `snippet` to at most `PROGRESS_MAX_BYTES`, on a char boundary
+fn truncate_snippet(mut snippet: String) -> String {
+    if snippet.len() > PROGRESS_MAX_BYTES {
+        let mut end = PROGRESS_MAX_BYTES;
+        while !snippet.is_char_boundary(end) {
+            end -= 1;
+        }
+        snippet.truncate(end);
+    }
+    snippet
+}
+
+/// Compare tokens without exiting at the first differing byte
+fn tokens_match(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected
+            .bytes()
+            .zip(given.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Bind a UDP socket to `addr` with the reuse options of `config`
+fn bind_socket(addr: SocketAddr, config: &CommConfig) -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(config.reuse_addr)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(config.reuse_port)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// Bind the configured address, retrying with exponential backoff while it
+/// is still held (e.g. by a daemon that is just exiting)
+async fn bind_with_retry(config: &CommConfig) -> std::io::Result<UdpSocket> {
+    let addr = config.bind_addr();
+    let mut attempt = 0;
+    loop {
+        match bind_socket(addr, config) {
+            Err(e) if e.kind() == ErrorKind::AddrInUse && attempt < config.bind_retries => {
+                attempt += 1;
+                let delay = restart_delay(config.bind_retry_base_delay_ms, attempt);
+                warn!(
+                    attempt,
+                    max_retries = config.bind_retries,
+                    delay_ms = delay.as_millis() as u64,
+                    "Comm address {} in use, retrying bind",
+                    addr
+                );
+                tokio::time::sleep(delay).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Exponential backoff delay for restart `attempt` (1-based), capped at 30s
+fn restart_delay(base_ms: u64, attempt: u32) -> Duration {
+    let multiplier = 2u64.saturating_pow(attempt.saturating_sub(1));
+    Duration::from_millis(base_ms.saturating_mul(multiplier).min(30_000))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comm::clock::MockClock;
+    use crate::comm::protocol::{decode_header, encode_packet};
+    use crate::comm::types::{ProgressPayload, RequestPayload};
+
+    #[tokio::test]
+    async fn test_dedup_entry_expires_exactly_at_ttl() {
+        let clock = Arc::new(MockClock::new());
+        let config = CommConfig {
+            listen_addr: "127.0.0.1".to_string(),
+            listen_port: 0,
+            dedup_ttl_secs: 60,
+            ..Default::default()
+        };
+        let (comm, mut loop_rx) = Comm::new(config).await.unwrap();
+        let comm = comm.with_clock(clock.clone());
+
+        // Main loop stand-in counting the requests that reach it
+        let handled = tokio::spawn(async move {
+            let mut handled = 0;
+            while let Some(req) = loop_rx.recv().await {
+                handled += 1;
+                req.reply.send(UserResponse::new("ok".to_string())).ok();
+            }
+            handled
+        });
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client_addr = client.local_addr().unwrap();
+        let packet = encode_packet(
+            MsgType::Request,
+            1,
+            Some(&RequestPayload {
+                content: "uptime".to_string(),
+                request_id: None,
+                no_tools: false,
+                progress: false,
+            }),
+        )
+        .unwrap();
+        comm.handle_packet(&packet, client_addr).await.unwrap();
+        assert_eq!(comm.dedup.stats(clock.now()).await.entries, 1);
+
+        // One tick short of the TTL the cached response is kept and resent
+        clock.advance(Duration::from_secs(60) - Duration::from_nanos(1));
+        comm.cleanup_dedup().await;
+        assert_eq!(comm.dedup.stats(clock.now()).await.entries, 1);
+        comm.handle_packet(&packet, client_addr).await.unwrap();
+
+        // At the TTL it is gone, so the same request is handled again
+        clock.advance(Duration::from_nanos(1));
+        comm.cleanup_dedup().await;
+        assert_eq!(comm.dedup.stats(clock.now()).await.entries, 0);
+        comm.handle_packet(&packet, client_addr).await.unwrap();
+
+        drop(comm);
+        assert_eq!(handled.await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_progress_throttled_by_injected_clock() {
+        let clock = Arc::new(MockClock::new());
+        let config = CommConfig {
+            listen_addr: "127.0.0.1".to_string(),
+            listen_port: 0,
+            progress_interval_ms: 100,
+            ..Default::default()
+        };
+        let (comm, mut loop_rx) = Comm::new(config).await.unwrap();
+        let comm = comm.with_clock(clock.clone());
+
+        // Main loop stand-in; each snippet is relayed before the next is sent
+        let loop_clock = clock.clone();
+        tokio::spawn(async move {
+            let req = loop_rx.recv().await.unwrap();
+            let progress = req.progress.unwrap();
+            let relay = async |snippet: &str| {
+                progress.send(snippet.to_string()).await.unwrap();
+                while progress.capacity() < progress.max_capacity() {
+                    tokio::task::yield_now().await;
+                }
+            };
+            relay("first").await;
+            // The clock has not moved, so this one is too soon
+            relay("too soon").await;
+            loop_clock.advance(Duration::from_millis(100));
+            relay("after interval").await;
+            req.reply.send(UserResponse::new("done".to_string())).ok();
+        });
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let packet = encode_packet(
+            MsgType::Request,
+            1,
+            Some(&RequestPayload {
+                content: "tail the log".to_string(),
+                request_id: None,
+                no_tools: false,
+                progress: true,
+            }),
+        )
+        .unwrap();
+        comm.handle_packet(&packet, client.local_addr().unwrap())
+            .await
+            .unwrap();
+
+        let mut snippets = Vec::new();
+        let mut buf = [0u8; 2048];
+        loop {
+            let len = client.recv(&mut buf).await.unwrap();
+            let header = decode_header(&buf[..len]).unwrap();
+            match header.msg_type {
+                MsgType::Progress => {
+                    let payload = header.payload(&buf[..len], len).unwrap();
+                    let progress: ProgressPayload = rmp_serde::from_slice(payload).unwrap();
+                    snippets.push(progress.content);
+                }
+                MsgType::Response => break,
+                _ => {}
+            }
+        }
+        assert_eq!(snippets, ["first", "after interval"]);
+    }
+
+    #[tokio::test]
+    async fn test_queue_time_measured_with_injected_clock() {
+        let clock = Arc::new(MockClock::new());
+        let config = CommConfig {
+            listen_addr: "127.0.0.1".to_string(),
+            listen_port: 0,
+            response_timing: true,
+            ..Default::default()
+        };
+        let (comm, mut loop_rx) = Comm::new(config).await.unwrap();
+        let comm = comm.with_clock(clock.clone());
+
+        // Main loop stand-in that is busy for 500ms, 200ms of them handling
+        let loop_clock = clock.clone();
+        tokio::spawn(async move {
+            let req = loop_rx.recv().await.unwrap();
+            loop_clock.advance(Duration::from_millis(500));
+            let handled = ResponseTiming {
+                queue_ms: 0,
+                inference_ms: 150,
+                tool_ms: 50,
+                total_ms: 200,
+            };
+            req.reply
+                .send(UserResponse::new("timed".to_string()).with_timing(handled))
+                .ok();
+        });
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let packet = encode_packet(
+            MsgType::Request,
+            1,
+            Some(&RequestPayload {
+                content: "time me".to_string(),
+                request_id: None,
+                no_tools: false,
+                progress: false,
+            }),
+        )
+        .unwrap();
+        comm.handle_packet(&packet, client.local_addr().unwrap())
+            .await
+            .unwrap();
+
+        let mut buf = [0u8; 2048];
+        let timing = loop {
+            let len = client.recv(&mut buf).await.unwrap();
+            let header = decode_header(&buf[..len]).unwrap();
+            if header.msg_type == MsgType::Response {
+                let payload = header.payload(&buf[..len], len).unwrap();
+                let response: ResponsePayload = rmp_serde::from_slice(payload).unwrap();
+                break response.timing.unwrap();
+            }
+        };
+        assert_eq!((timing.queue_ms, timing.total_ms), (300, 500));
     }
 }
@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
-use tokio::sync::oneshot;
+use std::sync::{Arc, RwLock};
+use tokio::sync::{mpsc, oneshot};
 
 /// Message types for the protocol
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -12,6 +13,25 @@ pub enum MsgType {
     RequestAck = 0x02,
     /// Shelly → Client: Shelly returns the response
     Response = 0x03,
+    /// Client → Shelly: re-run a past user query from the journal; handled
+    /// like a Request (ACK, then Response)
+    Replay = 0x06,
+    /// Client → Shelly: approve a plan proposed in plan mode; handled like a
+    /// Request (ACK, then Response)
+    Approve = 0x07,
+    /// Client → Shelly: ask for capabilities; Shelly answers with a Hello
+    /// carrying a `HelloResponse`
+    Hello = 0x08,
+    /// Client → Shelly: operator command (pause/resume); Shelly answers with
+    /// a Response
+    Control = 0x09,
+    /// Client → Shelly: fetch the last startup exploration report; Shelly
+    /// answers with a Response
+    InitReport = 0x0B,
+    /// Shelly → Client: output of a tool still running for the request with
+    /// this seq, sent between the ACK and the Response when the request
+    /// asked for it
+    Progress = 0x0C,
 }
 
 impl MsgType {
@@ -20,6 +40,12 @@ impl MsgType {
             0x01 => Some(Self::Request),
             0x02 => Some(Self::RequestAck),
             0x03 => Some(Self::Response),
+            0x06 => Some(Self::Replay),
+            0x07 => Some(Self::Approve),
+            0x08 => Some(Self::Hello),
+            0x09 => Some(Self::Control),
+            0x0B => Some(Self::InitReport),
+            0x0C => Some(Self::Progress),
             _ => None,
         }
     }
@@ -30,6 +56,40 @@ impl MsgType {
 pub struct RequestPayload {
     /// User input text
     pub content: String,
+    /// Client-generated idempotency key (UUID); dedup falls back to seq when absent
+    #[serde(default)]
+    pub request_id: Option<String>,
+    /// Answer without offering the model any tools
+    #[serde(default)]
+    pub no_tools: bool,
+    /// Stream output of running tools as PROGRESS frames, when the server
+    /// has them enabled
+    #[serde(default)]
+    pub progress: bool,
+}
+
+/// Approve payload from client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApprovePayload {
+    /// Id of the pending plan to execute
+    pub plan_id: String,
+    /// Client-generated idempotency key, as in `RequestPayload`
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+/// Replay payload from client; `index` wins when both are given
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayPayload {
+    /// Position among journaled user interactions, 0 = oldest
+    #[serde(default)]
+    pub index: Option<u32>,
+    /// Substring of the query; the most recent match is replayed
+    #[serde(default)]
+    pub query: Option<String>,
+    /// Client-generated idempotency key, as in `RequestPayload`
+    #[serde(default)]
+    pub request_id: Option<String>,
 }
 
 /// Response payload from Shelly
@@ -39,6 +99,85 @@ pub struct ResponsePayload {
     pub content: String,
     /// Whether this is an error response
     pub is_error: bool,
+    /// Where the request's time went; only sent with `response_timing` on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timing: Option<ResponseTiming>,
+}
+
+/// Progress payload from Shelly
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProgressPayload {
+    /// Snippet of a running tool's output
+    pub content: String,
+}
+
+/// Per-request timings, in milliseconds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResponseTiming {
+    /// Waiting for the main loop to start handling the request
+    pub queue_ms: u64,
+    /// Model inference
+    pub inference_ms: u64,
+    /// Tool execution
+    pub tool_ms: u64,
+    /// From handing the request to the main loop until its reply; at least
+    /// the sum of the other three
+    pub total_ms: u64,
+}
+
+/// Control payload from an operator client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ControlPayload {
+    /// "pause", "resume", "dedup_stats" or "dedup_clear"
+    pub command: String,
+    /// Must match `CommConfig.control_token`
+    pub token: String,
+}
+
+/// Capabilities Shelly reports in answer to a Hello
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HelloResponse {
+    /// Wire protocol version
+    pub protocol_version: u32,
+    /// Largest REQUEST payload accepted, in bytes
+    pub max_payload_bytes: usize,
+    /// Supported message types and optional behaviours
+    pub features: Vec<String>,
+}
+
+/// Last init report, written by the main loop and served on InitReport
+pub type InitReportSlot = Arc<RwLock<Option<String>>>;
+
+/// What a `UserRequest` asks the main loop to do
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum RequestKind {
+    /// Handle `content` as user input
+    #[default]
+    Input,
+    /// Handle `content` as user input without offering tools
+    Chat,
+    /// Execute the pending plan with this id
+    Approve(String),
+    /// Handle a past user query again and compare with its old response
+    Replay(ReplayTarget),
+}
+
+/// Which journaled interaction a replay re-runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayTarget {
+    /// Position among user interactions, 0 = oldest
+    Index(usize),
+    /// Most recent interaction whose query contains this text
+    Query(String),
+}
+
+impl std::fmt::Display for ReplayTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplayTarget::Index(index) => write!(f, "#{}", index),
+            ReplayTarget::Query(query) => write!(f, "matching {:?}", query),
+        }
+    }
 }
 
 /// Request sent from Comm to main loop
@@ -46,10 +185,18 @@ pub struct ResponsePayload {
 pub struct UserRequest {
     /// User input content
     pub content: String,
+    /// Input to handle, or a plan approval
+    pub kind: RequestKind,
     /// Channel to send response back to Comm
     pub reply: oneshot::Sender<UserResponse>,
     /// Client source address
     pub source_addr: SocketAddr,
+    /// Idempotency key the client sent, if any; identifies the request in logs
+    pub request_id: Option<String>,
+    /// Snippets of running tools' output, relayed to the client as PROGRESS
+    /// frames; None unless the client asked for them and Comm has them on.
+    /// Bounded, so senders should `try_send` and drop what does not fit
+    pub progress: Option<mpsc::Sender<String>>,
 }
 
 /// Response sent from main loop to Comm
@@ -59,6 +206,9 @@ pub struct UserResponse {
     pub content: String,
     /// Whether this is an error response
     pub is_error: bool,
+    /// Time the main loop spent handling the request; `queue_ms` is left
+    /// for Comm to fill in and `total_ms` covers the handling only
+    pub timing: Option<ResponseTiming>,
 }
 
 impl UserResponse {
@@ -66,6 +216,7 @@ impl UserResponse {
         Self {
             content,
             is_error: false,
+            timing: None,
         }
     }
 
@@ -73,6 +224,12 @@ impl UserResponse {
         Self {
             content,
             is_error: true,
+            timing: None,
         }
     }
+
+    pub fn with_timing(mut self, timing: ResponseTiming) -> Self {
+        self.timing = Some(timing);
+        self
+    }
 }
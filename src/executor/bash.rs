@@ -2,12 +2,19 @@
 #![allow(dead_code)]
 
 use crate::brain::ToolDefinition;
+use crate::brain::text::{floor_char_boundary, truncate_chars};
+use crate::executor::progress::{Progress, ProgressPolicy, ProgressTracker, report, ticking};
+use crate::executor::types::{BashOutputFormat, ExecutionConstraints, ProcessOutput};
 use crate::executor::{ExecutorError, Result, ToolImpl, ToolOutput};
 use async_trait::async_trait;
 use serde::Deserialize;
-use std::time::Instant;
+use std::io;
+use std::process::Stdio;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
 use tokio::process::Command;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 /// Bash tool input parameters
 #[derive(Debug, Deserialize)]
@@ -18,22 +25,61 @@ struct BashInput {
 /// Bash tool implementation
 pub struct BashTool {
     description: String,
+    constraints: ExecutionConstraints,
+    /// Interpreter the command is handed to
+    shell: String,
+    /// Arguments placed before the command, e.g. `-c`
+    shell_args: Vec<String>,
+    /// When to report progress of a command that is still running
+    progress: ProgressPolicy,
+    /// Layout of the content string
+    output_format: BashOutputFormat,
 }
 
 impl BashTool {
-    pub fn new(description: impl Into<String>) -> Self {
+    pub fn new(description: impl Into<String>, constraints: ExecutionConstraints) -> Self {
         Self {
             description: description.into(),
+            constraints,
+            shell: "/bin/sh".to_string(),
+            shell_args: vec!["-c".to_string()],
+            progress: ProgressPolicy::default(),
+            output_format: BashOutputFormat::default(),
         }
     }
+
+    /// Run commands with `shell`, passing `args` and then the command
+    pub fn with_shell(mut self, shell: impl Into<String>, args: Vec<String>) -> Self {
+        self.shell = shell.into();
+        self.shell_args = args;
+        self
+    }
+
+    /// Report progress of running commands as `policy` says
+    pub fn with_progress(mut self, policy: ProgressPolicy) -> Self {
+        self.progress = policy;
+        self
+    }
+
+    /// Assemble content strings as `format` says
+    pub fn with_output_format(mut self, format: BashOutputFormat) -> Self {
+        self.output_format = format;
+        self
+    }
 }
 
 #[async_trait]
 impl ToolImpl for BashTool {
     fn definition(&self) -> ToolDefinition {
+        // The model is told the interpreter actually configured
+        let mut invocation = self.shell.clone();
+        for arg in &self.shell_args {
+            invocation.push(' ');
+            invocation.push_str(arg);
+        }
         ToolDefinition {
             name: "bash".to_string(),
-            description: self.description.clone(),
+            description: self.description.replace(SHELL_PLACEHOLDER, &invocation),
             input_schema: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -47,6 +93,12 @@ impl ToolImpl for BashTool {
         }
     }
 
+    fn cacheable(&self) -> bool {
+        // Commands may depend on state they or others changed; a repeat
+        // runs again unless the tools config marks bash cacheable
+        false
+    }
+
     async fn run(&self, input: serde_json::Value) -> Result<ToolOutput> {
         let start = Instant::now();
 
@@ -56,55 +108,307 @@ impl ToolImpl for BashTool {
 
         debug!(command = %command, "executing bash command");
 
-        // Execute command
-        let output = Command::new("/bin/sh")
-            .arg("-c")
+        // Spawn with piped output so lines can be captured as they arrive
+        let mut cmd = Command::new(&self.shell);
+        cmd.args(&self.shell_args)
             .arg(&command)
-            .output()
-            .await
-            .map_err(|e| ExecutorError::SpawnFailed("bash".to_string(), e.to_string()))?;
-
-        let duration_ms = start.elapsed().as_millis() as u64;
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(dir) = &self.constraints.working_dir {
+            cmd.current_dir(dir);
+        }
+        let mut child = cmd.spawn().map_err(|e| {
+            if e.kind() == io::ErrorKind::NotFound {
+                ExecutorError::ShellNotFound(self.shell.clone())
+            } else {
+                ExecutorError::SpawnFailed(
+                    "bash".to_string(),
+                    format!("cannot start configured shell {}: {}", self.shell, e),
+                )
+            }
+        })?;
 
-        // Build output string
-        let mut content = String::new();
+        let stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+        let capture = Mutex::new(Capture::new(
+            self.constraints.max_output_bytes,
+            ProgressTracker::new("bash", self.progress),
+        ));
+        let timeout_secs = self.constraints.timeout_secs;
 
-        if !output.stdout.is_empty() {
-            content.push_str("[stdout]\n");
-            content.push_str(&String::from_utf8_lossy(&output.stdout));
-        }
+        let finished = tokio::time::timeout(Duration::from_secs(timeout_secs), async {
+            let streams = async {
+                let (out, err) = tokio::join!(
+                    stream_lines(stdout, Stream::Stdout, &capture),
+                    stream_lines(stderr, Stream::Stderr, &capture)
+                );
+                out.and(err)
+            };
+            ticking(streams, self.progress.interval, || {
+                let due = capture.lock().unwrap().progress.tick();
+                if let Some(progress) = due {
+                    report_progress(progress);
+                }
+            })
+            .await?;
+            child.wait().await
+        })
+        .await;
 
-        if !output.stderr.is_empty() {
-            if !content.is_empty() {
-                content.push('\n');
+        let status = match finished {
+            Ok(Ok(status)) => Some(status),
+            Ok(Err(e)) => {
+                return Err(ExecutorError::OutputCaptureFailed(
+                    "bash".to_string(),
+                    e.to_string(),
+                ));
             }
-            content.push_str("[stderr]\n");
-            content.push_str(&String::from_utf8_lossy(&output.stderr));
-        }
+            Err(_) => {
+                warn!(
+                    command = %truncate_chars(&command, 100),
+                    timeout_secs,
+                    "bash command timed out, returning partial output"
+                );
+                let _ = child.kill().await;
+                None
+            }
+        };
 
-        content.push_str(&format!(
-            "\n[exit_code]\n{}",
-            output.status.code().unwrap_or(-1)
-        ));
+        let duration_ms = start.elapsed().as_millis() as u64;
+        let capture = capture.into_inner().unwrap_or_else(|e| e.into_inner());
 
-        let is_error = !output.status.success();
+        let Some(status) = status else {
+            let content = capture.content(self.output_format, None, timeout_secs);
+            return Ok(ToolOutput {
+                content,
+                is_error: true,
+                partial: true,
+                process: Some(capture.into_process(None)),
+            });
+        };
+
+        let content = capture.content(
+            self.output_format,
+            Some(status.code().unwrap_or(-1)),
+            timeout_secs,
+        );
+
+        let is_error = !status.success();
 
         info!(
-            command = %command.chars().take(100).collect::<String>(),
+            command = %truncate_chars(&command, 100),
             duration_ms = duration_ms,
-            exit_code = output.status.code().unwrap_or(-1),
+            exit_code = status.code().unwrap_or(-1),
             output_bytes = content.len(),
             is_error = is_error,
             "bash command executed"
         );
 
-        Ok(ToolOutput { content, is_error })
+        Ok(ToolOutput {
+            content,
+            is_error,
+            partial: false,
+            process: Some(capture.into_process(status.code())),
+        })
     }
 }
 
+/// Which pipe a line came from
+#[derive(Debug, Clone, Copy)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Output captured so far, shared by both pipes
+struct Capture {
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    /// Bytes that may still be stored across both streams
+    remaining: usize,
+    /// Some output was dropped at the size limit
+    truncated: bool,
+    /// Lines seen across both streams, for progress reports
+    progress: ProgressTracker,
+}
+
+impl Capture {
+    fn new(max_bytes: usize, progress: ProgressTracker) -> Self {
+        Self {
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+            remaining: max_bytes,
+            truncated: false,
+            progress,
+        }
+    }
+
+    fn into_process(self, exit_code: Option<i32>) -> ProcessOutput {
+        ProcessOutput {
+            stdout: String::from_utf8_lossy(&self.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&self.stderr).into_owned(),
+            exit_code,
+        }
+    }
+
+    /// Content string in `format`; `exit_code` is None when the command
+    /// timed out after `timeout_secs`
+    fn content(
+        &self,
+        format: BashOutputFormat,
+        exit_code: Option<i32>,
+        timeout_secs: u64,
+    ) -> String {
+        let stdout = String::from_utf8_lossy(&self.stdout);
+        let stderr = String::from_utf8_lossy(&self.stderr);
+
+        if format == BashOutputFormat::Json {
+            return serde_json::json!({
+                "stdout": stdout,
+                "stderr": stderr,
+                "exit_code": exit_code,
+                "truncated": self.truncated,
+                "timed_out": exit_code.is_none(),
+            })
+            .to_string();
+        }
+
+        let mut content = String::new();
+        if format == BashOutputFormat::Raw {
+            content.push_str(if stdout.is_empty() { &stderr } else { &stdout });
+        } else {
+            if !stdout.is_empty() {
+                content.push_str("[stdout]\n");
+                content.push_str(&stdout);
+            }
+            if !stderr.is_empty() {
+                if !content.is_empty() {
+                    content.push('\n');
+                }
+                content.push_str("[stderr]\n");
+                content.push_str(&stderr);
+            }
+        }
+
+        // Kept in every text layout so the model never mistakes a cut or
+        // partial output for the whole of it
+        if self.truncated {
+            content.push_str("\n...(truncated)");
+        }
+        match exit_code {
+            None => content.push_str(&format!(
+                "\n[timeout]\nCommand did not finish within {} seconds; output above is partial",
+                timeout_secs
+            )),
+            Some(code) if format == BashOutputFormat::Labeled => {
+                content.push_str(&format!("\n[exit_code]\n{}", code))
+            }
+            Some(_) => {}
+        }
+        content
+    }
+
+    fn push(&mut self, stream: Stream, bytes: &[u8]) {
+        let keep = bytes.len().min(self.remaining);
+        let buf = match stream {
+            Stream::Stdout => &mut self.stdout,
+            Stream::Stderr => &mut self.stderr,
+        };
+        if keep < bytes.len() {
+            // Cut before a split character rather than leave half of it. The
+            // byte past the limit shows whether the cut splits one, checked
+            // on the whole stream since a chunk may start mid-character.
+            let start = buf.len();
+            buf.extend_from_slice(&bytes[..=keep]);
+            let end = floor_char_boundary(buf, start + keep);
+            buf.truncate(end);
+            self.truncated = true;
+            self.remaining = 0;
+        } else {
+            buf.extend_from_slice(bytes);
+            self.remaining -= keep;
+        }
+    }
+}
+
+/// Largest piece of a pipe read at once, so a line without newlines is
+/// never buffered whole
+const READ_CHUNK_BYTES: usize = 8 * 1024;
+
+/// Characters of an output line kept for the debug log and progress reports
+const LOGGED_LINE_CHARS: usize = 200;
+
+/// Log and capture a pipe chunk by chunk until it closes, counting lines for
+/// progress reports
+async fn stream_lines<R: AsyncRead + Unpin>(
+    pipe: Option<R>,
+    stream: Stream,
+    capture: &Mutex<Capture>,
+) -> io::Result<()> {
+    let Some(pipe) = pipe else { return Ok(()) };
+    let mut reader = BufReader::with_capacity(READ_CHUNK_BYTES, pipe);
+    // Start of the current line, for the log and progress reports
+    let mut head = String::new();
+    let mut line_bytes = 0;
+    loop {
+        let chunk = reader.fill_buf().await?;
+        if chunk.is_empty() {
+            if line_bytes > 0 {
+                end_line(stream, &head, line_bytes, capture);
+            }
+            return Ok(());
+        }
+        // Up to the end of the line, or the whole chunk; bytes past the
+        // output limit are dropped by Capture::push
+        let (len, line_end) = match chunk.iter().position(|&b| b == b'\n') {
+            Some(i) => (i + 1, true),
+            None => (chunk.len(), false),
+        };
+        let piece = &chunk[..len];
+        capture.lock().unwrap().push(stream, piece);
+        let room = LOGGED_LINE_CHARS.saturating_sub(head.chars().count());
+        if room > 0 {
+            head.push_str(truncate_chars(&String::from_utf8_lossy(piece), room));
+        }
+        line_bytes += len;
+        reader.consume(len);
+
+        if line_end {
+            end_line(stream, &head, line_bytes, capture);
+            head.clear();
+            line_bytes = 0;
+        }
+    }
+}
+
+/// Log a finished output line by its start and count it for progress
+fn end_line(stream: Stream, head: &str, bytes: usize, capture: &Mutex<Capture>) {
+    debug!(stream = ?stream, line = %head.trim_end(), bytes, "bash output");
+    let due = capture.lock().unwrap().progress.line(head);
+    // Reported outside the lock; the sink may take its own
+    if let Some(progress) = due {
+        report_progress(progress);
+    }
+}
+
+/// Log a progress report and send it to the current sink
+fn report_progress(progress: Progress) {
+    info!(
+        lines = progress.lines,
+        elapsed_ms = progress.elapsed.as_millis() as u64,
+        "bash command progress"
+    );
+    report(progress);
+}
+
+/// Replaced in the bash description by the configured shell and its args
+const SHELL_PLACEHOLDER: &str = "{shell}";
+
 /// Default bash tool description
 pub fn default_bash_description() -> String {
-    r#"Execute a shell command via /bin/sh -c.
+    r#"Execute a shell command via {shell}.
 The system is Linux.
 Commands run with daemon process privileges.
 Stdout and stderr are captured. Exit code is returned."#
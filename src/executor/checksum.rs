@@ -0,0 +1,336 @@
+// Checksum tool implementation
+#![allow(dead_code)]
+
+use crate::brain::ToolDefinition;
+use crate::executor::path::validate_path;
+use crate::executor::{ExecutorError, Result, ToolImpl, ToolOutput};
+use async_trait::async_trait;
+use serde::Deserialize;
+use sha2::Digest;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+/// Bytes read per step, so large files are hashed without being loaded whole
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Default number of files hashed for a directory manifest
+const DEFAULT_MAX_FILES: usize = 1000;
+
+/// Supported digest algorithms
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Algorithm {
+    Md5,
+    Sha1,
+    #[default]
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl Algorithm {
+    fn name(self) -> &'static str {
+        match self {
+            Algorithm::Md5 => "md5",
+            Algorithm::Sha1 => "sha1",
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Sha384 => "sha384",
+            Algorithm::Sha512 => "sha512",
+        }
+    }
+
+    /// Hex digest of everything `reader` yields
+    fn digest(self, reader: impl Read) -> io::Result<String> {
+        match self {
+            Algorithm::Md5 => hash_reader::<md5::Md5>(reader),
+            Algorithm::Sha1 => hash_reader::<sha1::Sha1>(reader),
+            Algorithm::Sha256 => hash_reader::<sha2::Sha256>(reader),
+            Algorithm::Sha384 => hash_reader::<sha2::Sha384>(reader),
+            Algorithm::Sha512 => hash_reader::<sha2::Sha512>(reader),
+        }
+    }
+}
+
+/// Checksum tool input parameters
+#[derive(Debug, Deserialize)]
+struct ChecksumInput {
+    path: String,
+    #[serde(default)]
+    algo: Algorithm,
+    #[serde(default)]
+    manifest: bool,
+    #[serde(default)]
+    max_files: Option<usize>,
+}
+
+/// Per-file digests of a directory
+struct Manifest {
+    /// `<digest>  <relative path>` lines, in path order
+    lines: Vec<String>,
+    /// Files that could not be hashed, with the reason
+    skipped: Vec<String>,
+    /// Stopped at `max_files`
+    truncated: bool,
+}
+
+/// Checksum tool implementation
+pub struct ChecksumTool {
+    description: String,
+    allowed_root: Option<PathBuf>,
+    max_file_bytes: u64,
+}
+
+impl ChecksumTool {
+    pub fn new(
+        description: impl Into<String>,
+        allowed_root: Option<PathBuf>,
+        max_file_bytes: u64,
+    ) -> Self {
+        Self {
+            description: description.into(),
+            allowed_root,
+            max_file_bytes,
+        }
+    }
+}
+
+#[async_trait]
+impl ToolImpl for ChecksumTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "checksum".to_string(),
+            description: self.description.clone(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "The file, or with manifest the directory, to hash"
+                    },
+                    "algo": {
+                        "type": "string",
+                        "enum": ["md5", "sha1", "sha256", "sha384", "sha512"],
+                        "description": "Digest algorithm (default sha256)"
+                    },
+                    "manifest": {
+                        "type": "boolean",
+                        "description": "Hash every file under a directory (default false)"
+                    },
+                    "max_files": {
+                        "type": "integer",
+                        "description": "Most files hashed for a manifest (default 1000)"
+                    }
+                },
+                "required": ["path"]
+            }),
+        }
+    }
+
+    async fn run(&self, input: serde_json::Value) -> Result<ToolOutput> {
+        let ChecksumInput {
+            path,
+            algo,
+            manifest,
+            max_files,
+        } = serde_json::from_value(input)
+            .map_err(|e| ExecutorError::InvalidInput("checksum".to_string(), e.to_string()))?;
+
+        let target = match validate_path(self.allowed_root.as_deref(), Path::new(&path)) {
+            Ok(target) => target,
+            Err(e) => return Ok(ToolOutput::error(e.to_string())),
+        };
+        let max_file_bytes = self.max_file_bytes;
+        let max_files = max_files.unwrap_or(DEFAULT_MAX_FILES);
+
+        debug!(path = %target.display(), algo = algo.name(), manifest, "computing checksum");
+
+        if target.is_dir() {
+            if !manifest {
+                return Ok(ToolOutput::error(format!(
+                    "{} is a directory; set manifest to true to hash every file in it",
+                    target.display()
+                )));
+            }
+            let walked = target.clone();
+            let listing = tokio::task::spawn_blocking(move || {
+                hash_dir(&walked, algo, max_file_bytes, max_files)
+            })
+            .await
+            .map_err(|e| {
+                ExecutorError::OutputCaptureFailed("checksum".to_string(), e.to_string())
+            })?;
+            let listing = match listing {
+                Ok(listing) => listing,
+                Err(e) => {
+                    return Ok(ToolOutput::error(format!(
+                        "cannot read {}: {}",
+                        target.display(),
+                        e
+                    )));
+                }
+            };
+
+            info!(
+                path = %target.display(),
+                files = listing.lines.len(),
+                skipped = listing.skipped.len(),
+                truncated = listing.truncated,
+                "directory manifest computed"
+            );
+
+            let mut content = listing.lines.join("\n");
+            for skipped in &listing.skipped {
+                content.push_str(&format!("\n[skipped {}]", skipped));
+            }
+            if listing.truncated {
+                content.push_str(&format!("\n[truncated: stopped after {} files]", max_files));
+            }
+            if content.is_empty() {
+                content = format!("no files under {}", target.display());
+            }
+            return Ok(ToolOutput::success(content.trim_start().to_string()));
+        }
+
+        let hashed = target.clone();
+        let digest = tokio::task::spawn_blocking(move || hash_file(&hashed, algo, max_file_bytes))
+            .await
+            .map_err(|e| {
+                ExecutorError::OutputCaptureFailed("checksum".to_string(), e.to_string())
+            })?;
+
+        match digest {
+            Ok(digest) => {
+                info!(path = %target.display(), algo = algo.name(), "checksum computed");
+                Ok(ToolOutput::success(format!(
+                    "{}  {}",
+                    digest,
+                    target.display()
+                )))
+            }
+            Err(e) => Ok(ToolOutput::error(format!(
+                "cannot hash {}: {}",
+                target.display(),
+                e
+            ))),
+        }
+    }
+
+    fn cacheable(&self) -> bool {
+        // Detecting changes is the point, so never reuse an earlier digest
+        false
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+}
+
+/// Hex digest of `reader`, read `CHUNK_SIZE` bytes at a time
+fn hash_reader<D: Digest>(mut reader: impl Read) -> io::Result<String> {
+    let mut hasher = D::new();
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => hasher.update(&buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+/// Hex digest of the regular file at `path`, refusing files over `max_bytes`
+fn hash_file(path: &Path, algo: Algorithm, max_bytes: u64) -> io::Result<String> {
+    let file = File::open(path)?;
+    let metadata = file.metadata()?;
+    if !metadata.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "not a regular file",
+        ));
+    }
+    if metadata.len() > max_bytes {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} bytes exceeds the {} byte limit",
+                metadata.len(),
+                max_bytes
+            ),
+        ));
+    }
+    algo.digest(file)
+}
+
+/// Hash regular files under `dir` depth-first in name order, stopping after
+/// `max_files`; symlinks are not followed
+fn hash_dir(dir: &Path, algo: Algorithm, max_bytes: u64, max_files: usize) -> io::Result<Manifest> {
+    let mut manifest = Manifest {
+        lines: Vec::new(),
+        skipped: Vec::new(),
+        truncated: false,
+    };
+    let mut pending = vec![PathBuf::new()];
+    let mut first = true;
+
+    while let Some(relative) = pending.pop() {
+        let read_dir = match fs::read_dir(dir.join(&relative)) {
+            Ok(read_dir) => read_dir,
+            Err(e) if first => return Err(e),
+            Err(e) => {
+                manifest
+                    .skipped
+                    .push(format!("{}: {}", relative.display(), e));
+                continue;
+            }
+        };
+        first = false;
+
+        // Reversed so popping `pending` visits subdirectories in name order
+        let mut children: Vec<_> = read_dir.filter_map(|e| e.ok()).collect();
+        children.sort_by_key(|e| std::cmp::Reverse(e.file_name()));
+
+        let mut subdirs = Vec::new();
+        let mut files = Vec::new();
+        for child in children {
+            let child_relative = relative.join(child.file_name());
+            match child.file_type() {
+                Ok(kind) if kind.is_dir() => subdirs.push(child_relative),
+                Ok(kind) if kind.is_file() => files.push(child_relative),
+                _ => {}
+            }
+        }
+
+        for file in files.into_iter().rev() {
+            if manifest.lines.len() >= max_files {
+                manifest.truncated = true;
+                return Ok(manifest);
+            }
+            match hash_file(&dir.join(&file), algo, max_bytes) {
+                Ok(digest) => manifest
+                    .lines
+                    .push(format!("{}  {}", digest, file.display())),
+                Err(e) => manifest.skipped.push(format!("{}: {}", file.display(), e)),
+            }
+        }
+        pending.extend(subdirs);
+    }
+
+    Ok(manifest)
+}
+
+/// Default checksum tool description
+pub fn default_checksum_description() -> String {
+    r#"Compute the checksum of a file, e.g. {"path": "/usr/bin/shelly", "algo": "sha256"}.
+Supports md5, sha1, sha256 (default), sha384 and sha512; returns "<hex digest>  <path>".
+With "manifest": true on a directory, returns one line per file, like sha256sum.
+Files are streamed, so large files are fine up to the configured size limit."#
+        .to_string()
+}
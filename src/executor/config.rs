@@ -1,8 +1,15 @@
 // Executor configuration
 #![allow(dead_code)]
 
-use crate::executor::types::ExecutionConstraints;
+use crate::executor::error::{ExecutorError, Result};
+use crate::executor::progress::ProgressPolicy;
+use crate::executor::types::{BashOutputFormat, ExecutionConstraints};
 use std::path::PathBuf;
+use std::time::Duration;
+
+/// Largest `max_concurrent_executions`; each running call holds a few file
+/// descriptors and processes, so more would exhaust typical limits
+const MAX_CONCURRENT_EXECUTIONS: usize = 1024;
 
 /// Executor configuration
 #[derive(Debug, Clone)]
@@ -13,6 +20,24 @@ pub struct ExecutorConfig {
     pub tools_toml_path: PathBuf,
     /// Shell path for command execution
     pub shell: String,
+    /// Arguments passed to the shell before the command (`-c` for POSIX
+    /// shells; e.g. `-Command` for PowerShell)
+    pub shell_args: Vec<String>,
+    /// Root directory filesystem tools are confined to (None = unrestricted)
+    pub allowed_root: Option<PathBuf>,
+    /// Maximum tool executions running at once (0 = unlimited)
+    pub max_concurrent_executions: usize,
+    /// When streaming tools report progress while they run
+    pub progress: ProgressPolicy,
+    /// systemctl binary used by the service_status tool
+    pub systemctl: String,
+    /// How the bash tool lays out stdout, stderr and the exit code
+    pub bash_output_format: BashOutputFormat,
+    /// Largest file the checksum tool will hash, in bytes
+    pub checksum_max_file_bytes: u64,
+    /// Treat two tools registered under one name as fatal instead of
+    /// logging it and keeping the later one
+    pub strict_tool_names: bool,
 }
 
 impl Default for ExecutorConfig {
@@ -21,6 +46,114 @@ impl Default for ExecutorConfig {
             constraints: ExecutionConstraints::default(),
             tools_toml_path: PathBuf::from("tools.toml"),
             shell: String::from("/bin/sh"),
+            shell_args: vec![String::from("-c")],
+            allowed_root: None,
+            max_concurrent_executions: 0,
+            progress: ProgressPolicy::default(),
+            systemctl: String::from("systemctl"),
+            bash_output_format: BashOutputFormat::default(),
+            checksum_max_file_bytes: 1024 * 1024 * 1024,
+            strict_tool_names: false,
+        }
+    }
+}
+
+impl ExecutorConfig {
+    /// Check ranges and well-formedness, reporting every problem at once
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+
+        if self.constraints.timeout_secs == 0 {
+            problems.push("timeout_secs must be greater than 0".to_string());
+        }
+        if self.constraints.max_output_bytes == 0 {
+            problems.push("max_output_bytes must be greater than 0".to_string());
+        }
+        if self.shell.trim().is_empty() {
+            problems.push("shell must not be empty".to_string());
+        }
+        if self.max_concurrent_executions > MAX_CONCURRENT_EXECUTIONS {
+            problems.push(format!(
+                "max_concurrent_executions must be at most {} (0 = unlimited)",
+                MAX_CONCURRENT_EXECUTIONS
+            ));
+        }
+        if self.checksum_max_file_bytes == 0 {
+            problems.push("checksum_max_file_bytes must be greater than 0".to_string());
+        }
+        if self.systemctl.trim().is_empty() {
+            problems.push("systemctl must not be empty".to_string());
+        }
+        if let Some(dir) = &self.constraints.working_dir
+            && !dir.is_dir()
+        {
+            problems.push(format!("working_dir {} is not a directory", dir.display()));
+        }
+        if let Some(root) = &self.allowed_root
+            && !root.is_dir()
+        {
+            problems.push(format!(
+                "allowed_root {} is not a directory",
+                root.display()
+            ));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ExecutorError::ConfigInvalid(problems.join("; ")))
+        }
+    }
+
+    /// Load configuration from environment variables, falling back to defaults
+    pub fn from_env() -> Self {
+        dotenvy::dotenv().ok();
+
+        let defaults = Self::default();
+        Self {
+            shell: std::env::var("EXECUTOR_SHELL")
+                .ok()
+                .filter(|v| !v.trim().is_empty())
+                .unwrap_or(defaults.shell),
+            shell_args: std::env::var("EXECUTOR_SHELL_ARGS")
+                .map(|v| v.split_whitespace().map(String::from).collect())
+                .unwrap_or(defaults.shell_args),
+            allowed_root: std::env::var("EXECUTOR_ALLOWED_ROOT")
+                .ok()
+                .filter(|v| !v.is_empty())
+                .map(PathBuf::from),
+            max_concurrent_executions: std::env::var("EXECUTOR_MAX_CONCURRENT")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.max_concurrent_executions),
+            progress: ProgressPolicy {
+                every_lines: std::env::var("EXECUTOR_PROGRESS_LINES")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(defaults.progress.every_lines),
+                interval: std::env::var("EXECUTOR_PROGRESS_INTERVAL_SECS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .map(Duration::from_secs)
+                    .unwrap_or(defaults.progress.interval),
+            },
+            systemctl: std::env::var("EXECUTOR_SYSTEMCTL")
+                .ok()
+                .filter(|v| !v.trim().is_empty())
+                .unwrap_or(defaults.systemctl),
+            bash_output_format: std::env::var("EXECUTOR_BASH_OUTPUT_FORMAT")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.bash_output_format),
+            checksum_max_file_bytes: std::env::var("EXECUTOR_CHECKSUM_MAX_BYTES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.checksum_max_file_bytes),
+            strict_tool_names: std::env::var("EXECUTOR_STRICT_TOOL_NAMES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.strict_tool_names),
+            ..defaults
         }
     }
 }
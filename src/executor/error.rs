@@ -9,18 +9,33 @@ pub enum ExecutorError {
     #[error("Unknown tool: {0}")]
     UnknownTool(String),
 
+    #[error("Tool not enabled: {0}")]
+    ToolDisabled(String),
+
+    #[error("Duplicate tool name: {0}")]
+    DuplicateTool(String),
+
     #[error("Invalid input for tool '{0}': {1}")]
     InvalidInput(String, String),
 
     #[error("Failed to spawn process for tool '{0}': {1}")]
     SpawnFailed(String, String),
 
+    #[error("Configured shell {0} not found")]
+    ShellNotFound(String),
+
     #[error("Execution timeout for tool '{0}' after {1} seconds")]
     Timeout(String, u64),
 
     #[error("Failed to capture output for tool '{0}': {1}")]
     OutputCaptureFailed(String, String),
 
+    #[error("Invalid configuration: {0}")]
+    ConfigInvalid(String),
+
+    #[error("Invalid path: {0}")]
+    InvalidPath(String),
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
@@ -31,4 +46,31 @@ pub enum ExecutorError {
     TomlParse(#[from] toml::de::Error),
 }
 
+impl ExecutorError {
+    /// Whether running the same call again may succeed
+    ///
+    /// Timeouts and process or I/O failures depend on the moment; a missing
+    /// or disabled tool or shell, bad input or a path outside the root fail
+    /// the same way every time.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            ExecutorError::SpawnFailed(..)
+                | ExecutorError::Timeout(..)
+                | ExecutorError::OutputCaptureFailed(..)
+                | ExecutorError::Io(_)
+        )
+    }
+
+    /// Hint appended to the error shown to the model, so it knows whether
+    /// retrying the call is worthwhile
+    pub fn retry_hint(&self) -> &'static str {
+        if self.is_transient() {
+            "(transient, may retry)"
+        } else {
+            "(permanent, do not retry)"
+        }
+    }
+}
+
 pub type Result<T> = std::result::Result<T, ExecutorError>;
@@ -0,0 +1,282 @@
+// List directory tool implementation
+#![allow(dead_code)]
+
+use crate::brain::ToolDefinition;
+use crate::executor::path::validate_path;
+use crate::executor::{ExecutorError, Result, ToolImpl, ToolOutput};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+/// Default maximum number of entries returned
+const DEFAULT_MAX_ENTRIES: usize = 1000;
+
+/// List directory tool input parameters
+#[derive(Debug, Deserialize)]
+struct ListDirInput {
+    path: String,
+    #[serde(default)]
+    recursive: bool,
+    #[serde(default)]
+    max_entries: Option<usize>,
+}
+
+/// A single directory entry in the listing
+#[derive(Debug, Serialize)]
+struct DirEntryInfo {
+    /// File name
+    name: String,
+    /// Path relative to the listed directory
+    path: String,
+    /// Entry type: file, dir, symlink, other, unknown
+    #[serde(rename = "type")]
+    kind: &'static str,
+    /// Size in bytes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+    /// Last modification time (RFC 3339)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mtime: Option<String>,
+    /// Unix permission bits in octal (e.g. "0644")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    permissions: Option<String>,
+    /// Symlink target, if this entry is a symlink
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    /// Error encountered while inspecting this entry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Complete listing returned to the model
+#[derive(Debug, Serialize)]
+struct Listing {
+    path: String,
+    entries: Vec<DirEntryInfo>,
+    truncated: bool,
+}
+
+/// List directory tool implementation
+pub struct ListDirTool {
+    description: String,
+    allowed_root: Option<PathBuf>,
+}
+
+impl ListDirTool {
+    pub fn new(description: impl Into<String>, allowed_root: Option<PathBuf>) -> Self {
+        Self {
+            description: description.into(),
+            allowed_root,
+        }
+    }
+}
+
+#[async_trait]
+impl ToolImpl for ListDirTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "list_dir".to_string(),
+            description: self.description.clone(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "The directory to list"
+                    },
+                    "recursive": {
+                        "type": "boolean",
+                        "description": "Descend into subdirectories (symlinks are not followed)"
+                    },
+                    "max_entries": {
+                        "type": "integer",
+                        "description": "Maximum number of entries to return"
+                    }
+                },
+                "required": ["path"]
+            }),
+        }
+    }
+
+    fn cacheable(&self) -> bool {
+        // Directory contents change between calls
+        false
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, input: serde_json::Value) -> Result<ToolOutput> {
+        let ListDirInput {
+            path,
+            recursive,
+            max_entries,
+        } = serde_json::from_value(input)
+            .map_err(|e| ExecutorError::InvalidInput("list_dir".to_string(), e.to_string()))?;
+
+        let dir = match validate_path(self.allowed_root.as_deref(), Path::new(&path)) {
+            Ok(dir) => dir,
+            Err(e) => return Ok(ToolOutput::error(e.to_string())),
+        };
+        let max_entries = max_entries.unwrap_or(DEFAULT_MAX_ENTRIES);
+
+        debug!(path = %dir.display(), recursive, max_entries, "listing directory");
+
+        let listed = dir.clone();
+        let listing = tokio::task::spawn_blocking(move || list(&listed, recursive, max_entries))
+            .await
+            .map_err(|e| {
+                ExecutorError::OutputCaptureFailed("list_dir".to_string(), e.to_string())
+            })?;
+
+        let listing = match listing {
+            Ok(listing) => listing,
+            Err(e) => {
+                return Ok(ToolOutput::error(format!(
+                    "cannot list {}: {}",
+                    dir.display(),
+                    e
+                )));
+            }
+        };
+
+        info!(
+            path = %dir.display(),
+            entries = listing.entries.len(),
+            truncated = listing.truncated,
+            "directory listed"
+        );
+
+        let content = serde_json::to_string_pretty(&listing)?;
+        Ok(ToolOutput::success(content))
+    }
+}
+
+/// Walk `dir` depth-first, collecting at most `max_entries` entries
+fn list(dir: &Path, recursive: bool, max_entries: usize) -> io::Result<Listing> {
+    let mut entries = Vec::new();
+    let mut truncated = false;
+    let mut pending = vec![PathBuf::new()];
+    let mut first = true;
+
+    'walk: while let Some(relative) = pending.pop() {
+        let current = dir.join(&relative);
+        let read_dir = match fs::read_dir(&current) {
+            Ok(read_dir) => read_dir,
+            // The top-level directory must be readable; nested failures are
+            // already marked on the corresponding entry.
+            Err(e) if first => return Err(e),
+            Err(_) => continue,
+        };
+        first = false;
+
+        let mut children: Vec<_> = read_dir.filter_map(|e| e.ok()).collect();
+        children.sort_by_key(|e| e.file_name());
+
+        for child in children {
+            if entries.len() >= max_entries {
+                truncated = true;
+                break 'walk;
+            }
+
+            let name = child.file_name().to_string_lossy().into_owned();
+            let child_relative = relative.join(&name);
+            let info = inspect(&child.path(), name, &child_relative);
+
+            if recursive && info.kind == "dir" {
+                if let Err(e) = fs::read_dir(child.path()) {
+                    entries.push(DirEntryInfo {
+                        error: Some(describe_io_error(&e)),
+                        ..info
+                    });
+                    continue;
+                }
+                pending.push(child_relative);
+            }
+            entries.push(info);
+        }
+    }
+
+    Ok(Listing {
+        path: dir.display().to_string(),
+        entries,
+        truncated,
+    })
+}
+
+/// Collect metadata for one entry without following symlinks
+fn inspect(path: &Path, name: String, relative: &Path) -> DirEntryInfo {
+    let relative = relative.display().to_string();
+
+    let meta = match fs::symlink_metadata(path) {
+        Ok(meta) => meta,
+        Err(e) => {
+            return DirEntryInfo {
+                name,
+                path: relative,
+                kind: "unknown",
+                size: None,
+                mtime: None,
+                permissions: None,
+                target: None,
+                error: Some(describe_io_error(&e)),
+            };
+        }
+    };
+
+    let file_type = meta.file_type();
+    let kind = if file_type.is_symlink() {
+        "symlink"
+    } else if file_type.is_dir() {
+        "dir"
+    } else if file_type.is_file() {
+        "file"
+    } else {
+        "other"
+    };
+
+    let target = if file_type.is_symlink() {
+        fs::read_link(path).ok().map(|t| t.display().to_string())
+    } else {
+        None
+    };
+
+    let mtime = meta
+        .modified()
+        .ok()
+        .map(|t| DateTime::<Utc>::from(t).to_rfc3339());
+
+    DirEntryInfo {
+        name,
+        path: relative,
+        kind,
+        size: Some(meta.len()),
+        mtime,
+        permissions: Some(format!("{:04o}", meta.permissions().mode() & 0o7777)),
+        target,
+        error: None,
+    }
+}
+
+/// Render an IO error compactly, calling out permission problems explicitly
+fn describe_io_error(e: &io::Error) -> String {
+    match e.kind() {
+        io::ErrorKind::PermissionDenied => "permission denied".to_string(),
+        _ => e.to_string(),
+    }
+}
+
+/// Default list_dir tool description
+pub fn default_list_dir_description() -> String {
+    r#"List a directory and return structured JSON entries.
+Each entry has name, path, type (file/dir/symlink/other), size, mtime and permissions.
+Symlinks are reported with their target and never followed.
+Entries that cannot be inspected are marked with an error instead of failing the listing."#
+        .to_string()
+}
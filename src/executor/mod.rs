@@ -3,14 +3,24 @@
 #![allow(unused_imports)]
 
 pub mod bash;
+pub mod checksum;
 pub mod config;
 pub mod error;
+pub mod list_dir;
+pub mod net_check;
+pub mod path;
+pub mod progress;
+pub mod read_metric;
 pub mod runner;
+pub mod scheduler;
+pub mod service_status;
+pub mod tail_file;
 pub mod tool;
 pub mod types;
 
 pub use config::ExecutorConfig;
 pub use error::{ExecutorError, Result};
+pub use progress::{Progress, ProgressPolicy, ProgressSink};
 pub use runner::Executor;
-pub use tool::ToolImpl;
-pub use types::{ExecutionConstraints, ToolOutput};
+pub use tool::{PreExecHook, ToolImpl};
+pub use types::{BashOutputFormat, ExecutionConstraints, ResultFormat, ToolOutput};
@@ -0,0 +1,217 @@
+// Network check tool implementation
+#![allow(dead_code)]
+
+use crate::brain::ToolDefinition;
+use crate::executor::{ExecutorError, Result, ToolImpl, ToolOutput};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tracing::{debug, info};
+
+/// Default timeout for each of the DNS and connect steps
+const DEFAULT_TIMEOUT_MS: u64 = 5000;
+
+/// Upper bound on the requested timeout
+const MAX_TIMEOUT_MS: u64 = 30_000;
+
+/// Network check tool input parameters
+#[derive(Debug, Deserialize)]
+struct NetCheckInput {
+    host: String,
+    #[serde(default)]
+    port: Option<u16>,
+    #[serde(default)]
+    timeout_ms: Option<u64>,
+}
+
+/// Result returned to the model
+#[derive(Debug, Serialize)]
+struct NetCheck {
+    host: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    port: Option<u16>,
+    /// Addresses the host resolved to
+    resolved: Vec<String>,
+    /// Time spent resolving the host
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dns_ms: Option<f64>,
+    /// Whether a TCP connection succeeded (only when a port was given)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reachable: Option<bool>,
+    /// Address the connection was made to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    connected_to: Option<String>,
+    /// Time taken by the successful TCP connect
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency_ms: Option<f64>,
+    /// Why resolution or the connection failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Network check tool implementation
+pub struct NetCheckTool {
+    description: String,
+}
+
+impl NetCheckTool {
+    pub fn new(description: impl Into<String>) -> Self {
+        Self {
+            description: description.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl ToolImpl for NetCheckTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "net_check".to_string(),
+            description: self.description.clone(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "host": {
+                        "type": "string",
+                        "description": "Hostname or IP address to check"
+                    },
+                    "port": {
+                        "type": "integer",
+                        "description": "TCP port to connect to; omit to only resolve the host"
+                    },
+                    "timeout_ms": {
+                        "type": "integer",
+                        "description": "Timeout for each of resolution and connect (default 5000, max 30000)"
+                    }
+                },
+                "required": ["host"]
+            }),
+        }
+    }
+
+    fn cacheable(&self) -> bool {
+        // Reachability is what changes, so always probe again
+        false
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, input: serde_json::Value) -> Result<ToolOutput> {
+        let NetCheckInput {
+            host,
+            port,
+            timeout_ms,
+        } = serde_json::from_value(input)
+            .map_err(|e| ExecutorError::InvalidInput("net_check".to_string(), e.to_string()))?;
+
+        if host.trim().is_empty() {
+            return Err(ExecutorError::InvalidInput(
+                "net_check".to_string(),
+                "host cannot be empty".to_string(),
+            ));
+        }
+        let timeout =
+            Duration::from_millis(timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS).min(MAX_TIMEOUT_MS));
+
+        debug!(host = %host, port, timeout_ms = timeout.as_millis() as u64, "checking network");
+
+        let check = check(host, port, timeout).await;
+
+        info!(
+            host = %check.host,
+            port = check.port,
+            resolved = check.resolved.len(),
+            reachable = check.reachable,
+            "network checked"
+        );
+
+        // An unreachable host is a valid answer, not a tool failure
+        let content = serde_json::to_string_pretty(&check)?;
+        Ok(ToolOutput::success(content))
+    }
+}
+
+/// Resolve `host` and, when a port is given, try each address in turn
+async fn check(host: String, port: Option<u16>, timeout: Duration) -> NetCheck {
+    let started = Instant::now();
+    let lookup = tokio::net::lookup_host((host.as_str(), port.unwrap_or(0)));
+    let lookup: std::result::Result<Vec<SocketAddr>, String> =
+        match tokio::time::timeout(timeout, lookup).await {
+            Ok(Ok(addrs)) => Ok(addrs.collect()),
+            Ok(Err(e)) => Err(format!("dns resolution failed: {}", e)),
+            Err(_) => Err(format!(
+                "dns resolution timed out after {} ms",
+                timeout.as_millis()
+            )),
+        };
+    let dns_ms = millis(started.elapsed());
+
+    let mut check = NetCheck {
+        host,
+        port,
+        resolved: Vec::new(),
+        dns_ms: None,
+        reachable: port.map(|_| false),
+        connected_to: None,
+        latency_ms: None,
+        error: None,
+    };
+
+    let addrs = match lookup {
+        Ok(addrs) => addrs,
+        Err(e) => {
+            check.error = Some(e);
+            return check;
+        }
+    };
+    check.dns_ms = Some(dns_ms);
+    check.resolved = addrs.iter().map(|a| a.ip().to_string()).collect();
+
+    if port.is_none() {
+        return check;
+    }
+    if addrs.is_empty() {
+        check.error = Some("host resolved to no addresses".to_string());
+        return check;
+    }
+
+    let mut failures = Vec::new();
+    for addr in addrs {
+        let started = Instant::now();
+        match tokio::time::timeout(timeout, TcpStream::connect(addr)).await {
+            Ok(Ok(_stream)) => {
+                check.reachable = Some(true);
+                check.connected_to = Some(addr.to_string());
+                check.latency_ms = Some(millis(started.elapsed()));
+                check.error = None;
+                return check;
+            }
+            Ok(Err(e)) => failures.push(format!("{}: {}", addr, e)),
+            Err(_) => failures.push(format!(
+                "{}: connect timed out after {} ms",
+                addr,
+                timeout.as_millis()
+            )),
+        }
+    }
+    check.error = Some(failures.join("; "));
+    check
+}
+
+/// Milliseconds with one decimal place
+fn millis(elapsed: Duration) -> f64 {
+    (elapsed.as_secs_f64() * 10_000.0).round() / 10.0
+}
+
+/// Default net_check tool description
+pub fn default_net_check_description() -> String {
+    r#"Check DNS resolution and TCP reachability of a host.
+With only a host, resolves it and returns the addresses.
+With a port, also opens a TCP connection and reports reachability, latency, or why it failed.
+Does not depend on ping or curl being installed."#
+        .to_string()
+}
@@ -0,0 +1,33 @@
+// Path validation shared by filesystem tools
+
+use crate::executor::error::{ExecutorError, Result};
+use std::path::{Path, PathBuf};
+
+/// Canonicalize `requested` and make sure it stays inside `root`, if one is set
+///
+/// Canonicalization resolves `..` components and symlinks first, so neither
+/// can be used to escape the root. With no root every path is allowed.
+pub fn validate_path(root: Option<&Path>, requested: &Path) -> Result<PathBuf> {
+    let resolved = requested.canonicalize().map_err(|e| {
+        ExecutorError::InvalidPath(format!("cannot resolve {}: {}", requested.display(), e))
+    })?;
+
+    if let Some(root) = root {
+        let root = root.canonicalize().map_err(|e| {
+            ExecutorError::InvalidPath(format!(
+                "cannot resolve allowed root {}: {}",
+                root.display(),
+                e
+            ))
+        })?;
+        if !resolved.starts_with(&root) {
+            return Err(ExecutorError::InvalidPath(format!(
+                "path {} is outside the allowed root {}",
+                requested.display(),
+                root.display()
+            )));
+        }
+    }
+
+    Ok(resolved)
+}
@@ -0,0 +1,221 @@
+// Progress reports from tools that are still running
+#![allow(dead_code)]
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How often a streaming tool reports progress; a report is due when either
+/// threshold is crossed since the last one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgressPolicy {
+    /// Output lines between reports (0 = no line threshold)
+    pub every_lines: usize,
+    /// Time between reports, checked on a timer so silent commands report
+    /// too (zero = no time threshold)
+    pub interval: Duration,
+}
+
+impl Default for ProgressPolicy {
+    fn default() -> Self {
+        Self {
+            every_lines: 100,
+            interval: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Snapshot of a running tool
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Progress {
+    pub tool: String,
+    /// Output lines seen so far
+    pub lines: usize,
+    /// Time since the tool started
+    pub elapsed: Duration,
+    /// Most recent output line, without its line ending
+    pub latest: String,
+}
+
+impl std::fmt::Display for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} still running after {}s, {} lines of output so far, latest: {}",
+            self.tool,
+            self.elapsed.as_secs(),
+            self.lines,
+            self.latest
+        )
+    }
+}
+
+/// Receives progress reports
+pub type ProgressSink = Arc<dyn Fn(Progress) + Send + Sync>;
+
+tokio::task_local! {
+    /// Sink of the tool call running on this task
+    static SINK: ProgressSink;
+}
+
+/// Run `f` with progress reports from tools it executes sent to `sink`
+///
+/// A task-local rather than a `run` argument, so only tools that stream
+/// output need to know about it.
+pub async fn scoped<F: Future>(sink: ProgressSink, f: F) -> F::Output {
+    SINK.scope(sink, f).await
+}
+
+/// Send `progress` to the current sink; does nothing outside `scoped`
+pub fn report(progress: Progress) {
+    let _ = SINK.try_with(|sink| sink(progress));
+}
+
+/// Counts output lines of one execution and decides when a report is due
+#[derive(Debug)]
+pub struct ProgressTracker {
+    tool: String,
+    policy: ProgressPolicy,
+    started: Instant,
+    lines: usize,
+    lines_at_report: usize,
+    last_report: Instant,
+    /// Most recent output line, for reports made between lines
+    latest: String,
+}
+
+impl ProgressTracker {
+    pub fn new(tool: impl Into<String>, policy: ProgressPolicy) -> Self {
+        let now = Instant::now();
+        Self {
+            tool: tool.into(),
+            policy,
+            started: now,
+            lines: 0,
+            lines_at_report: 0,
+            last_report: now,
+            latest: String::new(),
+        }
+    }
+
+    /// Record one output line, returning a report when the line threshold
+    /// is crossed
+    pub fn line(&mut self, line: &str) -> Option<Progress> {
+        self.lines += 1;
+        self.latest = line.trim_end().to_string();
+        let due = self.policy.every_lines > 0
+            && self.lines - self.lines_at_report >= self.policy.every_lines;
+        due.then(|| self.take_report())
+    }
+
+    /// Check the time threshold, returning a report when it is crossed
+    pub fn tick(&mut self) -> Option<Progress> {
+        let due =
+            !self.policy.interval.is_zero() && self.last_report.elapsed() >= self.policy.interval;
+        due.then(|| self.take_report())
+    }
+
+    fn take_report(&mut self) -> Progress {
+        let now = Instant::now();
+        self.lines_at_report = self.lines;
+        self.last_report = now;
+        Progress {
+            tool: self.tool.clone(),
+            lines: self.lines,
+            elapsed: now - self.started,
+            latest: self.latest.clone(),
+        }
+    }
+}
+
+/// Await `f`, calling `tick` every `interval` until it completes, so time
+/// based reports go out even while the tool prints nothing (zero = never)
+pub async fn ticking<F: Future>(f: F, interval: Duration, mut tick: impl FnMut()) -> F::Output {
+    if interval.is_zero() {
+        return f.await;
+    }
+    let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    tokio::pin!(f);
+    loop {
+        tokio::select! {
+            output = &mut f => return output,
+            _ = ticks.tick() => tick(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracker_reports_every_n_lines() {
+        let mut tracker = ProgressTracker::new(
+            "bash",
+            ProgressPolicy {
+                every_lines: 3,
+                interval: Duration::ZERO,
+            },
+        );
+        let reports: Vec<Progress> = (1..=7)
+            .filter_map(|i| tracker.line(&format!("line {}\n", i)))
+            .collect();
+
+        assert_eq!(reports.len(), 2);
+        assert_eq!(
+            (reports[0].lines, reports[0].latest.as_str()),
+            (3, "line 3")
+        );
+        assert_eq!(
+            (reports[1].lines, reports[1].latest.as_str()),
+            (6, "line 6")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ticking_reports_while_silent() {
+        let tracker = std::sync::Mutex::new(ProgressTracker::new(
+            "bash",
+            ProgressPolicy {
+                every_lines: 0,
+                interval: Duration::from_millis(40),
+            },
+        ));
+        assert!(tracker.lock().unwrap().line("started").is_none());
+
+        let mut reports = Vec::new();
+        ticking(
+            tokio::time::sleep(Duration::from_millis(200)),
+            Duration::from_millis(40),
+            || reports.extend(tracker.lock().unwrap().tick()),
+        )
+        .await;
+
+        assert!(reports.len() >= 2, "{:?}", reports);
+        assert!(
+            reports
+                .iter()
+                .all(|p| p.lines == 1 && p.latest == "started")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_report_reaches_scoped_sink_only() {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink: ProgressSink = {
+            let seen = seen.clone();
+            Arc::new(move |p: Progress| seen.lock().unwrap().push(p.lines))
+        };
+        let progress = |lines| Progress {
+            tool: "bash".to_string(),
+            lines,
+            elapsed: Duration::ZERO,
+            latest: String::new(),
+        };
+
+        report(progress(1));
+        scoped(sink, async { report(progress(2)) }).await;
+        assert_eq!(*seen.lock().unwrap(), [2]);
+    }
+}
@@ -0,0 +1,302 @@
+// Kernel metric tool implementation
+#![allow(dead_code)]
+
+use crate::brain::ToolDefinition;
+use crate::executor::{ExecutorError, Result, ToolImpl, ToolOutput};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+/// Metrics the tool may read; each maps to fixed files, so the model cannot
+/// point it anywhere else under /proc or /sys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Metric {
+    Loadavg,
+    Meminfo,
+    Uptime,
+    PressureCpu,
+    PressureMemory,
+    PressureIo,
+    FileNr,
+    EntropyAvail,
+    Thermal,
+}
+
+impl Metric {
+    fn name(self) -> &'static str {
+        match self {
+            Metric::Loadavg => "loadavg",
+            Metric::Meminfo => "meminfo",
+            Metric::Uptime => "uptime",
+            Metric::PressureCpu => "pressure_cpu",
+            Metric::PressureMemory => "pressure_memory",
+            Metric::PressureIo => "pressure_io",
+            Metric::FileNr => "file_nr",
+            Metric::EntropyAvail => "entropy_avail",
+            Metric::Thermal => "thermal",
+        }
+    }
+
+    /// Source file, or directory for thermal
+    fn path(self) -> &'static str {
+        match self {
+            Metric::Loadavg => "/proc/loadavg",
+            Metric::Meminfo => "/proc/meminfo",
+            Metric::Uptime => "/proc/uptime",
+            Metric::PressureCpu => "/proc/pressure/cpu",
+            Metric::PressureMemory => "/proc/pressure/memory",
+            Metric::PressureIo => "/proc/pressure/io",
+            Metric::FileNr => "/proc/sys/fs/file-nr",
+            Metric::EntropyAvail => "/proc/sys/kernel/random/entropy_avail",
+            Metric::Thermal => "/sys/class/thermal",
+        }
+    }
+}
+
+/// Read metric tool input parameters
+#[derive(Debug, Deserialize)]
+struct ReadMetricInput {
+    metric: Metric,
+}
+
+/// Read metric tool implementation
+pub struct ReadMetricTool {
+    description: String,
+}
+
+impl ReadMetricTool {
+    pub fn new(description: impl Into<String>) -> Self {
+        Self {
+            description: description.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl ToolImpl for ReadMetricTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "read_metric".to_string(),
+            description: self.description.clone(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "metric": {
+                        "type": "string",
+                        "enum": [
+                            "loadavg", "meminfo", "uptime", "pressure_cpu",
+                            "pressure_memory", "pressure_io", "file_nr",
+                            "entropy_avail", "thermal"
+                        ],
+                        "description": "Kernel metric to read"
+                    }
+                },
+                "required": ["metric"]
+            }),
+        }
+    }
+
+    async fn run(&self, input: serde_json::Value) -> Result<ToolOutput> {
+        let ReadMetricInput { metric } = serde_json::from_value(input)
+            .map_err(|e| ExecutorError::InvalidInput("read_metric".to_string(), e.to_string()))?;
+
+        let path = Path::new(metric.path());
+        debug!(metric = metric.name(), path = %path.display(), "reading metric");
+
+        let parsed = tokio::task::spawn_blocking(move || read(metric, path))
+            .await
+            .map_err(|e| {
+                ExecutorError::OutputCaptureFailed("read_metric".to_string(), e.to_string())
+            })?;
+
+        match parsed {
+            Ok(values) => {
+                info!(metric = metric.name(), "metric read");
+                let content = json!({ "metric": metric.name(), "values": values });
+                Ok(ToolOutput::success(serde_json::to_string_pretty(&content)?))
+            }
+            Err(e) => Ok(ToolOutput::error(format!(
+                "{} unavailable: {}",
+                metric.name(),
+                e
+            ))),
+        }
+    }
+
+    fn cacheable(&self) -> bool {
+        // Metrics change from one call to the next
+        false
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+}
+
+/// Read and parse `metric` from `path`
+fn read(metric: Metric, path: &Path) -> std::result::Result<Value, String> {
+    if metric == Metric::Thermal {
+        return read_thermal(path);
+    }
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let parsed = match metric {
+        Metric::Loadavg => parse_loadavg(&text),
+        Metric::Meminfo => parse_meminfo(&text),
+        Metric::Uptime => parse_uptime(&text),
+        Metric::PressureCpu | Metric::PressureMemory | Metric::PressureIo => parse_pressure(&text),
+        Metric::FileNr => parse_file_nr(&text),
+        Metric::EntropyAvail => text
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .map(|bits| json!({ "bits": bits })),
+        Metric::Thermal => unreachable!("read from a directory above"),
+    };
+    parsed.ok_or_else(|| format!("unexpected contents of {}", path.display()))
+}
+
+/// `/proc/loadavg`: "0.52 0.58 0.59 2/1187 43210"
+fn parse_loadavg(text: &str) -> Option<Value> {
+    let fields: Vec<&str> = text.split_whitespace().collect();
+    let [load1, load5, load15, tasks, last_pid] = fields[..] else {
+        return None;
+    };
+    let (running, total) = tasks.split_once('/')?;
+    Some(json!({
+        "load1": load1.parse::<f64>().ok()?,
+        "load5": load5.parse::<f64>().ok()?,
+        "load15": load15.parse::<f64>().ok()?,
+        "running": running.parse::<u64>().ok()?,
+        "total": total.parse::<u64>().ok()?,
+        "last_pid": last_pid.parse::<u64>().ok()?,
+    }))
+}
+
+/// `/proc/meminfo`: "MemTotal:       16303520 kB" lines, as name to kB
+/// (HugePages_* counts stay counts)
+fn parse_meminfo(text: &str) -> Option<Value> {
+    let values: Map<String, Value> = text
+        .lines()
+        .filter_map(|line| {
+            let (name, rest) = line.split_once(':')?;
+            let value = rest.split_whitespace().next()?.parse::<u64>().ok()?;
+            Some((name.trim().to_string(), Value::from(value)))
+        })
+        .collect();
+    (!values.is_empty()).then_some(Value::Object(values))
+}
+
+/// `/proc/uptime`: "350735.47 234388.90"
+fn parse_uptime(text: &str) -> Option<Value> {
+    let mut fields = text.split_whitespace();
+    Some(json!({
+        "uptime_secs": fields.next()?.parse::<f64>().ok()?,
+        "idle_secs": fields.next()?.parse::<f64>().ok()?,
+    }))
+}
+
+/// `/proc/pressure/*`: "some avg10=0.00 avg60=0.00 avg300=0.00 total=0"
+/// lines, keyed by "some" and "full"
+fn parse_pressure(text: &str) -> Option<Value> {
+    let mut lines = Map::new();
+    for line in text.lines() {
+        let mut fields = line.split_whitespace();
+        let kind = fields.next()?;
+        let mut values = Map::new();
+        for field in fields {
+            let (key, value) = field.split_once('=')?;
+            let value = if key == "total" {
+                Value::from(value.parse::<u64>().ok()?)
+            } else {
+                Value::from(value.parse::<f64>().ok()?)
+            };
+            values.insert(key.to_string(), value);
+        }
+        lines.insert(kind.to_string(), Value::Object(values));
+    }
+    (!lines.is_empty()).then_some(Value::Object(lines))
+}
+
+/// `/proc/sys/fs/file-nr`: "10240 0 9223372036854775807"
+fn parse_file_nr(text: &str) -> Option<Value> {
+    let mut fields = text.split_whitespace();
+    Some(json!({
+        "allocated": fields.next()?.parse::<u64>().ok()?,
+        "unused": fields.next()?.parse::<u64>().ok()?,
+        "max": fields.next()?.parse::<u64>().ok()?,
+    }))
+}
+
+/// Temperature of every `thermal_zone*` under `dir`, in zone order
+fn read_thermal(dir: &Path) -> std::result::Result<Value, String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let mut zones: Vec<(String, PathBuf)> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().into_owned();
+            name.starts_with("thermal_zone").then(|| (name, e.path()))
+        })
+        .collect();
+    zones.sort();
+
+    let read_trimmed = |path: PathBuf| {
+        std::fs::read_to_string(path)
+            .ok()
+            .map(|s| s.trim().to_string())
+    };
+    let zones: Vec<Value> = zones
+        .into_iter()
+        .filter_map(|(name, path)| {
+            // Millidegrees Celsius
+            let millis = read_trimmed(path.join("temp"))?.parse::<i64>().ok()?;
+            Some(json!({
+                "zone": name,
+                "type": read_trimmed(path.join("type")).unwrap_or_default(),
+                "celsius": millis as f64 / 1000.0,
+            }))
+        })
+        .collect();
+    if zones.is_empty() {
+        return Err(format!("no readable thermal zones under {}", dir.display()));
+    }
+    Ok(Value::Array(zones))
+}
+
+/// Default read_metric tool description
+pub fn default_read_metric_description() -> String {
+    r#"Read a kernel metric as structured JSON, e.g. {"metric": "loadavg"}.
+Metrics: loadavg, meminfo (kB), uptime, pressure_cpu, pressure_memory, pressure_io,
+file_nr, entropy_avail and thermal (°C per zone).
+Only these fixed /proc and /sys sources are readable."#
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pressure_and_meminfo() {
+        let pressure = parse_pressure(
+            "some avg10=1.25 avg60=0.50 avg300=0.10 total=123456\n\
+             full avg10=0.00 avg60=0.00 avg300=0.00 total=0\n",
+        )
+        .unwrap();
+        assert_eq!(pressure["some"]["avg10"], 1.25);
+        assert_eq!(pressure["some"]["total"], 123456);
+        assert_eq!(pressure["full"]["avg300"], 0.0);
+
+        let meminfo = parse_meminfo(
+            "MemTotal:       16303520 kB\nMemAvailable:    9876543 kB\nHugePages_Total:       0\n",
+        )
+        .unwrap();
+        assert_eq!(meminfo["MemTotal"], 16303520);
+        assert_eq!(meminfo["MemAvailable"], 9876543);
+        assert_eq!(meminfo["HugePages_Total"], 0);
+
+        assert!(parse_loadavg("0.52 0.58").is_none());
+    }
+}
@@ -3,66 +3,256 @@
 
 use crate::brain::ToolDefinition;
 use crate::executor::bash::{BashTool, default_bash_description};
+use crate::executor::checksum::{ChecksumTool, default_checksum_description};
 use crate::executor::config::ExecutorConfig;
 use crate::executor::error::{ExecutorError, Result};
-use crate::executor::tool::ToolImpl;
-use crate::executor::types::ToolOutput;
-use std::collections::HashMap;
+use crate::executor::list_dir::{ListDirTool, default_list_dir_description};
+use crate::executor::net_check::{NetCheckTool, default_net_check_description};
+use crate::executor::read_metric::{ReadMetricTool, default_read_metric_description};
+use crate::executor::scheduler::{DEFAULT_PRIORITY, Scheduler};
+use crate::executor::service_status::{ServiceStatusTool, default_service_status_description};
+use crate::executor::tail_file::{TailFileTool, default_tail_file_description};
+use crate::executor::tool::{PreExecHook, ToolImpl};
+use crate::executor::types::{ResultFormat, ToolOutput, ToolSettings};
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, RwLock};
-use tracing::{debug, info};
+use std::time::Duration;
+use tracing::{debug, error, info, instrument, warn};
 
 /// Main executor for tool execution
 pub struct Executor {
     config: ExecutorConfig,
     tools: RwLock<HashMap<String, Arc<dyn ToolImpl>>>,
+    scheduler: Scheduler,
+    /// Per-tool settings from the tools config, by tool name
+    settings: HashMap<String, ToolSettings>,
+    /// Tools exposed to the model (None = every registered tool)
+    enabled: RwLock<Option<HashSet<String>>>,
+    /// Consulted before every execution; may refuse the call
+    pre_exec_hook: Option<Arc<dyn PreExecHook>>,
 }
 
 impl Executor {
     /// Create a new Executor instance (backward compatibility)
-    pub fn new(config: ExecutorConfig) -> Self {
+    pub fn new(config: ExecutorConfig) -> Result<Self> {
         Self::init(config)
     }
 
     /// Initialize with registered tools
-    pub fn init(config: ExecutorConfig) -> Self {
+    ///
+    /// Fails with `DuplicateTool` when two built-in tools share a name and
+    /// `strict_tool_names` is set.
+    pub fn init(config: ExecutorConfig) -> Result<Self> {
         debug!(
             timeout_secs = config.constraints.timeout_secs,
             max_output_bytes = config.constraints.max_output_bytes,
             shell = %config.shell,
+            shell_args = ?config.shell_args,
+            max_concurrent = config.max_concurrent_executions,
             "initializing executor"
         );
 
         let mut tools = HashMap::new();
 
-        // Load tool descriptions from config file
-        let descriptions = crate::executor::tool::load_tool_descriptions(&config.tools_toml_path)
-            .unwrap_or_default();
+        // Load per-tool settings (descriptions, retry, ...) from config file
+        let settings = crate::executor::tool::load_tool_settings(&config.tools_toml_path)
+            .unwrap_or_else(|e| {
+                warn!(error = %e, "invalid tools config, using default tool settings");
+                HashMap::new()
+            });
+        let description = |name: &str| settings.get(name).and_then(|s| s.description.clone());
 
         // Register bash tool
-        let bash_desc = descriptions
-            .get("bash")
-            .cloned()
-            .unwrap_or_else(default_bash_description);
+        let bash_desc = description("bash").unwrap_or_else(default_bash_description);
+
+        let bash_tool = Arc::new(
+            BashTool::new(bash_desc, config.constraints.clone())
+                .with_shell(config.shell.clone(), config.shell_args.clone())
+                .with_progress(config.progress)
+                .with_output_format(config.bash_output_format),
+        ) as Arc<dyn ToolImpl>;
+        insert_tool(&mut tools, bash_tool, config.strict_tool_names)?;
+
+        // Register list_dir tool
+        let list_dir_desc = description("list_dir").unwrap_or_else(default_list_dir_description);
+
+        let list_dir_tool = Arc::new(ListDirTool::new(list_dir_desc, config.allowed_root.clone()))
+            as Arc<dyn ToolImpl>;
+        insert_tool(&mut tools, list_dir_tool, config.strict_tool_names)?;
+
+        // Register tail_file tool
+        let tail_file_desc = description("tail_file").unwrap_or_else(default_tail_file_description);
+
+        let tail_file_tool = Arc::new(TailFileTool::new(
+            tail_file_desc,
+            config.allowed_root.clone(),
+            config.constraints.max_output_bytes,
+        )) as Arc<dyn ToolImpl>;
+        insert_tool(&mut tools, tail_file_tool, config.strict_tool_names)?;
+
+        // Register checksum tool
+        let checksum_desc = description("checksum").unwrap_or_else(default_checksum_description);
+
+        let checksum_tool = Arc::new(ChecksumTool::new(
+            checksum_desc,
+            config.allowed_root.clone(),
+            config.checksum_max_file_bytes,
+        )) as Arc<dyn ToolImpl>;
+        insert_tool(&mut tools, checksum_tool, config.strict_tool_names)?;
+
+        // Register net_check tool
+        let net_check_desc = description("net_check").unwrap_or_else(default_net_check_description);
+
+        let net_check_tool = Arc::new(NetCheckTool::new(net_check_desc)) as Arc<dyn ToolImpl>;
+        insert_tool(&mut tools, net_check_tool, config.strict_tool_names)?;
+
+        // Register read_metric tool
+        let read_metric_desc =
+            description("read_metric").unwrap_or_else(default_read_metric_description);
+
+        let read_metric_tool = Arc::new(ReadMetricTool::new(read_metric_desc)) as Arc<dyn ToolImpl>;
+        insert_tool(&mut tools, read_metric_tool, config.strict_tool_names)?;
+
+        // Register service_status tool
+        let service_status_desc =
+            description("service_status").unwrap_or_else(default_service_status_description);
 
-        let bash_tool = Arc::new(BashTool::new(bash_desc)) as Arc<dyn ToolImpl>;
-        tools.insert("bash".to_string(), bash_tool);
+        let service_status_tool = Arc::new(ServiceStatusTool::new(
+            service_status_desc,
+            config.systemctl.clone(),
+            config.constraints.timeout_secs,
+        )) as Arc<dyn ToolImpl>;
+        insert_tool(&mut tools, service_status_tool, config.strict_tool_names)?;
 
-        info!(tool_count = 1, "executor initialized with tools");
+        info!(tool_count = tools.len(), "executor initialized with tools");
 
-        Self {
+        Ok(Self {
+            scheduler: Scheduler::new(config.max_concurrent_executions),
             config,
             tools: RwLock::new(tools),
-        }
+            settings,
+            enabled: RwLock::new(None),
+            pre_exec_hook: None,
+        })
+    }
+
+    /// Run `hook` before every tool execution; a refusal becomes the
+    /// call's error result and the tool never runs
+    pub fn with_pre_exec_hook(mut self, hook: Arc<dyn PreExecHook>) -> Self {
+        self.pre_exec_hook = Some(hook);
+        self
+    }
+
+    /// Register an additional tool, replacing any tool with the same name
+    ///
+    /// Returns the replaced tool. A replacement is logged as an error; with
+    /// `strict_tool_names` the tool is refused with `DuplicateTool` instead
+    /// and the registered one stays.
+    pub fn register(&self, tool: Arc<dyn ToolImpl>) -> Result<Option<Arc<dyn ToolImpl>>> {
+        debug!(tool_name = %tool.name(), "registering tool");
+        insert_tool(
+            &mut self.tools.write().unwrap(),
+            tool,
+            self.config.strict_tool_names,
+        )
+    }
+
+    /// Only expose and run the named tools; an empty list re-enables all
+    pub fn restrict_tools(&self, names: &[String]) {
+        let enabled = if names.is_empty() {
+            None
+        } else {
+            Some(names.iter().cloned().collect())
+        };
+        info!(enabled = ?names, "restricting tools");
+        *self.enabled.write().unwrap() = enabled;
+    }
+
+    /// Whether a registered tool may be exposed and run
+    fn is_enabled(&self, tool_name: &str) -> bool {
+        self.enabled
+            .read()
+            .unwrap()
+            .as_ref()
+            .is_none_or(|enabled| enabled.contains(tool_name))
     }
 
-    /// Get all tool definitions for Brain
+    /// Get all tool definitions for Brain; the `priority` field is only
+    /// advertised when concurrency is bounded, since calls never queue
+    /// otherwise
     pub fn tool_definitions(&self) -> Vec<ToolDefinition> {
         let tools = self.tools.read().unwrap();
-        tools.values().map(|t| t.definition()).collect()
+        tools
+            .iter()
+            .filter(|(name, _)| self.is_enabled(name))
+            .map(|(_, t)| {
+                if self.scheduler.is_bounded() {
+                    with_priority_field(t.definition())
+                } else {
+                    t.definition()
+                }
+            })
+            .collect()
+    }
+
+    /// How a tool's output should be rendered into its `tool_result`
+    pub fn result_format(&self, tool_name: &str) -> ResultFormat {
+        self.settings
+            .get(tool_name)
+            .and_then(|s| s.result_format)
+            .unwrap_or_default()
+    }
+
+    /// Whether a repeated identical call within one request may return the
+    /// earlier result instead of running again; `cacheable = false` opts out,
+    /// otherwise the tool's own default applies
+    pub fn is_cacheable(&self, tool_name: &str) -> bool {
+        let configured = self.settings.get(tool_name).and_then(|s| s.cacheable);
+        configured.unwrap_or_else(|| {
+            self.tools
+                .read()
+                .unwrap()
+                .get(tool_name)
+                .is_none_or(|tool| tool.cacheable())
+        })
+    }
+
+    /// Whether a tool only observes the system; the `read_only` tag in the
+    /// tools config wins, otherwise the tool's own default applies
+    pub fn is_read_only(&self, tool_name: &str) -> bool {
+        let configured = self.settings.get(tool_name).and_then(|s| s.read_only);
+        configured.unwrap_or_else(|| {
+            self.tools
+                .read()
+                .unwrap()
+                .get(tool_name)
+                .is_some_and(|tool| tool.read_only())
+        })
+    }
+
+    /// Number of tool calls waiting for an execution slot
+    pub fn queued_executions(&self) -> usize {
+        self.scheduler.queued()
     }
 
     /// Execute a tool by name with JSON input
-    pub async fn execute(&self, tool_name: &str, input: serde_json::Value) -> Result<ToolOutput> {
+    ///
+    /// An optional integer `priority` field in the input (higher runs first,
+    /// default 0) decides which waiting call gets the next slot when
+    /// `max_concurrent_executions` is reached. It is removed before the input
+    /// reaches the tool.
+    ///
+    /// Tools with a retry policy are re-run on `is_error` results, with
+    /// exponential backoff, before the last result is returned.
+    ///
+    /// A pre-exec hook sees the call once, without its priority, before it
+    /// waits for a slot; a refusal is returned as an error result.
+    #[instrument(skip_all, fields(tool = %tool_name))]
+    pub async fn execute(
+        &self,
+        tool_name: &str,
+        mut input: serde_json::Value,
+    ) -> Result<ToolOutput> {
         debug!(tool_name = %tool_name, "looking up tool");
 
         let tool = {
@@ -71,14 +261,112 @@ impl Executor {
         };
 
         let tool = tool.ok_or_else(|| ExecutorError::UnknownTool(tool_name.to_string()))?;
+        if !self.is_enabled(tool_name) {
+            return Err(ExecutorError::ToolDisabled(tool_name.to_string()));
+        }
+        // Models send `null` or leave input out for tools without arguments
+        if input.is_null() {
+            input = serde_json::Value::Object(serde_json::Map::new());
+        }
+        let priority = take_priority(tool_name, &mut input)?;
+
+        if let Some(hook) = &self.pre_exec_hook
+            && let Err(reason) = hook.before(tool_name, &input).await
+        {
+            warn!(tool_name = %tool_name, reason = %reason, "tool call refused by pre-exec hook");
+            return Ok(ToolOutput::error(reason));
+        }
+
+        let _permit = self.scheduler.acquire(priority).await;
+
+        info!(tool_name = %tool_name, priority, "executing tool");
+
+        let policy = self
+            .settings
+            .get(tool_name)
+            .and_then(|s| s.retry)
+            .unwrap_or_default();
+        let mut attempt = 0;
+        loop {
+            let output = tool.run(input.clone()).await?;
+            if !output.is_error || attempt >= policy.max {
+                return Ok(output);
+            }
+
+            let delay = policy
+                .backoff_ms
+                .saturating_mul(2u64.saturating_pow(attempt))
+                .min(30_000);
+            attempt += 1;
+            warn!(
+                tool_name = %tool_name,
+                attempt,
+                max_retries = policy.max,
+                delay_ms = delay,
+                "tool returned an error, retrying"
+            );
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+        }
+    }
+}
+
+/// Add `tool` under its own name, returning any tool it replaces
+///
+/// Two tools with one name would leave only the last reachable, so the
+/// collision is reported; with `strict` it is refused before anything is
+/// replaced.
+fn insert_tool(
+    tools: &mut HashMap<String, Arc<dyn ToolImpl>>,
+    tool: Arc<dyn ToolImpl>,
+    strict: bool,
+) -> Result<Option<Arc<dyn ToolImpl>>> {
+    let name = tool.name();
+    if strict && tools.contains_key(&name) {
+        return Err(ExecutorError::DuplicateTool(name));
+    }
+    let replaced = tools.insert(name.clone(), tool);
+    if replaced.is_some() {
+        error!(tool_name = %name, "duplicate tool name, the earlier tool is replaced");
+    }
+    Ok(replaced)
+}
+
+/// Remove and parse the scheduling `priority` field from a tool input
+fn take_priority(tool_name: &str, input: &mut serde_json::Value) -> Result<i32> {
+    let Some(value) = input.as_object_mut().and_then(|obj| obj.remove("priority")) else {
+        return Ok(DEFAULT_PRIORITY);
+    };
+
+    value
+        .as_i64()
+        .and_then(|p| i32::try_from(p).ok())
+        .ok_or_else(|| {
+            ExecutorError::InvalidInput(
+                tool_name.to_string(),
+                format!("priority must be an integer, got {}", value),
+            )
+        })
+}
 
-        info!(tool_name = %tool_name, "executing tool");
-        tool.run(input).await
+/// Advertise the optional `priority` field in a tool's input schema
+fn with_priority_field(mut def: ToolDefinition) -> ToolDefinition {
+    if let Some(props) = def
+        .input_schema
+        .get_mut("properties")
+        .and_then(|p| p.as_object_mut())
+    {
+        props.entry("priority").or_insert_with(|| {
+            serde_json::json!({
+                "type": "integer",
+                "description": "Scheduling priority when tool calls are queued (higher runs first, default 0)"
+            })
+        });
     }
+    def
 }
 
 impl Default for Executor {
     fn default() -> Self {
-        Self::init(ExecutorConfig::default())
+        Self::init(ExecutorConfig::default()).expect("built-in tool names are unique")
     }
 }
@@ -0,0 +1,237 @@
+// Priority-aware execution scheduler
+#![allow(dead_code)]
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+
+/// Priority used when a tool call does not specify one
+pub const DEFAULT_PRIORITY: i32 = 0;
+
+/// A tool call waiting for an execution slot
+struct Waiter {
+    priority: i32,
+    /// Arrival order, so equal priorities stay FIFO
+    seq: u64,
+    wake: oneshot::Sender<()>,
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.priority == other.priority && self.seq == other.seq
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Max-heap: higher priority first, then earlier arrival
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+struct State {
+    running: usize,
+    next_seq: u64,
+    waiting: BinaryHeap<Waiter>,
+}
+
+/// Bounds concurrent tool executions, handing free slots to the
+/// highest-priority waiter instead of the oldest one
+pub struct Scheduler {
+    /// Maximum concurrent executions (0 = unlimited)
+    limit: usize,
+    state: Arc<Mutex<State>>,
+}
+
+/// Execution slot; releasing it hands the slot to the next waiter
+pub struct Permit {
+    state: Option<Arc<Mutex<State>>>,
+}
+
+impl Scheduler {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            state: Arc::new(Mutex::new(State {
+                running: 0,
+                next_seq: 0,
+                waiting: BinaryHeap::new(),
+            })),
+        }
+    }
+
+    /// Wait for an execution slot
+    pub async fn acquire(&self, priority: i32) -> Permit {
+        if self.limit == 0 {
+            return Permit { state: None };
+        }
+
+        let rx = {
+            let mut state = self.state.lock().unwrap();
+            if state.running < self.limit && state.waiting.is_empty() {
+                state.running += 1;
+                return Permit {
+                    state: Some(self.state.clone()),
+                };
+            }
+
+            let (tx, rx) = oneshot::channel();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.waiting.push(Waiter {
+                priority,
+                seq,
+                wake: tx,
+            });
+            rx
+        };
+
+        // The slot is transferred to us by the releasing permit, so `running`
+        // is already accounted for when we wake up.
+        let mut pending = Pending {
+            rx,
+            state: self.state.clone(),
+            granted: false,
+        };
+        (&mut pending.rx)
+            .await
+            .expect("scheduler waiters are only dropped after being woken");
+        pending.granted = true;
+
+        Permit {
+            state: Some(self.state.clone()),
+        }
+    }
+
+    /// Whether calls can queue at all, i.e. a limit is set
+    pub fn is_bounded(&self) -> bool {
+        self.limit > 0
+    }
+
+    /// Number of calls currently waiting for a slot
+    pub fn queued(&self) -> usize {
+        self.state.lock().unwrap().waiting.len()
+    }
+
+    /// Number of calls currently holding a slot
+    pub fn running(&self) -> usize {
+        if self.limit == 0 {
+            return 0;
+        }
+        self.state.lock().unwrap().running
+    }
+}
+
+/// A queued acquire; gives back a slot that was handed over after the
+/// caller stopped waiting
+struct Pending {
+    rx: oneshot::Receiver<()>,
+    state: Arc<Mutex<State>>,
+    granted: bool,
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        if self.granted {
+            return;
+        }
+        self.rx.close();
+        if self.rx.try_recv().is_ok() {
+            release(&self.state);
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(state) = self.state.take() {
+            release(&state);
+        }
+    }
+}
+
+/// Hand a slot to the best waiter still listening, or free it
+fn release(state: &Mutex<State>) {
+    let mut state = state.lock().unwrap();
+    while let Some(waiter) = state.waiting.pop() {
+        if waiter.wake.send(()).is_ok() {
+            return;
+        }
+    }
+    state.running -= 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_unlimited_never_waits() {
+        let scheduler = Scheduler::new(0);
+        let _a = scheduler.acquire(0).await;
+        let _b = scheduler.acquire(0).await;
+        assert_eq!(scheduler.queued(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_slot_goes_to_highest_priority() {
+        let scheduler = Arc::new(Scheduler::new(1));
+        let held = scheduler.acquire(DEFAULT_PRIORITY).await;
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let mut handles = Vec::new();
+        for priority in [1, 5, 1, 3] {
+            let task_scheduler = scheduler.clone();
+            let order = order.clone();
+            handles.push(tokio::spawn(async move {
+                let _permit = task_scheduler.acquire(priority).await;
+                order.lock().unwrap().push(priority);
+            }));
+            while scheduler.queued() < handles.len() {
+                tokio::task::yield_now().await;
+            }
+        }
+
+        drop(held);
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        assert_eq!(*order.lock().unwrap(), vec![5, 3, 1, 1]);
+        assert_eq!(scheduler.running(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_waiter_releases_slot() {
+        let scheduler = Arc::new(Scheduler::new(1));
+        let held = scheduler.acquire(0).await;
+
+        let waiting = {
+            let scheduler = scheduler.clone();
+            tokio::spawn(async move {
+                let _permit = scheduler.acquire(0).await;
+            })
+        };
+        while scheduler.queued() < 1 {
+            tokio::task::yield_now().await;
+        }
+        waiting.abort();
+        let _ = waiting.await;
+
+        drop(held);
+        assert_eq!(scheduler.running(), 0);
+        let _again = scheduler.acquire(0).await;
+        assert_eq!(scheduler.running(), 1);
+    }
+}
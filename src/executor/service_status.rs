@@ -0,0 +1,282 @@
+// Service status tool implementation
+#![allow(dead_code)]
+
+use crate::brain::ToolDefinition;
+use crate::executor::{ExecutorError, Result, ToolImpl, ToolOutput};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::{debug, info};
+
+/// Exists only while systemd is the running init system (see sd_booted(3))
+const SYSTEMD_RUNTIME_DIR: &str = "/run/systemd/system";
+
+/// Properties queried for a single unit
+const UNIT_PROPERTIES: &str =
+    "Id,Description,LoadState,ActiveState,SubState,UnitFileState,MainPID,ActiveEnterTimestamp";
+
+/// Service status tool input parameters
+#[derive(Debug, Deserialize)]
+struct ServiceStatusInput {
+    #[serde(default)]
+    unit: Option<String>,
+    #[serde(default)]
+    all: bool,
+}
+
+/// One row of `systemctl list-units --output=json`
+#[derive(Debug, Deserialize, Serialize)]
+struct ListedUnit {
+    unit: String,
+    load: String,
+    active: String,
+    sub: String,
+    #[serde(default)]
+    description: String,
+}
+
+/// Status of a single unit, from `systemctl show`
+#[derive(Debug, Default, Serialize)]
+struct UnitStatus {
+    id: String,
+    description: String,
+    load_state: String,
+    active_state: String,
+    sub_state: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unit_file_state: Option<String>,
+    /// Main process, when the unit has one running
+    #[serde(skip_serializing_if = "Option::is_none")]
+    main_pid: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    active_since: Option<String>,
+}
+
+/// Service status tool implementation
+///
+/// Only systemd is supported; elsewhere the tool says so instead of
+/// returning whatever a missing or foreign `systemctl` prints.
+pub struct ServiceStatusTool {
+    description: String,
+    systemctl: String,
+    timeout: Duration,
+    systemd_dir: PathBuf,
+}
+
+impl ServiceStatusTool {
+    pub fn new(
+        description: impl Into<String>,
+        systemctl: impl Into<String>,
+        timeout_secs: u64,
+    ) -> Self {
+        Self {
+            description: description.into(),
+            systemctl: systemctl.into(),
+            timeout: Duration::from_secs(timeout_secs),
+            systemd_dir: PathBuf::from(SYSTEMD_RUNTIME_DIR),
+        }
+    }
+
+    /// Detect systemd by `dir` instead of /run/systemd/system
+    pub fn with_systemd_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.systemd_dir = dir.into();
+        self
+    }
+
+    /// Run systemctl with `args`, returning its stdout
+    async fn systemctl(&self, args: &[&str]) -> std::result::Result<String, String> {
+        let mut command = Command::new(&self.systemctl);
+        command.args(args).arg("--no-pager").kill_on_drop(true);
+        let output = match tokio::time::timeout(self.timeout, command.output()).await {
+            Ok(Ok(output)) => output,
+            Ok(Err(e)) => return Err(format!("failed to run {}: {}", self.systemctl, e)),
+            Err(_) => {
+                return Err(format!(
+                    "{} timed out after {}s",
+                    self.systemctl,
+                    self.timeout.as_secs()
+                ));
+            }
+        };
+        if !output.status.success() {
+            return Err(format!(
+                "{} failed ({}): {}",
+                self.systemctl,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    async fn list_units(&self, all: bool) -> std::result::Result<Vec<ListedUnit>, String> {
+        let mut args = vec!["list-units", "--type=service", "--output=json"];
+        if all {
+            args.push("--all");
+        }
+        let stdout = self.systemctl(&args).await?;
+        serde_json::from_str(&stdout)
+            .map_err(|e| format!("unexpected list-units output (systemd too old?): {}", e))
+    }
+
+    async fn show_unit(&self, unit: &str) -> std::result::Result<UnitStatus, String> {
+        let properties = format!("--property={}", UNIT_PROPERTIES);
+        let stdout = self.systemctl(&["show", &properties, "--", unit]).await?;
+        Ok(parse_show(&stdout))
+    }
+}
+
+#[async_trait]
+impl ToolImpl for ServiceStatusTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "service_status".to_string(),
+            description: self.description.clone(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "unit": {
+                        "type": "string",
+                        "description": "Unit to inspect, e.g. nginx or nginx.service; omit to list services"
+                    },
+                    "all": {
+                        "type": "boolean",
+                        "description": "When listing, include inactive services too (default false)"
+                    }
+                }
+            }),
+        }
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn cacheable(&self) -> bool {
+        // Service state changes between calls
+        false
+    }
+
+    async fn run(&self, input: serde_json::Value) -> Result<ToolOutput> {
+        let ServiceStatusInput { unit, all } = serde_json::from_value(input).map_err(|e| {
+            ExecutorError::InvalidInput("service_status".to_string(), e.to_string())
+        })?;
+        if let Some(unit) = &unit
+            && (unit.trim().is_empty() || unit.starts_with('-'))
+        {
+            return Err(ExecutorError::InvalidInput(
+                "service_status".to_string(),
+                format!("invalid unit name {:?}", unit),
+            ));
+        }
+
+        if !self.systemd_dir.is_dir() {
+            let init = init_system_name();
+            info!(init = %init, "service_status on unsupported init system");
+            return Ok(ToolOutput::error(format!(
+                "unsupported init system: {} (service_status needs systemd; \
+                 use the init system's own tools via bash)",
+                init
+            )));
+        }
+
+        debug!(unit = ?unit, all, "querying service status");
+
+        let result = match &unit {
+            Some(unit) => self
+                .show_unit(unit)
+                .await
+                .map(|status| serde_json::json!({ "init_system": "systemd", "unit": status })),
+            None => self.list_units(all).await.map(|units| {
+                serde_json::json!({
+                    "init_system": "systemd",
+                    "count": units.len(),
+                    "units": units,
+                })
+            }),
+        };
+
+        match result {
+            Ok(status) => {
+                info!(unit = ?unit, "service status queried");
+                Ok(ToolOutput::success(serde_json::to_string_pretty(&status)?))
+            }
+            Err(e) => Ok(ToolOutput::error(e)),
+        }
+    }
+}
+
+/// Parse `systemctl show` output (`Key=Value` lines) into a status
+fn parse_show(stdout: &str) -> UnitStatus {
+    let mut status = UnitStatus::default();
+    let non_empty = |v: &str| (!v.is_empty()).then(|| v.to_string());
+    for (key, value) in stdout.lines().filter_map(|line| line.split_once('=')) {
+        match key {
+            "Id" => status.id = value.to_string(),
+            "Description" => status.description = value.to_string(),
+            "LoadState" => status.load_state = value.to_string(),
+            "ActiveState" => status.active_state = value.to_string(),
+            "SubState" => status.sub_state = value.to_string(),
+            "UnitFileState" => status.unit_file_state = non_empty(value),
+            "MainPID" => status.main_pid = value.parse().ok().filter(|&pid| pid != 0),
+            "ActiveEnterTimestamp" => status.active_since = non_empty(value),
+            _ => {}
+        }
+    }
+    status
+}
+
+/// Name of the process running as PID 1, e.g. "init" or "openrc-init"
+fn init_system_name() -> String {
+    std::fs::read_to_string("/proc/1/comm")
+        .map(|name| name.trim().to_string())
+        .ok()
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Default service_status tool description
+pub fn default_service_status_description() -> String {
+    r#"Query the status of system services on a systemd host.
+Without a unit, lists service units with their load, active and sub states.
+With a unit, returns its states, unit file state, main PID and when it became active.
+On hosts without systemd it reports an unsupported init system."#
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_show() {
+        let stdout = "Id=nginx.service\n\
+                      Description=A high performance web server\n\
+                      LoadState=loaded\n\
+                      ActiveState=active\n\
+                      SubState=running\n\
+                      UnitFileState=enabled\n\
+                      MainPID=812\n\
+                      ActiveEnterTimestamp=Tue 2026-10-13 08:00:01 UTC\n";
+        let status = parse_show(stdout);
+        assert_eq!(status.id, "nginx.service");
+        assert_eq!(
+            (status.active_state.as_str(), status.sub_state.as_str()),
+            ("active", "running")
+        );
+        assert_eq!(status.main_pid, Some(812));
+        assert_eq!(status.unit_file_state.as_deref(), Some("enabled"));
+
+        // An unknown unit has no process and no timestamps
+        let status = parse_show(
+            "Id=missing.service\nLoadState=not-found\nActiveState=inactive\n\
+             SubState=dead\nUnitFileState=\nMainPID=0\nActiveEnterTimestamp=\n",
+        );
+        assert_eq!(status.load_state, "not-found");
+        assert_eq!(status.main_pid, None);
+        assert_eq!(status.unit_file_state, None);
+        assert_eq!(status.active_since, None);
+    }
+}
@@ -0,0 +1,213 @@
+// Tail file tool implementation
+#![allow(dead_code)]
+
+use crate::brain::ToolDefinition;
+use crate::executor::path::validate_path;
+use crate::executor::{ExecutorError, Result, ToolImpl, ToolOutput};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+/// Default number of lines returned
+const DEFAULT_LINES: usize = 10;
+
+/// Upper bound on requested lines
+const MAX_LINES: usize = 10_000;
+
+/// Bytes read per backward step
+const BLOCK_SIZE: u64 = 8192;
+
+/// Tail file tool input parameters
+#[derive(Debug, Deserialize)]
+struct TailFileInput {
+    path: String,
+    #[serde(default)]
+    lines: Option<usize>,
+}
+
+/// Last lines of a file
+struct Tail {
+    text: String,
+    /// Lines actually returned
+    lines: usize,
+    /// Output was cut at the byte limit before reaching the requested lines
+    truncated: bool,
+}
+
+/// Tail file tool implementation
+pub struct TailFileTool {
+    description: String,
+    allowed_root: Option<PathBuf>,
+    max_output_bytes: usize,
+}
+
+impl TailFileTool {
+    pub fn new(
+        description: impl Into<String>,
+        allowed_root: Option<PathBuf>,
+        max_output_bytes: usize,
+    ) -> Self {
+        Self {
+            description: description.into(),
+            allowed_root,
+            max_output_bytes,
+        }
+    }
+}
+
+#[async_trait]
+impl ToolImpl for TailFileTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "tail_file".to_string(),
+            description: self.description.clone(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "The file to read"
+                    },
+                    "lines": {
+                        "type": "integer",
+                        "description": "Number of lines from the end to return (default 10)"
+                    }
+                },
+                "required": ["path"]
+            }),
+        }
+    }
+
+    fn cacheable(&self) -> bool {
+        // Logs grow between calls, so always read the current tail
+        false
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, input: serde_json::Value) -> Result<ToolOutput> {
+        let TailFileInput { path, lines } = serde_json::from_value(input)
+            .map_err(|e| ExecutorError::InvalidInput("tail_file".to_string(), e.to_string()))?;
+
+        let file = match validate_path(self.allowed_root.as_deref(), Path::new(&path)) {
+            Ok(file) => file,
+            Err(e) => return Ok(ToolOutput::error(e.to_string())),
+        };
+        let lines = lines.unwrap_or(DEFAULT_LINES).min(MAX_LINES);
+        let max_bytes = self.max_output_bytes;
+
+        debug!(path = %file.display(), lines, "tailing file");
+
+        let read = file.clone();
+        let tail = tokio::task::spawn_blocking(move || tail(&read, lines, max_bytes))
+            .await
+            .map_err(|e| {
+                ExecutorError::OutputCaptureFailed("tail_file".to_string(), e.to_string())
+            })?;
+
+        let tail = match tail {
+            Ok(tail) => tail,
+            Err(e) => {
+                return Ok(ToolOutput::error(format!(
+                    "cannot read {}: {}",
+                    file.display(),
+                    e
+                )));
+            }
+        };
+
+        info!(
+            path = %file.display(),
+            lines = tail.lines,
+            truncated = tail.truncated,
+            "file tailed"
+        );
+
+        let mut content = tail.text;
+        if tail.truncated {
+            content.push_str(&format!(
+                "\n[truncated: only {} lines fit in {} bytes]",
+                tail.lines, max_bytes
+            ));
+        }
+        Ok(ToolOutput::success(content))
+    }
+}
+
+/// Read the last `lines` lines of `path` by seeking backwards from the end,
+/// never holding more than `max_bytes` of content
+fn tail(path: &Path, lines: usize, max_bytes: usize) -> io::Result<Tail> {
+    let mut file = File::open(path)?;
+    if !file.metadata()?.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "not a regular file",
+        ));
+    }
+
+    let len = file.seek(SeekFrom::End(0))?;
+    let mut pos = len;
+    let mut buf: Vec<u8> = Vec::new();
+
+    // A trailing newline terminates the last line rather than starting a new one
+    let newlines_needed = |buf: &[u8]| {
+        let body = buf.strip_suffix(b"\n").unwrap_or(buf);
+        body.iter().filter(|&&b| b == b'\n').count() >= lines
+    };
+
+    while pos > 0 && !newlines_needed(&buf) && buf.len() <= max_bytes {
+        let step = BLOCK_SIZE.min(pos);
+        pos -= step;
+        file.seek(SeekFrom::Start(pos))?;
+        let mut block = vec![0u8; step as usize];
+        file.read_exact(&mut block)?;
+        block.extend_from_slice(&buf);
+        buf = block;
+    }
+
+    let text = String::from_utf8_lossy(&buf);
+    let mut body = text.strip_suffix('\n').unwrap_or(&text);
+    if pos > 0 {
+        // We stopped mid-file, so the first line read is only partial
+        body = body.split_once('\n').map_or("", |(_, rest)| rest);
+    }
+    let mut selected: Vec<&str> = if lines == 0 || body.is_empty() {
+        Vec::new()
+    } else {
+        body.rsplit('\n').take(lines).collect()
+    };
+    let mut truncated = pos > 0 && selected.len() < lines;
+
+    // Drop the oldest lines until the output fits
+    let mut size: usize = selected.iter().map(|l| l.len() + 1).sum();
+    while size > max_bytes {
+        let Some(line) = selected.pop() else { break };
+        size -= line.len() + 1;
+        truncated = true;
+    }
+    selected.reverse();
+
+    let mut text = selected.join("\n");
+    if !selected.is_empty() {
+        text.push('\n');
+    }
+
+    Ok(Tail {
+        text,
+        lines: selected.len(),
+        truncated,
+    })
+}
+
+/// Default tail_file tool description
+pub fn default_tail_file_description() -> String {
+    r#"Return the last N lines of a file (default 10, max 10000).
+Reads backwards from the end, so it is cheap even on very large log files.
+Output is capped at the executor's output size limit; the oldest lines are dropped first."#
+        .to_string()
+}
@@ -3,6 +3,7 @@
 #![allow(clippy::collapsible_if)]
 
 use crate::brain::ToolDefinition;
+use crate::executor::types::ToolSettings;
 use crate::executor::{Result, ToolOutput};
 use async_trait::async_trait;
 use tracing::debug;
@@ -20,34 +21,48 @@ pub trait ToolImpl: Send + Sync {
     fn name(&self) -> String {
         self.definition().name.clone()
     }
+
+    /// Whether a repeated identical call may reuse an earlier result when
+    /// the tools config does not say; stateful tools return false
+    fn cacheable(&self) -> bool {
+        true
+    }
+
+    /// Whether the tool only observes and never changes the system, when
+    /// the tools config does not say
+    fn read_only(&self) -> bool {
+        false
+    }
 }
 
-/// Load tool descriptions from TOML config file
-pub fn load_tool_descriptions(
+/// Check run before every tool execution, e.g. an approval webhook or a
+/// maintenance window; returning an error refuses the call
+#[async_trait]
+pub trait PreExecHook: Send + Sync {
+    /// Inspect a call about to run; `Err` carries the message the model
+    /// sees as the tool result instead
+    async fn before(
+        &self,
+        tool_name: &str,
+        input: &serde_json::Value,
+    ) -> std::result::Result<(), String>;
+}
+
+/// Load per-tool settings from TOML config file, parsing it once; a missing
+/// file means every tool keeps its defaults
+pub fn load_tool_settings(
     path: &std::path::Path,
-) -> Result<std::collections::HashMap<String, String>> {
+) -> Result<std::collections::HashMap<String, ToolSettings>> {
     use std::collections::HashMap;
 
     if !path.exists() {
-        debug!(path = %path.display(), "tools.toml not found, using default descriptions");
+        debug!(path = %path.display(), "tools.toml not found, using default tool settings");
         return Ok(HashMap::new());
     }
 
     let content = std::fs::read_to_string(path)?;
-    let config: toml::Value = content.parse()?;
-
-    let mut descriptions = HashMap::new();
-
-    if let Some(table) = config.as_table() {
-        for (key, value) in table {
-            if let Some(desc) = value.get("description") {
-                if let Some(s) = desc.as_str() {
-                    descriptions.insert(key.clone(), s.to_string());
-                }
-            }
-        }
-    }
+    let settings: HashMap<String, ToolSettings> = toml::from_str(&content)?;
 
-    debug!(path = %path.display(), tool_count = descriptions.len(), "loaded tool descriptions from config");
-    Ok(descriptions)
+    debug!(path = %path.display(), tool_count = settings.len(), "loaded tool settings from config");
+    Ok(settings)
 }
@@ -11,6 +11,63 @@ pub struct ToolOutput {
     /// Whether the execution resulted in an error (non-zero exit code)
     #[serde(default)]
     pub is_error: bool,
+    /// Execution was cut short (e.g. timed out) and content is incomplete
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub partial: bool,
+    /// Separate streams and exit code, for tools that run a process
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub process: Option<ProcessOutput>,
+}
+
+/// Output of a process, kept apart for structured tool results
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProcessOutput {
+    pub stdout: String,
+    pub stderr: String,
+    /// None when the process was killed or timed out
+    pub exit_code: Option<i32>,
+}
+
+/// How a tool's output is written into the `tool_result` sent to the model,
+/// set per tool with `result_format` in tools.toml
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResultFormat {
+    /// The human-readable content, prefixed with "Error: " on failure
+    #[default]
+    Text,
+    /// A JSON object: stdout/stderr/exit_code/is_error for processes,
+    /// content/is_error otherwise
+    Json,
+}
+
+/// How the bash tool assembles its content string, set with
+/// `ExecutorConfig::bash_output_format`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BashOutputFormat {
+    /// `[stdout]`, `[stderr]` and `[exit_code]` sections
+    #[default]
+    Labeled,
+    /// Stdout alone, or stderr when stdout is empty
+    Raw,
+    /// A JSON object with stdout, stderr and exit_code
+    Json,
+}
+
+impl std::str::FromStr for BashOutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "labeled" => Ok(Self::Labeled),
+            "raw" => Ok(Self::Raw),
+            "json" => Ok(Self::Json),
+            other => Err(format!(
+                "unknown bash output format {:?} (expected labeled, raw or json)",
+                other
+            )),
+        }
+    }
 }
 
 impl ToolOutput {
@@ -18,6 +75,8 @@ impl ToolOutput {
         Self {
             content: content.into(),
             is_error: false,
+            partial: false,
+            process: None,
         }
     }
 
@@ -25,6 +84,34 @@ impl ToolOutput {
         Self {
             content: content.into(),
             is_error: true,
+            partial: false,
+            process: None,
+        }
+    }
+
+    /// Text for the `tool_result` content block in the given format
+    pub fn render(&self, format: ResultFormat) -> String {
+        match format {
+            ResultFormat::Text if self.is_error => format!("Error: {}", self.content),
+            ResultFormat::Text => self.content.clone(),
+            ResultFormat::Json => {
+                let mut object = match &self.process {
+                    Some(process) => serde_json::json!({
+                        "stdout": process.stdout,
+                        "stderr": process.stderr,
+                        "exit_code": process.exit_code,
+                        "is_error": self.is_error,
+                    }),
+                    None => serde_json::json!({
+                        "content": self.content,
+                        "is_error": self.is_error,
+                    }),
+                };
+                if self.partial {
+                    object["partial"] = true.into();
+                }
+                object.to_string()
+            }
         }
     }
 }
@@ -49,3 +136,30 @@ impl Default for ExecutionConstraints {
         }
     }
 }
+
+/// Settings for one tool, read from its `[<tool>]` table in tools.toml;
+/// an unset field falls back to the tool's built-in default
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ToolSettings {
+    /// Description shown to the model instead of the built-in one
+    pub description: Option<String>,
+    /// How the tool's output is rendered for the model (default text)
+    pub result_format: Option<ResultFormat>,
+    /// Whether a repeated identical call may reuse an earlier result
+    pub cacheable: Option<bool>,
+    /// Whether the tool only observes the system and never changes it
+    pub read_only: Option<bool>,
+    /// Retry policy; tools without one are not retried
+    pub retry: Option<RetryPolicy>,
+}
+
+/// Per-tool retry policy, configured under `[<tool>.retry]` in tools.toml
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub struct RetryPolicy {
+    /// Extra attempts after an `is_error` result (0 = no retries)
+    #[serde(default)]
+    pub max: u32,
+    /// Delay before the first retry in milliseconds, doubled per retry
+    #[serde(default)]
+    pub backoff_ms: u64,
+}
@@ -0,0 +1,202 @@
+// Startup check of the daemon's own resource limits
+// Diagnostic only: low limits are logged, never raised or enforced
+
+use tracing::{info, warn};
+
+/// File descriptors the daemon uses regardless of tool activity (sockets,
+/// HTTP connections, memory files, log output)
+const BASE_OPEN_FILES: u64 = 256;
+/// Descriptors one running tool call may hold: three pipes plus the child's ends
+const OPEN_FILES_PER_EXECUTION: u64 = 8;
+/// Processes (per user) the daemon needs besides its tool calls
+const BASE_PROCESSES: u64 = 128;
+/// Processes one bash call may spawn in a typical pipeline
+const PROCESSES_PER_EXECUTION: u64 = 4;
+/// Memory the daemon needs besides tool output buffers
+const BASE_MEMORY_BYTES: u64 = 128 * 1024 * 1024;
+/// Concurrency assumed when `max_concurrent_executions` is 0 (unlimited)
+const UNBOUNDED_EXECUTIONS: u64 = 32;
+/// cgroup v1 reports "no limit" as a huge page-aligned number
+const CGROUP_V1_UNLIMITED: u64 = 1 << 60;
+
+/// Limits that apply to this process; None = unlimited or unknown
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// Soft RLIMIT_NOFILE
+    pub open_files: Option<u64>,
+    /// Soft RLIMIT_NPROC
+    pub processes: Option<u64>,
+    /// Memory cgroup limit
+    pub memory_bytes: Option<u64>,
+}
+
+impl ResourceLimits {
+    /// Read rlimits from `/proc/self/limits` and the memory cgroup limit
+    pub fn read() -> Self {
+        let mut limits = std::fs::read_to_string("/proc/self/limits")
+            .map(|text| Self::parse_proc_limits(&text))
+            .unwrap_or_default();
+        limits.memory_bytes = read_cgroup_memory_limit();
+        limits
+    }
+
+    /// Soft limits from the text of `/proc/<pid>/limits`
+    fn parse_proc_limits(text: &str) -> Self {
+        let soft_limit = |name: &str| {
+            text.lines()
+                .find_map(|line| line.strip_prefix(name))
+                .and_then(|rest| rest.split_whitespace().next())
+                .and_then(|soft| soft.parse().ok())
+        };
+        Self {
+            open_files: soft_limit("Max open files"),
+            processes: soft_limit("Max processes"),
+            memory_bytes: None,
+        }
+    }
+
+    /// Limits too low for `executions` concurrent tool calls (0 = unlimited)
+    /// each buffering up to `max_output_bytes`
+    pub fn warnings(&self, executions: usize, max_output_bytes: usize) -> Vec<String> {
+        let executions = match executions as u64 {
+            0 => UNBOUNDED_EXECUTIONS,
+            n => n,
+        };
+        let needed_files = BASE_OPEN_FILES + OPEN_FILES_PER_EXECUTION * executions;
+        let needed_processes = BASE_PROCESSES + PROCESSES_PER_EXECUTION * executions;
+        // stdout and stderr are each buffered up to the limit
+        let needed_memory = BASE_MEMORY_BYTES + 2 * max_output_bytes as u64 * executions;
+
+        let mut warnings = Vec::new();
+        if let Some(limit) = self.open_files
+            && limit < needed_files
+        {
+            warnings.push(format!(
+                "open files limit (RLIMIT_NOFILE) is {}, below the {} needed for {} concurrent tool calls",
+                limit, needed_files, executions
+            ));
+        }
+        if let Some(limit) = self.processes
+            && limit < needed_processes
+        {
+            warnings.push(format!(
+                "process limit (RLIMIT_NPROC) is {}, below the {} needed for {} concurrent tool calls",
+                limit, needed_processes, executions
+            ));
+        }
+        if let Some(limit) = self.memory_bytes
+            && limit < needed_memory
+        {
+            warnings.push(format!(
+                "memory cgroup limit is {} MiB, below the {} MiB needed for {} concurrent tool calls",
+                limit / (1024 * 1024),
+                needed_memory / (1024 * 1024),
+                executions
+            ));
+        }
+        warnings
+    }
+}
+
+/// Memory limit of this process's cgroup (v2 `memory.max`, or v1
+/// `memory.limit_in_bytes`)
+fn read_cgroup_memory_limit() -> Option<u64> {
+    let cgroups = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+    let path = cgroups.lines().find_map(|line| {
+        let mut fields = line.splitn(3, ':');
+        let (_, controllers, path) = (fields.next()?, fields.next()?, fields.next()?);
+        if controllers.is_empty() {
+            Some(format!("/sys/fs/cgroup{}/memory.max", path))
+        } else if controllers.split(',').any(|c| c == "memory") {
+            Some(format!(
+                "/sys/fs/cgroup/memory{}/memory.limit_in_bytes",
+                path
+            ))
+        } else {
+            None
+        }
+    })?;
+    parse_cgroup_memory_limit(&std::fs::read_to_string(path).ok()?)
+}
+
+/// "max" (v2) and v1's huge sentinel both mean unlimited
+fn parse_cgroup_memory_limit(text: &str) -> Option<u64> {
+    text.trim()
+        .parse()
+        .ok()
+        .filter(|&bytes| bytes < CGROUP_V1_UNLIMITED)
+}
+
+/// Log the process limits, warning about any too low for the configured
+/// tool concurrency
+pub fn check(executions: usize, max_output_bytes: usize) {
+    let limits = ResourceLimits::read();
+    info!(
+        open_files = ?limits.open_files,
+        processes = ?limits.processes,
+        memory_bytes = ?limits.memory_bytes,
+        "Resource limits"
+    );
+    for warning in limits.warnings(executions, max_output_bytes) {
+        warn!("{}; heavy tool use may fail", warning);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROC_LIMITS: &str = "\
+Limit                     Soft Limit           Hard Limit           Units
+Max cpu time              unlimited            unlimited            seconds
+Max processes             24003                24003                processes
+Max open files            64                   1048576              files
+Max locked memory         8388608              8388608              bytes
+";
+
+    #[test]
+    fn test_low_fd_limit_warns() {
+        let limits = ResourceLimits::parse_proc_limits(PROC_LIMITS);
+        assert_eq!(limits.open_files, Some(64));
+        assert_eq!(limits.processes, Some(24003));
+
+        let warnings = limits.warnings(4, 1024 * 1024);
+        assert_eq!(warnings.len(), 1, "{:?}", warnings);
+        assert!(
+            warnings[0].contains("RLIMIT_NOFILE) is 64"),
+            "{}",
+            warnings[0]
+        );
+
+        // Generous limits pass, unknown ones are not guessed at
+        let roomy = ResourceLimits {
+            open_files: Some(65536),
+            ..limits
+        };
+        assert!(roomy.warnings(4, 1024 * 1024).is_empty());
+        assert!(
+            ResourceLimits::default()
+                .warnings(0, 1024 * 1024)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_memory_limit_scales_with_concurrency() {
+        let limits = ResourceLimits {
+            memory_bytes: Some(256 * 1024 * 1024),
+            ..ResourceLimits::default()
+        };
+        assert!(limits.warnings(4, 1024 * 1024).is_empty());
+        // Unlimited concurrency is assumed to mean 32 calls of 2 x 16 MiB
+        let warnings = limits.warnings(0, 16 * 1024 * 1024);
+        assert!(warnings[0].contains("memory cgroup limit is 256 MiB"));
+
+        assert_eq!(parse_cgroup_memory_limit("max\n"), None);
+        assert_eq!(parse_cgroup_memory_limit("9223372036854771712\n"), None);
+        assert_eq!(
+            parse_cgroup_memory_limit("536870912\n"),
+            Some(512 * 1024 * 1024)
+        );
+    }
+}
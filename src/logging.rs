@@ -0,0 +1,100 @@
+// Log subscriber setup: verbosity comes from the environment, per module
+
+use tracing::warn;
+use tracing_subscriber::EnvFilter;
+
+/// Variables holding the filter, in order of precedence
+const FILTER_VARS: [&str; 2] = ["SHELLY_LOG", "RUST_LOG"];
+
+/// Filter used when none is set or the one set does not parse
+const DEFAULT_FILTER: &str = "info";
+
+/// Install the global subscriber, filtered by `SHELLY_LOG`, else `RUST_LOG`,
+/// else INFO
+///
+/// Both take `tracing_subscriber` directives such as
+/// `info,shelly::brain=debug,shelly::comm=warn`. An invalid filter falls back
+/// to INFO with a warning rather than stopping the daemon.
+pub fn init() {
+    let configured = FILTER_VARS.iter().find_map(|var| {
+        std::env::var(var)
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .map(|v| (*var, v))
+    });
+    let (filter, invalid) = match &configured {
+        Some((var, spec)) => match parse_filter(spec) {
+            Ok(filter) => (filter, None),
+            Err(e) => (EnvFilter::new(DEFAULT_FILTER), Some((*var, e))),
+        },
+        None => (EnvFilter::new(DEFAULT_FILTER), None),
+    };
+
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_target(true)
+        .with_thread_ids(true)
+        .with_file(true)
+        .with_line_number(true)
+        .init();
+
+    if let Some((var, e)) = invalid {
+        warn!(var, error = %e, "invalid log filter, logging at {}", DEFAULT_FILTER);
+    }
+}
+
+/// Parse a filter such as `info,shelly::brain=debug`
+fn parse_filter(spec: &str) -> Result<EnvFilter, tracing_subscriber::filter::ParseError> {
+    EnvFilter::try_new(spec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct LogBuffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for LogBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_filter_applies_per_module_levels() {
+        let logs = LogBuffer::default();
+        let filter = parse_filter("info,shelly::brain=debug,shelly::comm=warn").unwrap();
+        let subscriber = tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_ansi(false)
+            .without_time()
+            .with_writer({
+                let logs = logs.clone();
+                move || logs.clone()
+            })
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::debug!(target: "shelly::brain::client", "brain debug");
+            tracing::info!(target: "shelly::comm::server", "comm info");
+            tracing::warn!(target: "shelly::comm::server", "comm warn");
+            tracing::debug!(target: "shelly::agent", "agent debug");
+            tracing::info!(target: "shelly::agent", "agent info");
+        });
+
+        let logged = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        assert!(logged.contains("brain debug"), "{}", logged);
+        assert!(!logged.contains("comm info"), "{}", logged);
+        assert!(logged.contains("comm warn"), "{}", logged);
+        assert!(!logged.contains("agent debug"), "{}", logged);
+        assert!(logged.contains("agent info"), "{}", logged);
+
+        assert!(parse_filter("shelly::brain=loud").is_err());
+    }
+}
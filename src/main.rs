@@ -2,40 +2,77 @@ mod agent;
 mod brain;
 mod comm;
 mod executor;
+mod limits;
+mod logging;
 mod memory;
+#[cfg(feature = "systemd")]
+mod systemd;
 
 use agent::{AgentConfig, AgentLoop};
 use brain::Brain;
 use brain::BrainConfig;
 use comm::{Comm, CommConfig};
 use executor::{Executor, ExecutorConfig};
+use memory::config::MemoryConfig;
 use std::process;
 use tokio::signal;
-use tracing::{Level, error, info};
-use tracing_subscriber::fmt;
+use tracing::{error, info};
+
+/// Extra time comm waits for a reply beyond the agent's handle timeout
+const RESPONSE_TIMEOUT_MARGIN_SECS: u64 = 30;
 
 /// Tokio runtime with signal handling
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize logging with high observability for dev
-    fmt()
-        .with_max_level(Level::DEBUG)
-        .with_target(true)
-        .with_thread_ids(true)
-        .with_file(true)
-        .with_line_number(true)
-        .init();
+    // Verbosity from SHELLY_LOG / RUST_LOG, INFO by default
+    logging::init();
 
     info!("Starting Shelly daemon...");
 
     // Initialize config
-    let comm_config = CommConfig::default();
     let brain_config = BrainConfig::from_env()?;
-    let executor_config = ExecutorConfig::default();
+    let executor_config = ExecutorConfig::from_env();
     let agent_config = AgentConfig::from_env()?;
+    let memory_config = MemoryConfig::from_env();
+
+    // Comm must outwait the agent, or it answers "Response timeout" while
+    // the agent is still working
+    let mut comm_config = CommConfig::from_env();
+    let min_response_timeout = agent_config.handle_timeout_secs + RESPONSE_TIMEOUT_MARGIN_SECS;
+    if comm_config.response_timeout_secs < min_response_timeout {
+        comm_config.response_timeout_secs = min_response_timeout;
+    }
+
+    // Report every misconfiguration up front rather than failing at runtime
+    let mut problems = Vec::new();
+    if let Err(e) = brain_config.validate() {
+        problems.push(format!("brain: {}", e));
+    }
+    if let Err(e) = executor_config.validate() {
+        problems.push(format!("executor: {}", e));
+    }
+    if let Err(e) = agent_config.validate() {
+        problems.push(format!("agent: {}", e));
+    }
+    if let Err(e) = comm_config.validate() {
+        problems.push(format!("comm: {}", e));
+    }
+    if !problems.is_empty() {
+        for problem in &problems {
+            error!(problem = %problem, "Invalid configuration");
+        }
+        return Err(format!("invalid configuration:\n  {}", problems.join("\n  ")).into());
+    }
+
+    // Diagnostic only: heavy tool use fails oddly under low limits
+    limits::check(
+        executor_config.max_concurrent_executions,
+        executor_config.constraints.max_output_bytes,
+    );
 
     info!(
         comm_port = comm_config.listen_port,
+        response_timeout_secs = comm_config.response_timeout_secs,
         model = %brain_config.default_model,
         "Configuration loaded"
     );
@@ -43,28 +80,42 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Initialize comm
     let (comm, mut user_rx) = Comm::new(comm_config).await?;
     info!(addr = %comm.local_addr()?, "Comm initialized");
+    let pause_flag = comm.pause_flag();
+    let init_report = comm.init_report_slot();
 
     // Initialize brain
     let brain = Brain::new(brain_config).await?;
-    info!(model = brain.default_model(), "Brain initialized");
+    info!(
+        model = brain.default_model(),
+        max_tokens = brain.max_output_tokens(),
+        "Brain initialized"
+    );
 
     // Initialize executor
-    let executor = Executor::new(executor_config);
+    let executor = Executor::new(executor_config)?;
     info!(
         tools = executor.tool_definitions().len(),
         "Executor initialized"
     );
 
     // Initialize agent loop
-    let agent = AgentLoop::new(brain, executor, agent_config);
+    let agent = AgentLoop::new(brain, executor, agent_config)
+        .with_pause_flag(pause_flag)
+        .with_init_report(init_report)
+        .with_semantic_memory(memory_config);
 
     // Spawn comm server
     let comm_handle = tokio::spawn(async move {
-        if let Err(e) = comm.run().await {
-            error!(error = %e, "Comm server error");
+        if let Err(e) = comm.run_supervised().await {
+            error!(error = %e, "Comm server stopped");
         }
     });
 
+    // SIGUSR1 dumps memory for live debugging, without waiting for the
+    // request in flight; installed before init so a hung init can be dumped
+    // too, instead of the signal's default action killing the daemon
+    agent.memory_dumper().spawn_on_sigusr1()?;
+
     // Run initialization
     info!("Running agent initialization...");
     if let Err(e) = agent.run_init().await {
@@ -72,27 +123,44 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         process::exit(1);
     }
 
+    // Startup is complete: comm is bound and init has run
+    #[cfg(feature = "systemd")]
+    let notifier = systemd::Notifier::from_env().map(std::sync::Arc::new);
+    #[cfg(feature = "systemd")]
+    if let Some(notifier) = &notifier {
+        notifier.ready();
+        if let Some(interval) = systemd::watchdog_interval() {
+            info!(
+                interval_ms = interval.as_millis() as u64,
+                "systemd watchdog enabled"
+            );
+            tokio::spawn(notifier.clone().watchdog(interval));
+        }
+    }
+
     // Main loop with signal handling
     info!("Entering main loop...");
 
-    loop {
-        tokio::select! {
-            // Handle user requests
-            Some(req) = user_rx.recv() => {
-                agent.handle_user_request(req).await;
-            }
-            // Handle Ctrl+C / SIGTERM
-            _ = async {
-                signal::ctrl_c().await.ok();
-            } => {
-                info!("Received shutdown signal");
-                break;
-            }
-        }
+    // Ctrl+C / SIGTERM; requests in flight when it arrives get
+    // drain_timeout_secs to finish
+    let shutdown = async {
+        signal::ctrl_c().await.ok();
+        info!("Received shutdown signal");
+    };
+    agent.serve(&mut user_rx, shutdown).await;
+
+    // Requests still queued will not be handled
+    user_rx.close();
+    while let Ok(req) = user_rx.try_recv() {
+        agent.refuse_shutting_down(req);
     }
 
     // Shutdown handling
     info!("Starting shutdown...");
+    #[cfg(feature = "systemd")]
+    if let Some(notifier) = &notifier {
+        notifier.stopping();
+    }
     agent.shutdown().await;
 
     // Clean up
@@ -4,16 +4,20 @@ use std::path::PathBuf;
 
 /// Memory configuration
 #[derive(Debug, Clone)]
-#[allow(dead_code)]
 pub struct MemoryConfig {
     /// Storage directory
     pub storage_dir: PathBuf,
     /// Number of entries to retrieve
     pub top_k: usize,
     /// Maximum cognition rounds
+    #[allow(dead_code)]
     pub max_cognition_rounds: usize,
     /// Embedding model identifier
     pub embedding_model: String,
+    /// OpenAI-style embeddings endpoint (None = offline word hashing)
+    pub embedding_endpoint: Option<String>,
+    /// Bearer token for `embedding_endpoint`
+    pub embedding_api_key: Option<String>,
 }
 
 impl Default for MemoryConfig {
@@ -25,6 +29,29 @@ impl Default for MemoryConfig {
             top_k: 5,
             max_cognition_rounds: 3,
             embedding_model: "default".to_string(),
+            embedding_endpoint: None,
+            embedding_api_key: None,
+        }
+    }
+}
+
+impl MemoryConfig {
+    /// Load from environment variables, keeping the default for any unset
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+
+        Self {
+            storage_dir: var("MEMORY_DIR")
+                .map(PathBuf::from)
+                .unwrap_or(defaults.storage_dir),
+            top_k: var("MEMORY_TOP_K")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.top_k),
+            max_cognition_rounds: defaults.max_cognition_rounds,
+            embedding_model: var("MEMORY_EMBEDDING_MODEL").unwrap_or(defaults.embedding_model),
+            embedding_endpoint: var("MEMORY_EMBEDDING_ENDPOINT"),
+            embedding_api_key: var("MEMORY_EMBEDDING_API_KEY"),
         }
     }
 }
@@ -0,0 +1,177 @@
+// Embedding backends for semantic memory
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::config::MemoryConfig;
+use super::error::MemoryError;
+use async_trait::async_trait;
+use serde::Deserialize;
+
+/// Dimensions of `HashEmbedder` vectors
+pub const HASH_DIMENSIONS: usize = 256;
+/// Timeout for one embeddings request
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Turns text into a vector for similarity search
+#[async_trait]
+pub trait Embedder: Send + Sync + std::fmt::Debug {
+    async fn embed(&self, text: &str) -> Result<Vec<f32>, MemoryError>;
+}
+
+/// The embedder `config` asks for: HTTP when an endpoint is set, otherwise
+/// hashing
+pub fn from_config(config: &MemoryConfig) -> Result<Arc<dyn Embedder>, MemoryError> {
+    match &config.embedding_endpoint {
+        Some(endpoint) => Ok(Arc::new(HttpEmbedder::new(
+            endpoint,
+            &config.embedding_model,
+            config.embedding_api_key.clone(),
+        )?)),
+        None => Ok(Arc::new(HashEmbedder)),
+    }
+}
+
+/// Client for an OpenAI-style embeddings endpoint (`POST {"model", "input"}`
+/// answered with `{"data": [{"embedding": [...]}]}`)
+#[derive(Debug)]
+pub struct HttpEmbedder {
+    client: reqwest::Client,
+    endpoint: String,
+    model: String,
+    api_key: Option<String>,
+}
+
+impl HttpEmbedder {
+    pub fn new(endpoint: &str, model: &str, api_key: Option<String>) -> Result<Self, MemoryError> {
+        let client = reqwest::Client::builder()
+            .timeout(HTTP_TIMEOUT)
+            .build()
+            .map_err(|e| MemoryError::EmbeddingFailed(e.to_string()))?;
+        Ok(Self {
+            client,
+            endpoint: endpoint.to_string(),
+            model: model.to_string(),
+            api_key,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+    embedding: Vec<f32>,
+}
+
+/// The first vector of an embeddings response body
+fn parse_embeddings_response(body: &str) -> Result<Vec<f32>, MemoryError> {
+    let response: EmbeddingsResponse = serde_json::from_str(body)
+        .map_err(|e| MemoryError::EmbeddingFailed(format!("invalid response: {}", e)))?;
+    response
+        .data
+        .into_iter()
+        .next()
+        .map(|data| data.embedding)
+        .filter(|embedding| !embedding.is_empty())
+        .ok_or_else(|| MemoryError::EmbeddingFailed("response holds no embedding".to_string()))
+}
+
+#[async_trait]
+impl Embedder for HttpEmbedder {
+    async fn embed(&self, text: &str) -> Result<Vec<f32>, MemoryError> {
+        let mut request = self.client.post(&self.endpoint).json(&serde_json::json!({
+            "model": self.model,
+            "input": [text],
+        }));
+        if let Some(key) = &self.api_key {
+            request = request.bearer_auth(key);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| MemoryError::EmbeddingFailed(e.to_string()))?;
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| MemoryError::EmbeddingFailed(e.to_string()))?;
+        if !status.is_success() {
+            return Err(MemoryError::EmbeddingFailed(format!(
+                "{}: {}",
+                status, body
+            )));
+        }
+        parse_embeddings_response(&body)
+    }
+}
+
+/// Offline embedder hashing each word into one of `HASH_DIMENSIONS` buckets
+///
+/// Texts sharing words come out similar; there is no notion of meaning
+/// beyond that. Deterministic across runs and builds, so stored vectors stay
+/// comparable.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HashEmbedder;
+
+impl HashEmbedder {
+    /// 64-bit FNV-1a, stable unlike `DefaultHasher`
+    fn hash(word: &str) -> u64 {
+        word.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+}
+
+#[async_trait]
+impl Embedder for HashEmbedder {
+    async fn embed(&self, text: &str) -> Result<Vec<f32>, MemoryError> {
+        let mut vector = vec![0.0f32; HASH_DIMENSIONS];
+        for word in text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+        {
+            let hash = Self::hash(&word.to_lowercase());
+            // A hash-chosen sign keeps colliding words from always adding up
+            let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+            vector[hash as usize % HASH_DIMENSIONS] += sign;
+        }
+
+        let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|x| *x /= norm);
+        }
+        Ok(vector)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::similarity::cosine_similarity;
+
+    #[tokio::test]
+    async fn test_hash_embedder_similarity() {
+        let embed = |text: &'static str| async move { HashEmbedder.embed(text).await.unwrap() };
+
+        let redis = embed("deployed redis cluster").await;
+        assert_eq!(redis, embed("Deployed Redis cluster.").await);
+        assert!(cosine_similarity(&redis, &embed("set up redis cluster").await) > 0.5);
+        assert!(cosine_similarity(&redis, &embed("went to the grocery store").await) < 0.3);
+    }
+
+    #[test]
+    fn test_parse_embeddings_response() {
+        let body = r#"{"object":"list","data":[{"index":0,"embedding":[0.5,-0.25]}]}"#;
+        assert_eq!(parse_embeddings_response(body).unwrap(), [0.5, -0.25]);
+
+        assert!(matches!(
+            parse_embeddings_response(r#"{"data":[]}"#),
+            Err(MemoryError::EmbeddingFailed(_))
+        ));
+    }
+}
@@ -15,4 +15,7 @@ pub enum MemoryError {
 
     #[error("Failed to generate embedding: {0}")]
     EmbeddingFailed(String),
+
+    #[error("Failed to export journal: {0}")]
+    ExportFailed(String),
 }
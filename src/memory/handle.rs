@@ -0,0 +1,106 @@
+// Shared memory access that cannot span an await point
+
+use super::Memory;
+use super::error::MemoryError;
+use super::types::MemoryEntry;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Cloneable handle to the agent's `Memory`
+///
+/// The lock is only reachable through `read` and `write`, which run a
+/// synchronous closure and release the lock when it returns. Since a closure
+/// cannot `.await`, the lock can never be held across inference or tool
+/// execution, so concurrent requests never serialize on memory.
+#[derive(Clone)]
+pub struct MemoryHandle {
+    inner: Arc<Mutex<Memory>>,
+}
+
+impl MemoryHandle {
+    pub fn new(memory: Memory) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(memory)),
+        }
+    }
+
+    /// Run `f` with shared access to memory
+    pub fn read<R>(&self, f: impl FnOnce(&Memory) -> R) -> R {
+        let memory = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        f(&memory)
+    }
+
+    /// Run `f` with exclusive access to memory
+    pub fn write<R>(&self, f: impl FnOnce(&mut Memory) -> R) -> R {
+        let mut memory = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        f(&mut memory)
+    }
+
+    /// Embed `content` and store it as a semantic memory entry
+    ///
+    /// The embedding is computed before the lock is taken, so a slow
+    /// embeddings endpoint does not block other users of memory.
+    pub async fn store(&self, content: String) -> Result<(), MemoryError> {
+        let embedder = self.read(|m| m.embedder());
+        let embedding = embedder.embed(&content).await?;
+        self.write(|m| m.insert_embedded(MemoryEntry::new(content, embedding)))
+    }
+
+    /// The `top_k` semantic memory entries most similar to `query`, embedded
+    /// outside the lock
+    pub async fn recall(&self, query: &str, top_k: usize) -> Result<Vec<MemoryEntry>, MemoryError> {
+        let Some(embedder) = self.read(|m| (!m.entries().is_empty()).then(|| m.embedder())) else {
+            return Ok(Vec::new());
+        };
+        let embedding = embedder.embed(query).await?;
+        Ok(self.read(|m| m.recall_by_embedding(&embedding, top_k)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clones_share_memory() {
+        let handle = MemoryHandle::new(Memory::new("Shelly".to_string()));
+        let other = handle.clone();
+
+        other.write(|m| m.add_observation("disk is 90% full"));
+        let context = handle.read(|m| m.context());
+        assert!(context.contains("disk is 90% full"));
+    }
+
+    #[test]
+    fn test_usable_after_panic_in_closure() {
+        let handle = MemoryHandle::new(Memory::new("Shelly".to_string()));
+        let poisoner = handle.clone();
+        let _ = std::thread::spawn(move || {
+            poisoner.write(|_| panic!("boom"));
+        })
+        .join();
+
+        handle.write(|m| m.add_observation("still working"));
+        assert_eq!(handle.read(|m| m.journal_records().len()), 1);
+    }
+
+    #[tokio::test]
+    async fn test_store_embeds_and_recall_ranks_by_similarity() {
+        let handle = MemoryHandle::new(Memory::default());
+        assert!(handle.recall("anything", 5).await.unwrap().is_empty());
+
+        for content in [
+            "deployed a redis cluster on the cache nodes",
+            "nginx config reloaded after certificate renewal",
+            "disk cleanup freed 20G under /var/log",
+        ] {
+            handle.store(content.to_string()).await.unwrap();
+        }
+        assert!(handle.read(|m| m.entries().iter().all(|e| !e.embedding.is_empty())));
+
+        let recalled = handle.recall("is the redis cluster up?", 2).await.unwrap();
+        assert_eq!(recalled.len(), 2);
+        assert!(recalled[0].content.contains("redis"), "{:?}", recalled);
+        let recalled = handle.recall("what filled /var/log", 1).await.unwrap();
+        assert!(recalled[0].content.contains("/var/log"), "{:?}", recalled);
+    }
+}
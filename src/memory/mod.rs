@@ -2,9 +2,13 @@
 // See docs/mem-design.md for design details
 
 pub mod config;
+pub mod embedder;
 pub mod error;
+pub mod handle;
 pub mod similarity;
 pub mod storage;
 pub mod types;
+pub mod wal;
 
+pub use handle::MemoryHandle;
 pub use storage::Memory;
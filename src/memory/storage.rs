@@ -2,31 +2,47 @@
 
 use std::collections::VecDeque;
 use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
 
 use super::config::MemoryConfig;
+use super::embedder::{self, Embedder, HashEmbedder};
 use super::error::MemoryError;
 use super::similarity::cosine_similarity;
-use super::types::{JournalEntry, MemoryEntry};
-use tracing::{debug, info};
+use super::types::{CategoryFilter, JournalEntry, JournalRecord, MemoryEntry};
+use super::wal::JournalWal;
+use tracing::{debug, info, warn};
 
 /// Maximum number of journal entries to keep
 const MAX_JOURNAL_ENTRIES: usize = 100;
 
 /// Memory - stores agent's semantic memory and journal
-#[derive(Debug, Clone, Default)]
+#[derive(Debug)]
 pub struct Memory {
     /// Semantic memory entries
-    #[allow(dead_code)]
     entries: Vec<MemoryEntry>,
-    /// Journal entries (backward compatible)
-    journal: VecDeque<JournalEntry>,
+    /// Journal entries (backward compatible), oldest first
+    journal: VecDeque<JournalRecord>,
     /// Identity (static info about the agent)
     identity: String,
     /// Topology (known system structure)
     topology: Vec<String>,
     /// Configuration
-    #[allow(dead_code)]
     config: MemoryConfig,
+    /// Write-ahead log every journal record is appended to, if attached
+    wal: Option<JournalWal>,
+    /// Embeds stored entries and recall queries
+    embedder: Arc<dyn Embedder>,
+    /// Whether entries are written to `config.storage_dir`; only memory
+    /// opened with `open_store` persists
+    persistent: bool,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new(String::new())
+    }
 }
 
 impl Memory {
@@ -38,60 +54,58 @@ impl Memory {
             identity,
             topology: Vec::new(),
             config: MemoryConfig::default(),
+            wal: None,
+            embedder: Arc::new(HashEmbedder),
+            persistent: false,
         }
     }
 
-    /// Load memory from disk
-    #[allow(dead_code)]
-    pub fn load(config: MemoryConfig) -> Result<Self, MemoryError> {
+    /// Back semantic memory with `config.storage_dir`, loading the entries
+    /// stored there and embedding with the backend `config` names. Returns
+    /// the number of entries loaded.
+    pub fn open_store(&mut self, config: MemoryConfig) -> Result<usize, MemoryError> {
         let entries_file = config.storage_dir.join("entries.json");
+        let embedder = embedder::from_config(&config)?;
 
-        if !entries_file.exists() {
+        let entries: Vec<MemoryEntry> = if entries_file.exists() {
+            let content = fs::read_to_string(&entries_file)
+                .map_err(|e| MemoryError::LoadFailed(e.to_string()))?;
+            serde_json::from_str(&content).map_err(|e| MemoryError::LoadFailed(e.to_string()))?
+        } else {
             info!("Memory file not found, starting with empty memory");
-            return Ok(Self {
-                entries: Vec::new(),
-                journal: VecDeque::new(),
-                identity: String::new(),
-                topology: Vec::new(),
-                config,
-            });
-        }
-
-        let content = fs::read_to_string(&entries_file)
-            .map_err(|e| MemoryError::LoadFailed(e.to_string()))?;
-
-        let entries: Vec<MemoryEntry> =
-            serde_json::from_str(&content).map_err(|e| MemoryError::LoadFailed(e.to_string()))?;
+            Vec::new()
+        };
 
         info!("Loaded {} memory entries", entries.len());
+        let count = entries.len();
+        self.entries = entries;
+        self.config = config;
+        self.embedder = embedder;
+        self.persistent = true;
+        Ok(count)
+    }
 
-        Ok(Self {
-            entries,
-            journal: VecDeque::new(),
-            identity: String::new(),
-            topology: Vec::new(),
-            config,
-        })
+    /// The embedder, for computing embeddings without holding the memory
+    /// lock
+    pub fn embedder(&self) -> Arc<dyn Embedder> {
+        Arc::clone(&self.embedder)
     }
 
-    /// Store a memory entry
-    #[allow(dead_code)]
-    pub async fn store(&mut self, entry: MemoryEntry) -> Result<(), MemoryError> {
+    /// Store an entry whose embedding is already computed, persisting it
+    /// when memory was opened with `open_store`
+    pub fn insert_embedded(&mut self, entry: MemoryEntry) -> Result<(), MemoryError> {
+        self.entries.push(entry);
+        if !self.persistent {
+            return Ok(());
+        }
+
         // Ensure storage directory exists
         fs::create_dir_all(&self.config.storage_dir)
             .map_err(|e| MemoryError::StoreFailed(e.to_string()))?;
-
-        // Add entry
-        self.entries.push(entry);
-
-        // Persist to disk
-        self.persist()?;
-
-        Ok(())
+        self.persist()
     }
 
     /// Persist entries to disk
-    #[allow(dead_code)]
     fn persist(&self) -> Result<(), MemoryError> {
         let entries_file = self.config.storage_dir.join("entries.json");
 
@@ -105,9 +119,8 @@ impl Memory {
         Ok(())
     }
 
-    /// Recall relevant memories by semantic similarity
-    #[allow(dead_code)]
-    pub fn recall(&self, _query: &str, query_embedding: &[f32], top_k: usize) -> Vec<MemoryEntry> {
+    /// Recall the `top_k` memories most similar to an already embedded query
+    pub fn recall_by_embedding(&self, query_embedding: &[f32], top_k: usize) -> Vec<MemoryEntry> {
         if self.entries.is_empty() {
             return Vec::new();
         }
@@ -141,13 +154,11 @@ impl Memory {
     }
 
     /// Get configuration
-    #[allow(dead_code)]
     pub fn config(&self) -> &MemoryConfig {
         &self.config
     }
 
     /// Generate context string from recalled entries
-    #[allow(dead_code)]
     pub fn context_from_recall(&self, entries: &[MemoryEntry]) -> String {
         if entries.is_empty() {
             return String::new();
@@ -169,11 +180,49 @@ impl Memory {
 
     /// Add entry to journal
     pub fn add(&mut self, entry: JournalEntry) {
-        self.journal.push_back(entry);
+        self.push_record(JournalRecord::new(entry));
+    }
+
+    /// Append a record to the journal and the WAL, trimming both
+    fn push_record(&mut self, record: JournalRecord) {
+        if let Some(wal) = self.wal.as_mut()
+            && let Err(e) = wal.append(&record)
+        {
+            warn!(error = %e, "Journal record not written to WAL");
+        }
+
+        self.journal.push_back(record);
         // Trim if too large
         while self.journal.len() > MAX_JOURNAL_ENTRIES {
             self.journal.pop_front();
         }
+
+        // The WAL keeps trimmed records until it is twice the journal's size
+        if let Some(wal) = self.wal.as_mut()
+            && wal.records() > 2 * MAX_JOURNAL_ENTRIES
+            && let Err(e) = wal.compact(&self.journal)
+        {
+            warn!(error = %e, "Journal WAL compaction failed");
+        }
+    }
+
+    /// Persist the journal to a write-ahead log at `path`, first restoring
+    /// the records it already holds. Returns the number restored.
+    ///
+    /// Each record is flushed to the OS as it is added, and also synced to
+    /// disk when `fsync` is set.
+    pub fn attach_wal(&mut self, path: &Path, fsync: bool) -> Result<usize, MemoryError> {
+        let (wal, restored) = JournalWal::open(path, fsync, MAX_JOURNAL_ENTRIES)?;
+        let count = restored.len();
+        // Restored records predate anything added before attaching
+        let added = std::mem::replace(&mut self.journal, restored.into());
+        self.wal = Some(wal);
+        for record in added {
+            self.push_record(record);
+        }
+
+        info!(path = %path.display(), records = count, "Journal restored from WAL");
+        Ok(count)
     }
 
     /// Add system info
@@ -203,6 +252,30 @@ impl Memory {
         self.add(JournalEntry::Observation(observation.into()));
     }
 
+    /// Record a progress snapshot of a running tool call, replacing
+    /// `previous`, the snapshot that call recorded last, if it is still in the
+    /// journal, so a chatty command takes one entry rather than evicting history
+    ///
+    /// Snapshots are not written to the WAL; the call's result supersedes them.
+    pub fn replace_observation(&mut self, previous: Option<&str>, observation: impl Into<String>) {
+        let record = JournalRecord::new(JournalEntry::Observation(observation.into()));
+        let slot = previous.and_then(|previous| {
+            self.journal
+                .iter_mut()
+                .rev()
+                .find(|r| matches!(&r.entry, JournalEntry::Observation(text) if text == previous))
+        });
+        match slot {
+            Some(slot) => *slot = record,
+            None => {
+                self.journal.push_back(record);
+                while self.journal.len() > MAX_JOURNAL_ENTRIES {
+                    self.journal.pop_front();
+                }
+            }
+        }
+    }
+
     /// Add error
     pub fn add_error(&mut self, error: impl Into<String>) {
         self.add(JournalEntry::Error(error.into()));
@@ -234,7 +307,7 @@ impl Memory {
             let journal_str = recent
                 .iter()
                 .rev()
-                .map(|e| format!("- {}", e))
+                .map(|r| format!("- {}", r))
                 .collect::<Vec<_>>()
                 .join("\n");
             parts.push(format!("## Recent History\n{}", journal_str));
@@ -246,9 +319,88 @@ impl Memory {
     /// Get full journal for debugging
     #[allow(dead_code)]
     pub fn journal_entries(&self) -> Vec<&JournalEntry> {
+        self.journal.iter().map(|r| &r.entry).collect()
+    }
+
+    /// Get full journal with timestamps
+    #[allow(dead_code)]
+    pub fn journal_records(&self) -> Vec<&JournalRecord> {
         self.journal.iter().collect()
     }
 
+    /// Journal records passing `filter`, oldest first
+    pub fn entries_by_category(&self, filter: impl Into<CategoryFilter>) -> Vec<&JournalRecord> {
+        let filter = filter.into();
+        self.journal
+            .iter()
+            .filter(|r| filter.matches(&r.entry))
+            .collect()
+    }
+
+    /// User interactions still in the journal as (query, response), oldest
+    /// first; positions shift as old entries are trimmed
+    pub fn interactions(&self) -> Vec<(&str, &str)> {
+        self.journal
+            .iter()
+            .filter_map(|r| match &r.entry {
+                JournalEntry::UserInteraction { query, response } => {
+                    Some((query.as_str(), response.as_str()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The interaction at `index` in `interactions()`
+    pub fn interaction(&self, index: usize) -> Option<(&str, &str)> {
+        self.interactions().get(index).copied()
+    }
+
+    /// Index and contents of the most recent interaction whose query
+    /// contains `needle`
+    pub fn find_interaction(&self, needle: &str) -> Option<(usize, &str, &str)> {
+        self.interactions()
+            .into_iter()
+            .enumerate()
+            .rev()
+            .find(|(_, (query, _))| query.contains(needle))
+            .map(|(index, (query, response))| (index, query, response))
+    }
+
+    /// Write every journal record as one JSON line, oldest first.
+    /// Returns the number of records written.
+    pub fn export_jsonl<W: Write>(&self, mut writer: W) -> Result<usize, MemoryError> {
+        for record in &self.journal {
+            serde_json::to_writer(&mut writer, record)
+                .map_err(|e| MemoryError::ExportFailed(e.to_string()))?;
+            writer
+                .write_all(b"\n")
+                .map_err(|e| MemoryError::ExportFailed(e.to_string()))?;
+        }
+        writer
+            .flush()
+            .map_err(|e| MemoryError::ExportFailed(e.to_string()))?;
+
+        debug!("Exported {} journal records", self.journal.len());
+        Ok(self.journal.len())
+    }
+
+    /// Like `export_jsonl`, preceded by one header line holding identity,
+    /// topology and the record count. Returns the number of records written.
+    pub fn dump_jsonl<W: Write>(&self, mut writer: W) -> Result<usize, MemoryError> {
+        let header = serde_json::json!({
+            "identity": self.identity,
+            "topology": self.topology,
+            "records": self.journal.len(),
+        });
+        serde_json::to_writer(&mut writer, &header)
+            .map_err(|e| MemoryError::ExportFailed(e.to_string()))?;
+        writer
+            .write_all(b"\n")
+            .map_err(|e| MemoryError::ExportFailed(e.to_string()))?;
+        self.export_jsonl(writer)
+    }
+
     /// Set identity
     #[allow(dead_code)]
     pub fn set_identity(&mut self, identity: impl Into<String>) {
@@ -271,8 +423,56 @@ mod tests {
     #[test]
     fn test_memory_empty_recall() {
         let memory = Memory::default();
-        let results = memory.recall("query", &[0.1, 0.2, 0.3], 5);
-        assert!(results.is_empty());
+        assert!(memory.recall_by_embedding(&[0.1, 0.2, 0.3], 5).is_empty());
+    }
+
+    #[test]
+    fn test_open_store_reloads_inserted_entries() {
+        let storage_dir =
+            std::env::temp_dir().join(format!("shelly-memory-{}", uuid::Uuid::new_v4()));
+        let config = MemoryConfig {
+            storage_dir: storage_dir.clone(),
+            ..MemoryConfig::default()
+        };
+
+        let mut memory = Memory::default();
+        assert_eq!(memory.open_store(config.clone()).unwrap(), 0);
+        memory
+            .insert_embedded(MemoryEntry::new("redis is up".to_string(), vec![1.0]))
+            .unwrap();
+
+        let mut reopened = Memory::default();
+        assert_eq!(reopened.open_store(config).unwrap(), 1);
+        assert_eq!(reopened.entries()[0].content, "redis is up");
+
+        fs::remove_dir_all(storage_dir).unwrap();
+    }
+
+    #[test]
+    fn test_replace_observation_keeps_latest_snapshot() {
+        let mut memory = Memory::default();
+        memory.add_observation("before");
+        memory.replace_observation(None, "bash: 100 lines");
+        memory.add_tool_result("other", "ok");
+        memory.replace_observation(Some("bash: 100 lines"), "bash: 200 lines");
+
+        let entries: Vec<String> = memory
+            .journal_entries()
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                "[observation] before",
+                "[observation] bash: 200 lines",
+                "[tool: other] ok"
+            ]
+        );
+
+        // A snapshot no longer in the journal is added again
+        memory.replace_observation(Some("gone"), "bash: 300 lines");
+        assert_eq!(memory.journal_entries().len(), 4);
     }
 
     #[test]
@@ -303,6 +503,144 @@ mod tests {
         assert!(ctx.contains("network"));
     }
 
+    #[test]
+    fn test_journal_timestamps_in_context() {
+        let mut memory = Memory::new("TestAgent".to_string());
+        memory.add_observation("first");
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        memory.add_observation("second");
+
+        let records = memory.journal_records();
+        assert!(records[1].timestamp > records[0].timestamp);
+
+        let stamp = records[0].timestamp.format("%Y-%m-%d %H:%M:%S").to_string();
+        assert_eq!(
+            records[0].to_string(),
+            format!("[{}] [observation] first", stamp)
+        );
+        assert!(
+            memory
+                .context()
+                .contains(&format!("- [{}] [observation] first", stamp))
+        );
+    }
+
+    #[test]
+    fn test_export_jsonl_roundtrip() {
+        let mut memory = Memory::new("TestAgent".to_string());
+        memory.add_system_info("hostname: test");
+        memory.add_interaction("query", "multi\nline \"response\"");
+        memory.add_tool_result("bash", "output");
+        memory.add_observation("note");
+        memory.add_error("warning");
+
+        let mut buf = Vec::new();
+        let written = memory.export_jsonl(&mut buf).unwrap();
+        assert_eq!(written, 5);
+
+        let text = String::from_utf8(buf).unwrap();
+        let parsed: Vec<JournalRecord> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let expected: Vec<JournalRecord> = memory.journal_records().into_iter().cloned().collect();
+        assert_eq!(parsed, expected);
+        assert_eq!(
+            parsed[1].entry,
+            JournalEntry::UserInteraction {
+                query: "query".to_string(),
+                response: "multi\nline \"response\"".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_interaction_lookup() {
+        let mut memory = Memory::new("Test".to_string());
+        memory.add_interaction("check disk", "40% used");
+        memory.add_observation("nginx restarted");
+        memory.add_interaction("check memory", "2G free");
+        memory.add_interaction("check disk again", "41% used");
+
+        assert_eq!(memory.interactions().len(), 3);
+        assert_eq!(memory.interaction(1), Some(("check memory", "2G free")));
+        assert_eq!(memory.interaction(3), None);
+        // The most recent match wins
+        assert_eq!(
+            memory.find_interaction("disk"),
+            Some((2, "check disk again", "41% used"))
+        );
+        assert_eq!(memory.find_interaction("network"), None);
+    }
+
+    #[test]
+    fn test_entries_by_category() {
+        use crate::memory::types::JournalCategory;
+
+        let mut memory = Memory::new("Test".to_string());
+        memory.add_system_info("Linux 6.1");
+        memory.add_tool_result("bash", "df: 40% used");
+        memory.add_interaction("check disk", "40% used");
+        memory.add_error("ping timed out");
+        memory.add_tool_result("net_check", "8.8.8.8 unreachable");
+        memory.add_observation("network flapping");
+        memory.add_tool_result("bash", "free: 2G");
+
+        let tools: Vec<&str> = memory
+            .entries_by_category(JournalCategory::Tool)
+            .iter()
+            .map(|r| match &r.entry {
+                JournalEntry::ToolResult { result, .. } => result.as_str(),
+                other => panic!("not a tool result: {}", other),
+            })
+            .collect();
+        assert_eq!(tools, ["df: 40% used", "8.8.8.8 unreachable", "free: 2G"]);
+
+        let bash = memory.entries_by_category(CategoryFilter::Tool("bash".to_string()));
+        assert_eq!(bash.len(), 2);
+        assert!(
+            bash.iter()
+                .all(|r| r.entry.to_string().starts_with("[tool: bash]"))
+        );
+
+        let errors = memory.entries_by_category(JournalCategory::Error);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].entry,
+            JournalEntry::Error("ping timed out".into())
+        );
+        assert!(
+            memory
+                .entries_by_category(CategoryFilter::Tool("tail_file".to_string()))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_dump_jsonl_header() {
+        let mut memory = Memory::new("TestAgent".to_string());
+        memory.add_topology("db on 10.0.0.5");
+        memory.add_observation("note");
+
+        let mut buf = Vec::new();
+        assert_eq!(memory.dump_jsonl(&mut buf).unwrap(), 1);
+
+        let text = String::from_utf8(buf).unwrap();
+        let mut lines = text.lines();
+        let header: serde_json::Value = serde_json::from_str(lines.next().unwrap()).unwrap();
+        assert_eq!(
+            header,
+            serde_json::json!({
+                "identity": "TestAgent",
+                "topology": ["db on 10.0.0.5"],
+                "records": 1,
+            })
+        );
+        let record: JournalRecord = serde_json::from_str(lines.next().unwrap()).unwrap();
+        assert_eq!(record.entry, JournalEntry::Observation("note".to_string()));
+        assert!(lines.next().is_none());
+    }
+
     #[test]
     fn test_memory_store_and_recall() {
         let config = MemoryConfig {
@@ -323,7 +661,7 @@ mod tests {
         ));
 
         // Recall with query similar to entry1
-        let results = memory.recall("redis deployment", &[0.85, 0.15, 0.1], 5);
+        let results = memory.recall_by_embedding(&[0.85, 0.15, 0.1], 5);
         assert_eq!(results.len(), 2);
         // First result should be entry1 (more similar)
         assert!(results[0].content.contains("redis"));
@@ -30,7 +30,7 @@ impl MemoryEntry {
 }
 
 /// Journal entry types (backward compatible interface)
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum JournalEntry {
     /// System information (hostname, OS, etc.)
     SystemInfo(String),
@@ -59,3 +59,83 @@ impl std::fmt::Display for JournalEntry {
         }
     }
 }
+
+/// Kind of a journal entry, one per `JournalEntry` variant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalCategory {
+    System,
+    Interaction,
+    Tool,
+    Observation,
+    Error,
+}
+
+impl JournalEntry {
+    /// Category of the entry
+    pub fn category(&self) -> JournalCategory {
+        match self {
+            JournalEntry::SystemInfo(_) => JournalCategory::System,
+            JournalEntry::UserInteraction { .. } => JournalCategory::Interaction,
+            JournalEntry::ToolResult { .. } => JournalCategory::Tool,
+            JournalEntry::Observation(_) => JournalCategory::Observation,
+            JournalEntry::Error(_) => JournalCategory::Error,
+        }
+    }
+}
+
+/// Which journal entries `Memory::entries_by_category` returns
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CategoryFilter {
+    /// Every entry of one category
+    Category(JournalCategory),
+    /// Results of one tool only
+    Tool(String),
+}
+
+impl CategoryFilter {
+    /// Whether `entry` passes the filter
+    pub fn matches(&self, entry: &JournalEntry) -> bool {
+        match (self, entry) {
+            (CategoryFilter::Category(category), entry) => entry.category() == *category,
+            (CategoryFilter::Tool(name), JournalEntry::ToolResult { tool, .. }) => tool == name,
+            (CategoryFilter::Tool(_), _) => false,
+        }
+    }
+}
+
+impl From<JournalCategory> for CategoryFilter {
+    fn from(category: JournalCategory) -> Self {
+        CategoryFilter::Category(category)
+    }
+}
+
+/// Journal entry stamped with the time it was recorded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalRecord {
+    /// When the entry was added
+    pub timestamp: DateTime<Utc>,
+    /// The entry itself
+    pub entry: JournalEntry,
+}
+
+impl JournalRecord {
+    /// Stamp an entry with the current time
+    pub fn new(entry: JournalEntry) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            entry,
+        }
+    }
+}
+
+impl std::fmt::Display for JournalRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[{}] {}",
+            self.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            self.entry
+        )
+    }
+}
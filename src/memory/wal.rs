@@ -0,0 +1,221 @@
+// Journal write-ahead log
+// Each record is appended as it is added, so a crash loses at most the one
+// being written instead of everything since startup
+
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use super::error::MemoryError;
+use super::types::JournalRecord;
+use tracing::{debug, warn};
+
+/// Append-only JSON Lines file of journal records
+#[derive(Debug)]
+pub struct JournalWal {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    /// fsync after every record, not just flush to the OS
+    fsync: bool,
+    /// Records in the file, including ones since trimmed from memory
+    records: usize,
+}
+
+impl JournalWal {
+    /// Open the log at `path`, returning it with the last `keep` records it
+    /// already holds, oldest first
+    ///
+    /// Unreadable lines (normally a last line torn by a crash) are skipped,
+    /// and the file is rewritten with just the returned records so it does
+    /// not grow across restarts.
+    pub fn open(
+        path: &Path,
+        fsync: bool,
+        keep: usize,
+    ) -> Result<(Self, Vec<JournalRecord>), MemoryError> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                return Err(MemoryError::LoadFailed(format!(
+                    "{}: {}",
+                    path.display(),
+                    e
+                )));
+            }
+        };
+
+        let mut records = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            match serde_json::from_str::<JournalRecord>(line) {
+                Ok(record) => records.push(record),
+                Err(e) => warn!(
+                    path = %path.display(),
+                    line = i + 1,
+                    error = %e,
+                    "Skipping unreadable journal WAL line"
+                ),
+            }
+        }
+        let records = records.split_off(records.len().saturating_sub(keep));
+
+        let writer = rewrite(path, &records)
+            .map_err(|e| MemoryError::LoadFailed(format!("{}: {}", path.display(), e)))?;
+        debug!(path = %path.display(), records = records.len(), "Opened journal WAL");
+
+        Ok((
+            Self {
+                path: path.to_path_buf(),
+                writer,
+                fsync,
+                records: records.len(),
+            },
+            records,
+        ))
+    }
+
+    /// Append one record, flushed to the OS (and synced if configured)
+    /// before returning
+    pub fn append(&mut self, record: &JournalRecord) -> Result<(), MemoryError> {
+        let store_failed = |e: String| MemoryError::StoreFailed(format!("journal WAL: {}", e));
+
+        serde_json::to_writer(&mut self.writer, record).map_err(|e| store_failed(e.to_string()))?;
+        self.writer
+            .write_all(b"\n")
+            .and_then(|()| self.writer.flush())
+            .map_err(|e| store_failed(e.to_string()))?;
+        if self.fsync {
+            self.writer
+                .get_ref()
+                .sync_data()
+                .map_err(|e| store_failed(e.to_string()))?;
+        }
+
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Number of records in the file
+    pub fn records(&self) -> usize {
+        self.records
+    }
+
+    /// Replace the file's contents with `records`
+    pub fn compact<'a>(
+        &mut self,
+        records: impl IntoIterator<Item = &'a JournalRecord>,
+    ) -> Result<(), MemoryError> {
+        let records: Vec<_> = records.into_iter().cloned().collect();
+        self.writer = rewrite(&self.path, &records)
+            .map_err(|e| MemoryError::StoreFailed(format!("journal WAL: {}", e)))?;
+        self.records = records.len();
+        debug!(path = %self.path.display(), records = self.records, "Compacted journal WAL");
+        Ok(())
+    }
+}
+
+/// Write `records` to a temporary file renamed over `path`, so a crash
+/// leaves either the old or the new log, then reopen it for appending
+fn rewrite(path: &Path, records: &[JournalRecord]) -> std::io::Result<BufWriter<File>> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut partial = OsString::from(path.as_os_str());
+    partial.push(".tmp");
+    let mut writer = BufWriter::new(File::create(&partial)?);
+    for record in records {
+        serde_json::to_writer(&mut writer, record)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.into_inner()?.sync_all()?;
+    fs::rename(&partial, path)?;
+
+    let file = OpenOptions::new().append(true).open(path)?;
+    Ok(BufWriter::new(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+    use crate::memory::types::JournalEntry;
+
+    fn temp_wal() -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("shelly-wal-{}", uuid::Uuid::new_v4()))
+            .join("journal.wal")
+    }
+
+    #[test]
+    fn test_records_recovered_after_crash() {
+        let path = temp_wal();
+
+        let mut memory = Memory::new("Shelly".to_string());
+        assert_eq!(memory.attach_wal(&path, false).unwrap(), 0);
+        memory.add_interaction("check disk", "/ is 90% full");
+        memory.add_tool_result("bash", "df -h output");
+        memory.add_observation("disk filling up");
+        // Simulate a crash: no shutdown, no destructors
+        std::mem::forget(memory);
+        // ... that tore the write of a fourth record
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"timestamp":"2024-01-01T00:00:00Z","entry":{"Obser"#)
+            .unwrap();
+        drop(file);
+
+        let mut recovered = Memory::new("Shelly".to_string());
+        assert_eq!(recovered.attach_wal(&path, true).unwrap(), 3);
+        assert_eq!(
+            recovered.journal_entries(),
+            [
+                &JournalEntry::UserInteraction {
+                    query: "check disk".to_string(),
+                    response: "/ is 90% full".to_string(),
+                },
+                &JournalEntry::ToolResult {
+                    tool: "bash".to_string(),
+                    result: "df -h output".to_string(),
+                },
+                &JournalEntry::Observation("disk filling up".to_string()),
+            ]
+        );
+
+        // The torn line was dropped, so new records stay readable
+        recovered.add_error("tool timed out");
+        drop(recovered);
+        let mut again = Memory::new("Shelly".to_string());
+        assert_eq!(again.attach_wal(&path, false).unwrap(), 4);
+        assert_eq!(
+            again.journal_entries().last(),
+            Some(&&JournalEntry::Error("tool timed out".to_string()))
+        );
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_wal_compacted_to_journal_size() {
+        let path = temp_wal();
+
+        let mut memory = Memory::new("Shelly".to_string());
+        memory.attach_wal(&path, false).unwrap();
+        for i in 0..500 {
+            memory.add_observation(format!("observation {}", i));
+        }
+        drop(memory);
+
+        let lines = fs::read_to_string(&path).unwrap().lines().count();
+        assert!(lines <= 200, "WAL holds {} lines", lines);
+
+        let mut recovered = Memory::new("Shelly".to_string());
+        assert_eq!(recovered.attach_wal(&path, false).unwrap(), 100);
+        assert_eq!(
+            recovered.journal_entries()[0],
+            &JournalEntry::Observation("observation 400".to_string())
+        );
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,166 @@
+// systemd notify protocol (sd_notify) for Type=notify services
+// See docs/mainloop-design.md for when each state is sent
+
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// Sends state updates to the socket named by `NOTIFY_SOCKET`
+pub struct Notifier {
+    socket: UnixDatagram,
+}
+
+impl Notifier {
+    /// Connect to systemd's notify socket; None when not run under systemd
+    pub fn from_env() -> Option<Self> {
+        let path = std::env::var("NOTIFY_SOCKET").ok()?;
+        match Self::connect(&path) {
+            Ok(notifier) => {
+                info!(socket = %path, "systemd notify socket connected");
+                Some(notifier)
+            }
+            Err(e) => {
+                warn!(socket = %path, error = %e, "cannot connect to systemd notify socket");
+                None
+            }
+        }
+    }
+
+    /// Connect to a notify socket path; a leading `@` names an abstract socket
+    pub fn connect(path: &str) -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        match path.strip_prefix('@') {
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+                socket.connect_addr(&addr)?;
+            }
+            None => socket.connect(path)?,
+        }
+        Ok(Self { socket })
+    }
+
+    /// Send a raw state string such as `READY=1`
+    pub fn notify(&self, state: &str) -> io::Result<()> {
+        debug!(state = %state, "sd_notify");
+        self.socket.send(state.as_bytes()).map(|_| ())
+    }
+
+    /// Tell systemd startup is complete
+    pub fn ready(&self) {
+        if let Err(e) = self.notify("READY=1") {
+            warn!(error = %e, "failed to notify systemd of readiness");
+        }
+    }
+
+    /// Tell systemd shutdown has begun
+    pub fn stopping(&self) {
+        if let Err(e) = self.notify("STOPPING=1") {
+            warn!(error = %e, "failed to notify systemd of shutdown");
+        }
+    }
+
+    /// Send `WATCHDOG=1` every `interval`, forever
+    pub async fn watchdog(self: std::sync::Arc<Self>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.notify("WATCHDOG=1") {
+                warn!(error = %e, "failed to send watchdog ping");
+            }
+        }
+    }
+}
+
+/// Ping interval derived from `WATCHDOG_USEC`/`WATCHDOG_PID`, if the
+/// watchdog is enabled for this process
+pub fn watchdog_interval() -> Option<Duration> {
+    parse_watchdog(
+        std::env::var("WATCHDOG_USEC").ok().as_deref(),
+        std::env::var("WATCHDOG_PID").ok().as_deref(),
+        std::process::id(),
+    )
+}
+
+/// Ping at half the timeout, as sd_watchdog_enabled(3) recommends
+fn parse_watchdog(usec: Option<&str>, pid: Option<&str>, own_pid: u32) -> Option<Duration> {
+    // The watchdog may be meant for another process in the service
+    if let Some(pid) = pid
+        && pid.trim().parse::<u32>().ok()? != own_pid
+    {
+        return None;
+    }
+
+    let usec: u64 = usec?.trim().parse().ok()?;
+    if usec == 0 {
+        return None;
+    }
+    Some(Duration::from_micros(usec / 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// Fake systemd: a datagram socket bound at a fresh temp path
+    fn fake_notify_socket() -> (UnixDatagram, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!("shelly-notify-{}", uuid::Uuid::new_v4()));
+        let socket = UnixDatagram::bind(&path).unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        (socket, path)
+    }
+
+    fn recv(socket: &UnixDatagram) -> String {
+        let mut buf = [0u8; 256];
+        let n = socket.recv(&mut buf).unwrap();
+        String::from_utf8_lossy(&buf[..n]).into_owned()
+    }
+
+    #[test]
+    fn test_ready_and_stopping_sent() {
+        let (systemd, path) = fake_notify_socket();
+        let notifier = Notifier::connect(path.to_str().unwrap()).unwrap();
+
+        notifier.ready();
+        notifier.stopping();
+        assert_eq!(recv(&systemd), "READY=1");
+        assert_eq!(recv(&systemd), "STOPPING=1");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_watchdog_pings() {
+        let (systemd, path) = fake_notify_socket();
+        let notifier = Arc::new(Notifier::connect(path.to_str().unwrap()).unwrap());
+
+        let pinger = tokio::spawn(notifier.watchdog(Duration::from_millis(10)));
+        let pings = tokio::task::spawn_blocking(move || [recv(&systemd), recv(&systemd)])
+            .await
+            .unwrap();
+        pinger.abort();
+
+        assert_eq!(pings, ["WATCHDOG=1", "WATCHDOG=1"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_parse_watchdog() {
+        assert_eq!(
+            parse_watchdog(Some("30000000"), None, 42),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(
+            parse_watchdog(Some("30000000"), Some("42"), 42),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(parse_watchdog(Some("30000000"), Some("7"), 42), None);
+        assert_eq!(parse_watchdog(None, None, 42), None);
+        assert_eq!(parse_watchdog(Some("0"), None, 42), None);
+        assert_eq!(parse_watchdog(Some("soon"), None, 42), None);
+    }
+}
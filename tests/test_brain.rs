@@ -1,4 +1,5 @@
 #[path = "../src/brain/mod.rs"]
+#[allow(dead_code, unused_imports)]
 mod brain;
 
 // Import from the brain module
@@ -2,6 +2,7 @@
 // This file should be run with cargo test --test test_comm
 
 #[path = "../src/comm/mod.rs"]
+#[allow(dead_code, unused_imports)]
 mod comm;
 
 fn init_tracing() {
@@ -21,8 +22,17 @@ enum MsgType {
     Request = 0x01,
     RequestAck = 0x02,
     Response = 0x03,
+    Replay = 0x06,
+    Approve = 0x07,
+    Hello = 0x08,
+    Control = 0x09,
+    InitReport = 0x0B,
+    Progress = 0x0C,
 }
 
+// Set on the type byte of a RESPONSE replayed from the dedup cache
+const CACHED_FLAG: u8 = 0x80;
+
 // Test helper: encode a request packet
 fn encode_request(seq: u32, content: &str) -> Vec<u8> {
     use rmp_serde::encode::Serializer;
@@ -44,6 +54,74 @@ fn encode_request(seq: u32, content: &str) -> Vec<u8> {
     packet
 }
 
+// Test helper: encode a request packet carrying a client request_id
+fn encode_request_with_id(seq: u32, content: &str, request_id: &str) -> Vec<u8> {
+    use rmp_serde::encode::Serializer;
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct RequestPayload<'a> {
+        content: &'a str,
+        request_id: Option<&'a str>,
+    }
+
+    let payload = RequestPayload {
+        content,
+        request_id: Some(request_id),
+    };
+    let mut payload_bytes = Vec::new();
+    let mut ser = Serializer::new(&mut payload_bytes);
+    payload.serialize(&mut ser).unwrap();
+
+    let mut packet = vec![MsgType::Request as u8];
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend_from_slice(&payload_bytes);
+    packet
+}
+
+// Test helper: encode a control packet
+fn encode_control(seq: u32, command: &str, token: &str) -> Vec<u8> {
+    use rmp_serde::encode::Serializer;
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct ControlPayload<'a> {
+        command: &'a str,
+        token: &'a str,
+    }
+
+    let mut packet = vec![MsgType::Control as u8];
+    packet.extend_from_slice(&seq.to_be_bytes());
+    let mut ser = Serializer::new(&mut packet);
+    ControlPayload { command, token }
+        .serialize(&mut ser)
+        .unwrap();
+    packet
+}
+
+// First byte of a length-prefixed (protocol version 2) packet
+const VERSION_BYTE: u8 = 0x40 | 2;
+
+// Test helper: convert a legacy packet to length-prefixed framing
+fn length_prefixed(legacy: &[u8]) -> Vec<u8> {
+    let payload = &legacy[5..];
+    let mut packet = vec![VERSION_BYTE];
+    packet.extend_from_slice(&legacy[..5]);
+    packet.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    packet.extend_from_slice(payload);
+    packet
+}
+
+// Test helper: append the HMAC-SHA256 tag a secret-protected server expects
+fn sign(mut packet: Vec<u8>, secret: &str) -> Vec<u8> {
+    use hmac::{Hmac, Mac};
+
+    let mut mac = Hmac::<sha2::Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(&packet);
+    packet.extend_from_slice(&mac.finalize().into_bytes());
+    packet
+}
+
 // Test helper: decode response payload
 fn decode_response(data: &[u8]) -> (u32, String, bool) {
     use rmp_serde::decode::Deserializer;
@@ -76,10 +154,7 @@ mod integration_tests {
         let config = comm::CommConfig {
             listen_addr: "127.0.0.1".to_string(),
             listen_port: 0,
-            max_payload_bytes: 65536,
-            dedup_capacity: 256,
-            dedup_ttl_secs: 300,
-            recv_buffer_size: 65536,
+            ..Default::default()
         };
 
         let (comm, mut loop_rx) = comm::Comm::new(config).await.unwrap();
@@ -109,7 +184,7 @@ mod integration_tests {
 
         // Should receive ACK
         let mut buf = [0u8; 1024];
-        let (len, _) = tokio::time::timeout(Duration::from_secs(1), client.recv_from(&mut buf))
+        let (_len, _) = tokio::time::timeout(Duration::from_secs(1), client.recv_from(&mut buf))
             .await
             .unwrap()
             .unwrap();
@@ -139,10 +214,7 @@ mod integration_tests {
         let config = comm::CommConfig {
             listen_addr: "127.0.0.1".to_string(),
             listen_port: 0,
-            max_payload_bytes: 65536,
-            dedup_capacity: 256,
-            dedup_ttl_secs: 300,
-            recv_buffer_size: 65536,
+            ..Default::default()
         };
 
         let (comm, mut loop_rx) = comm::Comm::new(config).await.unwrap();
@@ -176,14 +248,14 @@ mod integration_tests {
 
         // Get ACK for first
         let mut buf = [0u8; 1024];
-        let (len, _) = tokio::time::timeout(Duration::from_secs(1), client.recv_from(&mut buf))
+        let (_len, _) = tokio::time::timeout(Duration::from_secs(1), client.recv_from(&mut buf))
             .await
             .unwrap()
             .unwrap();
         assert_eq!(buf[0], MsgType::RequestAck as u8);
 
         // Wait for response (from first request)
-        let (len, _) = tokio::time::timeout(Duration::from_secs(1), client.recv_from(&mut buf))
+        let (_len, _) = tokio::time::timeout(Duration::from_secs(1), client.recv_from(&mut buf))
             .await
             .unwrap()
             .unwrap();
@@ -194,12 +266,12 @@ mod integration_tests {
         client.send(&packet).await.unwrap();
 
         // Should get cached response
-        let (len, _) = tokio::time::timeout(Duration::from_secs(1), client.recv_from(&mut buf))
+        let (_len, _) = tokio::time::timeout(Duration::from_secs(1), client.recv_from(&mut buf))
             .await
             .unwrap()
             .unwrap();
         // Should be Response (cached), not RequestAck
-        assert_eq!(buf[0], MsgType::Response as u8);
+        assert_eq!(buf[0], MsgType::Response as u8 | CACHED_FLAG);
 
         // Count received messages in main loop
         let mut received = Vec::new();
@@ -213,84 +285,1332 @@ mod integration_tests {
         assert_eq!(received.len(), 1, "Expected 1 request, got {:?}", received);
     }
 
-    // T-EDGE-01: Empty packet - should be rejected
+    // Dedup stays per client when many clients share the sharded table
     #[tokio::test]
-    async fn test_empty_packet() {
+    async fn test_dedup_many_clients_sharded() {
         init_tracing();
 
         let config = comm::CommConfig {
             listen_addr: "127.0.0.1".to_string(),
             listen_port: 0,
-            max_payload_bytes: 65536,
-            dedup_capacity: 256,
-            dedup_ttl_secs: 300,
-            recv_buffer_size: 65536,
+            dedup_shards: 4,
+            ..Default::default()
         };
-        let (comm, _rx) = comm::Comm::new(config).await.unwrap();
+        let (comm, mut loop_rx) = comm::Comm::new(config).await.unwrap();
         let comm_addr = comm.local_addr().unwrap();
 
         tokio::spawn(async move {
             let _ = comm.run().await;
         });
 
+        let (req_tx, mut req_rx) = tokio::sync::mpsc::channel::<String>(64);
+        tokio::spawn(async move {
+            while let Some(req) = loop_rx.recv().await {
+                let content = req.content.clone();
+                let _ = req_tx.send(content.clone()).await;
+                let _ = req
+                    .reply
+                    .send(comm::UserResponse::new(format!("echo: {}", content)));
+            }
+        });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // Every client uses seq=1, so only the address tells them apart
+        let mut clients = Vec::new();
+        for i in 0..16 {
+            let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            client.connect(comm_addr).await.unwrap();
+            let packet = encode_request(1, &format!("client-{}", i));
+            let mut buf = [0u8; 1024];
+
+            client.send(&packet).await.unwrap();
+            for expected in [MsgType::RequestAck, MsgType::Response] {
+                let len = tokio::time::timeout(Duration::from_secs(1), client.recv(&mut buf))
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(buf[0], expected as u8);
+                if matches!(expected, MsgType::Response) {
+                    let (_, reply, _) = decode_response(&buf[..len]);
+                    assert_eq!(reply, format!("echo: client-{}", i));
+                }
+            }
+            clients.push((client, packet));
+        }
+
+        // Each retry is answered from the cache, with that client's reply
+        for (i, (client, packet)) in clients.iter().enumerate() {
+            client.send(packet).await.unwrap();
+            let mut buf = [0u8; 1024];
+            let len = tokio::time::timeout(Duration::from_secs(1), client.recv(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(buf[0], MsgType::Response as u8 | CACHED_FLAG);
+            let (_, reply, _) = decode_response(&buf[..len]);
+            assert_eq!(reply, format!("echo: client-{}", i));
+        }
+
+        let mut received = 0;
+        while let Ok(Some(_)) =
+            tokio::time::timeout(Duration::from_millis(100), req_rx.recv()).await
+        {
+            received += 1;
+        }
+        assert_eq!(received, 16);
+    }
+
+    // Requests reusing seq=1 (e.g. from a restarted CLI) are distinct when their request_ids differ
+    #[tokio::test]
+    async fn test_request_id_distinguishes_same_seq() {
+        init_tracing();
+
+        let config = comm::CommConfig {
+            listen_addr: "127.0.0.1".to_string(),
+            listen_port: 0,
+            ..Default::default()
+        };
+
+        let (comm, mut loop_rx) = comm::Comm::new(config).await.unwrap();
+        let comm_addr = comm.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let _ = comm.run().await;
+        });
+
+        // Mock main loop echoes the request content
+        tokio::spawn(async move {
+            while let Some(req) = loop_rx.recv().await {
+                let reply = format!("echo: {}", req.content);
+                let _ = req.reply.send(comm::UserResponse::new(reply));
+            }
+        });
+
         tokio::time::sleep(Duration::from_millis(50)).await;
 
-        // Send empty packet
         let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
-        let _ = client.send_to(&[], comm_addr).await;
+        client.connect(comm_addr).await.unwrap();
+        let mut buf = [0u8; 1024];
 
-        // Should not crash - server continues
-        tokio::time::sleep(Duration::from_millis(100)).await;
+        for (content, request_id) in [("first", "id-a"), ("second", "id-b")] {
+            let packet = encode_request_with_id(1, content, request_id);
+            client.send(&packet).await.unwrap();
+
+            let (_len, _) =
+                tokio::time::timeout(Duration::from_secs(1), client.recv_from(&mut buf))
+                    .await
+                    .unwrap()
+                    .unwrap();
+            assert_eq!(buf[0], MsgType::RequestAck as u8);
+
+            let (len, _) = tokio::time::timeout(Duration::from_secs(1), client.recv_from(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(buf[0], MsgType::Response as u8);
+            let (seq, reply, is_error) = decode_response(&buf[..len]);
+            assert_eq!(seq, 1);
+            assert_eq!(reply, format!("echo: {}", content));
+            assert!(!is_error);
+        }
+
+        // Retrying the first request_id still hits the cache
+        let packet = encode_request_with_id(1, "first", "id-a");
+        client.send(&packet).await.unwrap();
+        let (len, _) = tokio::time::timeout(Duration::from_secs(1), client.recv_from(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(buf[0], MsgType::Response as u8 | CACHED_FLAG);
+        let (_, reply, _) = decode_response(&buf[..len]);
+        assert_eq!(reply, "echo: first");
     }
 
-    // T-EDGE-04: Invalid REQUEST_ACK from client - should be ignored
+    // Only a RESPONSE replayed from the dedup cache carries the cached flag
     #[tokio::test]
-    async fn test_invalid_request_ack_from_client() {
+    async fn test_replayed_response_flagged_cached() {
         init_tracing();
 
         let config = comm::CommConfig {
             listen_addr: "127.0.0.1".to_string(),
             listen_port: 0,
-            max_payload_bytes: 65536,
-            dedup_capacity: 256,
-            dedup_ttl_secs: 300,
-            recv_buffer_size: 65536,
+            ..Default::default()
         };
-        let (comm, _rx) = comm::Comm::new(config).await.unwrap();
+        let (comm, mut loop_rx) = comm::Comm::new(config).await.unwrap();
         let comm_addr = comm.local_addr().unwrap();
 
         tokio::spawn(async move {
             let _ = comm.run().await;
         });
 
+        tokio::spawn(async move {
+            while let Some(req) = loop_rx.recv().await {
+                let reply = format!("echo: {}", req.content);
+                let _ = req.reply.send(comm::UserResponse::new(reply));
+            }
+        });
+
         tokio::time::sleep(Duration::from_millis(50)).await;
 
-        // Send REQUEST_ACK (should be ignored - server->client only)
         let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
-        let mut packet = vec![MsgType::RequestAck as u8];
-        packet.extend_from_slice(&1u32.to_be_bytes());
-        let _ = client.send_to(&packet, comm_addr).await;
+        client.connect(comm_addr).await.unwrap();
+        let mut buf = [0u8; 1024];
+        let mut recv = async || {
+            let len = tokio::time::timeout(Duration::from_secs(1), client.recv(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            buf[..len].to_vec()
+        };
 
-        // Should not crash - server continues
-        tokio::time::sleep(Duration::from_millis(100)).await;
+        let packet = encode_request_with_id(4, "uptime", "id-u");
+        client.send(&packet).await.unwrap();
+        assert_eq!(recv().await[0], MsgType::RequestAck as u8);
+        let fresh = recv().await;
+        assert_eq!(fresh[0], MsgType::Response as u8);
+
+        // The retry arrives with a new seq but the same request_id
+        let packet = encode_request_with_id(5, "uptime", "id-u");
+        client.send(&packet).await.unwrap();
+        let replayed = recv().await;
+        assert_eq!(replayed[0], MsgType::Response as u8 | CACHED_FLAG);
+        let (_, content, is_error) = decode_response(&fresh);
+        assert_eq!(decode_response(&replayed), (5, content, is_error));
     }
 
-    // T-EDGE-10: Daemon not running - client should timeout
+    // An oversized payload is answered with an error RESPONSE, not dropped
     #[tokio::test]
-    async fn test_client_timeout_no_daemon() {
+    async fn test_oversized_payload_answered() {
         init_tracing();
 
-        // Try to connect to port where nothing is running
+        let config = comm::CommConfig {
+            listen_addr: "127.0.0.1".to_string(),
+            listen_port: 0,
+            max_payload_bytes: 64,
+            ..Default::default()
+        };
+        let (comm, mut loop_rx) = comm::Comm::new(config).await.unwrap();
+        let comm_addr = comm.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let _ = comm.run().await;
+        });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
         let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
-        client.connect("127.0.0.1:19999").await.unwrap();
+        client.connect(comm_addr).await.unwrap();
+        client
+            .send(&encode_request(7, &"x".repeat(200)))
+            .await
+            .unwrap();
 
-        let packet = encode_request(1, "test");
-        let _ = client.send(&packet).await;
+        let mut buf = [0u8; 1024];
+        let len = tokio::time::timeout(Duration::from_secs(1), client.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(buf[0], MsgType::Response as u8);
+        let (seq, content, is_error) = decode_response(&buf[..len]);
+        assert_eq!(seq, 7);
+        assert!(is_error);
+        assert!(content.contains("max 64 bytes"), "{}", content);
 
-        // Receiving should timeout
+        // Nothing reached the main loop
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), loop_rx.recv())
+                .await
+                .is_err()
+        );
+    }
+
+    // A length-prefixed request is answered in the same framing; one whose
+    // length prefix disagrees with the datagram is dropped
+    #[tokio::test]
+    async fn test_length_prefixed_request() {
+        init_tracing();
+
+        let config = comm::CommConfig {
+            listen_addr: "127.0.0.1".to_string(),
+            listen_port: 0,
+            ..Default::default()
+        };
+        let (comm, mut loop_rx) = comm::Comm::new(config).await.unwrap();
+        let comm_addr = comm.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let _ = comm.run().await;
+        });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect(comm_addr).await.unwrap();
         let mut buf = [0u8; 1024];
-        let result =
-            tokio::time::timeout(Duration::from_millis(100), client.recv_from(&mut buf)).await;
-        assert!(result.is_err()); // Timeout
+
+        let packet = length_prefixed(&encode_request(3, "uptime"));
+        client.send(&packet[..packet.len() - 2]).await.unwrap();
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), client.recv(&mut buf))
+                .await
+                .is_err()
+        );
+
+        client.send(&packet).await.unwrap();
+        let len = tokio::time::timeout(Duration::from_secs(1), client.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            &buf[..len],
+            [
+                VERSION_BYTE,
+                MsgType::RequestAck as u8,
+                0,
+                0,
+                0,
+                3,
+                0,
+                0,
+                0,
+                0
+            ]
+        );
+
+        let req = loop_rx.recv().await.unwrap();
+        assert_eq!(req.content, "uptime");
+        req.reply
+            .send(comm::UserResponse::new("up 3 days".to_string()))
+            .ok();
+
+        let len = tokio::time::timeout(Duration::from_secs(1), client.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(buf[0], VERSION_BYTE);
+        assert_eq!(buf[1], MsgType::Response as u8);
+        let declared = u32::from_be_bytes([buf[6], buf[7], buf[8], buf[9]]) as usize;
+        assert_eq!(declared, len - 10);
+        // Same as the legacy reply once the version byte and length are dropped
+        let legacy: Vec<u8> = buf[1..6].iter().chain(&buf[10..len]).copied().collect();
+        assert_eq!(
+            decode_response(&legacy),
+            (3, "up 3 days".to_string(), false)
+        );
+
+        // Nothing else reached the main loop
+        assert!(loop_rx.try_recv().is_err());
+    }
+
+    // HELLO reports protocol version, features and the configured payload limit
+    #[tokio::test]
+    async fn test_hello_reports_capabilities() {
+        init_tracing();
+
+        let config = comm::CommConfig {
+            listen_addr: "127.0.0.1".to_string(),
+            listen_port: 0,
+            max_payload_bytes: 4096,
+            ..Default::default()
+        };
+        let (comm, _rx) = comm::Comm::new(config).await.unwrap();
+        let comm_addr = comm.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let _ = comm.run().await;
+        });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut packet = vec![MsgType::Hello as u8];
+        packet.extend_from_slice(&9u32.to_be_bytes());
+        client.send_to(&packet, comm_addr).await.unwrap();
+
+        let mut buf = [0u8; 1024];
+        let (len, _) = tokio::time::timeout(Duration::from_secs(1), client.recv_from(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(buf[0], MsgType::Hello as u8);
+        assert_eq!(u32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]]), 9);
+
+        let hello = comm::protocol::decode_hello_response(&buf[5..len]).unwrap();
+        assert_eq!(hello.protocol_version, 2);
+        assert_eq!(hello.max_payload_bytes, 4096);
+        assert_eq!(
+            hello.features,
+            [
+                "request",
+                "hello",
+                "control",
+                "init_report",
+                "approve",
+                "replay",
+                "request_id",
+                "cached_flag",
+                "length_prefix",
+                "no_tools"
+            ]
+        );
+    }
+
+    // APPROVE is ACKed and forwarded as an approval of the given plan
+    #[tokio::test]
+    async fn test_approve_forwarded() {
+        use rmp_serde::encode::Serializer;
+        use serde::Serialize;
+
+        init_tracing();
+
+        let config = comm::CommConfig {
+            listen_addr: "127.0.0.1".to_string(),
+            listen_port: 0,
+            ..Default::default()
+        };
+        let (comm, mut loop_rx) = comm::Comm::new(config).await.unwrap();
+        let comm_addr = comm.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let _ = comm.run().await;
+        });
+
+        let (kind_tx, mut kind_rx) = tokio::sync::mpsc::channel(1);
+        tokio::spawn(async move {
+            while let Some(req) = loop_rx.recv().await {
+                let _ = kind_tx.send(req.kind).await;
+                let _ = req.reply.send(comm::UserResponse::new("done".to_string()));
+            }
+        });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        #[derive(Serialize)]
+        struct ApprovePayload<'a> {
+            plan_id: &'a str,
+        }
+        let mut packet = vec![MsgType::Approve as u8];
+        packet.extend_from_slice(&3u32.to_be_bytes());
+        ApprovePayload { plan_id: "plan-1" }
+            .serialize(&mut Serializer::new(&mut packet))
+            .unwrap();
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.send_to(&packet, comm_addr).await.unwrap();
+
+        let mut buf = [0u8; 1024];
+        for expected in [MsgType::RequestAck, MsgType::Response] {
+            let (len, _) = tokio::time::timeout(Duration::from_secs(1), client.recv_from(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(buf[0], expected as u8);
+            if matches!(expected, MsgType::Response) {
+                assert_eq!(decode_response(&buf[..len]), (3, "done".to_string(), false));
+            }
+        }
+        assert_eq!(
+            kind_rx.recv().await.unwrap(),
+            comm::types::RequestKind::Approve("plan-1".to_string())
+        );
+    }
+
+    // REPLAY is forwarded with its target; one without index or query is refused
+    #[tokio::test]
+    async fn test_replay_forwarded() {
+        use rmp_serde::encode::Serializer;
+        use serde::Serialize;
+
+        init_tracing();
+
+        let config = comm::CommConfig {
+            listen_addr: "127.0.0.1".to_string(),
+            listen_port: 0,
+            ..Default::default()
+        };
+        let (comm, mut loop_rx) = comm::Comm::new(config).await.unwrap();
+        let comm_addr = comm.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let _ = comm.run().await;
+        });
+
+        let (kind_tx, mut kind_rx) = tokio::sync::mpsc::channel(4);
+        tokio::spawn(async move {
+            while let Some(req) = loop_rx.recv().await {
+                let _ = kind_tx.send(req.kind).await;
+                let _ = req.reply.send(comm::UserResponse::new("done".to_string()));
+            }
+        });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        #[derive(Serialize)]
+        struct ReplayPayload<'a> {
+            index: Option<u32>,
+            query: Option<&'a str>,
+        }
+        let replay = |seq: u32, index: Option<u32>, query: Option<&str>| {
+            let mut packet = vec![MsgType::Replay as u8];
+            packet.extend_from_slice(&seq.to_be_bytes());
+            ReplayPayload { index, query }
+                .serialize(&mut Serializer::new(&mut packet))
+                .unwrap();
+            packet
+        };
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect(comm_addr).await.unwrap();
+        let mut buf = [0u8; 1024];
+
+        for (seq, index, query, expected) in [
+            (1, Some(2), None, comm::types::ReplayTarget::Index(2)),
+            (
+                2,
+                None,
+                Some("disk"),
+                comm::types::ReplayTarget::Query("disk".to_string()),
+            ),
+        ] {
+            client.send(&replay(seq, index, query)).await.unwrap();
+            for msg_type in [MsgType::RequestAck, MsgType::Response] {
+                tokio::time::timeout(Duration::from_secs(1), client.recv(&mut buf))
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(buf[0], msg_type as u8);
+            }
+            assert_eq!(
+                kind_rx.recv().await.unwrap(),
+                comm::types::RequestKind::Replay(expected)
+            );
+        }
+
+        client.send(&replay(3, None, None)).await.unwrap();
+        let len = tokio::time::timeout(Duration::from_secs(1), client.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        let (seq, content, is_error) = decode_response(&buf[..len]);
+        assert_eq!((seq, is_error), (3, true));
+        assert!(content.contains("index or a query"), "{}", content);
+    }
+
+    // INIT_REPORT returns whatever the main loop stored, or an error before init
+    #[tokio::test]
+    async fn test_init_report_served() {
+        init_tracing();
+
+        let config = comm::CommConfig {
+            listen_addr: "127.0.0.1".to_string(),
+            listen_port: 0,
+            ..Default::default()
+        };
+        let (comm, _rx) = comm::Comm::new(config).await.unwrap();
+        let comm_addr = comm.local_addr().unwrap();
+        let slot = comm.init_report_slot();
+
+        tokio::spawn(async move {
+            let _ = comm.run().await;
+        });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut buf = [0u8; 1024];
+        let mut fetch = async |seq: u32| {
+            let mut packet = vec![MsgType::InitReport as u8];
+            packet.extend_from_slice(&seq.to_be_bytes());
+            client.send_to(&packet, comm_addr).await.unwrap();
+            let (len, _) = tokio::time::timeout(Duration::from_secs(1), client.recv_from(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(buf[0], MsgType::Response as u8);
+            decode_response(&buf[..len])
+        };
+
+        let (seq, content, is_error) = fetch(1).await;
+        assert_eq!(
+            (seq, content.as_str(), is_error),
+            (1, "no init report yet", true)
+        );
+
+        *slot.write().unwrap() = Some("Debian VM with docker".to_string());
+        let (seq, content, is_error) = fetch(2).await;
+        assert_eq!(
+            (seq, content.as_str(), is_error),
+            (2, "Debian VM with docker", false)
+        );
+    }
+
+    // CONTROL pause/resume toggles the pause flag, only with the right token
+    #[tokio::test]
+    async fn test_control_pause_resume() {
+        init_tracing();
+
+        let config = comm::CommConfig {
+            listen_addr: "127.0.0.1".to_string(),
+            listen_port: 0,
+            control_token: Some("s3cret".to_string()),
+            ..Default::default()
+        };
+        let (comm, _rx) = comm::Comm::new(config).await.unwrap();
+        let comm_addr = comm.local_addr().unwrap();
+        let paused = comm.pause_flag();
+
+        tokio::spawn(async move {
+            let _ = comm.run().await;
+        });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut buf = [0u8; 1024];
+        let mut send = async |seq: u32, command: &str, token: &str| {
+            client
+                .send_to(&encode_control(seq, command, token), comm_addr)
+                .await
+                .unwrap();
+            let (len, _) = tokio::time::timeout(Duration::from_secs(1), client.recv_from(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(buf[0], MsgType::Response as u8);
+            decode_response(&buf[..len])
+        };
+
+        let (seq, content, is_error) = send(1, "pause", "wrong").await;
+        assert_eq!((seq, content.as_str(), is_error), (1, "unauthorized", true));
+        assert!(!paused.load(std::sync::atomic::Ordering::SeqCst));
+
+        let (_, content, is_error) = send(2, "pause", "s3cret").await;
+        assert_eq!((content.as_str(), is_error), ("paused", false));
+        assert!(paused.load(std::sync::atomic::Ordering::SeqCst));
+
+        let (_, content, is_error) = send(3, "resume", "s3cret").await;
+        assert_eq!((content.as_str(), is_error), ("resumed", false));
+        assert!(!paused.load(std::sync::atomic::Ordering::SeqCst));
+    }
+
+    // CONTROL dedup_stats counts remembered requests per client, dedup_clear
+    // forgets them
+    #[tokio::test]
+    async fn test_control_dedup_stats_and_clear() {
+        init_tracing();
+
+        let config = comm::CommConfig {
+            listen_addr: "127.0.0.1".to_string(),
+            listen_port: 0,
+            control_token: Some("s3cret".to_string()),
+            ..Default::default()
+        };
+        let (comm, mut loop_rx) = comm::Comm::new(config).await.unwrap();
+        let comm_addr = comm.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let _ = comm.run().await;
+        });
+        tokio::spawn(async move {
+            while let Some(req) = loop_rx.recv().await {
+                req.reply
+                    .send(comm::UserResponse::new("ok".to_string()))
+                    .ok();
+            }
+        });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // Three requests from one client, one from another
+        let first = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let second = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut buf = [0u8; 1024];
+        for (client, seq) in [(&first, 1), (&first, 2), (&first, 3), (&second, 1)] {
+            client
+                .send_to(&encode_request(seq, "hi"), comm_addr)
+                .await
+                .unwrap();
+            // ACK, then the RESPONSE
+            for _ in 0..2 {
+                tokio::time::timeout(Duration::from_secs(1), client.recv_from(&mut buf))
+                    .await
+                    .unwrap()
+                    .unwrap();
+            }
+        }
+
+        let operator = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut control = async |seq: u32, command: &str| {
+            operator
+                .send_to(&encode_control(seq, command, "s3cret"), comm_addr)
+                .await
+                .unwrap();
+            let (len, _) =
+                tokio::time::timeout(Duration::from_secs(1), operator.recv_from(&mut buf))
+                    .await
+                    .unwrap()
+                    .unwrap();
+            let (_, content, is_error) = decode_response(&buf[..len]);
+            assert!(!is_error, "{}", content);
+            content
+        };
+
+        let stats = control(1, "dedup_stats").await;
+        let mut lines = stats.lines();
+        assert_eq!(lines.next(), Some("4 entries from 2 clients"));
+        let mut per_client: Vec<&str> = lines.collect();
+        per_client.sort();
+        let mut expected = vec![
+            format!("{} 3", first.local_addr().unwrap()),
+            format!("{} 1", second.local_addr().unwrap()),
+        ];
+        expected.sort();
+        assert_eq!(per_client, expected);
+
+        assert_eq!(control(2, "dedup_clear").await, "cleared 4 dedup entries");
+        assert_eq!(control(3, "dedup_stats").await, "0 entries from 0 clients");
+    }
+
+    // With auth_secret set, only correctly signed packets are answered
+    #[tokio::test]
+    async fn test_auth_secret_drops_unsigned_and_tampered() {
+        init_tracing();
+
+        let config = comm::CommConfig {
+            listen_addr: "127.0.0.1".to_string(),
+            listen_port: 0,
+            auth_secret: Some("shared".to_string()),
+            ..Default::default()
+        };
+        let (comm, mut loop_rx) = comm::Comm::new(config).await.unwrap();
+        let comm_addr = comm.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let _ = comm.run().await;
+        });
+        tokio::spawn(async move {
+            while let Some(req) = loop_rx.recv().await {
+                req.reply.send(comm::UserResponse::new(req.content)).ok();
+            }
+        });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut buf = [0u8; 1024];
+        let silence = Duration::from_millis(200);
+
+        // Unsigned
+        client
+            .send_to(&encode_request(1, "unsigned"), comm_addr)
+            .await
+            .unwrap();
+        assert!(
+            tokio::time::timeout(silence, client.recv_from(&mut buf))
+                .await
+                .is_err()
+        );
+
+        // Signed with the wrong secret
+        client
+            .send_to(&sign(encode_request(2, "forged"), "guess"), comm_addr)
+            .await
+            .unwrap();
+        assert!(
+            tokio::time::timeout(silence, client.recv_from(&mut buf))
+                .await
+                .is_err()
+        );
+
+        // Tampered after signing
+        let mut tampered = sign(encode_request(3, "tampered"), "shared");
+        tampered[7] ^= 0x01;
+        client.send_to(&tampered, comm_addr).await.unwrap();
+        assert!(
+            tokio::time::timeout(silence, client.recv_from(&mut buf))
+                .await
+                .is_err()
+        );
+
+        // Correctly signed
+        client
+            .send_to(&sign(encode_request(4, "signed"), "shared"), comm_addr)
+            .await
+            .unwrap();
+        tokio::time::timeout(Duration::from_secs(1), client.recv_from(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(buf[0], MsgType::RequestAck as u8);
+        let (len, _) = tokio::time::timeout(Duration::from_secs(1), client.recv_from(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        let (seq, content, is_error) = decode_response(&buf[..len]);
+        assert_eq!((seq, content.as_str(), is_error), (4, "signed", false));
+    }
+
+    // T-EDGE-01: Empty packet - should be rejected
+    #[tokio::test]
+    async fn test_empty_packet() {
+        init_tracing();
+
+        let config = comm::CommConfig {
+            listen_addr: "127.0.0.1".to_string(),
+            listen_port: 0,
+            ..Default::default()
+        };
+        let (comm, _rx) = comm::Comm::new(config).await.unwrap();
+        let comm_addr = comm.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let _ = comm.run().await;
+        });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // Send empty packet
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let _ = client.send_to(&[], comm_addr).await;
+
+        // Should not crash - server continues
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    // T-EDGE-04: Invalid REQUEST_ACK from client - should be ignored
+    #[tokio::test]
+    async fn test_invalid_request_ack_from_client() {
+        init_tracing();
+
+        let config = comm::CommConfig {
+            listen_addr: "127.0.0.1".to_string(),
+            listen_port: 0,
+            ..Default::default()
+        };
+        let (comm, _rx) = comm::Comm::new(config).await.unwrap();
+        let comm_addr = comm.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let _ = comm.run().await;
+        });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // Send REQUEST_ACK (should be ignored - server->client only)
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut packet = vec![MsgType::RequestAck as u8];
+        packet.extend_from_slice(&1u32.to_be_bytes());
+        let _ = client.send_to(&packet, comm_addr).await;
+
+        // Should not crash - server continues
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    // T-EDGE-10: Daemon not running - client should timeout
+    #[tokio::test]
+    async fn test_client_timeout_no_daemon() {
+        init_tracing();
+
+        // Try to connect to port where nothing is running
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect("127.0.0.1:19999").await.unwrap();
+
+        let packet = encode_request(1, "test");
+        let _ = client.send(&packet).await;
+
+        // Receiving should timeout
+        let mut buf = [0u8; 1024];
+        let result =
+            tokio::time::timeout(Duration::from_millis(100), client.recv_from(&mut buf)).await;
+        assert!(result.is_err()); // Timeout
+    }
+
+    // Supervisor restarts the server after a receive failure
+    #[tokio::test]
+    async fn test_supervisor_restarts_after_recv_error() {
+        init_tracing();
+
+        let config = comm::CommConfig {
+            listen_addr: "127.0.0.1".to_string(),
+            listen_port: 0,
+            restart_base_delay_ms: 10,
+            ..Default::default()
+        };
+
+        let (comm, mut loop_rx) = comm::Comm::new(config).await.unwrap();
+        let comm_addr = comm.local_addr().unwrap();
+        comm.fail_next_recv();
+
+        let server = tokio::spawn(comm.run_supervised());
+
+        tokio::spawn(async move {
+            while let Some(req) = loop_rx.recv().await {
+                req.reply
+                    .send(comm::UserResponse::new(format!("echo: {}", req.content)))
+                    .ok();
+            }
+        });
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect(comm_addr).await.unwrap();
+
+        // This packet trips the injected failure and is lost with the socket
+        client.send(&encode_request(1, "lost")).await.unwrap();
+
+        // Retry like the CLI does until the restarted server answers
+        let mut buf = [0u8; 1024];
+        let mut acked = false;
+        for _ in 0..50 {
+            client.send(&encode_request(2, "again")).await.unwrap();
+            match tokio::time::timeout(Duration::from_millis(100), client.recv(&mut buf)).await {
+                Ok(Ok(_)) if buf[0] == MsgType::RequestAck as u8 => {
+                    acked = true;
+                    break;
+                }
+                _ => continue,
+            }
+        }
+        assert!(acked, "Restarted server should acknowledge requests");
+        assert!(!server.is_finished(), "Supervisor should still be running");
+
+        loop {
+            let len = tokio::time::timeout(Duration::from_secs(1), client.recv(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            if buf[0] == MsgType::Response as u8 {
+                let (seq, content, is_error) = decode_response(&buf[..len]);
+                assert_eq!(seq, 2);
+                assert_eq!(content, "echo: again");
+                assert!(!is_error);
+                break;
+            }
+        }
+
+        server.abort();
+    }
+
+    // Supervisor gives up once the restart limit is exhausted
+    #[tokio::test]
+    async fn test_supervisor_gives_up_after_max_restarts() {
+        init_tracing();
+
+        let config = comm::CommConfig {
+            listen_addr: "127.0.0.1".to_string(),
+            listen_port: 0,
+            max_restarts: 0,
+            ..Default::default()
+        };
+
+        let (comm, _rx) = comm::Comm::new(config).await.unwrap();
+        let comm_addr = comm.local_addr().unwrap();
+        comm.fail_next_recv();
+        let server = tokio::spawn(comm.run_supervised());
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client
+            .send_to(&encode_request(1, "boom"), comm_addr)
+            .await
+            .unwrap();
+
+        let result = tokio::time::timeout(Duration::from_secs(1), server)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(result, Err(comm::error::CommError::RecvError(_))));
+    }
+
+    // Server answers "Response timeout" once response_timeout_secs elapses
+    #[tokio::test]
+    async fn test_response_timeout_configurable() {
+        init_tracing();
+
+        let config = comm::CommConfig {
+            listen_addr: "127.0.0.1".to_string(),
+            listen_port: 0,
+            response_timeout_secs: 1,
+            ..Default::default()
+        };
+
+        let (comm, mut loop_rx) = comm::Comm::new(config).await.unwrap();
+        let comm_addr = comm.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _ = comm.run().await;
+        });
+
+        // Mock main loop that holds the request without ever replying
+        tokio::spawn(async move {
+            let _held = loop_rx.recv().await;
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        });
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect(comm_addr).await.unwrap();
+        client.send(&encode_request(1, "slow")).await.unwrap();
+
+        let mut buf = [0u8; 1024];
+        tokio::time::timeout(Duration::from_secs(1), client.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(buf[0], MsgType::RequestAck as u8);
+        let acked_at = std::time::Instant::now();
+
+        let len = tokio::time::timeout(Duration::from_secs(3), client.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        let waited = acked_at.elapsed();
+        assert_eq!(buf[0], MsgType::Response as u8);
+        let (seq, content, is_error) = decode_response(&buf[..len]);
+        assert_eq!(seq, 1);
+        assert_eq!(content, "Response timeout");
+        assert!(is_error);
+        assert!(
+            waited >= Duration::from_millis(900) && waited < Duration::from_millis(2000),
+            "timeout fired after {:?}",
+            waited
+        );
+    }
+
+    // With response_timing on, Comm adds the queue wait to the loop's timings
+    #[tokio::test]
+    async fn test_response_timing_envelope() {
+        use comm::types::ResponseTiming;
+        use rmp_serde::decode::Deserializer;
+        use serde::Deserialize;
+
+        #[derive(Deserialize)]
+        struct TimedPayload {
+            content: String,
+            #[allow(dead_code)]
+            is_error: bool,
+            #[serde(default)]
+            timing: Option<ResponseTiming>,
+        }
+
+        init_tracing();
+
+        let config = comm::CommConfig {
+            listen_addr: "127.0.0.1".to_string(),
+            listen_port: 0,
+            response_timing: true,
+            ..Default::default()
+        };
+        let (comm, mut loop_rx) = comm::Comm::new(config).await.unwrap();
+        let comm_addr = comm.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _ = comm.run().await;
+        });
+
+        // Mock main loop that is busy for a while before handling
+        tokio::spawn(async move {
+            let req = loop_rx.recv().await.unwrap();
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            let handled = ResponseTiming {
+                queue_ms: 0,
+                inference_ms: 20,
+                tool_ms: 30,
+                total_ms: 60,
+            };
+            req.reply
+                .send(comm::UserResponse::new("timed".to_string()).with_timing(handled))
+                .ok();
+        });
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect(comm_addr).await.unwrap();
+        client.send(&encode_request(1, "time me")).await.unwrap();
+
+        let mut buf = [0u8; 1024];
+        tokio::time::timeout(Duration::from_secs(1), client.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(buf[0], MsgType::RequestAck as u8);
+        let len = tokio::time::timeout(Duration::from_secs(2), client.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(buf[0], MsgType::Response as u8);
+
+        let mut de = Deserializer::new(&buf[5..len]);
+        let payload: TimedPayload = Deserialize::deserialize(&mut de).unwrap();
+        assert_eq!(payload.content, "timed");
+        let timing = payload.timing.expect("timing requested");
+        assert_eq!((timing.inference_ms, timing.tool_ms), (20, 30));
+        assert!(timing.total_ms >= 150, "{:?}", timing);
+        assert_eq!(timing.queue_ms + 60, timing.total_ms);
+    }
+
+    // Tool output the main loop reports is relayed as PROGRESS frames, rate
+    // limited, before the RESPONSE
+    #[tokio::test]
+    async fn test_progress_frames_precede_response() {
+        use rmp_serde::decode::Deserializer;
+        use rmp_serde::encode::Serializer;
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Serialize)]
+        struct RequestPayload<'a> {
+            content: &'a str,
+            request_id: Option<&'a str>,
+            no_tools: bool,
+            progress: bool,
+        }
+
+        #[derive(Deserialize)]
+        struct ProgressPayload {
+            content: String,
+        }
+
+        init_tracing();
+
+        let config = comm::CommConfig {
+            listen_addr: "127.0.0.1".to_string(),
+            listen_port: 0,
+            progress_interval_ms: 100,
+            ..Default::default()
+        };
+        let (comm, mut loop_rx) = comm::Comm::new(config).await.unwrap();
+        let comm_addr = comm.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _ = comm.run().await;
+        });
+
+        // Mock main loop running a tool that prints a line every 150ms, with
+        // one extra line too soon after another to be sent
+        tokio::spawn(async move {
+            let req = loop_rx.recv().await.unwrap();
+            let progress = req.progress.expect("client asked for progress");
+            for line in 1..=3 {
+                progress.try_send(format!("line {}", line)).unwrap();
+                if line == 2 {
+                    progress.try_send("line 2b".to_string()).unwrap();
+                }
+                tokio::time::sleep(Duration::from_millis(150)).await;
+            }
+            req.reply
+                .send(comm::UserResponse::new("done".to_string()))
+                .ok();
+        });
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect(comm_addr).await.unwrap();
+        let mut packet = vec![MsgType::Request as u8];
+        packet.extend_from_slice(&1u32.to_be_bytes());
+        let mut ser = Serializer::new(&mut packet);
+        RequestPayload {
+            content: "count lines",
+            request_id: None,
+            no_tools: false,
+            progress: true,
+        }
+        .serialize(&mut ser)
+        .unwrap();
+        client.send(&packet).await.unwrap();
+
+        let mut buf = [0u8; 2048];
+        let mut snippets = Vec::new();
+        loop {
+            let len = tokio::time::timeout(Duration::from_secs(2), client.recv(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(u32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]]), 1);
+            if buf[0] == MsgType::RequestAck as u8 {
+                continue;
+            }
+            if buf[0] == MsgType::Progress as u8 {
+                let mut de = Deserializer::new(&buf[5..len]);
+                let payload: ProgressPayload = Deserialize::deserialize(&mut de).unwrap();
+                snippets.push(payload.content);
+                continue;
+            }
+            let (_, content, is_error) = decode_response(&buf[..len]);
+            assert_eq!((content.as_str(), is_error), ("done", false));
+            break;
+        }
+        assert_eq!(snippets, ["line 1", "line 2", "line 3"]);
+    }
+
+    // Requests past max_in_flight_per_client are refused, earlier ones finish
+    #[tokio::test]
+    async fn test_max_in_flight_per_client() {
+        init_tracing();
+
+        let config = comm::CommConfig {
+            listen_addr: "127.0.0.1".to_string(),
+            listen_port: 0,
+            max_in_flight_per_client: 2,
+            ..Default::default()
+        };
+
+        let (comm, mut loop_rx) = comm::Comm::new(config).await.unwrap();
+        let comm_addr = comm.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _ = comm.run().await;
+        });
+
+        // Mock main loop that holds two requests until released, then
+        // answers everything
+        let (release_tx, release_rx) = tokio::sync::oneshot::channel::<()>();
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while held.len() < 2 {
+                held.push(loop_rx.recv().await.unwrap());
+            }
+            release_rx.await.ok();
+            for req in held {
+                let reply = format!("done {}", req.content);
+                req.reply.send(comm::UserResponse::new(reply)).ok();
+            }
+            while let Some(req) = loop_rx.recv().await {
+                let reply = format!("done {}", req.content);
+                req.reply.send(comm::UserResponse::new(reply)).ok();
+            }
+        });
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect(comm_addr).await.unwrap();
+        let mut buf = [0u8; 1024];
+
+        for seq in 1..=2 {
+            client
+                .send(&encode_request(seq, &format!("job {}", seq)))
+                .await
+                .unwrap();
+            tokio::time::timeout(Duration::from_secs(1), client.recv(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(buf[0], MsgType::RequestAck as u8);
+        }
+
+        // Third concurrent request is answered at once with an error
+        client.send(&encode_request(3, "job 3")).await.unwrap();
+        let len = tokio::time::timeout(Duration::from_secs(1), client.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(buf[0], MsgType::Response as u8);
+        let (seq, content, is_error) = decode_response(&buf[..len]);
+        assert_eq!(seq, 3);
+        assert_eq!(content, "too many in-flight requests (max 2)");
+        assert!(is_error);
+
+        // The first two still complete
+        release_tx.send(()).unwrap();
+        let mut answered = Vec::new();
+        for _ in 0..2 {
+            let len = tokio::time::timeout(Duration::from_secs(1), client.recv(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(buf[0], MsgType::Response as u8);
+            let (seq, content, is_error) = decode_response(&buf[..len]);
+            assert!(!is_error);
+            answered.push((seq, content));
+        }
+        answered.sort();
+        assert_eq!(
+            answered,
+            [(1, "done job 1".to_string()), (2, "done job 2".to_string())]
+        );
+
+        // With those answered, a retry of the refused request goes through
+        client.send(&encode_request(3, "job 3")).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(1), client.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(buf[0], MsgType::RequestAck as u8);
+        let len = tokio::time::timeout(Duration::from_secs(1), client.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        let (seq, content, is_error) = decode_response(&buf[..len]);
+        assert_eq!((seq, content.as_str(), is_error), (3, "done job 3", false));
+    }
+
+    // validate() names every out-of-range value
+    #[test]
+    fn test_config_validate() {
+        assert!(comm::CommConfig::default().validate().is_ok());
+
+        type Mutation = fn(&mut comm::CommConfig);
+        let cases: Vec<(Mutation, &str)> = vec![
+            (
+                |c| c.listen_addr = "localhost".to_string(),
+                "listen_addr must be an IP address",
+            ),
+            (|c| c.listen_port = 0, "listen_port must be nonzero"),
+            (|c| c.max_payload_bytes = 0, "max_payload_bytes"),
+            (|c| c.recv_buffer_size = 0, "recv_buffer_size"),
+            (|c| c.dedup_capacity = 0, "dedup_capacity"),
+            (|c| c.response_timeout_secs = 0, "response_timeout_secs"),
+        ];
+        for (mutate, expected) in cases {
+            let mut config = comm::CommConfig::default();
+            mutate(&mut config);
+            let err = config.validate().unwrap_err().to_string();
+            assert!(err.contains(expected), "{:?} not in {:?}", expected, err);
+        }
+
+        let config = comm::CommConfig {
+            listen_addr: "nowhere".to_string(),
+            listen_port: 0,
+            ..Default::default()
+        };
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("listen_addr") && err.contains("listen_port"));
+    }
+
+    // A port still held is retried until freed; with SO_REUSEADDR a second
+    // socket binds it at once
+    #[tokio::test]
+    async fn test_bind_retry_and_reuse_addr() {
+        init_tracing();
+
+        let config = |port: u16, reuse_addr: bool| comm::CommConfig {
+            listen_addr: "127.0.0.1".to_string(),
+            listen_port: port,
+            reuse_addr,
+            bind_retries: 3,
+            bind_retry_base_delay_ms: 20,
+            ..Default::default()
+        };
+
+        // Without reuse the port stays taken, and retries run out
+        let (first, _rx) = comm::Comm::new(config(0, false)).await.unwrap();
+        let port = first.local_addr().unwrap().port();
+        let started = std::time::Instant::now();
+        let err = comm::Comm::new(config(port, false)).await.err().unwrap();
+        assert!(
+            matches!(err, comm::error::CommInitError::BindFailed(_)),
+            "{}",
+            err
+        );
+        // 20 + 40 + 80 ms of backoff before giving up
+        assert!(started.elapsed() >= Duration::from_millis(140));
+
+        // The port freed during the retries is picked up
+        let release = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            drop(first);
+        });
+        let (second, _rx) = comm::Comm::new(config(port, false)).await.unwrap();
+        assert_eq!(second.local_addr().unwrap().port(), port);
+        release.await.unwrap();
+        drop(second);
+
+        // Both sockets set SO_REUSEADDR: the second binds without waiting
+        let (first, _rx) = comm::Comm::new(config(0, true)).await.unwrap();
+        let port = first.local_addr().unwrap().port();
+        let started = std::time::Instant::now();
+        let (second, _rx) = comm::Comm::new(config(port, true)).await.unwrap();
+        assert_eq!(second.local_addr().unwrap().port(), port);
+        assert!(started.elapsed() < Duration::from_millis(20));
     }
 }
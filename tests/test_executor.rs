@@ -2,9 +2,11 @@
 // This file should be run with cargo test --test test_executor
 
 #[path = "../src/brain/mod.rs"]
+#[allow(dead_code, unused_imports)]
 mod brain;
 
 #[path = "../src/executor/mod.rs"]
+#[allow(dead_code)]
 mod executor;
 
 fn init_tracing() {
@@ -19,12 +21,114 @@ fn init_tracing() {
     });
 }
 
+/// Create a fresh, uniquely named directory under the system temp dir
+fn create_temp_dir(prefix: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("shelly-{}-{}", prefix, uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
 fn create_executor() -> executor::Executor {
     let config = executor::ExecutorConfig {
         tools_toml_path: std::path::PathBuf::from("tools.toml"),
         ..Default::default()
     };
-    executor::Executor::init(config)
+    executor::Executor::init(config).unwrap()
+}
+
+/// Mock tool that records the order in which calls start and can be held
+/// open until released
+struct RecordingTool {
+    started: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+    release: std::sync::Arc<tokio::sync::Semaphore>,
+}
+
+#[async_trait::async_trait]
+impl executor::ToolImpl for RecordingTool {
+    fn definition(&self) -> brain::ToolDefinition {
+        brain::ToolDefinition {
+            name: "record".to_string(),
+            description: "Record the call label".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": { "label": { "type": "string" } },
+                "required": ["label"]
+            }),
+        }
+    }
+
+    async fn run(&self, input: serde_json::Value) -> executor::Result<executor::ToolOutput> {
+        let label = input["label"].as_str().unwrap_or_default().to_string();
+        self.started.lock().unwrap().push(label.clone());
+        let _held = self.release.acquire().await.unwrap();
+        Ok(executor::ToolOutput::success(label))
+    }
+}
+
+/// Mock tool that fails a fixed number of times before succeeding
+struct FlakyTool {
+    failures_left: std::sync::atomic::AtomicU32,
+    calls: std::sync::atomic::AtomicU32,
+}
+
+#[async_trait::async_trait]
+impl executor::ToolImpl for FlakyTool {
+    fn definition(&self) -> brain::ToolDefinition {
+        brain::ToolDefinition {
+            name: "flaky".to_string(),
+            description: "Fails transiently".to_string(),
+            input_schema: serde_json::json!({ "type": "object", "properties": {} }),
+        }
+    }
+
+    async fn run(&self, _input: serde_json::Value) -> executor::Result<executor::ToolOutput> {
+        use std::sync::atomic::Ordering;
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        let failed = self
+            .failures_left
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok();
+        if failed {
+            Ok(executor::ToolOutput::error("resource temporarily locked"))
+        } else {
+            Ok(executor::ToolOutput::success("done"))
+        }
+    }
+}
+
+/// Mock tool without arguments, parsing its input like the built-in tools do
+struct GetTimeTool;
+
+#[derive(serde::Deserialize)]
+struct GetTimeInput {}
+
+#[async_trait::async_trait]
+impl executor::ToolImpl for GetTimeTool {
+    fn definition(&self) -> brain::ToolDefinition {
+        brain::ToolDefinition {
+            name: "get_time".to_string(),
+            description: "Get the current time".to_string(),
+            input_schema: serde_json::json!({ "type": "object", "properties": {} }),
+        }
+    }
+
+    async fn run(&self, input: serde_json::Value) -> executor::Result<executor::ToolOutput> {
+        let GetTimeInput {} = serde_json::from_value(input).map_err(|e| {
+            executor::ExecutorError::InvalidInput("get_time".to_string(), e.to_string())
+        })?;
+        Ok(executor::ToolOutput::success("12:00"))
+    }
+}
+
+/// Executor reading a tools.toml that gives `flaky` the given retry table
+fn create_executor_with_retry(dir: &std::path::Path, retry: &str) -> executor::Executor {
+    let tools_toml = dir.join("tools.toml");
+    std::fs::write(&tools_toml, format!("[flaky]\nretry = {}\n", retry)).unwrap();
+    executor::Executor::init(executor::ExecutorConfig {
+        tools_toml_path: tools_toml,
+        ..Default::default()
+    })
+    .unwrap()
 }
 
 #[cfg(test)]
@@ -53,6 +157,130 @@ mod tests {
         assert!(!output.is_error, "Exit code 0 should not be an error");
     }
 
+    /// Test a timed-out command still returns the lines it printed
+    #[tokio::test]
+    async fn test_bash_timeout_returns_partial_output() {
+        init_tracing();
+
+        let mut config = executor::ExecutorConfig::default();
+        config.constraints.timeout_secs = 1;
+        let executor = executor::Executor::init(config).unwrap();
+
+        let input = serde_json::json!({
+            "command": "echo first; echo second; echo oops >&2; sleep 5; echo never"
+        });
+        let started = std::time::Instant::now();
+        let output = executor.execute("bash", input).await.unwrap();
+        assert!(started.elapsed() < std::time::Duration::from_secs(4));
+
+        assert!(output.is_error);
+        assert!(output.partial);
+        assert!(output.content.contains("first\nsecond\n"));
+        assert!(output.content.contains("[stderr]\noops"));
+        assert!(output.content.contains("[timeout]"));
+        assert!(!output.content.contains("never"));
+        assert!(!output.content.contains("[exit_code]"));
+    }
+
+    /// Test bash output is cut at max_output_bytes
+    #[tokio::test]
+    async fn test_bash_output_limit() {
+        init_tracing();
+
+        let mut config = executor::ExecutorConfig::default();
+        config.constraints.max_output_bytes = 16;
+        let executor = executor::Executor::init(config).unwrap();
+
+        let input = serde_json::json!({ "command": "seq 1 1000" });
+        let output = executor.execute("bash", input).await.unwrap();
+        assert!(!output.is_error);
+        assert!(!output.partial);
+        assert!(output.content.starts_with("[stdout]\n1\n2\n"));
+        assert!(output.content.contains("...(truncated)"));
+        assert!(output.content.ends_with("[exit_code]\n0"));
+    }
+
+    /// Test the output limit never splits a multibyte character
+    #[tokio::test]
+    async fn test_bash_output_limit_keeps_characters_whole() {
+        init_tracing();
+
+        let mut config = executor::ExecutorConfig::default();
+        // "a" then 3-byte characters: byte 8 falls inside the third one
+        config.constraints.max_output_bytes = 8;
+        let executor = executor::Executor::init(config).unwrap();
+
+        let input = serde_json::json!({ "command": "printf 'a日本語テキスト\\n'" });
+        let output = executor.execute("bash", input).await.unwrap();
+        assert!(!output.content.contains('\u{FFFD}'), "{}", output.content);
+        assert!(
+            output
+                .content
+                .starts_with("[stdout]\na日本\n...(truncated)")
+        );
+        assert_eq!(output.process.unwrap().stdout, "a日本");
+    }
+
+    /// Test a command printing nothing still reports progress on the timer
+    #[tokio::test]
+    async fn test_bash_silent_command_reports_progress() {
+        init_tracing();
+
+        let config = executor::ExecutorConfig {
+            progress: executor::ProgressPolicy {
+                every_lines: 0,
+                interval: std::time::Duration::from_millis(100),
+            },
+            ..Default::default()
+        };
+        let executor = executor::Executor::init(config).unwrap();
+
+        let reports = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink: executor::ProgressSink = {
+            let reports = reports.clone();
+            std::sync::Arc::new(move |p: executor::Progress| reports.lock().unwrap().push(p))
+        };
+        let input = serde_json::json!({ "command": "echo started; sleep 0.5; echo done" });
+        let output = executor::progress::scoped(sink, executor.execute("bash", input))
+            .await
+            .unwrap();
+        assert!(output.content.contains("done"));
+
+        let reports = reports.lock().unwrap();
+        assert!(reports.len() >= 2, "{:?}", reports);
+        assert!(
+            reports
+                .iter()
+                .all(|p| p.lines == 1 && p.latest == "started"),
+            "{:?}",
+            reports
+        );
+    }
+
+    /// Test output without newlines is read in chunks and cut at the limit
+    #[tokio::test]
+    async fn test_bash_output_limit_without_newlines() {
+        init_tracing();
+
+        let mut config = executor::ExecutorConfig::default();
+        config.constraints.max_output_bytes = 10_000;
+        let executor = executor::Executor::init(config).unwrap();
+
+        // 8 MB on one line
+        let input = serde_json::json!({ "command": "head -c 8000000 /dev/zero | tr '\\0' a" });
+        let output = executor.execute("bash", input).await.unwrap();
+        assert!(!output.is_error);
+        assert!(output.content.contains("...(truncated)"));
+        assert_eq!(output.process.unwrap().stdout, "a".repeat(10_000));
+
+        // 3-byte characters, so read chunks end inside them
+        let input = serde_json::json!({ "command": "yes 日 | head -n 5000 | tr -d '\\n'" });
+        let output = executor.execute("bash", input).await.unwrap();
+        let stdout = output.process.unwrap().stdout;
+        assert!(!stdout.contains('\u{FFFD}'));
+        assert_eq!(stdout, "日".repeat(3333));
+    }
+
     /// Test bash with non-zero exit code
     #[tokio::test]
     async fn test_bash_error_exit() {
@@ -71,6 +299,145 @@ mod tests {
         assert!(output.is_error, "Non-zero exit code should be an error");
     }
 
+    /// Test the configured shell runs the command, and a missing one is named
+    #[tokio::test]
+    async fn test_bash_configured_shell() {
+        init_tracing();
+
+        let executor = executor::Executor::init(executor::ExecutorConfig {
+            shell: "/bin/bash".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        let definition = executor
+            .tool_definitions()
+            .into_iter()
+            .find(|d| d.name == "bash")
+            .unwrap();
+        assert!(
+            definition.description.contains("via /bin/bash -c."),
+            "{}",
+            definition.description
+        );
+        let output = executor
+            .execute(
+                "bash",
+                serde_json::json!({ "command": "echo \"bash=${BASH_VERSION:-none}\"" }),
+            )
+            .await
+            .unwrap();
+        assert!(!output.is_error);
+        assert!(
+            !output.content.contains("bash=none"),
+            "command ran outside bash: {}",
+            output.content
+        );
+
+        let executor = executor::Executor::init(executor::ExecutorConfig {
+            shell: "/nonexistent/shell".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        let err = executor
+            .execute("bash", serde_json::json!({ "command": "echo hi" }))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, executor::ExecutorError::ShellNotFound(..)));
+        assert!(!err.is_transient());
+        assert!(
+            err.to_string().contains("/nonexistent/shell not found"),
+            "{}",
+            err
+        );
+    }
+
+    /// Test each bash output format on a command writing both streams
+    #[tokio::test]
+    async fn test_bash_output_formats() {
+        init_tracing();
+
+        let run = |format: executor::BashOutputFormat, command: &'static str| async move {
+            let executor = executor::Executor::init(executor::ExecutorConfig {
+                bash_output_format: format,
+                ..Default::default()
+            })
+            .unwrap();
+            executor
+                .execute("bash", serde_json::json!({ "command": command }))
+                .await
+                .unwrap()
+        };
+        let both = "echo out; echo err >&2; exit 3";
+
+        let output = run(executor::BashOutputFormat::Labeled, both).await;
+        assert_eq!(
+            output.content,
+            "[stdout]\nout\n\n[stderr]\nerr\n\n[exit_code]\n3"
+        );
+        assert!(output.is_error);
+
+        let output = run(executor::BashOutputFormat::Raw, both).await;
+        assert_eq!(output.content, "out\n");
+        assert!(output.is_error);
+        // Stderr stands in when there is no stdout
+        let output = run(executor::BashOutputFormat::Raw, "echo err >&2").await;
+        assert_eq!(output.content, "err\n");
+
+        let output = run(executor::BashOutputFormat::Json, both).await;
+        let json: serde_json::Value = serde_json::from_str(&output.content).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "stdout": "out\n",
+                "stderr": "err\n",
+                "exit_code": 3,
+                "truncated": false,
+                "timed_out": false,
+            })
+        );
+        assert!(output.is_error);
+
+        assert_eq!(
+            "RAW".parse::<executor::BashOutputFormat>(),
+            Ok(executor::BashOutputFormat::Raw)
+        );
+        assert!("yaml".parse::<executor::BashOutputFormat>().is_err());
+    }
+
+    /// Test a tool without arguments runs on empty, null and missing input
+    #[tokio::test]
+    async fn test_no_arg_tool_accepts_empty_input() {
+        init_tracing();
+
+        let executor = executor::Executor::default();
+        executor.register(std::sync::Arc::new(GetTimeTool)).unwrap();
+
+        // A tool_use block without an input field
+        let missing: brain::ContentBlock = serde_json::from_value(serde_json::json!({
+            "type": "tool_use",
+            "id": "toolu_1",
+            "name": "get_time",
+        }))
+        .unwrap();
+        let brain::ContentBlock::ToolUse { input: missing, .. } = missing else {
+            panic!("not a tool_use block");
+        };
+
+        for input in [serde_json::json!({}), serde_json::Value::Null, missing] {
+            let output = executor
+                .execute("get_time", input.clone())
+                .await
+                .unwrap_or_else(|e| panic!("input {}: {}", input, e));
+            assert_eq!(output.content, "12:00");
+        }
+
+        // Built-in tools without required fields take null as well
+        let output = executor
+            .execute("service_status", serde_json::Value::Null)
+            .await;
+        assert!(output.is_ok(), "{:?}", output);
+    }
+
     /// Test unknown tool
     #[tokio::test]
     async fn test_unknown_tool() {
@@ -144,4 +511,903 @@ mod tests {
         assert!(output.content.contains("line1"));
         assert!(output.content.contains("line2"));
     }
+
+    /// Test list_dir returns structured metadata for known files
+    #[tokio::test]
+    async fn test_list_dir_metadata() {
+        init_tracing();
+
+        let dir = create_temp_dir("list-dir");
+        std::fs::write(dir.join("a.txt"), "hello").unwrap();
+        std::fs::create_dir(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("sub").join("b.txt"), "nested").unwrap();
+        std::os::unix::fs::symlink(dir.join("a.txt"), dir.join("link")).unwrap();
+
+        let executor = create_executor();
+        let input = serde_json::json!({ "path": dir.to_str().unwrap() });
+        let output = executor.execute("list_dir", input).await.unwrap();
+        assert!(
+            !output.is_error,
+            "Listing should succeed: {}",
+            output.content
+        );
+
+        let listing: serde_json::Value = serde_json::from_str(&output.content).unwrap();
+        let entries = listing["entries"].as_array().unwrap();
+        assert_eq!(entries.len(), 3, "Non-recursive listing has 3 entries");
+        assert_eq!(listing["truncated"], false);
+
+        let file = entries.iter().find(|e| e["name"] == "a.txt").unwrap();
+        assert_eq!(file["type"], "file");
+        assert_eq!(file["size"], 5);
+        assert!(file["mtime"].is_string());
+        assert!(file["permissions"].as_str().unwrap().starts_with('0'));
+
+        let sub = entries.iter().find(|e| e["name"] == "sub").unwrap();
+        assert_eq!(sub["type"], "dir");
+
+        let link = entries.iter().find(|e| e["name"] == "link").unwrap();
+        assert_eq!(link["type"], "symlink");
+        assert!(link["target"].as_str().unwrap().ends_with("a.txt"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Test list_dir recursion and max_entries truncation
+    #[tokio::test]
+    async fn test_list_dir_recursive_and_truncated() {
+        init_tracing();
+
+        let dir = create_temp_dir("list-dir-rec");
+        std::fs::create_dir(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("sub").join("b.txt"), "nested").unwrap();
+
+        let executor = create_executor();
+        let input = serde_json::json!({ "path": dir.to_str().unwrap(), "recursive": true });
+        let output = executor.execute("list_dir", input).await.unwrap();
+        let listing: serde_json::Value = serde_json::from_str(&output.content).unwrap();
+        let entries = listing["entries"].as_array().unwrap();
+        assert!(entries.iter().any(|e| e["path"] == "sub/b.txt"));
+
+        for i in 0..5 {
+            std::fs::write(dir.join(format!("f{}", i)), "x").unwrap();
+        }
+        let input = serde_json::json!({ "path": dir.to_str().unwrap(), "max_entries": 2 });
+        let output = executor.execute("list_dir", input).await.unwrap();
+        let listing: serde_json::Value = serde_json::from_str(&output.content).unwrap();
+        assert_eq!(listing["entries"].as_array().unwrap().len(), 2);
+        assert_eq!(listing["truncated"], true);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Test list_dir refuses paths outside the allowed root
+    #[tokio::test]
+    async fn test_list_dir_outside_allowed_root() {
+        init_tracing();
+
+        let root = create_temp_dir("list-dir-root");
+        let executor = executor::Executor::init(executor::ExecutorConfig {
+            allowed_root: Some(root.clone()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let input = serde_json::json!({ "path": root.to_str().unwrap() });
+        let output = executor.execute("list_dir", input).await.unwrap();
+        assert!(!output.is_error);
+
+        let input = serde_json::json!({ "path": "/" });
+        let output = executor.execute("list_dir", input).await.unwrap();
+        assert!(output.is_error);
+        assert!(output.content.contains("outside the allowed root"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// Test queued tool calls start in priority order, not arrival order
+    #[tokio::test]
+    async fn test_high_priority_tool_starts_first() {
+        init_tracing();
+
+        let executor = std::sync::Arc::new(
+            executor::Executor::init(executor::ExecutorConfig {
+                max_concurrent_executions: 1,
+                ..Default::default()
+            })
+            .unwrap(),
+        );
+        let started = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let release = std::sync::Arc::new(tokio::sync::Semaphore::new(0));
+        executor
+            .register(std::sync::Arc::new(RecordingTool {
+                started: started.clone(),
+                release: release.clone(),
+            }))
+            .unwrap();
+
+        let spawn_call = |input: serde_json::Value| {
+            let executor = executor.clone();
+            tokio::spawn(async move { executor.execute("record", input).await })
+        };
+
+        // Occupy the only slot
+        let blocker = spawn_call(serde_json::json!({ "label": "blocker" }));
+        while started.lock().unwrap().is_empty() {
+            tokio::task::yield_now().await;
+        }
+
+        let low = spawn_call(serde_json::json!({ "label": "low", "priority": 1 }));
+        while executor.queued_executions() < 1 {
+            tokio::task::yield_now().await;
+        }
+        let normal = spawn_call(serde_json::json!({ "label": "normal" }));
+        let high = spawn_call(serde_json::json!({ "label": "high", "priority": 10 }));
+        while executor.queued_executions() < 3 {
+            tokio::task::yield_now().await;
+        }
+
+        release.add_permits(4);
+        for call in [blocker, low, normal, high] {
+            let output = call.await.unwrap().unwrap();
+            assert!(!output.is_error);
+        }
+
+        assert_eq!(
+            *started.lock().unwrap(),
+            vec!["blocker", "high", "low", "normal"]
+        );
+    }
+
+    /// Test priority is only advertised when calls can queue
+    #[test]
+    fn test_priority_field_only_when_bounded() {
+        let with_priority = |executor: &executor::Executor| {
+            let defs = executor.tool_definitions();
+            let count = defs
+                .iter()
+                .filter(|d| d.input_schema["properties"].get("priority").is_some())
+                .count();
+            (count, defs.len())
+        };
+        assert_eq!(with_priority(&executor::Executor::default()).0, 0);
+
+        let bounded = executor::Executor::init(executor::ExecutorConfig {
+            max_concurrent_executions: 2,
+            ..Default::default()
+        })
+        .unwrap();
+        let (count, total) = with_priority(&bounded);
+        assert_eq!(count, total);
+    }
+
+    /// Test a non-integer priority is rejected
+    #[tokio::test]
+    async fn test_invalid_priority() {
+        init_tracing();
+
+        let executor = create_executor();
+        let input = serde_json::json!({ "command": "echo hi", "priority": "urgent" });
+        let result = executor.execute("bash", input).await;
+        assert!(result.is_err(), "Non-integer priority should be rejected");
+    }
+
+    /// Test tail_file returns exactly the last N lines of a large file
+    #[tokio::test]
+    async fn test_tail_file_last_lines() {
+        init_tracing();
+
+        let dir = create_temp_dir("tail-file");
+        let file = dir.join("app.log");
+        let content: String = (1..=1000).map(|i| format!("line {}\n", i)).collect();
+        std::fs::write(&file, content).unwrap();
+
+        let executor = create_executor();
+        let input = serde_json::json!({ "path": file.to_str().unwrap(), "lines": 10 });
+        let output = executor.execute("tail_file", input).await.unwrap();
+        assert!(!output.is_error, "Tail should succeed: {}", output.content);
+
+        let lines: Vec<&str> = output.content.lines().collect();
+        let expected: Vec<String> = (991..=1000).map(|i| format!("line {}", i)).collect();
+        assert_eq!(lines, expected);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Test tail_file on short, unterminated and missing files
+    #[tokio::test]
+    async fn test_tail_file_edge_cases() {
+        init_tracing();
+
+        let dir = create_temp_dir("tail-file-edge");
+        let short = dir.join("short.log");
+        std::fs::write(&short, "a\nb\nc").unwrap();
+        let empty = dir.join("empty.log");
+        std::fs::write(&empty, "").unwrap();
+
+        let executor = create_executor();
+
+        let input = serde_json::json!({ "path": short.to_str().unwrap(), "lines": 10 });
+        let output = executor.execute("tail_file", input).await.unwrap();
+        assert!(!output.is_error);
+        assert_eq!(output.content, "a\nb\nc\n");
+
+        let input = serde_json::json!({ "path": empty.to_str().unwrap() });
+        let output = executor.execute("tail_file", input).await.unwrap();
+        assert!(!output.is_error);
+        assert!(output.content.is_empty());
+
+        let input = serde_json::json!({ "path": dir.join("missing.log").to_str().unwrap() });
+        let output = executor.execute("tail_file", input).await.unwrap();
+        assert!(output.is_error);
+        assert!(output.content.contains("cannot resolve"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Test tail_file drops the oldest lines to stay within max_output_bytes
+    #[tokio::test]
+    async fn test_tail_file_output_limit() {
+        init_tracing();
+
+        let dir = create_temp_dir("tail-file-limit");
+        let file = dir.join("big.log");
+        let content: String = (0..100).map(|i| format!("{:09}\n", i)).collect();
+        std::fs::write(&file, content).unwrap();
+
+        let mut config = executor::ExecutorConfig::default();
+        config.constraints.max_output_bytes = 50;
+        let executor = executor::Executor::init(config).unwrap();
+
+        let input = serde_json::json!({ "path": file.to_str().unwrap(), "lines": 20 });
+        let output = executor.execute("tail_file", input).await.unwrap();
+        assert!(!output.is_error);
+        let lines: Vec<&str> = output.content.lines().collect();
+        assert_eq!(
+            &lines[..5],
+            [
+                "000000095",
+                "000000096",
+                "000000097",
+                "000000098",
+                "000000099"
+            ]
+        );
+        assert!(output.content.contains("[truncated"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Test read_metric parses /proc/loadavg and refuses unlisted metrics
+    #[tokio::test]
+    async fn test_read_metric_loadavg() {
+        init_tracing();
+
+        let executor = create_executor();
+        let input = serde_json::json!({ "metric": "loadavg" });
+        let output = executor.execute("read_metric", input).await.unwrap();
+        assert!(!output.is_error, "{}", output.content);
+
+        let parsed: serde_json::Value = serde_json::from_str(&output.content).unwrap();
+        assert_eq!(parsed["metric"], "loadavg");
+        let values = &parsed["values"];
+        for load in ["load1", "load5", "load15"] {
+            assert!(values[load].as_f64().unwrap() >= 0.0, "{}", output.content);
+        }
+        assert!(values["running"].as_u64().unwrap() >= 1);
+        assert!(values["total"].as_u64().unwrap() >= values["running"].as_u64().unwrap());
+
+        let input = serde_json::json!({ "metric": "/etc/shadow" });
+        let result = executor.execute("read_metric", input).await;
+        assert!(matches!(
+            result,
+            Err(executor::ExecutorError::InvalidInput(..))
+        ));
+    }
+
+    /// Test checksum streams a file to a known digest and builds manifests
+    #[tokio::test]
+    async fn test_checksum_known_digest() {
+        init_tracing();
+
+        let dir = create_temp_dir("checksum");
+        let file = dir.join("hello.txt");
+        std::fs::write(&file, "hello world\n").unwrap();
+        std::fs::create_dir(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("sub").join("nested.txt"), "hello world\n").unwrap();
+
+        let executor = create_executor();
+        let input = serde_json::json!({ "path": file.to_str().unwrap() });
+        let output = executor.execute("checksum", input).await.unwrap();
+        assert!(
+            !output.is_error,
+            "Checksum should succeed: {}",
+            output.content
+        );
+        assert!(
+            output
+                .content
+                .starts_with("a948904f2f0f479b8f8197694b30184b0d2ed1c1cd2a1ec0fb85d299a192a447  "),
+            "{}",
+            output.content
+        );
+
+        let input = serde_json::json!({ "path": file.to_str().unwrap(), "algo": "md5" });
+        let output = executor.execute("checksum", input).await.unwrap();
+        assert!(
+            output
+                .content
+                .starts_with("6f5902ac237024bdd0c176cb93063dc4  ")
+        );
+
+        // A directory needs manifest, which lists every file by relative path
+        let input = serde_json::json!({ "path": dir.to_str().unwrap() });
+        let output = executor.execute("checksum", input).await.unwrap();
+        assert!(output.is_error);
+        let input = serde_json::json!({ "path": dir.to_str().unwrap(), "manifest": true });
+        let output = executor.execute("checksum", input).await.unwrap();
+        let lines: Vec<&str> = output.content.lines().collect();
+        assert_eq!(lines.len(), 2, "{}", output.content);
+        assert!(lines[0].ends_with("  hello.txt"));
+        assert!(lines[1].ends_with("  sub/nested.txt"));
+
+        // Files over the size limit are refused rather than read
+        let executor = executor::Executor::init(executor::ExecutorConfig {
+            checksum_max_file_bytes: 4,
+            ..Default::default()
+        })
+        .unwrap();
+        let input = serde_json::json!({ "path": file.to_str().unwrap() });
+        let output = executor.execute("checksum", input).await.unwrap();
+        assert!(output.is_error);
+        assert!(
+            output.content.contains("exceeds the 4 byte limit"),
+            "{}",
+            output.content
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Test a pre-exec hook refuses a command before it runs
+    #[tokio::test]
+    async fn test_pre_exec_hook_blocks_command() {
+        init_tracing();
+
+        /// Refuses bash commands that touch the marker file
+        struct BlockMarker;
+
+        #[async_trait::async_trait]
+        impl executor::PreExecHook for BlockMarker {
+            async fn before(
+                &self,
+                tool_name: &str,
+                input: &serde_json::Value,
+            ) -> Result<(), String> {
+                let command = input["command"].as_str().unwrap_or_default();
+                if tool_name == "bash" && command.contains("marker") {
+                    return Err(format!("blocked by policy: {}", command));
+                }
+                Ok(())
+            }
+        }
+
+        let dir = create_temp_dir("pre-exec-hook");
+        let marker = dir.join("marker");
+        let executor = create_executor().with_pre_exec_hook(std::sync::Arc::new(BlockMarker));
+
+        let command = format!("touch {}", marker.display());
+        let input = serde_json::json!({ "command": command, "priority": 5 });
+        let output = executor.execute("bash", input).await.unwrap();
+        assert!(output.is_error);
+        assert_eq!(output.content, format!("blocked by policy: {}", command));
+        assert!(!marker.exists(), "blocked command must not run");
+
+        let input = serde_json::json!({ "command": "echo allowed" });
+        let output = executor.execute("bash", input).await.unwrap();
+        assert!(!output.is_error);
+        assert!(output.content.contains("allowed"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Test registering a second tool under a taken name reports the collision
+    #[tokio::test]
+    async fn test_duplicate_tool_name_reported() {
+        init_tracing();
+
+        let executor = executor::Executor::default();
+        assert!(
+            executor
+                .register(std::sync::Arc::new(GetTimeTool))
+                .unwrap()
+                .is_none()
+        );
+        let replaced = executor.register(std::sync::Arc::new(GetTimeTool)).unwrap();
+        assert_eq!(
+            replaced.map(|tool| tool.name()).as_deref(),
+            Some("get_time")
+        );
+
+        let names: Vec<String> = executor
+            .tool_definitions()
+            .into_iter()
+            .map(|def| def.name)
+            .filter(|name| name == "get_time")
+            .collect();
+        assert_eq!(names.len(), 1);
+    }
+
+    /// Test a name collision is refused in strict mode, leaving the
+    /// executor usable
+    #[tokio::test]
+    async fn test_duplicate_tool_name_strict_refused() {
+        init_tracing();
+
+        let executor = executor::Executor::init(executor::ExecutorConfig {
+            strict_tool_names: true,
+            ..Default::default()
+        })
+        .unwrap();
+        executor.register(std::sync::Arc::new(GetTimeTool)).unwrap();
+        let refused = executor.register(std::sync::Arc::new(GetTimeTool));
+        assert!(
+            matches!(refused, Err(executor::ExecutorError::DuplicateTool(ref name)) if name == "get_time")
+        );
+
+        let output = executor
+            .execute("get_time", serde_json::json!({}))
+            .await
+            .unwrap();
+        assert!(!output.is_error);
+        let output = executor
+            .execute("bash", serde_json::json!({ "command": "echo still here" }))
+            .await
+            .unwrap();
+        assert!(output.content.contains("still here"));
+    }
+
+    /// Test a tool with a retry policy is re-run after a transient error
+    #[tokio::test]
+    async fn test_retry_policy_retries_until_success() {
+        init_tracing();
+
+        let dir = create_temp_dir("retry");
+        let executor = create_executor_with_retry(&dir, "{ max = 2, backoff_ms = 1 }");
+        let flaky = std::sync::Arc::new(FlakyTool {
+            failures_left: 1.into(),
+            calls: 0.into(),
+        });
+        executor.register(flaky.clone()).unwrap();
+
+        let output = executor
+            .execute("flaky", serde_json::json!({}))
+            .await
+            .unwrap();
+        assert!(!output.is_error);
+        assert_eq!(output.content, "done");
+        assert_eq!(flaky.calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Test retries stop at the limit and tools without a policy run once
+    #[tokio::test]
+    async fn test_retry_policy_limit_and_default() {
+        init_tracing();
+
+        let dir = create_temp_dir("retry-limit");
+        let executor = create_executor_with_retry(&dir, "{ max = 2, backoff_ms = 1 }");
+        let flaky = std::sync::Arc::new(FlakyTool {
+            failures_left: 5.into(),
+            calls: 0.into(),
+        });
+        executor.register(flaky.clone()).unwrap();
+
+        let output = executor
+            .execute("flaky", serde_json::json!({}))
+            .await
+            .unwrap();
+        assert!(output.is_error);
+        assert_eq!(flaky.calls.load(std::sync::atomic::Ordering::SeqCst), 3);
+
+        // Default tools.toml has no retry table for flaky
+        let executor = create_executor();
+        let flaky = std::sync::Arc::new(FlakyTool {
+            failures_left: 1.into(),
+            calls: 0.into(),
+        });
+        executor.register(flaky.clone()).unwrap();
+        let output = executor
+            .execute("flaky", serde_json::json!({}))
+            .await
+            .unwrap();
+        assert!(output.is_error);
+        assert_eq!(flaky.calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Test validate_path accepts paths inside the root and anything without one
+    #[test]
+    fn test_validate_path_inside_root() {
+        let root = create_temp_dir("path-root");
+        std::fs::create_dir(root.join("logs")).unwrap();
+        std::fs::write(root.join("logs/app.log"), "x").unwrap();
+
+        let resolved =
+            executor::path::validate_path(Some(&root), &root.join("logs/app.log")).unwrap();
+        assert_eq!(resolved, root.canonicalize().unwrap().join("logs/app.log"));
+
+        // No root: everything that exists is allowed
+        assert!(executor::path::validate_path(None, std::path::Path::new("/")).is_ok());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// Test `..` components cannot climb out of the root
+    #[test]
+    fn test_validate_path_rejects_traversal() {
+        let root = create_temp_dir("path-traversal");
+        std::fs::create_dir(root.join("logs")).unwrap();
+
+        let escape = root.join("logs/../..");
+        let err = executor::path::validate_path(Some(&root), &escape).unwrap_err();
+        assert!(matches!(err, executor::ExecutorError::InvalidPath(_)));
+        assert!(err.to_string().contains("outside the allowed root"));
+
+        // Traversal that stays inside the root is fine
+        assert!(executor::path::validate_path(Some(&root), &root.join("logs/..")).is_ok());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// Test a symlink inside the root pointing outside is rejected
+    #[tokio::test]
+    async fn test_validate_path_rejects_symlink_escape() {
+        init_tracing();
+
+        let root = create_temp_dir("path-symlink");
+        let outside = create_temp_dir("path-outside");
+        std::fs::write(outside.join("secret"), "top secret\n").unwrap();
+        std::os::unix::fs::symlink(outside.join("secret"), root.join("link")).unwrap();
+
+        let err = executor::path::validate_path(Some(&root), &root.join("link")).unwrap_err();
+        assert!(err.to_string().contains("outside the allowed root"));
+
+        // Every filesystem tool goes through the same check
+        let executor = executor::Executor::init(executor::ExecutorConfig {
+            allowed_root: Some(root.clone()),
+            ..Default::default()
+        })
+        .unwrap();
+        let input = serde_json::json!({ "path": root.join("link").to_str().unwrap() });
+        let output = executor.execute("tail_file", input).await.unwrap();
+        assert!(output.is_error);
+        assert!(!output.content.contains("top secret"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+        std::fs::remove_dir_all(&outside).unwrap();
+    }
+
+    /// Test validate() names every out-of-range value
+    #[test]
+    fn test_config_validate() {
+        assert!(executor::ExecutorConfig::default().validate().is_ok());
+
+        type Mutation = fn(&mut executor::ExecutorConfig);
+        let cases: Vec<(Mutation, &str)> = vec![
+            (
+                |c| c.constraints.timeout_secs = 0,
+                "timeout_secs must be greater than 0",
+            ),
+            (|c| c.constraints.max_output_bytes = 0, "max_output_bytes"),
+            (|c| c.shell = " ".to_string(), "shell must not be empty"),
+            (
+                |c| c.allowed_root = Some("/nonexistent/shelly-root".into()),
+                "allowed_root /nonexistent/shelly-root is not a directory",
+            ),
+            (
+                |c| c.constraints.working_dir = Some("/nonexistent/shelly-cwd".into()),
+                "working_dir",
+            ),
+            (
+                |c| c.max_concurrent_executions = 100_000,
+                "max_concurrent_executions must be at most",
+            ),
+        ];
+        for (mutate, expected) in cases {
+            let mut config = executor::ExecutorConfig::default();
+            mutate(&mut config);
+            let err = config.validate().unwrap_err();
+            assert!(matches!(err, executor::ExecutorError::ConfigInvalid(_)));
+            assert!(
+                err.to_string().contains(expected),
+                "{:?} not in {:?}",
+                expected,
+                err.to_string()
+            );
+        }
+    }
+
+    /// Test restricted tools are hidden from the model and refused
+    #[tokio::test]
+    async fn test_restrict_tools() {
+        init_tracing();
+
+        let executor = create_executor();
+        executor.restrict_tools(&["list_dir".to_string()]);
+
+        let defs = executor.tool_definitions();
+        assert_eq!(defs.len(), 1);
+        assert_eq!(defs[0].name, "list_dir");
+
+        let result = executor
+            .execute("bash", serde_json::json!({ "command": "echo hi" }))
+            .await;
+        assert!(
+            matches!(result, Err(executor::ExecutorError::ToolDisabled(name)) if name == "bash")
+        );
+
+        // Empty list re-enables everything
+        executor.restrict_tools(&[]);
+        assert!(executor.tool_definitions().iter().any(|d| d.name == "bash"));
+    }
+
+    /// Test net_check against a listening and a closed local port
+    #[tokio::test]
+    async fn test_net_check_local_ports() {
+        init_tracing();
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let open_port = listener.local_addr().unwrap().port();
+        let closed_port = {
+            let probe = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            probe.local_addr().unwrap().port()
+        };
+
+        let executor = create_executor();
+
+        let input = serde_json::json!({ "host": "127.0.0.1", "port": open_port });
+        let output = executor.execute("net_check", input).await.unwrap();
+        assert!(!output.is_error);
+        let check: serde_json::Value = serde_json::from_str(&output.content).unwrap();
+        assert_eq!(check["reachable"], true);
+        assert_eq!(check["resolved"], serde_json::json!(["127.0.0.1"]));
+        assert!(check["latency_ms"].is_number());
+
+        let input = serde_json::json!({ "host": "127.0.0.1", "port": closed_port });
+        let output = executor.execute("net_check", input).await.unwrap();
+        assert!(!output.is_error);
+        let check: serde_json::Value = serde_json::from_str(&output.content).unwrap();
+        assert_eq!(check["reachable"], false);
+        assert!(
+            check["error"]
+                .as_str()
+                .unwrap()
+                .to_lowercase()
+                .contains("connection refused"),
+            "unexpected reason: {}",
+            check["error"]
+        );
+
+        drop(listener);
+    }
+
+    /// Test service_status against the host's systemd; skipped elsewhere
+    #[tokio::test]
+    async fn test_service_status_systemd() {
+        init_tracing();
+
+        if !std::path::Path::new("/run/systemd/system").is_dir() {
+            eprintln!("skipping: systemd is not the running init system");
+            return;
+        }
+        let executor = create_executor();
+
+        let output = executor
+            .execute("service_status", serde_json::json!({}))
+            .await
+            .unwrap();
+        assert!(!output.is_error, "{}", output.content);
+        let listing: serde_json::Value = serde_json::from_str(&output.content).unwrap();
+        assert_eq!(listing["init_system"], "systemd");
+        let units = listing["units"].as_array().unwrap();
+        assert_eq!(listing["count"], units.len());
+
+        let Some(first) = units.first() else {
+            return;
+        };
+        let name = first["unit"].as_str().unwrap();
+        let input = serde_json::json!({ "unit": name });
+        let output = executor.execute("service_status", input).await.unwrap();
+        assert!(!output.is_error, "{}", output.content);
+        let status: serde_json::Value = serde_json::from_str(&output.content).unwrap();
+        assert_eq!(status["unit"]["id"], name);
+        assert_eq!(status["unit"]["active_state"], first["active"]);
+    }
+
+    /// Without systemd, service_status reports an unsupported init system
+    #[tokio::test]
+    async fn test_service_status_unsupported_init_system() {
+        init_tracing();
+        use executor::ToolImpl;
+
+        let dir = create_temp_dir("no-systemd");
+        let tool = executor::service_status::ServiceStatusTool::new("", "systemctl", 5)
+            .with_systemd_dir(dir.join("systemd/system"));
+
+        let output = tool
+            .run(serde_json::json!({ "unit": "nginx" }))
+            .await
+            .unwrap();
+        assert!(output.is_error);
+        assert!(
+            output.content.starts_with("unsupported init system: "),
+            "{}",
+            output.content
+        );
+
+        // Option-like unit names never reach systemctl
+        let result = tool.run(serde_json::json!({ "unit": "--user" })).await;
+        assert!(matches!(
+            result,
+            Err(executor::ExecutorError::InvalidInput(..))
+        ));
+    }
+
+    /// Test result_format from tools.toml: json yields an object, text the legacy string
+    #[tokio::test]
+    async fn test_result_format_json_and_text() {
+        init_tracing();
+
+        let dir = create_temp_dir("result-format");
+        let tools_toml = dir.join("tools.toml");
+        std::fs::write(&tools_toml, "[bash]\nresult_format = \"json\"\n").unwrap();
+        let executor = executor::Executor::init(executor::ExecutorConfig {
+            tools_toml_path: tools_toml,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(executor.result_format("bash"), executor::ResultFormat::Json);
+        assert_eq!(
+            executor.result_format("list_dir"),
+            executor::ResultFormat::Text
+        );
+
+        let input = serde_json::json!({ "command": "echo out; echo err >&2; exit 3" });
+        let output = executor.execute("bash", input).await.unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&output.render(executor::ResultFormat::Json)).unwrap();
+        assert_eq!(json["stdout"], "out\n");
+        assert_eq!(json["stderr"], "err\n");
+        assert_eq!(json["exit_code"], 3);
+        assert_eq!(json["is_error"], true);
+
+        assert_eq!(
+            output.render(executor::ResultFormat::Text),
+            "Error: [stdout]\nout\n\n[stderr]\nerr\n\n[exit_code]\n3"
+        );
+
+        let plain = executor::ToolOutput::success("listing");
+        assert_eq!(plain.render(executor::ResultFormat::Text), "listing");
+        let json: serde_json::Value =
+            serde_json::from_str(&plain.render(executor::ResultFormat::Json)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "content": "listing", "is_error": false })
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Test cacheable flags from tools.toml; the built-in tools all default
+    /// to non-cacheable, so without a tag every call runs
+    #[test]
+    fn test_cacheable_flags() {
+        init_tracing();
+
+        let dir = create_temp_dir("cacheable");
+        let tools_toml = dir.join("tools.toml");
+        std::fs::write(&tools_toml, "[bash]\ncacheable = true\n").unwrap();
+        let executor = executor::Executor::init(executor::ExecutorConfig {
+            tools_toml_path: tools_toml,
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(executor.is_cacheable("bash"));
+        for tool in ["list_dir", "tail_file", "net_check", "service_status"] {
+            assert!(!executor.is_cacheable(tool), "{}", tool);
+        }
+
+        let executor = executor::Executor::default();
+        assert!(!executor.is_cacheable("bash"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Test that every setting in one tool's table applies, and that an
+    /// invalid tools.toml leaves every tool on its defaults
+    #[test]
+    fn test_tool_settings_from_one_table() {
+        init_tracing();
+
+        let dir = create_temp_dir("tool-settings");
+        let tools_toml = dir.join("tools.toml");
+        std::fs::write(
+            &tools_toml,
+            "[tail_file]\ndescription = \"tail it\"\nresult_format = \"json\"\ncacheable = true\nread_only = false\n",
+        )
+        .unwrap();
+        let executor = executor::Executor::init(executor::ExecutorConfig {
+            tools_toml_path: tools_toml.clone(),
+            ..Default::default()
+        })
+        .unwrap();
+        let description = |executor: &executor::Executor| {
+            executor
+                .tool_definitions()
+                .into_iter()
+                .find(|d| d.name == "tail_file")
+                .unwrap()
+                .description
+        };
+        assert_eq!(description(&executor), "tail it");
+        assert_eq!(
+            executor.result_format("tail_file"),
+            executor::ResultFormat::Json
+        );
+        assert!(executor.is_cacheable("tail_file"));
+        assert!(!executor.is_read_only("tail_file"));
+
+        std::fs::write(&tools_toml, "[tail_file]\nread_only = \"yes\"\n").unwrap();
+        let executor = executor::Executor::init(executor::ExecutorConfig {
+            tools_toml_path: tools_toml,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_ne!(description(&executor), "tail it");
+        assert_eq!(
+            executor.result_format("tail_file"),
+            executor::ResultFormat::Text
+        );
+        assert!(!executor.is_cacheable("tail_file"));
+        assert!(executor.is_read_only("tail_file"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Test read_only tags from tools.toml; tags override each tool's default
+    #[test]
+    fn test_read_only_flags() {
+        init_tracing();
+
+        let dir = create_temp_dir("read_only");
+        let tools_toml = dir.join("tools.toml");
+        std::fs::write(&tools_toml, "[tail_file]\nread_only = false\n").unwrap();
+        let executor = executor::Executor::init(executor::ExecutorConfig {
+            tools_toml_path: tools_toml,
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(!executor.is_read_only("tail_file"));
+        assert!(!executor.is_read_only("bash"));
+        assert!(!executor.is_read_only("no_such_tool"));
+
+        // The observing tools are read-only without a tag
+        let executor = executor::Executor::default();
+        for tool in [
+            "list_dir",
+            "tail_file",
+            "checksum",
+            "net_check",
+            "read_metric",
+            "service_status",
+        ] {
+            assert!(executor.is_read_only(tool), "{}", tool);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }